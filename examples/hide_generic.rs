@@ -1,4 +1,5 @@
-use rb_tree::RBQueue;
+use rb_tree::{Comparator, RBQueue};
+use std::cmp::Ordering;
 
 struct ByReference {
     queue: RBQueue<i32, &'static dyn Fn(&i32, &i32) -> std::cmp::Ordering>,
@@ -24,10 +25,31 @@ impl Default for Boxing {
     }
 }
 
+// a comparator type can be named directly, with
+// no indirection, and lets the queue derive Default
+#[derive(Default)]
+struct Descending;
+
+impl Comparator<i32> for Descending {
+    fn compare(&self, l: &i32, r: &i32) -> Ordering {
+        r.cmp(l)
+    }
+}
+
+#[derive(Default)]
+struct Named {
+    queue: RBQueue<i32, Descending>,
+}
+
 fn main() {
     let mut h = ByReference::default();
     h.queue.insert(1i32);
 
     let mut b = Boxing::default();
     b.queue.insert(1i32);
+
+    let mut n = Named::default();
+    n.queue.insert(1i32);
+    n.queue.insert(2i32);
+    assert_eq!(n.queue.pop(), Some(2));
 }
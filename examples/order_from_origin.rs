@@ -1,8 +1,6 @@
 #[macro_use(new_c_queue)]
 extern crate rb_tree;
 
-use rb_tree::RBQueue;
-
 fn from_origin(p: &(f64, f64)) -> f64 {
     ((p.0 * p.0) + (p.1 * p.1)).sqrt()
 }
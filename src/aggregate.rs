@@ -0,0 +1,522 @@
+use crate::mapper::{KeyRef, Mapper};
+use crate::node::{Arena, Index};
+use crate::{Comparator, NaturalOrder, RBAggregateMap};
+
+use std::borrow::Borrow;
+use std::fmt::{Debug, Formatter, Result};
+use std::iter::{ExactSizeIterator, FromIterator, FusedIterator};
+use std::ops::Add;
+use std::ops::Bound::{self, Excluded, Included, Unbounded};
+use std::ops::RangeBounds;
+
+/// A summary of a run of values, kept by an RBAggregateMap
+/// for each of its subtrees. Combining summaries must be
+/// associative, as the map is free to group the runs of
+/// values however its tree is shaped.
+/// # Example:
+/// ```
+/// use rb_tree::{Aggregate, RBAggregateMap};
+///
+/// // the number of values and their total
+/// #[derive(Clone, Debug, PartialEq)]
+/// struct Mean(usize, f64);
+///
+/// impl Aggregate<f64> for Mean {
+///     fn of(val: &f64) -> Self {
+///         Mean(1, *val)
+///     }
+///
+///     fn combine(&self, other: &Self) -> Self {
+///         Mean(self.0 + other.0, self.1 + other.1)
+///     }
+/// }
+///
+/// let mut readings = RBAggregateMap::new();
+/// readings.insert(1, 2.0);
+/// readings.insert(2, 4.0);
+/// readings.insert(3, 9.0);
+/// let Mean(n, total) = readings.aggregate_range(1..3).unwrap();
+/// assert_eq!(total / n as f64, 3.0);
+/// ```
+pub trait Aggregate<V>: Clone {
+    /// Returns the summary of a single value.
+    fn of(val: &V) -> Self;
+
+    /// Returns the summary of the values summarised by
+    /// `self` followed by those summarised by `other`.
+    fn combine(&self, other: &Self) -> Self;
+}
+
+/// Aggregates values by adding them up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Total<T>(pub T);
+
+impl<T: Clone + Add<Output = T>> Aggregate<T> for Total<T> {
+    fn of(val: &T) -> Self {
+        Total(val.clone())
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        Total(self.0.clone() + other.0.clone())
+    }
+}
+
+/// Aggregates values by keeping the greatest of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Max<T>(pub T);
+
+impl<T: Clone + PartialOrd> Aggregate<T> for Max<T> {
+    fn of(val: &T) -> Self {
+        Max(val.clone())
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        if other.0 > self.0 {
+            other.clone()
+        } else {
+            self.clone()
+        }
+    }
+}
+
+/// Aggregates values by keeping the least of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Min<T>(pub T);
+
+impl<T: Clone + PartialOrd> Aggregate<T> for Min<T> {
+    fn of(val: &T) -> Self {
+        Min(val.clone())
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        if other.0 < self.0 {
+            other.clone()
+        } else {
+            self.clone()
+        }
+    }
+}
+
+/// Keeps two aggregates of the same values at once.
+impl<V, A: Aggregate<V>, B: Aggregate<V>> Aggregate<V> for (A, B) {
+    fn of(val: &V) -> Self {
+        (A::of(val), B::of(val))
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        (self.0.combine(&other.0), self.1.combine(&other.1))
+    }
+}
+
+// orders entries, or a key against an entry, as the
+// other maps do
+fn order<L: PartialOrd<R> + ?Sized, R>(l: &L, r: &R) -> std::cmp::Ordering {
+    NaturalOrder.compare(l, r)
+}
+
+// recomputes the aggregate of the subtree rooted at `at`
+// from those of its children, installed as the arena's
+// augmentation so it runs whenever the tree is reshaped
+fn refresh<K: PartialOrd, V, A: Aggregate<V>>(nodes: &mut Arena<Mapper<K, (V, A)>>, at: Index) {
+    let mut agg = A::of(&nodes.value(at).unwrap().as_ref().0);
+    if let Some(l) = nodes.value(nodes.get_left(at)) {
+        agg = l.as_ref().1.combine(&agg);
+    }
+    if let Some(r) = nodes.value(nodes.get_right(at)) {
+        agg = agg.combine(&r.as_ref().1);
+    }
+    nodes.value_mut(at).unwrap().as_mut().1 = agg;
+}
+
+// joins two optional summaries, the first coming before the second
+fn chain<V, A: Aggregate<V>>(first: Option<A>, then: Option<A>) -> Option<A> {
+    match (first, then) {
+        (Some(f), Some(t)) => Some(f.combine(&t)),
+        (f, None) => f,
+        (None, t) => t,
+    }
+}
+
+fn after_start<Q: PartialOrd + ?Sized>(start: Bound<&Q>, key: &Q) -> bool {
+    match start {
+        Included(s) => key >= s,
+        Excluded(s) => key > s,
+        Unbounded => true,
+    }
+}
+
+fn before_end<Q: PartialOrd + ?Sized>(end: Bound<&Q>, key: &Q) -> bool {
+    match end {
+        Included(e) => key <= e,
+        Excluded(e) => key < e,
+        Unbounded => true,
+    }
+}
+
+impl<K: PartialOrd + Debug, V: Debug, A: Aggregate<V>> Debug for RBAggregateMap<K, V, A> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: PartialOrd, V, A: Aggregate<V>> RBAggregateMap<K, V, A> {
+    /// Creates and returns a new, empty RBAggregateMap.
+    /// # Example:
+    /// ```
+    /// use rb_tree::aggregate::Total;
+    /// use rb_tree::RBAggregateMap;
+    ///
+    /// let mut buffered: RBAggregateMap<u64, u64, Total<u64>> = RBAggregateMap::new();
+    /// buffered.insert(100, 512);
+    /// buffered.insert(105, 1024);
+    /// buffered.insert(230, 64);
+    /// assert_eq!(buffered.aggregate_range(100..200), Some(Total(1536)));
+    /// ```
+    pub fn new() -> RBAggregateMap<K, V, A> {
+        let mut nodes = Arena::new();
+        nodes.set_augment(refresh::<K, V, A>);
+        RBAggregateMap {
+            nodes,
+            contained: 0,
+        }
+    }
+
+    /// Inserts a value to associate with the given key,
+    /// returning the value previously associated with
+    /// the key, if any.
+    /// # Example:
+    /// ```
+    /// use rb_tree::aggregate::Max;
+    /// use rb_tree::RBAggregateMap;
+    ///
+    /// let mut map: RBAggregateMap<_, _, Max<_>> = RBAggregateMap::new();
+    /// assert_eq!(map.insert("a", 3), None);
+    /// assert_eq!(map.insert("a", 1), Some(3));
+    /// assert_eq!(map.aggregate(), Some(Max(1)));
+    /// ```
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        let agg = A::of(&val);
+        let old = self.nodes.insert(Mapper::new(key, Some((val, agg))), &order);
+        if old.is_none() {
+            self.contained += 1;
+        }
+        old.map(|m| m.consume().1 .0)
+    }
+
+    /// Returns the value associated with the key, if any.
+    /// # Example:
+    /// ```
+    /// use rb_tree::aggregate::Total;
+    /// use rb_tree::RBAggregateMap;
+    ///
+    /// let mut map: RBAggregateMap<_, _, Total<_>> = RBAggregateMap::new();
+    /// map.insert(1, 10);
+    /// assert_eq!(map.get(&1), Some(&10));
+    /// assert_eq!(map.get(&2), None);
+    /// ```
+    pub fn get<Q: PartialOrd + ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        self.nodes.get(&KeyRef(key), &order).map(|m| &m.as_ref().0)
+    }
+
+    /// Returns true if the key has a value associated
+    /// with it.
+    /// # Example:
+    /// ```
+    /// use rb_tree::aggregate::Total;
+    /// use rb_tree::RBAggregateMap;
+    ///
+    /// let mut map: RBAggregateMap<_, _, Total<_>> = RBAggregateMap::new();
+    /// map.insert(1, 10);
+    /// assert!(map.contains_key(&1));
+    /// ```
+    pub fn contains_key<Q: PartialOrd + ?Sized>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Applies `f` to the value associated with the key,
+    /// updating the aggregates that include it. Returns
+    /// false if the key has no value.
+    /// # Example:
+    /// ```
+    /// use rb_tree::aggregate::Total;
+    /// use rb_tree::RBAggregateMap;
+    ///
+    /// let mut map: RBAggregateMap<_, _, Total<_>> = RBAggregateMap::new();
+    /// map.insert(1, 10);
+    /// map.insert(2, 20);
+    /// assert!(map.modify(&1, |v| *v += 5));
+    /// assert_eq!(map.aggregate(), Some(Total(35)));
+    /// assert!(!map.modify(&3, |v| *v += 5));
+    /// ```
+    pub fn modify<Q, F>(&mut self, key: &Q, f: F) -> bool
+    where
+        K: Borrow<Q>,
+        Q: PartialOrd + ?Sized,
+        F: FnOnce(&mut V),
+    {
+        match self.nodes.locate(&KeyRef(key), &order) {
+            Ok(at) => {
+                f(&mut self.nodes.value_mut(at).unwrap().as_mut().0);
+                self.nodes.resize_up(at);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Removes the key from the map, returning the value
+    /// that was associated with it, if any.
+    /// # Example:
+    /// ```
+    /// use rb_tree::aggregate::Total;
+    /// use rb_tree::RBAggregateMap;
+    ///
+    /// let mut map: RBAggregateMap<_, _, Total<_>> = RBAggregateMap::new();
+    /// map.insert(1, 10);
+    /// map.insert(2, 20);
+    /// assert_eq!(map.remove(&1), Some(10));
+    /// assert_eq!(map.aggregate(), Some(Total(20)));
+    /// ```
+    pub fn remove<Q: PartialOrd + ?Sized>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+    {
+        let m = self.nodes.remove(&KeyRef(key), &order)?;
+        self.contained -= 1;
+        Some(m.consume().1 .0)
+    }
+
+    /// Returns the number of entries in the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::aggregate::Total;
+    /// use rb_tree::RBAggregateMap;
+    ///
+    /// let mut map: RBAggregateMap<_, _, Total<_>> = RBAggregateMap::new();
+    /// map.insert(1, 10);
+    /// assert_eq!(map.len(), 1);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.contained
+    }
+
+    /// Returns true if the map has no entries.
+    /// # Example:
+    /// ```
+    /// use rb_tree::aggregate::Total;
+    /// use rb_tree::RBAggregateMap;
+    ///
+    /// let map: RBAggregateMap<u8, u8, Total<u8>> = RBAggregateMap::new();
+    /// assert!(map.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.contained == 0
+    }
+
+    /// An iterator that visits all key-value
+    /// pairs in their key's partialord order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::aggregate::Total;
+    /// use rb_tree::RBAggregateMap;
+    ///
+    /// let mut map: RBAggregateMap<_, _, Total<_>> = RBAggregateMap::new();
+    /// map.insert(2, 20);
+    /// map.insert(1, 10);
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), vec!((&1, &10), (&2, &20)));
+    /// ```
+    pub fn iter(&self) -> Iter<'_, K, V, A> {
+        Iter {
+            remaining: self.contained,
+            nodes: &self.nodes,
+            next: self.nodes.first(),
+            back: self.nodes.last(),
+        }
+    }
+
+    /// Returns the aggregate of all the values in
+    /// the map, or None if it is empty. Takes O(1) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::aggregate::{Max, Total};
+    /// use rb_tree::RBAggregateMap;
+    ///
+    /// let mut map: RBAggregateMap<_, _, (Total<_>, Max<_>)> = RBAggregateMap::new();
+    /// map.insert("a", 4);
+    /// map.insert("b", 7);
+    /// assert_eq!(map.aggregate(), Some((Total(11), Max(7))));
+    /// ```
+    pub fn aggregate(&self) -> Option<A> {
+        self.summary(self.nodes.root())
+    }
+
+    /// Returns the aggregate of the values whose keys
+    /// fall within the given range, or None if there are
+    /// no such values. Takes O(log n) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::aggregate::Max;
+    /// use rb_tree::RBAggregateMap;
+    ///
+    /// let mut map: RBAggregateMap<_, _, Max<_>> = RBAggregateMap::new();
+    /// for (k, v) in vec!((1, 5), (2, 9), (3, 2), (4, 7)) {
+    ///     map.insert(k, v);
+    /// }
+    /// assert_eq!(map.aggregate_range(3..), Some(Max(7)));
+    /// assert_eq!(map.aggregate_range(..=2), Some(Max(9)));
+    /// assert_eq!(map.aggregate_range(5..), None);
+    /// ```
+    pub fn aggregate_range<Q, R>(&self, range: R) -> Option<A>
+    where
+        K: Borrow<Q>,
+        Q: PartialOrd + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let nodes = &self.nodes;
+        let (start, end) = (range.start_bound(), range.end_bound());
+        // descends to the first node within the range, whose
+        // subtree holds every node of the range
+        let mut cur = nodes.root();
+        while let Some(m) = nodes.value(cur) {
+            let key = m.key().borrow();
+            if !after_start(start, key) {
+                cur = nodes.get_right(cur);
+            } else if !before_end(end, key) {
+                cur = nodes.get_left(cur);
+            } else {
+                let left = self.suffix(nodes.get_left(cur), start);
+                let right = self.prefix(nodes.get_right(cur), end);
+                return chain(chain(left, Some(A::of(&m.as_ref().0))), right);
+            }
+        }
+        None
+    }
+
+    // the aggregate of the whole subtree rooted at `at`
+    fn summary(&self, at: Index) -> Option<A> {
+        self.nodes.value(at).map(|m| m.as_ref().1.clone())
+    }
+
+    // the aggregate of the nodes beneath `at` after the start
+    fn suffix<Q>(&self, at: Index, start: Bound<&Q>) -> Option<A>
+    where
+        K: Borrow<Q>,
+        Q: PartialOrd + ?Sized,
+    {
+        let nodes = &self.nodes;
+        let m = nodes.value(at)?;
+        if after_start(start, m.key().borrow()) {
+            let left = self.suffix(nodes.get_left(at), start);
+            let here = chain(left, Some(A::of(&m.as_ref().0)));
+            chain(here, self.summary(nodes.get_right(at)))
+        } else {
+            self.suffix(nodes.get_right(at), start)
+        }
+    }
+
+    // the aggregate of the nodes beneath `at` before the end
+    fn prefix<Q>(&self, at: Index, end: Bound<&Q>) -> Option<A>
+    where
+        K: Borrow<Q>,
+        Q: PartialOrd + ?Sized,
+    {
+        let nodes = &self.nodes;
+        let m = nodes.value(at)?;
+        if before_end(end, m.key().borrow()) {
+            let here = chain(self.summary(nodes.get_left(at)), Some(A::of(&m.as_ref().0)));
+            chain(here, self.prefix(nodes.get_right(at), end))
+        } else {
+            self.prefix(nodes.get_left(at), end)
+        }
+    }
+}
+
+impl<K: PartialOrd, V, A: Aggregate<V>> Default for RBAggregateMap<K, V, A> {
+    fn default() -> Self {
+        RBAggregateMap::new()
+    }
+}
+
+impl<K: PartialOrd, V, A: Aggregate<V>> FromIterator<(K, V)> for RBAggregateMap<K, V, A> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = RBAggregateMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: PartialOrd, V, A: Aggregate<V>> Extend<(K, V)> for RBAggregateMap<K, V, A> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, val) in iter {
+            self.insert(key, val);
+        }
+    }
+}
+
+pub struct Iter<'a, K: PartialOrd, V, A> {
+    remaining: usize,
+    nodes: &'a Arena<Mapper<K, (V, A)>>,
+    next: Index,
+    back: Index,
+}
+
+impl<'a, K: PartialOrd, V, A> Iterator for Iter<'a, K, V, A> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let m = self.nodes.value(self.next)?;
+        self.remaining -= 1;
+        self.next = self.nodes.step(self.next, true);
+        Some((m.key(), &m.as_ref().0))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, K: PartialOrd, V, A> DoubleEndedIterator for Iter<'a, K, V, A> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let m = self.nodes.value(self.back)?;
+        self.remaining -= 1;
+        self.back = self.nodes.step(self.back, false);
+        Some((m.key(), &m.as_ref().0))
+    }
+}
+
+impl<'a, K: PartialOrd, V, A> ExactSizeIterator for Iter<'a, K, V, A> {
+    fn len(&self) -> usize {
+        self.remaining
+    }
+}
+
+impl<'a, K: PartialOrd, V, A> FusedIterator for Iter<'a, K, V, A> {}
+
+impl<'a, K: PartialOrd, V, A> Clone for Iter<'a, K, V, A> {
+    fn clone(&self) -> Self {
+        Iter {
+            remaining: self.remaining,
+            nodes: self.nodes,
+            next: self.next,
+            back: self.back,
+        }
+    }
+}
+
+impl<'a, K: PartialOrd + Debug, V: Debug, A> Debug for Iter<'a, K, V, A> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
@@ -0,0 +1,478 @@
+use crate::{AsyncRBQueue, Comparator, RBQueue};
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter, Result};
+use std::future::Future;
+use std::mem::take;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::Instant;
+
+// the state behind every handle to an AsyncRBQueue
+pub(crate) struct Shared<T, C> {
+    state: Mutex<State<T, C>>,
+    timer: Arc<Timer>,
+}
+
+struct State<T, C> {
+    queue: RBQueue<T, C>,
+    // the tasks waiting on the queue, which are all woken
+    // when an element is pushed so that each looks again
+    waiting: Vec<Waker>,
+}
+
+impl<T, C> Shared<T, C> {
+    // the queue is left whole by a panic in its comparator,
+    // so a lock poisoned by one is safe to carry on with
+    fn lock(&self) -> MutexGuard<'_, State<T, C>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T, C> Drop for Shared<T, C> {
+    fn drop(&mut self) {
+        self.timer.close();
+    }
+}
+
+impl<T, C> State<T, C> {
+    // a task polled again by the same waker is only kept once
+    fn wait(&mut self, waker: &Waker) {
+        if !self.waiting.iter().any(|w| w.will_wake(waker)) {
+            self.waiting.push(waker.clone());
+        }
+    }
+}
+
+// the deadlines PopDue futures are waiting on, kept apart
+// from the queue so that the one thread sleeping until the
+// earliest of them does not keep the queue alive
+#[derive(Default)]
+struct Timer {
+    armed: Mutex<Armed>,
+    rearmed: Condvar,
+}
+
+#[derive(Default)]
+struct Armed {
+    // keyed by deadline and then by the order they were
+    // armed in, so the earliest comes first and futures
+    // waiting on the same deadline are kept apart
+    wakers: BTreeMap<(Instant, u64), Waker>,
+    registered: u64,
+    running: bool,
+    closed: bool,
+}
+
+impl Timer {
+    // wakers are only ever moved in and out, so a
+    // poisoned lock is as safe to carry on with
+    fn lock(&self) -> MutexGuard<'_, Armed> {
+        self.armed.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // has the task woken at `at`, returning the key to cancel
+    // it by; the thread is only started for the first deadline
+    // and is told of any that is earlier than those it has
+    fn arm(self: &Arc<Self>, at: Instant, waker: Waker) -> (Instant, u64) {
+        let mut armed = self.lock();
+        let key = (at, armed.registered);
+        armed.registered += 1;
+        let earliest = match armed.wakers.keys().next() {
+            Some(first) => key < *first,
+            None => true,
+        };
+        armed.wakers.insert(key, waker);
+        if !armed.running {
+            armed.running = true;
+            let timer = self.clone();
+            thread::spawn(move || timer.run());
+        } else if earliest {
+            self.rearmed.notify_one();
+        }
+        key
+    }
+
+    // the thread need not be told, as at worst it wakes
+    // to find nothing due and goes back to sleep
+    fn cancel(&self, key: (Instant, u64)) {
+        self.lock().wakers.remove(&key);
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.rearmed.notify_one();
+    }
+
+    // sleeps until the earliest deadline armed, wakes each
+    // task due by then, and carries on until the queue is
+    // dropped
+    fn run(&self) {
+        let mut armed = self.lock();
+        while !armed.closed {
+            let now = Instant::now();
+            armed = match armed.wakers.keys().next().copied() {
+                Some(key) if key.0 <= now => {
+                    let waker = armed.wakers.remove(&key).unwrap();
+                    drop(armed);
+                    waker.wake();
+                    self.lock()
+                }
+                Some((at, _)) => {
+                    let waited = self.rearmed.wait_timeout(armed, at - now);
+                    waited.unwrap_or_else(PoisonError::into_inner).0
+                }
+                None => {
+                    let waited = self.rearmed.wait(armed);
+                    waited.unwrap_or_else(PoisonError::into_inner)
+                }
+            };
+        }
+    }
+}
+
+impl<T: Debug, C: Comparator<T>> Debug for AsyncRBQueue<T, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{:?}", self.shared.lock().queue)
+    }
+}
+
+impl<T, C> Clone for AsyncRBQueue<T, C> {
+    fn clone(&self) -> Self {
+        AsyncRBQueue {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T, C: Comparator<T> + Default> AsyncRBQueue<T, C> {
+    /// Creates and returns a new, empty AsyncRBQueue
+    /// ordered by the comparator's default value.
+    /// # Example:
+    /// ```
+    /// use rb_tree::AsyncRBQueue;
+    ///
+    /// let q = AsyncRBQueue::<u32>::new();
+    /// assert!(q.is_empty());
+    /// ```
+    pub fn new() -> AsyncRBQueue<T, C> {
+        AsyncRBQueue::from(RBQueue::default())
+    }
+}
+
+impl<T, C: Comparator<T>> AsyncRBQueue<T, C> {
+    /// Creates and returns a new, empty AsyncRBQueue
+    /// that orders its elements using `cmp`.
+    /// # Example:
+    /// ```
+    /// use rb_tree::{AsyncRBQueue, Reversed, TotalOrder};
+    ///
+    /// let q = AsyncRBQueue::with_comparator(Reversed(TotalOrder));
+    /// q.push(1);
+    /// q.push(3);
+    /// assert_eq!(q.try_pop(), Some(3));
+    /// ```
+    pub fn with_comparator(cmp: C) -> AsyncRBQueue<T, C> {
+        AsyncRBQueue::from(RBQueue::with_comparator(cmp))
+    }
+
+    /// Inserts `val` into the queue as `RBQueue::insert`
+    /// does, returning whether it was added, and wakes the
+    /// tasks waiting on the queue.
+    /// # Example:
+    /// ```
+    /// use rb_tree::AsyncRBQueue;
+    ///
+    /// let q = AsyncRBQueue::<u32>::new();
+    /// assert!(q.push(2));
+    /// assert_eq!(q.len(), 1);
+    /// ```
+    pub fn push(&self, val: T) -> bool {
+        let mut state = self.shared.lock();
+        let added = state.queue.insert(val);
+        let waiting = take(&mut state.waiting);
+        drop(state);
+        waiting.into_iter().for_each(Waker::wake);
+        added
+    }
+
+    /// Removes and returns the element at the front of
+    /// the queue, or None if the queue is empty, without
+    /// waiting.
+    /// # Example:
+    /// ```
+    /// use rb_tree::AsyncRBQueue;
+    ///
+    /// let q = AsyncRBQueue::<u32>::new();
+    /// assert_eq!(q.try_pop(), None);
+    /// q.push(7);
+    /// q.push(4);
+    /// assert_eq!(q.try_pop(), Some(4));
+    /// ```
+    pub fn try_pop(&self) -> Option<T> {
+        self.shared.lock().queue.pop()
+    }
+
+    /// Returns a future that removes and completes with the
+    /// element at the front of the queue, waiting for one to
+    /// be pushed if the queue is empty. Where several tasks
+    /// wait on the queue, each element goes to just one.
+    /// # Example:
+    /// ```
+    /// use rb_tree::AsyncRBQueue;
+    /// # use std::future::Future;
+    /// # use std::sync::Arc;
+    /// # use std::task::{Context, Poll, Wake, Waker};
+    /// # struct Unpark(std::thread::Thread);
+    /// # impl Wake for Unpark {
+    /// #     fn wake(self: Arc<Self>) {
+    /// #         self.0.unpark();
+    /// #     }
+    /// # }
+    /// # fn block_on<F: Future>(f: F) -> F::Output {
+    /// #     let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+    /// #     let mut cx = Context::from_waker(&waker);
+    /// #     let mut f = Box::pin(f);
+    /// #     loop {
+    /// #         if let Poll::Ready(v) = f.as_mut().poll(&mut cx) {
+    /// #             return v;
+    /// #         }
+    /// #         std::thread::park();
+    /// #     }
+    /// # }
+    /// use std::thread;
+    ///
+    /// let jobs = AsyncRBQueue::<u32>::new();
+    /// let producer = jobs.clone();
+    /// thread::spawn(move || producer.push(42));
+    /// // block_on stands in for any executor
+    /// assert_eq!(block_on(jobs.pop()), 42);
+    /// ```
+    pub fn pop(&self) -> Pop<'_, T, C> {
+        Pop { queue: self }
+    }
+
+    /// Returns a future that removes and completes with the
+    /// element at the front of the queue once the deadline
+    /// `deadline` gives for it has passed, waiting for one to
+    /// be pushed if the queue is empty. Elements pushed while
+    /// it waits are taken into account, so the queue should be
+    /// ordered by deadline. As the crate depends on no async
+    /// runtime, each queue has a single thread, started the
+    /// first time a future waits on a deadline, that sleeps
+    /// until the earliest one and wakes the waiting task.
+    /// Dropping the future before then cancels its wake-up.
+    /// The thread is not shared between queues, and lives
+    /// until the last handle to its queue is dropped, so
+    /// each queue that has waited on a deadline costs an OS
+    /// thread; many short-lived timers are better kept in
+    /// one queue than spread over many.
+    /// # Example:
+    /// ```
+    /// use rb_tree::AsyncRBQueue;
+    /// # use std::future::Future;
+    /// # use std::sync::Arc;
+    /// # use std::task::{Context, Poll, Wake, Waker};
+    /// # struct Unpark(std::thread::Thread);
+    /// # impl Wake for Unpark {
+    /// #     fn wake(self: Arc<Self>) {
+    /// #         self.0.unpark();
+    /// #     }
+    /// # }
+    /// # fn block_on<F: Future>(f: F) -> F::Output {
+    /// #     let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+    /// #     let mut cx = Context::from_waker(&waker);
+    /// #     let mut f = Box::pin(f);
+    /// #     loop {
+    /// #         if let Poll::Ready(v) = f.as_mut().poll(&mut cx) {
+    /// #             return v;
+    /// #         }
+    /// #         std::thread::park();
+    /// #     }
+    /// # }
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let start = Instant::now();
+    /// let timers = AsyncRBQueue::<(Instant, &str)>::new();
+    /// timers.push((start + Duration::from_millis(30), "retry"));
+    /// timers.push((start + Duration::from_millis(10), "ping"));
+    /// let due = |timer: &(Instant, &str)| timer.0;
+    /// // block_on stands in for any executor
+    /// assert_eq!(block_on(timers.pop_due(due)).1, "ping");
+    /// assert_eq!(block_on(timers.pop_due(due)).1, "retry");
+    /// assert!(start.elapsed() >= Duration::from_millis(30));
+    /// ```
+    pub fn pop_due<F: Fn(&T) -> Instant>(&self, deadline: F) -> PopDue<'_, T, C, F> {
+        PopDue {
+            queue: self,
+            deadline,
+            armed: RefCell::new(None),
+        }
+    }
+
+    /// Returns the number of elements in the queue.
+    /// # Example:
+    /// ```
+    /// use rb_tree::AsyncRBQueue;
+    ///
+    /// let q = AsyncRBQueue::<u32>::new();
+    /// q.push(1);
+    /// q.push(2);
+    /// assert_eq!(q.len(), 2);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.shared.lock().queue.len()
+    }
+
+    /// Returns true if the queue holds no
+    /// elements, false otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::AsyncRBQueue;
+    ///
+    /// let q = AsyncRBQueue::<u32>::new();
+    /// assert!(q.is_empty());
+    /// q.push(1);
+    /// assert!(!q.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.shared.lock().queue.is_empty()
+    }
+
+    /// Removes all elements from the queue. Tasks
+    /// waiting on it carry on waiting.
+    /// # Example:
+    /// ```
+    /// use rb_tree::AsyncRBQueue;
+    ///
+    /// let q = AsyncRBQueue::<u32>::new();
+    /// q.push(1);
+    /// q.clear();
+    /// assert!(q.is_empty());
+    /// ```
+    pub fn clear(&self) {
+        self.shared.lock().queue.clear();
+    }
+
+    #[cfg(test)]
+    pub(crate) fn armed(&self) -> usize {
+        self.shared.timer.lock().wakers.len()
+    }
+}
+
+impl<T, C: Comparator<T> + Default> Default for AsyncRBQueue<T, C> {
+    fn default() -> Self {
+        AsyncRBQueue::new()
+    }
+}
+
+/// Shares an existing queue between tasks, keeping
+/// its elements, comparator and any bound.
+/// # Example:
+/// ```
+/// use rb_tree::{AsyncRBQueue, RBQueue};
+///
+/// let mut q = RBQueue::<u32>::default();
+/// q.extend(vec!(3, 1, 2));
+/// let shared = AsyncRBQueue::from(q);
+/// assert_eq!(shared.try_pop(), Some(1));
+/// ```
+impl<T, C> From<RBQueue<T, C>> for AsyncRBQueue<T, C> {
+    fn from(queue: RBQueue<T, C>) -> Self {
+        AsyncRBQueue {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    queue,
+                    waiting: Vec::new(),
+                }),
+                timer: Arc::default(),
+            }),
+        }
+    }
+}
+
+/// A future completing with the element at the front of
+/// an AsyncRBQueue once there is one. Created by
+/// `AsyncRBQueue::pop`.
+pub struct Pop<'a, T, C> {
+    queue: &'a AsyncRBQueue<T, C>,
+}
+
+impl<'a, T, C: Comparator<T>> Future for Pop<'a, T, C> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.queue.shared.lock();
+        match state.queue.pop() {
+            Some(v) => Poll::Ready(v),
+            None => {
+                state.wait(cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// A future completing with the element at the front of
+/// an AsyncRBQueue once its deadline has passed. Created
+/// by `AsyncRBQueue::pop_due`.
+pub struct PopDue<'a, T, C, F> {
+    queue: &'a AsyncRBQueue<T, C>,
+    deadline: F,
+    // the key the task was last armed under with the
+    // queue's timer, and the waker it was given
+    armed: RefCell<Option<((Instant, u64), Waker)>>,
+}
+
+impl<'a, T, C, F> Future for PopDue<'a, T, C, F>
+where
+    C: Comparator<T>,
+    F: Fn(&T) -> Instant,
+{
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.queue.shared.lock();
+        let due = match state.queue.peek() {
+            Some(front) => (self.deadline)(front),
+            None => {
+                state.wait(cx.waker());
+                return Poll::Pending;
+            }
+        };
+        let now = Instant::now();
+        if due <= now {
+            return Poll::Ready(state.queue.pop().unwrap());
+        }
+        state.wait(cx.waker());
+        drop(state);
+
+        // a wake-up already armed for no later than the
+        // deadline will have the task look again in time
+        let mut armed = self.armed.borrow_mut();
+        let in_time = match &*armed {
+            Some(((at, _), waker)) => *at > now && *at <= due && waker.will_wake(cx.waker()),
+            None => false,
+        };
+        if !in_time {
+            let timer = &self.queue.shared.timer;
+            if let Some((key, _)) = armed.take() {
+                timer.cancel(key);
+            }
+            let key = timer.arm(due, cx.waker().clone());
+            *armed = Some((key, cx.waker().clone()));
+        }
+        Poll::Pending
+    }
+}
+
+impl<'a, T, C, F> Drop for PopDue<'a, T, C, F> {
+    fn drop(&mut self) {
+        if let Some((key, _)) = self.armed.get_mut().take() {
+            self.queue.shared.timer.cancel(key);
+        }
+    }
+}
@@ -0,0 +1,966 @@
+use crate::error::{AllocError, CompareError};
+use crate::node::{self, index, pos, Index, NIL};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize};
+use std::cmp::Ordering::{self, Equal, Greater, Less};
+use std::fmt::{Debug, Formatter};
+use std::mem::replace as m_replace;
+
+// with the "chunked" feature enabled each node of the tree
+// holds a sorted run of up to CHUNK values rather than just
+// one, so that lookups and traversals touch far fewer nodes;
+// a value is referred to by its node's index with its place
+// in the node's chunk in the low SHIFT bits
+const SHIFT: u32 = 3;
+const CHUNK: usize = 1 << SHIFT;
+
+// the greatest number of chunks whose handles fit an Index
+const MAX_CHUNKS: usize = pos(NIL >> SHIFT);
+
+fn handle(node: Index, offset: usize) -> Index {
+    (node << SHIFT) | index(offset)
+}
+fn node_of(at: Index) -> Index {
+    at >> SHIFT
+}
+fn offset(at: Index) -> usize {
+    pos(at) & (CHUNK - 1)
+}
+
+// the values of a node, in order; those past len are None
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Chunk<T> {
+    values: [Option<T>; CHUNK],
+    len: u8,
+    // the number of values in the subtree rooted here
+    count: Index,
+}
+
+// owns all values of the rb_tree, in chunks
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(transparent))]
+pub struct Arena<T> {
+    chunks: node::Arena<Chunk<T>>,
+}
+
+impl<T> Chunk<T> {
+    fn new(value: T) -> Chunk<T> {
+        let mut values: [Option<T>; CHUNK] = Default::default();
+        values[0] = Some(value);
+        Chunk {
+            values,
+            len: 1,
+            count: 1,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    fn value(&self, i: usize) -> &T {
+        self.values[i].as_ref().unwrap()
+    }
+
+    // places the value at i, shifting those after it up
+    fn insert(&mut self, i: usize, value: T) {
+        let len = self.len();
+        self.values[len] = Some(value);
+        self.values[i..=len].rotate_right(1);
+        self.len += 1;
+    }
+
+    // takes the value at i, shifting those after it down
+    fn remove(&mut self, i: usize) -> T {
+        let len = self.len();
+        let value = self.values[i].take().unwrap();
+        self.values[i..len].rotate_left(1);
+        self.len -= 1;
+        value
+    }
+
+    // moves the values from i onwards into a new chunk
+    fn split_off(&mut self, i: usize) -> Chunk<T> {
+        let mut values: [Option<T>; CHUNK] = Default::default();
+        let len = self.len();
+        for (to, from) in values.iter_mut().zip(self.values[i..len].iter_mut()) {
+            *to = from.take();
+        }
+        self.len = i as u8;
+        Chunk {
+            values,
+            len: (len - i) as u8,
+            count: 0,
+        }
+    }
+
+    // the place of the value ord gives Equal for, or else the
+    // number of values ord gives Less for (i.e. those before it);
+    // the ends are compared first so that a search passing by
+    // the chunk on its way down the tree stops early
+    fn search<F>(&self, mut ord: F) -> Result<std::result::Result<usize, usize>, CompareError>
+    where
+        F: FnMut(&T) -> Option<Ordering>,
+    {
+        let last = self.len() - 1;
+        match ord(self.value(0)).ok_or(CompareError)? {
+            Equal => return Ok(Ok(0)),
+            Greater => return Ok(Err(0)),
+            Less => {}
+        }
+        match ord(self.value(last)).ok_or(CompareError)? {
+            Equal => return Ok(Ok(last)),
+            Less => return Ok(Err(last + 1)),
+            Greater => {}
+        }
+        let mut lo = 1;
+        let mut hi = last;
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match ord(self.value(mid)).ok_or(CompareError)? {
+                Equal => return Ok(Ok(mid)),
+                Less => lo = mid + 1,
+                Greater => hi = mid,
+            }
+        }
+        Ok(Err(lo))
+    }
+
+    #[cfg(feature = "set")]
+    fn map<U, F: FnMut(T) -> U>(self, f: &mut F) -> Chunk<U> {
+        Chunk {
+            values: self.values.map(|v| v.map(&mut *f)),
+            len: self.len,
+            count: self.count,
+        }
+    }
+}
+
+impl<T: Debug> Debug for Chunk<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.values[..self.len()].iter().flatten())
+            .finish()
+    }
+}
+
+// keeps the count of each chunk up to date, run by the
+// node arena wherever it recounts a subtree
+fn recount<T>(chunks: &mut node::Arena<Chunk<T>>, at: Index) {
+    let count = chunks.value(at).unwrap().len()
+        + count(chunks, chunks.get_left(at))
+        + count(chunks, chunks.get_right(at));
+    chunks.value_mut(at).unwrap().count = index(count);
+}
+
+fn count<T>(chunks: &node::Arena<Chunk<T>>, at: Index) -> usize {
+    chunks.value(at).map_or(0, |c| pos(c.count))
+}
+
+// hands out the values of the chunks it walks, taking them
+// from a chunk at a time at either end
+#[cfg(feature = "map")]
+pub struct ValuesMut<'a, T> {
+    chunks: node::ValuesMut<'a, Chunk<T>>,
+    front: std::slice::IterMut<'a, Option<T>>,
+    back: std::slice::IterMut<'a, Option<T>>,
+    // where the values start in the first chunk and end in the
+    // last, kept until whichever end reaches that chunk
+    lo: Option<usize>,
+    hi: Option<usize>,
+    remaining: usize,
+}
+
+#[cfg(feature = "map")]
+impl<'a, T> ValuesMut<'a, T> {
+    // the values of a chunk just taken from the front or back,
+    // trimmed if it is the first or last chunk; the front's
+    // first chunk is the first, and is also the last if no
+    // other chunk is left and the back hasn't taken the last
+    fn values(&mut self, chunk: &'a mut Chunk<T>, front: bool) -> std::slice::IterMut<'a, Option<T>> {
+        let rest = self.chunks.len() == 0;
+        let (lo, hi) = if front {
+            (self.lo.take(), if rest { self.hi.take() } else { None })
+        } else {
+            (if rest { self.lo.take() } else { None }, self.hi.take())
+        };
+        let len = chunk.len();
+        chunk.values[lo.unwrap_or(0)..hi.unwrap_or(len)].iter_mut()
+    }
+
+    pub fn len(&self) -> usize {
+        self.remaining
+    }
+
+    // passes f each value not yet handed out, in order
+    pub fn for_each_remaining<F: FnMut(&T)>(&self, mut f: F) {
+        self.front.as_slice().iter().flatten().for_each(&mut f);
+        let (mut i, last) = (0, self.chunks.len());
+        self.chunks.for_each_remaining(|c| {
+            i += 1;
+            let lo = if i == 1 { self.lo } else { None };
+            let hi = if i == last { self.hi } else { None };
+            c.values[lo.unwrap_or(0)..hi.unwrap_or(c.len())]
+                .iter()
+                .flatten()
+                .for_each(&mut f);
+        });
+        self.back.as_slice().iter().flatten().for_each(&mut f);
+    }
+}
+
+#[cfg(feature = "map")]
+impl<'a, T> Iterator for ValuesMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<&'a mut T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        loop {
+            if let Some(v) = self.front.next() {
+                return v.as_mut();
+            }
+            match self.chunks.next() {
+                Some(c) => self.front = self.values(c, true),
+                None => return self.back.next().and_then(Option::as_mut),
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+#[cfg(feature = "map")]
+impl<'a, T> DoubleEndedIterator for ValuesMut<'a, T> {
+    fn next_back(&mut self) -> Option<&'a mut T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        loop {
+            if let Some(v) = self.back.next_back() {
+                return v.as_mut();
+            }
+            match self.chunks.next_back() {
+                Some(c) => self.back = self.values(c, false),
+                None => return self.front.next_back().and_then(Option::as_mut),
+            }
+        }
+    }
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Arena<T> {
+        let mut chunks = node::Arena::new();
+        chunks.set_augment(recount::<T>);
+        Arena { chunks }
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+
+    fn chunk(&self, node: Index) -> &Chunk<T> {
+        self.chunks.value(node).unwrap()
+    }
+    fn chunk_mut(&mut self, node: Index) -> &mut Chunk<T> {
+        self.chunks.value_mut(node).unwrap()
+    }
+
+    // the first (back=false) or last (back=true) value of a node
+    fn end(&self, node: Index, back: bool) -> Index {
+        if node == NIL {
+            NIL
+        } else if back {
+            handle(node, self.chunk(node).len() - 1)
+        } else {
+            handle(node, 0)
+        }
+    }
+
+    // an insertion adds at most one chunk, so room is reserved
+    // for a chunk per value to be sure none will allocate
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), AllocError> {
+        match self.chunks.size(self.chunks.root()).checked_add(additional) {
+            Some(n) if n <= MAX_CHUNKS => self.chunks.try_reserve(additional),
+            _ => Err(AllocError::CapacityOverflow),
+        }
+    }
+
+    pub fn reserve_nodes(&mut self, n: usize) {
+        self.chunks.reserve_nodes(n);
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.chunks.shrink_to_fit();
+    }
+
+    pub fn try_make_room(&mut self) -> Result<(), AllocError> {
+        self.chunks.try_make_room()
+    }
+
+    pub fn value(&self, at: Index) -> Option<&T> {
+        if at == NIL {
+            None
+        } else {
+            self.chunks
+                .value(node_of(at))
+                .and_then(|c| c.values[offset(at)].as_ref())
+        }
+    }
+
+    pub fn value_mut(&mut self, at: Index) -> Option<&mut T> {
+        if at == NIL {
+            None
+        } else {
+            self.chunks
+                .value_mut(node_of(at))
+                .and_then(|c| c.values[offset(at)].as_mut())
+        }
+    }
+
+    pub fn first(&self) -> Index {
+        self.end(self.chunks.first(), false)
+    }
+
+    pub fn last(&self) -> Index {
+        self.end(self.chunks.last(), true)
+    }
+
+    // the next value within the chunk, or else
+    // the nearest value of the next node over
+    pub fn step(&self, at: Index, forward: bool) -> Index {
+        if at == NIL {
+            return NIL;
+        }
+        let node = node_of(at);
+        let offset = offset(at);
+        if forward && offset + 1 < self.chunk(node).len() {
+            at + 1
+        } else if !forward && offset > 0 {
+            at - 1
+        } else {
+            self.end(self.chunks.step(node, forward), !forward)
+        }
+    }
+
+    // a removal shifts the values after it in its chunk down
+    // a place, so the successor `next` of the value removed
+    // from `at` now has its handle unless it was in another
+    // node, which the removal leaves where it is
+    #[cfg(feature = "set")]
+    pub fn next_after_removal(&self, at: Index, next: Index) -> Index {
+        if next != NIL && node_of(next) == node_of(at) {
+            at
+        } else {
+            next
+        }
+    }
+
+    // returns the value replaced by the insertion, if any
+    pub fn insert<P>(&mut self, new_v: T, cmp: &P) -> Option<T>
+    where
+        P: Fn(&T, &T) -> Ordering,
+    {
+        match self.try_insert(new_v, &|l: &T, r: &T| Some(cmp(l, r))) {
+            Ok(old) => old,
+            Err(_) => unreachable!(),
+        }
+    }
+
+    // inserts the value only if no equal value is present,
+    // otherwise handing the value back untouched
+    #[cfg(feature = "set")]
+    pub fn insert_new<P>(&mut self, new_v: T, cmp: &P) -> Option<T>
+    where
+        P: Fn(&T, &T) -> Ordering,
+    {
+        match self.insert_with(new_v, &|l: &T, r: &T| Some(cmp(l, r)), false) {
+            Ok(rejected) => rejected,
+            Err(_) => unreachable!(),
+        }
+    }
+
+    // as with insert, but gives up before anything is changed
+    // if the new value can't be compared to one on its path
+    pub fn try_insert<P>(&mut self, new_v: T, cmp: &P) -> Result<Option<T>, CompareError>
+    where
+        P: Fn(&T, &T) -> Option<Ordering>,
+    {
+        self.insert_with(new_v, cmp, true)
+    }
+
+    // when an equal value is present, either swaps the new
+    // value in and returns the old one or returns the new one
+    fn insert_with<P>(
+        &mut self,
+        new_v: T,
+        cmp: &P,
+        replace: bool,
+    ) -> Result<Option<T>, CompareError>
+    where
+        P: Fn(&T, &T) -> Option<Ordering>,
+    {
+        let place =
+            self.try_locate(&new_v, &|new: &T, v: &T| cmp(v, new).map(Ordering::reverse))?;
+        match place {
+            Ok(at) if replace => Ok(Some(m_replace(self.value_mut(at).unwrap(), new_v))),
+            Ok(_) => Ok(Some(new_v)),
+            Err((at, right)) => {
+                self.insert_at(at, right, new_v);
+                Ok(None)
+            }
+        }
+    }
+
+    // places the value straight before the value at `next`,
+    // or after the greatest value if `next` is NIL; returns
+    // the handle of the new value
+    #[cfg(feature = "set")]
+    pub fn insert_before(&mut self, next: Index, new_v: T) -> Index {
+        if next == NIL {
+            let last = self.last();
+            return self.insert_at(last, true, new_v);
+        }
+        self.insert_at(next, false, new_v)
+    }
+
+    // places the value straight after (right=true) or before
+    // (right=false) the value at `at`, or as the only value if
+    // `at` is NIL; returns the handle of the new value
+    pub fn insert_at(&mut self, at: Index, right: bool, new_v: T) -> Index {
+        let at = self.place(at, right, new_v);
+        #[cfg(feature = "debug_invariants")]
+        self.verify();
+        at
+    }
+
+    fn place(&mut self, at: Index, right: bool, new_v: T) -> Index {
+        if at == NIL {
+            return handle(self.link(NIL, false, Chunk::new(new_v)), 0);
+        }
+        let node = node_of(at);
+        let i = offset(at) + right as usize;
+        if self.chunk(node).len() < CHUNK {
+            self.chunk_mut(node).insert(i, new_v);
+            self.chunks.resize_up(node);
+            return handle(node, i);
+        }
+        // a full chunk gets a new neighbour for values
+        // past either of its ends, and is split in half
+        // for values that go between its own
+        if i == 0 || i == CHUNK {
+            return handle(self.link(node, i == CHUNK, Chunk::new(new_v)), 0);
+        }
+        let upper = self.chunk_mut(node).split_off(CHUNK / 2);
+        let next = self.link(node, true, upper);
+        let (node, i) = if i <= CHUNK / 2 {
+            (node, i)
+        } else {
+            (next, i - CHUNK / 2)
+        };
+        self.chunk_mut(node).insert(i, new_v);
+        self.chunks.resize_up(node);
+        handle(node, i)
+    }
+
+    // adds a node for the chunk right after (right=true) or
+    // before (right=false) `node`, returning its index
+    fn link(&mut self, node: Index, right: bool, chunk: Chunk<T>) -> Index {
+        if self.chunks.size(self.chunks.root()) >= MAX_CHUNKS {
+            panic!("Exceeded the maximum number of nodes in a tree");
+        }
+        if node == NIL {
+            return self.chunks.insert_at(NIL, false, chunk);
+        }
+        let child = if right {
+            self.chunks.get_right(node)
+        } else {
+            self.chunks.get_left(node)
+        };
+        if child == NIL {
+            self.chunks.insert_at(node, right, chunk)
+        } else {
+            let beside = self.chunks.outermost(child, !right);
+            self.chunks.insert_at(beside, !right, chunk)
+        }
+    }
+
+    // finds the value equal to val, or else the place it
+    // would be inserted as (the value it goes beside, right)
+    #[cfg(any(feature = "set", feature = "queue"))]
+    pub fn locate<K, P>(&self, val: &K, cmp: &P) -> Result<Index, (Index, bool)>
+    where
+        P: Fn(&K, &T) -> Ordering,
+    {
+        match self.try_locate(val, &|l: &K, r: &T| Some(cmp(l, r))) {
+            Ok(place) => place,
+            Err(_) => unreachable!(),
+        }
+    }
+
+    // descends past the chunks the value is outside of until
+    // it lands within one or on the outside of a leaf chunk
+    fn try_locate<K, P>(
+        &self,
+        val: &K,
+        cmp: &P,
+    ) -> Result<Result<Index, (Index, bool)>, CompareError>
+    where
+        P: Fn(&K, &T) -> Option<Ordering>,
+    {
+        let mut place = (NIL, false);
+        let mut cur = self.chunks.root();
+        while cur != NIL {
+            let c = self.chunk(cur);
+            match c.search(|v| cmp(val, v).map(Ordering::reverse))? {
+                Ok(i) => return Ok(Ok(handle(cur, i))),
+                Err(0) => {
+                    place = (handle(cur, 0), false);
+                    cur = self.chunks.get_left(cur);
+                }
+                Err(i) if i == c.len() => {
+                    place = (handle(cur, i - 1), true);
+                    cur = self.chunks.get_right(cur);
+                }
+                Err(i) => return Ok(Err((handle(cur, i - 1), true))),
+            }
+        }
+        Ok(Err(place))
+    }
+
+    // takes the value out of its chunk, removing
+    // the chunk's node if it is left empty
+    pub fn remove_at(&mut self, at: Index) -> T {
+        let node = node_of(at);
+        let chunk = self.chunk_mut(node);
+        let value = chunk.remove(offset(at));
+        if chunk.len == 0 {
+            self.chunks.remove_at(node);
+        } else {
+            self.chunks.resize_up(node);
+        }
+        #[cfg(feature = "debug_invariants")]
+        self.verify();
+        value
+    }
+
+    pub fn pop(&mut self, back: bool) -> Option<T> {
+        let at = if back { self.last() } else { self.first() };
+        if at == NIL {
+            None
+        } else {
+            Some(self.remove_at(at))
+        }
+    }
+
+    // as with insertion, returns the removed value if found
+    pub fn remove<K, P>(&mut self, val: &K, cmp: &P) -> Option<T>
+    where
+        P: Fn(&K, &T) -> Ordering,
+    {
+        let at = self.find(val, cmp);
+        if at == NIL {
+            None
+        } else {
+            Some(self.remove_at(at))
+        }
+    }
+
+    pub fn find<K, P>(&self, val: &K, cmp: &P) -> Index
+    where
+        P: Fn(&K, &T) -> Ordering,
+    {
+        match self.try_find(val, &|l: &K, r: &T| Some(cmp(l, r))) {
+            Ok(at) => at,
+            Err(_) => unreachable!(),
+        }
+    }
+
+    fn try_find<K, P>(&self, val: &K, cmp: &P) -> Result<Index, CompareError>
+    where
+        P: Fn(&K, &T) -> Option<Ordering>,
+    {
+        Ok(self.try_locate(val, cmp)?.unwrap_or(NIL))
+    }
+
+    pub fn get<K, P>(&self, val: &K, cmp: &P) -> Option<&T>
+    where
+        P: Fn(&K, &T) -> Ordering,
+    {
+        self.value(self.find(val, cmp))
+    }
+
+    #[cfg(feature = "set")]
+    pub fn try_get<K, P>(&self, val: &K, cmp: &P) -> Result<Option<&T>, CompareError>
+    where
+        P: Fn(&K, &T) -> Option<Ordering>,
+    {
+        Ok(self.value(self.try_find(val, cmp)?))
+    }
+
+    #[cfg(feature = "map")]
+    pub fn get_mut<K, P>(&mut self, val: &K, cmp: &P) -> Option<&mut T>
+    where
+        P: Fn(&K, &T) -> Ordering,
+    {
+        self.value_mut(self.find(val, cmp))
+    }
+
+    pub fn peek(&self, back: bool) -> Option<&T> {
+        self.value(if back { self.last() } else { self.first() })
+    }
+
+    // removes the values keep returns false for, visiting them
+    // in order; returns the number of values removed
+    pub fn retain<F>(&mut self, mut keep: F) -> usize
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let mut removed = 0;
+        let mut cur = self.first();
+        while cur != NIL {
+            let next = self.step(cur, true);
+            if keep(self.value_mut(cur).unwrap()) {
+                cur = next;
+            } else {
+                self.remove_at(cur);
+                removed += 1;
+                // as in next_after_removal
+                if next == NIL || node_of(next) != node_of(cur) {
+                    cur = next;
+                }
+            }
+        }
+        removed
+    }
+
+    // the nth smallest value, or NIL if
+    // there are no more than n values
+    #[cfg(any(feature = "set", feature = "queue"))]
+    pub fn nth(&self, mut n: usize) -> Index {
+        let mut cur = self.chunks.root();
+        while cur != NIL {
+            let left = count(&self.chunks, self.chunks.get_left(cur));
+            if n < left {
+                cur = self.chunks.get_left(cur);
+                continue;
+            }
+            n -= left;
+            let len = self.chunk(cur).len();
+            if n < len {
+                return handle(cur, n);
+            }
+            n -= len;
+            cur = self.chunks.get_right(cur);
+        }
+        NIL
+    }
+
+    // the number of values less than the one at `at`
+    #[cfg(any(feature = "set", feature = "queue"))]
+    pub fn rank(&self, at: Index) -> usize {
+        let mut cur = node_of(at);
+        let mut rank = count(&self.chunks, self.chunks.get_left(cur)) + offset(at);
+        let mut parent = self.chunks.get_parent(cur);
+        while parent != NIL {
+            if self.chunks.get_right(parent) == cur {
+                rank += count(&self.chunks, self.chunks.get_left(parent));
+                rank += self.chunk(parent).len();
+            }
+            cur = parent;
+            parent = self.chunks.get_parent(cur);
+        }
+        rank
+    }
+
+    // f gives the ordering of each value relative to the target
+    #[cfg(feature = "set")]
+    pub fn search_by<F>(&self, mut f: F) -> Option<&T>
+    where
+        F: FnMut(&T) -> Ordering,
+    {
+        let mut cur = self.chunks.root();
+        while cur != NIL {
+            let c = self.chunk(cur);
+            match c.search(|v| Some(f(v))) {
+                Ok(Ok(i)) => return Some(c.value(i)),
+                Ok(Err(0)) => cur = self.chunks.get_left(cur),
+                Ok(Err(i)) if i == c.len() => cur = self.chunks.get_right(cur),
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    // the first value for which pred is false, given pred
+    // holds for a prefix of the values and not the rest
+    pub fn partition_point<P>(&self, mut pred: P) -> Index
+    where
+        P: FnMut(&T) -> bool,
+    {
+        let mut found = NIL;
+        let mut cur = self.chunks.root();
+        while cur != NIL {
+            let c = self.chunk(cur);
+            let i = c.values[..c.len()].partition_point(|v| pred(v.as_ref().unwrap()));
+            if i == c.len() {
+                cur = self.chunks.get_right(cur);
+            } else {
+                found = handle(cur, i);
+                if i > 0 {
+                    break;
+                }
+                cur = self.chunks.get_left(cur);
+            }
+        }
+        found
+    }
+
+    // an iterator of mutable references to the values from
+    // `from` up to but not including `to`, in order
+    #[cfg(feature = "map")]
+    pub fn values_mut(&mut self, from: Index, to: Index) -> ValuesMut<'_, T> {
+        let remaining = if from == NIL || from == to {
+            0
+        } else if to == NIL {
+            count(&self.chunks, self.chunks.root()) - self.rank(from)
+        } else {
+            self.rank(to) - self.rank(from)
+        };
+        // a range ending at the start of a chunk leaves it out
+        let (end, hi) = match to {
+            NIL => (NIL, None),
+            to if offset(to) == 0 => (node_of(to), None),
+            to => (self.chunks.step(node_of(to), true), Some(offset(to))),
+        };
+        let (first, lo) = if remaining == 0 {
+            (NIL, None)
+        } else {
+            (node_of(from), Some(offset(from)))
+        };
+        ValuesMut {
+            chunks: self.chunks.values_mut(first, end),
+            front: <&mut [_]>::default().iter_mut(),
+            back: <&mut [_]>::default().iter_mut(),
+            lo,
+            hi,
+            remaining,
+        }
+    }
+
+    // replaces every value with the result of f, called on
+    // the values in order, leaving the structure untouched
+    #[cfg(feature = "set")]
+    pub fn map_values<U, F>(self, mut f: F) -> Arena<U>
+    where
+        F: FnMut(T) -> U,
+    {
+        let mut chunks = self.chunks.map_values(|c| c.map(&mut f));
+        chunks.set_augment(recount::<U>);
+        Arena { chunks }
+    }
+
+    // splits the tree before the value at `at`, leaving the
+    // values before it here and returning an arena holding it
+    // and the values after it; its chunk is split in two
+    // first if it falls within one
+    #[cfg(feature = "queue")]
+    pub fn split_off(&mut self, at: Index) -> Arena<T> {
+        if at == NIL {
+            return Arena::new();
+        }
+        let mut node = node_of(at);
+        if offset(at) > 0 {
+            let upper = self.chunk_mut(node).split_off(offset(at));
+            self.chunks.resize_up(node);
+            node = self.link(node, true, upper);
+        }
+        let other = Arena {
+            chunks: self.chunks.split_off(node),
+        };
+        #[cfg(feature = "debug_invariants")]
+        {
+            self.verify();
+            other.verify();
+        }
+        other
+    }
+
+    // moves the chunks of other into this tree, where all of
+    // their values come after (after=true) or before
+    // (after=false) those already here
+    #[cfg(feature = "queue")]
+    pub fn append(&mut self, other: Arena<T>, after: bool) {
+        self.chunks.append(other.chunks, after);
+        #[cfg(feature = "debug_invariants")]
+        self.verify();
+    }
+
+    // passes each value to f in order, leaving the
+    // arena empty but with the room it had
+    #[cfg(feature = "set")]
+    pub fn drain_ordered<F: FnMut(T)>(&mut self, mut f: F) {
+        self.chunks
+            .drain_ordered(|c| IntoIterator::into_iter(c.values).flatten().for_each(&mut f));
+    }
+
+    // consumes the arena, returning all values in order
+    pub fn into_ordered(self) -> Vec<T> {
+        self.chunks
+            .into_ordered()
+            .into_iter()
+            .flat_map(|c| IntoIterator::into_iter(c.values).flatten())
+            .collect()
+    }
+
+    // builds a balanced tree of full chunks (but for the
+    // last) from values already in order in linear time
+    pub fn from_sorted(values: Vec<T>) -> Result<Arena<T>, AllocError> {
+        if values.len() / CHUNK >= MAX_CHUNKS {
+            return Err(AllocError::CapacityOverflow);
+        }
+        let mut chunks: Vec<Chunk<T>> = Vec::with_capacity(values.len() / CHUNK + 1);
+        for v in values {
+            match chunks.last_mut() {
+                Some(c) if c.len() < CHUNK => c.insert(c.len(), v),
+                _ => chunks.push(Chunk::new(v)),
+            }
+        }
+        let mut arena = Arena {
+            chunks: node::Arena::from_sorted(chunks)?,
+        };
+        arena.chunks.set_augment(recount::<T>);
+        arena.recount_below(arena.chunks.root());
+        #[cfg(feature = "debug_invariants")]
+        arena.verify();
+        Ok(arena)
+    }
+
+    // counts the values of every subtree beneath `at`
+    fn recount_below(&mut self, at: Index) {
+        if at != NIL {
+            self.recount_below(self.chunks.get_left(at));
+            self.recount_below(self.chunks.get_right(at));
+            recount(&mut self.chunks, at);
+        }
+    }
+
+    // the tree drawn level by level, a chunk to a node
+    pub fn levels(&self) -> Vec<String>
+    where
+        T: Debug,
+    {
+        self.chunks.levels()
+    }
+
+    #[cfg(feature = "debug_invariants")]
+    pub fn shape(&self) -> Vec<String> {
+        self.chunks.shape()
+    }
+
+    // panics, drawing the shape of the tree, unless its nodes
+    // hold up and every chunk is packed at the front and
+    // counts the values beneath it
+    #[cfg(feature = "debug_invariants")]
+    pub fn verify(&self) {
+        self.chunks.verify();
+        let mut node = self.chunks.first();
+        while node != NIL {
+            let c = self.chunk(node);
+            let (left, right) = (self.chunks.get_left(node), self.chunks.get_right(node));
+            let e = if c.len() == 0 {
+                "is empty"
+            } else if !c.values[..c.len()].iter().all(Option::is_some)
+                || !c.values[c.len()..].iter().all(Option::is_none)
+            {
+                "has gaps"
+            } else if pos(c.count)
+                != c.len() + count(&self.chunks, left) + count(&self.chunks, right)
+            {
+                "has the wrong count"
+            } else {
+                node = self.chunks.step(node, true);
+                continue;
+            };
+            panic!(
+                "chunk invariant violated: chunk {} {}\n{}",
+                node,
+                e,
+                self.chunks.shape().join("\n")
+            );
+        }
+    }
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Arena::new()
+    }
+}
+
+// the counts are kept with the chunks, but the
+// hook that keeps them up to date needs setting
+#[cfg(feature = "serde")]
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Arena<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut chunks = node::Arena::deserialize(deserializer)?;
+        chunks.set_augment(recount::<T>);
+        Ok(Arena { chunks })
+    }
+}
+
+// checks used by the tests, as the node views
+// are used to check the structure of plain trees
+#[cfg(test)]
+impl<T: PartialOrd> Arena<T> {
+    #[cfg(feature = "set")]
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.size(self.chunks.root())
+    }
+
+    // panics unless every chunk is non-empty with its values
+    // packed at the front, the counts add up, the values are
+    // in order and the nodes form a valid red-black tree
+    pub fn check(&self) {
+        self.check_below(self.chunks.root());
+        let mut prev: Option<&T> = None;
+        let mut cur = self.first();
+        while cur != NIL {
+            let v = self.value(cur).unwrap();
+            if let Some(p) = prev {
+                assert!(p < v, "values out of order");
+            }
+            prev = Some(v);
+            cur = self.step(cur, true);
+        }
+    }
+
+    // returns the black height of the subtree
+    fn check_below(&self, at: Index) -> usize {
+        if at == NIL {
+            return 1;
+        }
+        let c = self.chunk(at);
+        assert!(c.len() > 0, "empty chunk");
+        assert!(c.values[..c.len()].iter().all(Option::is_some));
+        assert!(c.values[c.len()..].iter().all(Option::is_none));
+        let left = self.chunks.get_left(at);
+        let right = self.chunks.get_right(at);
+        assert_eq!(
+            pos(c.count),
+            c.len() + count(&self.chunks, left) + count(&self.chunks, right)
+        );
+        if self.chunks.is_red(at) {
+            assert!(!self.chunks.is_red(left) && !self.chunks.is_red(right));
+        }
+        let height = self.check_below(left);
+        assert_eq!(height, self.check_below(right), "unequal black heights");
+        height + self.chunks.is_black(at) as usize
+    }
+}
@@ -0,0 +1,15 @@
+/// The colour of a node of a red black tree.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Colour {
+    Red,
+    Black,
+}
+
+impl std::fmt::Display for Colour {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Colour::Red => write!(f, "R"),
+            Colour::Black => write!(f, "B"),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+/// Decides the order of the elements of a collection.
+/// `K` is the type of value being looked up (or inserted)
+/// and `T` the type of the elements stored, which allows
+/// lookups by a borrowed or otherwise related type where
+/// the comparator supports it.
+///
+/// Any closure or function taking two references to the
+/// element type and returning an `Ordering` is a comparator.
+/// # Example:
+/// ```
+/// # #[cfg(feature = "set")] {
+/// use rb_tree::{Comparator, RBTree};
+///
+/// struct ByLength;
+/// impl Comparator<String> for ByLength {
+///     fn compare(&self, l: &String, r: &String) -> std::cmp::Ordering {
+///         l.len().cmp(&r.len())
+///     }
+/// }
+///
+/// let mut t = RBTree::with_comparator(ByLength);
+/// t.insert("three".to_string());
+/// t.insert("one".to_string());
+/// t.insert("eleven".to_string());
+/// assert_eq!(t.ordered(), vec!("one", "three", "eleven"));
+/// # }
+/// ```
+pub trait Comparator<K: ?Sized, T: ?Sized = K> {
+    /// Returns the ordering of `l` relative to `r`.
+    fn compare(&self, l: &K, r: &T) -> Ordering;
+
+    /// Returns the ordering of `l` relative to `r`, or
+    /// None if the two can't be ordered. Used by the `try_`
+    /// methods of collections; comparators that can fail
+    /// should override this and panic in `compare` instead.
+    fn try_compare(&self, l: &K, r: &T) -> Option<Ordering> {
+        Some(self.compare(l, r))
+    }
+}
+
+impl<T, F> Comparator<T> for F
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    fn compare(&self, l: &T, r: &T) -> Ordering {
+        self(l, r)
+    }
+}
+
+/// Orders elements by their `PartialOrd` implementation,
+/// panicking on elements that can't be compared (such as
+/// `f64::NAN`). This is the default comparator.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NaturalOrder;
+
+impl<K: ?Sized + PartialOrd<T>, T: ?Sized> Comparator<K, T> for NaturalOrder {
+    fn compare(&self, l: &K, r: &T) -> Ordering {
+        l.partial_cmp(r)
+            .expect("Attempted to order incomparable elements (partial_cmp returned None)")
+    }
+
+    fn try_compare(&self, l: &K, r: &T) -> Option<Ordering> {
+        l.partial_cmp(r)
+    }
+}
+
+/// Orders elements by their `Ord` implementation. For
+/// totally ordered types this skips the checks that
+/// `NaturalOrder` has to make on every comparison.
+/// # Example:
+/// ```
+/// # #[cfg(feature = "set")] {
+/// use rb_tree::{RBTree, TotalOrder};
+///
+/// let mut t = RBTree::with_comparator(TotalOrder);
+/// t.insert("b");
+/// t.insert("a");
+/// assert_eq!(t.pop().unwrap(), "a");
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TotalOrder;
+
+impl<T: ?Sized + Ord> Comparator<T> for TotalOrder {
+    fn compare(&self, l: &T, r: &T) -> Ordering {
+        l.cmp(r)
+    }
+}
+
+/// Reverses the order given by another comparator.
+/// # Example:
+/// ```
+/// # #[cfg(feature = "set")] {
+/// use rb_tree::{NaturalOrder, RBTree, Reversed};
+///
+/// let mut t = RBTree::with_comparator(Reversed(NaturalOrder));
+/// t.insert(1);
+/// t.insert(3);
+/// t.insert(2);
+/// assert_eq!(t.ordered(), vec!(&3, &2, &1));
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Reversed<C>(pub C);
+
+impl<K: ?Sized, T: ?Sized, C: Comparator<K, T>> Comparator<K, T> for Reversed<C> {
+    fn compare(&self, l: &K, r: &T) -> Ordering {
+        self.0.compare(l, r).reverse()
+    }
+
+    fn try_compare(&self, l: &K, r: &T) -> Option<Ordering> {
+        self.0.try_compare(l, r).map(Ordering::reverse)
+    }
+}
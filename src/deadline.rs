@@ -0,0 +1,176 @@
+use crate::{Comparator, DeadlineQueue, RBQueue};
+
+use std::cmp::Ordering;
+use std::fmt::{Debug, Formatter, Result};
+use std::time::Instant;
+
+// orders timers by deadline, then by the order in
+// which they were scheduled, so that no two timers
+// are ever equal and none replaces another
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ByDeadline;
+
+impl<T> Comparator<(Instant, u64, T)> for ByDeadline {
+    fn compare(&self, l: &(Instant, u64, T), r: &(Instant, u64, T)) -> Ordering {
+        l.0.cmp(&r.0).then(l.1.cmp(&r.1))
+    }
+}
+
+impl<T: Debug> Debug for DeadlineQueue<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list()
+            .entries(self.timers.iter().map(|(at, _, item)| (at, item)))
+            .finish()
+    }
+}
+
+impl<T> DeadlineQueue<T> {
+    /// Creates and returns a new, empty DeadlineQueue.
+    /// # Example:
+    /// ```
+    /// use rb_tree::DeadlineQueue;
+    ///
+    /// let q = DeadlineQueue::<&str>::new();
+    /// assert!(q.is_empty());
+    /// ```
+    pub fn new() -> DeadlineQueue<T> {
+        DeadlineQueue {
+            timers: RBQueue::with_comparator(ByDeadline),
+            scheduled: 0,
+        }
+    }
+
+    /// Holds `item` until `at`, after which
+    /// `pop_due` will hand it back.
+    /// # Example:
+    /// ```
+    /// use rb_tree::DeadlineQueue;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let now = Instant::now();
+    /// let mut q = DeadlineQueue::new();
+    /// q.schedule(now + Duration::from_secs(5), "retry");
+    /// q.schedule(now + Duration::from_secs(1), "ping");
+    /// assert_eq!(q.len(), 2);
+    /// assert_eq!(q.next_deadline(), Some(now + Duration::from_secs(1)));
+    /// ```
+    pub fn schedule(&mut self, at: Instant, item: T) {
+        self.timers.insert((at, self.scheduled, item));
+        self.scheduled = self.scheduled.wrapping_add(1);
+    }
+
+    /// Removes and returns the item with the earliest
+    /// deadline, if that deadline is no later than
+    /// `now`. Returns None if no item is due yet.
+    /// # Example:
+    /// ```
+    /// use rb_tree::DeadlineQueue;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let now = Instant::now();
+    /// let mut q = DeadlineQueue::new();
+    /// q.schedule(now + Duration::from_secs(2), "second");
+    /// q.schedule(now, "first");
+    /// q.schedule(now + Duration::from_secs(1), "also first");
+    ///
+    /// let later = now + Duration::from_secs(1);
+    /// assert_eq!(q.pop_due(later), Some("first"));
+    /// assert_eq!(q.pop_due(later), Some("also first"));
+    /// assert_eq!(q.pop_due(later), None);
+    /// assert_eq!(q.len(), 1);
+    /// ```
+    pub fn pop_due(&mut self, now: Instant) -> Option<T> {
+        match self.timers.peek() {
+            Some((at, _, _)) if *at <= now => self.timers.pop().map(|(_, _, item)| item),
+            _ => None,
+        }
+    }
+
+    /// Returns the earliest deadline of the items
+    /// held, or None if the queue is empty. A timer
+    /// thread can sleep until this deadline, as no
+    /// item will be due before it.
+    /// # Example:
+    /// ```
+    /// use rb_tree::DeadlineQueue;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let now = Instant::now();
+    /// let mut q = DeadlineQueue::new();
+    /// assert_eq!(q.next_deadline(), None);
+    /// q.schedule(now + Duration::from_millis(30), 'b');
+    /// q.schedule(now + Duration::from_millis(10), 'a');
+    /// assert_eq!(q.next_deadline(), Some(now + Duration::from_millis(10)));
+    /// ```
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.timers.peek().map(|(at, _, _)| *at)
+    }
+
+    /// Returns the item with the earliest deadline,
+    /// along with that deadline, without removing it.
+    /// # Example:
+    /// ```
+    /// use rb_tree::DeadlineQueue;
+    /// use std::time::Instant;
+    ///
+    /// let now = Instant::now();
+    /// let mut q = DeadlineQueue::new();
+    /// q.schedule(now, 1);
+    /// assert_eq!(q.peek(), Some((now, &1)));
+    /// ```
+    pub fn peek(&self) -> Option<(Instant, &T)> {
+        self.timers.peek().map(|(at, _, item)| (*at, item))
+    }
+
+    /// Returns the number of items held.
+    /// # Example:
+    /// ```
+    /// use rb_tree::DeadlineQueue;
+    /// use std::time::Instant;
+    ///
+    /// let now = Instant::now();
+    /// let mut q = DeadlineQueue::new();
+    /// q.schedule(now, 1);
+    /// q.schedule(now, 1);
+    /// assert_eq!(q.len(), 2);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.timers.len()
+    }
+
+    /// Returns true if no items are held.
+    /// # Example:
+    /// ```
+    /// use rb_tree::DeadlineQueue;
+    /// use std::time::Instant;
+    ///
+    /// let mut q = DeadlineQueue::new();
+    /// assert!(q.is_empty());
+    /// q.schedule(Instant::now(), ());
+    /// assert!(!q.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.timers.is_empty()
+    }
+
+    /// Removes all items from the queue.
+    /// # Example:
+    /// ```
+    /// use rb_tree::DeadlineQueue;
+    /// use std::time::Instant;
+    ///
+    /// let mut q = DeadlineQueue::new();
+    /// q.schedule(Instant::now(), ());
+    /// q.clear();
+    /// assert!(q.is_empty());
+    /// ```
+    pub fn clear(&mut self) {
+        self.timers.clear();
+    }
+}
+
+impl<T> Default for DeadlineQueue<T> {
+    fn default() -> Self {
+        DeadlineQueue::new()
+    }
+}
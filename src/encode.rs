@@ -0,0 +1,196 @@
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+
+/// A compact binary encoding of a value, used by
+/// `RBTree::write_to` and `RBTree::read_from` to store
+/// a tree without depending on serde. Integers and
+/// floats are written in little endian, and strings
+/// and vectors are prefixed by their length.
+/// # Example:
+/// ```
+/// use rb_tree::{Encode, RBTree};
+/// use std::io::{Read, Result, Write};
+///
+/// #[derive(PartialEq, PartialOrd, Debug)]
+/// struct Point {
+///     x: i32,
+///     y: i32,
+/// }
+///
+/// impl Encode for Point {
+///     fn encode<W: Write>(&self, writer: &mut W) -> Result<()> {
+///         self.x.encode(writer)?;
+///         self.y.encode(writer)
+///     }
+///
+///     fn decode<R: Read>(reader: &mut R) -> Result<Self> {
+///         Ok(Point {
+///             x: i32::decode(reader)?,
+///             y: i32::decode(reader)?,
+///         })
+///     }
+/// }
+///
+/// let mut t = RBTree::new();
+/// t.insert(Point { x: 1, y: 2 });
+/// t.insert(Point { x: 0, y: 5 });
+/// let mut buf = Vec::new();
+/// t.write_to(&mut buf).unwrap();
+/// let read: RBTree<Point> = RBTree::read_from(&buf[..]).unwrap();
+/// assert_eq!(read.peek(), Some(&Point { x: 0, y: 5 }));
+/// ```
+pub trait Encode: Sized {
+    /// Writes the encoding of the value to `writer`.
+    fn encode<W: Write>(&self, writer: &mut W) -> Result<()>;
+
+    /// Reads a value previously written by `encode`
+    /// from `reader`.
+    fn decode<R: Read>(reader: &mut R) -> Result<Self>;
+}
+
+pub(crate) fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+// reads a length prefix, which is always stored as a u64
+pub(crate) fn decode_len<R: Read>(reader: &mut R) -> Result<usize> {
+    usize::try_from(u64::decode(reader)?).map_err(|_| invalid("length prefix too large"))
+}
+
+pub(crate) fn encode_len<W: Write>(len: usize, writer: &mut W) -> Result<()> {
+    (len as u64).encode(writer)
+}
+
+// the capacity to reserve for a length read from the input,
+// which can't be trusted not to be absurdly large
+pub(crate) fn initial_capacity(len: usize) -> usize {
+    len.min(4096)
+}
+
+macro_rules! encode_number {
+    ($($t:ty),*) => {
+        $(
+            impl Encode for $t {
+                fn encode<W: Write>(&self, writer: &mut W) -> Result<()> {
+                    writer.write_all(&self.to_le_bytes())
+                }
+
+                fn decode<R: Read>(reader: &mut R) -> Result<Self> {
+                    let mut bytes = [0; std::mem::size_of::<$t>()];
+                    reader.read_exact(&mut bytes)?;
+                    Ok(<$t>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+encode_number!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+impl Encode for usize {
+    fn encode<W: Write>(&self, writer: &mut W) -> Result<()> {
+        (*self as u64).encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self> {
+        usize::try_from(u64::decode(reader)?).map_err(|_| invalid("usize out of range"))
+    }
+}
+
+impl Encode for isize {
+    fn encode<W: Write>(&self, writer: &mut W) -> Result<()> {
+        (*self as i64).encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self> {
+        isize::try_from(i64::decode(reader)?).map_err(|_| invalid("isize out of range"))
+    }
+}
+
+impl Encode for bool {
+    fn encode<W: Write>(&self, writer: &mut W) -> Result<()> {
+        (*self as u8).encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self> {
+        match u8::decode(reader)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("invalid bool")),
+        }
+    }
+}
+
+impl Encode for char {
+    fn encode<W: Write>(&self, writer: &mut W) -> Result<()> {
+        (*self as u32).encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self> {
+        char::from_u32(u32::decode(reader)?).ok_or_else(|| invalid("invalid char"))
+    }
+}
+
+impl Encode for String {
+    fn encode<W: Write>(&self, writer: &mut W) -> Result<()> {
+        encode_len(self.len(), writer)?;
+        writer.write_all(self.as_bytes())
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self> {
+        let len = decode_len(reader)?;
+        let mut bytes = Vec::with_capacity(initial_capacity(len));
+        reader.take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != len {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "string cut short"));
+        }
+        String::from_utf8(bytes).map_err(|_| invalid("invalid utf-8"))
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode<W: Write>(&self, writer: &mut W) -> Result<()> {
+        match self {
+            Some(v) => {
+                true.encode(writer)?;
+                v.encode(writer)
+            }
+            None => false.encode(writer),
+        }
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self> {
+        if bool::decode(reader)? {
+            Ok(Some(T::decode(reader)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode<W: Write>(&self, writer: &mut W) -> Result<()> {
+        encode_len(self.len(), writer)?;
+        self.iter().try_for_each(|v| v.encode(writer))
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self> {
+        let len = decode_len(reader)?;
+        let mut values = Vec::with_capacity(initial_capacity(len));
+        for _ in 0..len {
+            values.push(T::decode(reader)?);
+        }
+        Ok(values)
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode<W: Write>(&self, writer: &mut W) -> Result<()> {
+        self.0.encode(writer)?;
+        self.1.encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self> {
+        Ok((A::decode(reader)?, B::decode(reader)?))
+    }
+}
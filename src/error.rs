@@ -0,0 +1,158 @@
+use std::collections::TryReserveError;
+use std::error::Error as StdError;
+use std::fmt::{Debug, Display, Formatter, Result};
+
+/// The error returned by the fallible `try_*` methods of
+/// the collections, sorting each failure into a category.
+/// The more specific errors below convert into it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// An element could not be ordered against those
+    /// already in the collection (see `CompareError`).
+    IncomparableElement,
+    /// The key given already has a value associated with it.
+    OccupiedKey,
+    /// The collection would need more nodes than it is able
+    /// to index (see the `compact` feature).
+    CapacityExceeded,
+    /// The allocator failed to provide the memory requested.
+    AllocError(TryReserveError),
+    /// The collection, or the input it was being built from,
+    /// broke one of the invariants it relies on.
+    CorruptInvariant(String),
+}
+
+/// The error returned by the fallible allocation methods
+/// when room for more nodes could not be made.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AllocError {
+    /// The tree would need more nodes than it is able
+    /// to index (see the `compact` feature).
+    CapacityOverflow,
+    /// The allocator failed to provide the memory requested.
+    ReserveFailed(TryReserveError),
+}
+
+/// The error returned by the fallible comparison methods when
+/// an element could not be ordered against those already in
+/// the collection, i.e. `partial_cmp` returned None as it does
+/// for `f64::NAN`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompareError;
+
+/// The error returned when building a collection from
+/// input that was expected to be sorted but wasn't. Holds
+/// the position in the input of the first element that
+/// was out of order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnsortedError {
+    pub position: usize,
+}
+
+/// The error returned by `RBMap::replace_key`, which
+/// hands back the new key it was given.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplaceKeyError<K> {
+    /// The old key has no value associated with it.
+    NotFound(K),
+    /// The new key already has a value associated with it.
+    Occupied(K),
+}
+
+impl<K> ReplaceKeyError<K> {
+    /// Returns the new key given to `replace_key`.
+    pub fn into_key(self) -> K {
+        match self {
+            ReplaceKeyError::NotFound(k) | ReplaceKeyError::Occupied(k) => k,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Error::IncomparableElement => write!(f, "{}", CompareError),
+            Error::OccupiedKey => write!(f, "the key is already in the collection"),
+            Error::CapacityExceeded => write!(f, "{}", AllocError::CapacityOverflow),
+            Error::AllocError(e) => write!(f, "{}", e),
+            Error::CorruptInvariant(msg) => write!(f, "invariant violated: {}", msg),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::AllocError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<CompareError> for Error {
+    fn from(_: CompareError) -> Self {
+        Error::IncomparableElement
+    }
+}
+
+impl From<AllocError> for Error {
+    fn from(e: AllocError) -> Self {
+        match e {
+            AllocError::CapacityOverflow => Error::CapacityExceeded,
+            AllocError::ReserveFailed(e) => Error::AllocError(e),
+        }
+    }
+}
+
+impl From<UnsortedError> for Error {
+    fn from(e: UnsortedError) -> Self {
+        Error::CorruptInvariant(e.to_string())
+    }
+}
+
+impl Display for AllocError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            AllocError::CapacityOverflow => {
+                write!(f, "exceeded the maximum number of nodes in a tree")
+            }
+            AllocError::ReserveFailed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl StdError for AllocError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            AllocError::CapacityOverflow => None,
+            AllocError::ReserveFailed(e) => Some(e),
+        }
+    }
+}
+
+impl Display for CompareError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "elements could not be compared")
+    }
+}
+
+impl StdError for CompareError {}
+
+impl Display for UnsortedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "element {} of the input is out of order", self.position)
+    }
+}
+
+impl StdError for UnsortedError {}
+
+impl<K> Display for ReplaceKeyError<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            ReplaceKeyError::NotFound(_) => write!(f, "the key to replace is not in the map"),
+            ReplaceKeyError::Occupied(_) => write!(f, "the new key is already in the map"),
+        }
+    }
+}
+
+impl<K: Debug> StdError for ReplaceKeyError<K> {}
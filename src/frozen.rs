@@ -0,0 +1,190 @@
+use crate::{Comparator, FrozenRBTree, RBTree};
+
+use std::fmt::{Debug, Display, Formatter, Result};
+
+impl<T: Debug, C> Debug for FrozenRBTree<T, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{:?}", self.values)
+    }
+}
+
+impl<T: Debug, C> Display for FrozenRBTree<T, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{:?}", self.ordered())
+    }
+}
+
+impl<T, C> FrozenRBTree<T, C> {
+    /// Returns the number of elements contained
+    /// in the tree.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let t: RBTree<_> = (0..3).collect();
+    /// assert_eq!(t.freeze().len(), 3);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns true if there are no items
+    /// present in the tree, false otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let t: RBTree<usize> = RBTree::new();
+    /// assert!(t.freeze().is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Peeks the smallest item in the tree if any
+    /// elements are present, or None otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let t: RBTree<_> = vec!(2, 1, 3).into_iter().collect();
+    /// assert_eq!(*t.freeze().peek().unwrap(), 1);
+    /// ```
+    pub fn peek(&self) -> Option<&T> {
+        self.values.first()
+    }
+
+    /// Peeks the largest item in the tree if any
+    /// elements are present, or None otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let t: RBTree<_> = vec!(2, 1, 3).into_iter().collect();
+    /// assert_eq!(*t.freeze().peek_back().unwrap(), 3);
+    /// ```
+    pub fn peek_back(&self) -> Option<&T> {
+        self.values.last()
+    }
+
+    /// Returns a vector presenting the contained
+    /// elements of the tree in their PartialOrd order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let t: RBTree<_> = vec!(3, 1, 2).into_iter().collect();
+    /// assert_eq!(t.freeze().ordered(), vec!(&1, &2, &3));
+    /// ```
+    pub fn ordered(&self) -> Vec<&T> {
+        self.values.iter().collect()
+    }
+
+    /// Returns an iterator over the elements
+    /// contained in the tree in their PartialOrd order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let t: RBTree<_> = vec!(5, 1, 3).into_iter().collect();
+    /// let f = t.freeze();
+    /// assert_eq!(f.iter().collect::<Vec<&usize>>(), vec!(&1, &3, &5));
+    /// ```
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.values.iter()
+    }
+
+    /// Returns the contained elements as a slice
+    /// in their PartialOrd order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let t: RBTree<_> = vec!(5, 1, 3).into_iter().collect();
+    /// assert_eq!(t.freeze().as_slice(), &[1, 3, 5]);
+    /// ```
+    pub fn as_slice(&self) -> &[T] {
+        &self.values
+    }
+}
+
+impl<T, C: Comparator<T>> FrozenRBTree<T, C> {
+    /// Returns true if the tree contains the
+    /// specified item, false otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let t: RBTree<_> = (0..3).collect();
+    /// let f = t.freeze();
+    /// assert!(f.contains(&2));
+    /// assert!(!f.contains(&3));
+    /// ```
+    pub fn contains<K>(&self, val: &K) -> bool
+    where
+        C: Comparator<K, T>,
+    {
+        self.get(val).is_some()
+    }
+
+    /// Returns the item specified if contained,
+    /// None otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let t: RBTree<_> = (0..3).collect();
+    /// let f = t.freeze();
+    /// assert_eq!(*f.get(&1).unwrap(), 1);
+    /// assert_eq!(f.get(&3), None);
+    /// ```
+    pub fn get<K>(&self, val: &K) -> Option<&T>
+    where
+        C: Comparator<K, T>,
+    {
+        self.values
+            .binary_search_by(|v| self.cmp.compare(val, v).reverse())
+            .ok()
+            .map(|i| &self.values[i])
+    }
+
+    /// Turns the frozen tree back into an RBTree
+    /// that can be modified, in O(n) time as the
+    /// values are already in order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let t: RBTree<_> = (0..3).collect();
+    /// let mut t = t.freeze().thaw();
+    /// t.insert(3);
+    /// assert_eq!(t.len(), 4);
+    /// ```
+    pub fn thaw(self) -> RBTree<T, C> {
+        RBTree::from_sorted_vec(self.values.into_vec(), self.cmp)
+    }
+}
+
+impl<T, C> From<RBTree<T, C>> for FrozenRBTree<T, C> {
+    fn from(t: RBTree<T, C>) -> Self {
+        t.freeze()
+    }
+}
+
+impl<T, C> IntoIterator for FrozenRBTree<T, C> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> std::vec::IntoIter<T> {
+        self.values.into_vec().into_iter()
+    }
+}
+
+impl<'a, T, C> IntoIterator for &'a FrozenRBTree<T, C> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> std::slice::Iter<'a, T> {
+        self.values.iter()
+    }
+}
@@ -1,69 +1,351 @@
+#![forbid(unsafe_code)]
+
+#[cfg(feature = "map")]
+pub mod aggregate;
+#[cfg(feature = "async")]
+pub mod asyncqueue;
+#[cfg(all(feature = "chunked", any(feature = "set", feature = "queue")))]
+mod chunk;
+#[cfg(any(feature = "set", feature = "queue", feature = "persistent"))]
+mod colour;
+#[cfg(feature = "set")]
+pub mod frozen;
+#[cfg(any(feature = "set", feature = "queue"))]
 mod node;
 #[cfg(feature = "map")]
+pub mod rangemap;
+#[cfg(any(feature = "set", feature = "queue"))]
+mod raw;
+#[cfg(feature = "map")]
+pub mod rbbimap;
+#[cfg(feature = "map")]
 pub mod rbmap;
+#[cfg(feature = "map")]
+pub mod rbmultimap;
+#[cfg(all(feature = "map", feature = "queue"))]
+pub mod rbprioritymap;
 #[cfg(feature = "set")]
 pub mod rbtree;
+#[cfg(feature = "queue")]
+pub mod topk;
 #[macro_use]
 #[cfg(feature = "queue")]
 pub mod rbqueue;
+pub mod comparator;
+#[cfg(feature = "queue")]
+pub mod deadline;
+#[cfg(feature = "set")]
+pub mod encode;
+pub mod error;
+#[cfg(any(feature = "set", feature = "queue"))]
 mod helpers;
-#[cfg(feature = "map")]
+#[cfg(any(feature = "map", feature = "persistent"))]
 mod mapper;
+#[cfg(all(feature = "rayon", feature = "set"))]
+mod par;
+#[cfg(feature = "persistent")]
+mod pnode;
+#[cfg(feature = "persistent")]
+pub mod prbmap;
+#[cfg(feature = "persistent")]
+pub mod prbtree;
 #[cfg(test)]
 mod rbtree_tests;
+#[cfg(feature = "persistent")]
+mod snapshot;
+#[cfg(feature = "set")]
+pub mod sortedlist;
 #[cfg(test)]
 mod stress_test;
-#[cfg(feature = "serde")]
+#[cfg(feature = "test_util")]
+pub mod test_util;
+#[cfg(all(feature = "serde", any(feature = "set", feature = "queue")))]
 use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "map")]
+pub use aggregate::Aggregate;
+pub use comparator::{Comparator, NaturalOrder, Reversed, TotalOrder};
+#[cfg(feature = "set")]
+pub use encode::Encode;
+pub use error::{AllocError, CompareError, Error, ReplaceKeyError, UnsortedError};
+#[cfg(any(feature = "map", feature = "persistent"))]
 use mapper::Mapper;
-use node::Node;
+#[cfg(feature = "persistent")]
+use pnode::Link;
+#[cfg(any(feature = "set", feature = "queue"))]
+use raw::RawTree;
 
 /// A map implemented using a red black tree to
 /// store key-value pairs.
+///
+/// Methods that need to order keys panic, leaving the
+/// map unchanged, if `partial_cmp` returns None for the
+/// keys involved (as it does for `f64::NAN`). `try_insert`
+/// and `try_get` return an `Error::IncomparableElement`
+/// instead.
 #[cfg(feature = "map")]
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Clone)]
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RBMap<K: PartialOrd, V> {
     map: RBTree<Mapper<K, V>>,
+    // boxed so that a map with no observers only
+    // pays for a pointer
+    observers: Option<Box<rbmap::Observers<K, V>>>,
+}
+
+/// A map that can associate any number of values with
+/// each key. Keys are ordered by their `PartialOrd`
+/// ordering and the values of each key are kept in the
+/// order they were inserted.
+#[cfg(feature = "map")]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RBMultiMap<K: PartialOrd, V> {
+    map: RBMap<K, Vec<V>>,
+    contained: usize,
+}
+
+/// A map from ranges of keys to values. The ranges never
+/// overlap; inserting a range cuts back any it overlaps,
+/// and joins it with those next to it that are mapped to
+/// an equal value. Finding the value of the range that
+/// covers a key takes O(log n) time.
+#[cfg(feature = "map")]
+#[derive(Clone)]
+pub struct RangeMap<K: PartialOrd, V> {
+    // maps the start of each range to its end and value
+    ranges: RBMap<K, (K, V)>,
+}
+
+/// A map of pairs of values in which each value is
+/// paired with exactly one other, so that either can be
+/// looked up from the other. Keeps a copy of each value
+/// in two trees, one ordered by the left values and one
+/// by the right, which is why both must be `Clone`.
+#[cfg(feature = "map")]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone)]
+pub struct RBBiMap<L: PartialOrd, R: PartialOrd> {
+    left: RBMap<L, R>,
+    right: RBMap<R, L>,
+}
+
+/// A priority queue of keys, each with a priority that
+/// can be looked up or changed by its key in O(log n)
+/// time. Keys are popped from the lowest priority to the
+/// highest, with equal priorities popped in key order.
+/// Keeps a copy of each key and priority in both the
+/// queue and an index of the keys, which is why both
+/// must be `Clone`.
+#[cfg(all(feature = "map", feature = "queue"))]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone)]
+pub struct RBPriorityMap<K: PartialOrd, P: PartialOrd> {
+    queue: RBQueue<(P, K)>,
+    priorities: RBMap<K, P>,
+}
+
+/// A map that keeps an aggregate (such as the sum or
+/// maximum) of the values of every subtree, so that the
+/// aggregate of the values of any range of keys can be
+/// found in O(log n) time. The aggregate is described by
+/// an implementation of `Aggregate`.
+#[cfg(feature = "map")]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone)]
+pub struct RBAggregateMap<K: PartialOrd, V, A> {
+    nodes: node::Arena<Mapper<K, (V, A)>>,
+    contained: usize,
 }
 
 /// A red black tree that can be used to store
 /// elements sorted by their PartialOrd provided
-/// ordering.
+/// ordering, or by the ordering of a `Comparator`
+/// given to `RBTree::with_comparator`.
+///
+/// Methods that need to order elements panic, leaving
+/// the tree unchanged, if `partial_cmp` returns None for
+/// the elements involved (as it does for `f64::NAN`).
+/// `try_insert` and `try_get` return an
+/// `Error::IncomparableElement` instead.
 #[cfg(feature = "set")]
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-pub struct RBTree<T: PartialOrd> {
-    root: Node<T>,
-    contained: usize,
+#[cfg_attr(
+    feature = "serde",
+    serde(
+        transparent,
+        bound(
+            serialize = "T: Serialize",
+            deserialize = "T: Deserialize<'de>, C: Default"
+        )
+    )
+)]
+pub struct RBTree<T, C = NaturalOrder> {
+    raw: RawTree<T, C>,
+}
+
+/// A list kept sorted by the PartialOrd provided ordering
+/// of its elements, or by the ordering of a `Comparator`
+/// given to `SortedList::with_comparator`. Unlike an
+/// RBTree it may hold any number of equal elements, which
+/// are kept in the order they were inserted. Each node
+/// knows the size of its subtree, so elements can be
+/// found and removed by their position in the list as
+/// well as by value, both in O(log n) time.
+#[cfg(feature = "set")]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(
+        transparent,
+        bound(
+            serialize = "T: Serialize",
+            deserialize = "T: Deserialize<'de>, C: Default"
+        )
+    )
+)]
+pub struct SortedList<T, C = NaturalOrder> {
+    raw: RawTree<T, C>,
+}
+
+/// A read-only snapshot of an RBTree, produced by
+/// `RBTree::freeze`. The elements are flattened into
+/// a sorted array and can no longer be modified, so
+/// the structure can be shared between threads for
+/// lookups without any locking.
+#[cfg(feature = "set")]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FrozenRBTree<T, C = NaturalOrder> {
+    values: Box<[T]>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    cmp: C,
 }
 
 /// A priority queue implemented using a red black
 /// tree. The ordering supplied must satisfy the assymetry
 /// and transitivity rules as outlined by  the dorumentation
-/// of std::cmp::PartialOrd.
+/// of std::cmp::PartialOrd. The ordering is given either
+/// by a closure (`RBQueue::new`) or by a `Comparator`
+/// (`RBQueue::with_comparator`), and is `NaturalOrder`
+/// for a queue created with `RBQueue::default()`.
+///
+/// A queue may be given a bound on the number of elements
+/// it holds (`RBQueue::bounded`), in which case inserting
+/// into a full queue is handled by its `BoundPolicy`.
 #[cfg(feature = "queue")]
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-pub struct RBQueue<T, P>
-where
-    P: Fn(&T, &T) -> std::cmp::Ordering,
-{
-    root: Node<T>,
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "T: Serialize",
+        deserialize = "T: Deserialize<'de>, C: Default"
+    ))
+)]
+pub struct RBQueue<T, C = NaturalOrder> {
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    raw: RawTree<T, C>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    bound: Option<(usize, rbqueue::BoundPolicy)>,
+}
+
+/// A queue that pops its smallest element first,
+/// as created by `RBQueue::min`.
+#[cfg(feature = "queue")]
+pub type MinQueue<T> = RBQueue<T, TotalOrder>;
+
+/// A queue that pops its largest element first,
+/// as created by `RBQueue::max`.
+#[cfg(feature = "queue")]
+pub type MaxQueue<T> = RBQueue<T, Reversed<TotalOrder>>;
+
+/// Keeps the `k` largest elements offered to it in a
+/// `MaxQueue`, evicting its smallest element when a
+/// larger one is offered while it holds `k`.
+#[cfg(feature = "queue")]
+#[derive(Clone)]
+pub struct TopK<T> {
+    best: MaxQueue<T>,
+    k: usize,
+}
+
+/// A timer queue, holding items until the `Instant`
+/// each was scheduled for and handing them back in
+/// order of their deadlines. Items due at the same
+/// instant are handed back in the order they were
+/// scheduled.
+#[cfg(feature = "queue")]
+#[derive(Clone)]
+pub struct DeadlineQueue<T> {
+    timers: RBQueue<(std::time::Instant, u64, T), deadline::ByDeadline>,
+    scheduled: u64,
+}
+
+/// A priority queue that can be awaited on, for sharing
+/// between the tasks of an async scheduler. `pop` returns
+/// a future that completes once the queue has an element
+/// to hand back, and `pop_due` one that also waits until
+/// the front element's deadline has passed, so consumers
+/// need not poll. Clones of an AsyncRBQueue are handles to
+/// the same queue.
+#[cfg(feature = "async")]
+pub struct AsyncRBQueue<T, C = NaturalOrder> {
+    shared: std::sync::Arc<asyncqueue::Shared<T, C>>,
+}
+
+/// An immutable red black tree. Inserting into or
+/// removing from a PersistentRBTree returns a new
+/// version of the tree, leaving the original intact;
+/// the two versions share every subtree the change
+/// did not touch, so keeping old versions around is
+/// cheap.
+#[cfg(feature = "persistent")]
+pub struct PersistentRBTree<T: PartialOrd> {
+    root: Link<T>,
     contained: usize,
-    cmp: P,
+}
+
+/// An immutable map implemented using a
+/// PersistentRBTree to store key-value pairs.
+#[cfg(feature = "persistent")]
+pub struct PersistentRBMap<K: PartialOrd, V> {
+    map: PersistentRBTree<Mapper<K, V>>,
+}
+
+/// A map that many threads can read from at once without
+/// waiting on each other or on writers. Each write produces
+/// a new version of the map, a PersistentRBMap sharing all
+/// it did not change with the last, which then replaces
+/// the current one in a single step; readers keep working
+/// from the version they last took until they ask again.
+/// Clones of a SnapshotMap are handles to the same map.
+#[cfg(feature = "persistent")]
+pub struct SnapshotMap<K: PartialOrd, V> {
+    shared: std::sync::Arc<snapshot::Shared<K, V>>,
+}
+
+/// A reader of a SnapshotMap, caching the version it last
+/// took so that taking it again is wait-free until the
+/// map is written to. Created by `SnapshotMap::reader`.
+#[cfg(feature = "persistent")]
+pub struct SnapshotReader<K: PartialOrd, V> {
+    shared: std::sync::Arc<snapshot::Shared<K, V>>,
+    version: usize,
+    cached: PersistentRBMap<K, V>,
 }
 
 /// Returns an RBTree containing the items
-/// given separated by commas.
+/// given separated by commas, or the items of
+/// any iterable given after `from`.
 /// # Example:
 /// ```
-/// use rb_tree::{RBTree, new_set};
+/// use rb_tree::new_set;
 ///
 /// let t1 = new_set!('b', 'a', 'd', 'c');
 /// let t2 = new_set!('d', 'f', 'e', 'c');
@@ -72,12 +354,22 @@ where
 /// assert_eq!(in_both.next().unwrap(), &'c');
 /// assert_eq!(in_both.next().unwrap(), &'d');
 /// assert_eq!(in_both.next(), None);
+///
+/// let t3 = new_set!(from 0..100);
+/// assert_eq!(t3.len(), 100);
+/// assert_eq!(t3.peek_back(), Some(&99));
 /// ```
 #[cfg(feature = "set")]
 #[macro_export]
 macro_rules! new_set {
+    (from $iter:expr) => {{
+        let mut t = $crate::RBTree::new();
+        ::std::iter::Extend::extend(&mut t, $iter);
+        t
+    }};
+
     ( $($v:expr),* ) => {{
-        let mut t = RBTree::new();
+        let mut t = $crate::RBTree::new();
         $(
             t.insert($v);
         )*
@@ -89,9 +381,11 @@ macro_rules! new_set {
 /// closure and contains the comma-separated
 /// elements following it.
 /// # Example:
-/// use rb_tree::{RBQueue, new_queue};
+/// ```
+/// use rb_tree::new_queue;
+/// use std::cmp::Ordering::*;
 ///
-/// let mut q = new_queue!(|l, r| {
+/// let mut q = new_queue!(|l: &i32, r: &i32| {
 /// match l - r {
 ///     i32::MIN..=-1_i32 => Greater,
 ///     0 => Equal,
@@ -108,7 +402,7 @@ macro_rules! new_set {
 #[macro_export]
 macro_rules! new_queue {
     ($comp:expr; $($v:expr),*) => {{
-        let mut q = RBQueue::new($comp);
+        let mut q = $crate::RBQueue::new($comp);
         $(q.insert($v);)*
         q
     }};
@@ -129,7 +423,6 @@ macro_rules! new_queue {
 /// ```
 /// # #[macro_use(new_c_queue)]
 /// # extern crate rb_tree;
-/// # use rb_tree::RBQueue;
 /// # fn main() {
 /// let mut q = new_c_queue!(|l: &i64, r| (r - l));
 /// q.insert(1);
@@ -143,7 +436,6 @@ macro_rules! new_queue {
 /// ```
 /// # #[macro_use(new_c_queue)]
 /// # extern crate rb_tree;
-/// # use rb_tree::RBQueue;
 /// # fn main() {
 /// let q = new_c_queue!(|l: &i64, r| (r - l); 1, 2, 3);
 /// assert_eq!(q.ordered(), [&3, &2, &1]);
@@ -153,23 +445,23 @@ macro_rules! new_queue {
 #[macro_export]
 macro_rules! new_c_queue {
     ($cmp:expr) => {
-        RBQueue::new(move |l, r| {
-            let comp = Box::new($cmp);
+        $crate::RBQueue::new(move |l, r| {
+            let comp = ::std::boxed::Box::new($cmp);
             match comp(l, r) as i8 {
-                -128i8 ..= -1 => std::cmp::Ordering::Less,
-                0 => std::cmp::Ordering::Equal,
-                1 ..= 127i8 => std::cmp::Ordering::Greater
+                -128i8 ..= -1 => ::std::cmp::Ordering::Less,
+                0 => ::std::cmp::Ordering::Equal,
+                1 ..= 127i8 => ::std::cmp::Ordering::Greater
             }
         })
     };
 
     ($cmp:expr; $($v:expr),*) => {{
-        let mut q = RBQueue::new(move |l, r| {
-            let comp = Box::new($cmp);
+        let mut q = $crate::RBQueue::new(move |l, r| {
+            let comp = ::std::boxed::Box::new($cmp);
             match comp(l, r) as i8 {
-                -128i8 ..= -1 => std::cmp::Ordering::Less,
-                0 => std::cmp::Ordering::Equal,
-                1 ..= 127i8 => std::cmp::Ordering::Greater
+                -128i8 ..= -1 => ::std::cmp::Ordering::Less,
+                0 => ::std::cmp::Ordering::Equal,
+                1 ..= 127i8 => ::std::cmp::Ordering::Greater
             }
         });
         $(
@@ -180,21 +472,32 @@ macro_rules! new_c_queue {
 }
 
 /// Returns an RBMap containing the (key, value)
-/// pairs separated by commas.
+/// pairs separated by commas, or the pairs of
+/// any iterable given after `from`.
 /// # Example:
 /// ```
-/// use rb_tree::{RBMap, new_map};
+/// use rb_tree::new_map;
 ///
 /// let m = new_map!((1, 'a'), (2, 'b'), (3, 'c'));
 /// assert_eq!(m.get(&1).unwrap(), &'a');
 /// assert_eq!(m.get(&2).unwrap(), &'b');
 /// assert_eq!(m.get(&3).unwrap(), &'c');
+///
+/// let squares = new_map!(from (0..10).map(|i| (i, i * i)));
+/// assert_eq!(squares.len(), 10);
+/// assert_eq!(squares.get(&7), Some(&49));
 /// ```
 #[cfg(feature = "map")]
 #[macro_export]
 macro_rules! new_map {
+    (from $iter:expr) => {{
+        let mut m = $crate::RBMap::new();
+        ::std::iter::Extend::extend(&mut m, $iter);
+        m
+    }};
+
     ( $(($k:expr, $v:expr)),* ) => {{
-        let mut m = RBMap::new();
+        let mut m = $crate::RBMap::new();
         $(
             m.insert($k, $v);
         )*
@@ -1,5 +1,7 @@
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "map")]
+use std::borrow::Borrow;
 use std::fmt::{Debug, Formatter, Result};
 
 #[derive(Clone)]
@@ -18,6 +20,12 @@ impl<K: PartialOrd, V> Mapper<K, V> {
         &self.key
     }
 
+    #[cfg(feature = "map")]
+    pub fn key_mut(&mut self) -> &mut K {
+        &mut self.key
+    }
+
+    #[cfg(feature = "map")]
     pub fn is_some(&self) -> bool {
         self.val.is_some()
     }
@@ -38,9 +46,23 @@ impl<K: PartialOrd, V> Mapper<K, V> {
         (&self.key, self.val.as_ref().unwrap())
     }
 
+    #[cfg(feature = "map")]
     pub fn mut_pair(&mut self) -> (&K, &mut V) {
         (&self.key, self.val.as_mut().unwrap())
     }
+
+    #[cfg(feature = "map")]
+    pub fn val_mut(&mut self) -> &mut Option<V> {
+        &mut self.val
+    }
+
+    #[cfg(feature = "map")]
+    pub fn map_val<W, F: FnOnce(V) -> W>(self, f: F) -> Mapper<K, W> {
+        Mapper {
+            key: self.key,
+            val: self.val.map(f),
+        }
+    }
 }
 
 impl<K: PartialOrd + Debug, V: Debug> Debug for Mapper<K, V> {
@@ -72,3 +94,30 @@ impl<K: PartialOrd, V> PartialOrd<Mapper<K, V>> for Mapper<&K, V> {
         self.key.partial_cmp(&other.key)
     }
 }
+
+// a key to look up entries by, in any borrowed
+// form of the keys they were inserted with
+#[cfg(feature = "map")]
+pub struct KeyRef<'a, Q: ?Sized>(pub &'a Q);
+
+#[cfg(feature = "map")]
+impl<K, Q, V> PartialEq<Mapper<K, V>> for KeyRef<'_, Q>
+where
+    K: PartialOrd + Borrow<Q>,
+    Q: PartialOrd + ?Sized,
+{
+    fn eq(&self, other: &Mapper<K, V>) -> bool {
+        *self.0 == *other.key.borrow()
+    }
+}
+
+#[cfg(feature = "map")]
+impl<K, Q, V> PartialOrd<Mapper<K, V>> for KeyRef<'_, Q>
+where
+    K: PartialOrd + Borrow<Q>,
+    Q: PartialOrd + ?Sized,
+{
+    fn partial_cmp(&self, other: &Mapper<K, V>) -> Option<std::cmp::Ordering> {
+        self.0.partial_cmp(other.key.borrow())
+    }
+}
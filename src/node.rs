@@ -1,642 +1,1732 @@
+pub use crate::colour::Colour;
+use crate::error::{AllocError, CompareError};
+use crate::helpers::write_to_level;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
-use std::boxed::Box;
 use std::cmp::Ordering::{Equal, Greater, Less};
-use std::mem::swap as m_swap;
-use std::ops::{Deref, DerefMut};
+#[cfg(feature = "map")]
+use std::collections::BTreeMap;
+use std::mem::replace as m_replace;
 
-#[derive(PartialEq, Debug, Clone, Copy)]
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-pub enum Colour {
-    Red,
-    Black,
-    DBlack,
-}
+// nodes refer to each other by their position in the arena
+// rather than by pointer; with the "compact" feature enabled
+// these positions are 32 bits wide, roughly halving the link
+// overhead of each node (at the cost of capping the number
+// of elements a single tree can hold at 2^31 - 1)
+#[cfg(feature = "compact")]
+pub type Index = u32;
+#[cfg(not(feature = "compact"))]
+pub type Index = usize;
+
+// the index used to represent a leaf (i.e., no node)
+pub const NIL: Index = Index::MAX;
 
-enum Insertion<T> {
-    InvalidLeft,
-    InvalidRight,
-    Recoloured,
-    Inserted,
-    Replaced(T),
-    Success,
+// a node's colour is kept in the top bit of its size (set
+// for red) rather than in a field of its own, which would
+// be padded out to a full word; this caps the number of
+// nodes an arena can hold at RED_BIT - 1
+const RED_BIT: Index = !(Index::MAX >> 1);
+
+// conversions between arena indices and vector positions,
+// these are no-ops unless the "compact" feature is enabled
+#[allow(clippy::unnecessary_cast)]
+pub const fn pos(at: Index) -> usize {
+    at as usize
+}
+#[allow(clippy::unnecessary_cast)]
+pub fn index(pos: usize) -> Index {
+    pos as Index
 }
 
-enum Removal<T> {
-    Removed(T),
-    Doubled(T),
-    Match,
-    NotFound,
+// the depth at which a balanced tree of len nodes stops being
+// full, whose nodes are coloured red so every path to a leaf
+// passes the same number of black nodes
+#[cfg(any(feature = "set", feature = "queue"))]
+fn red_depth(len: usize) -> usize {
+    (usize::BITS - (len + 1).leading_zeros() - 1) as usize
 }
 
-// makes matches nicer
 #[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Innards<T> {
     value: T,
-    colour: Colour,
-    r_child: Box<Node<T>>,
-    l_child: Box<Node<T>>,
+    r_child: Index,
+    l_child: Index,
+    parent: Index,
+    // the number of nodes in the subtree rooted here,
+    // tagged with the node's colour (see RED_BIT)
+    size: Index,
+}
+
+// a position in the arena is either in use by a node
+// or vacant, in which case it links to the next vacancy
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum Slot<T> {
+    Occupied(Innards<T>),
+    Vacant(Index),
 }
 
+// owns all nodes of the rb_tree
 #[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-// represents a node in the rb_tree
-pub enum Node<T> {
-    Internal(Innards<T>),
-    Leaf(Colour),
+pub struct Arena<T> {
+    slots: Vec<Slot<T>>,
+    root: Index,
+    free: Index,
+    // the node holding the greatest value, or NIL if not
+    // known; lets values inserted in ascending order skip
+    // the descent from the root
+    #[cfg_attr(feature = "serde", serde(skip, default = "unknown"))]
+    last: Index,
+    // recomputes any extra data a node keeps about its
+    // subtree, called wherever the subtree sizes are
+    #[cfg_attr(feature = "serde", serde(skip, default = "no_augment"))]
+    augment: Option<Augment<T>>,
+}
+
+pub type Augment<T> = fn(&mut Arena<T>, Index);
+
+#[cfg(feature = "serde")]
+fn unknown() -> Index {
+    NIL
+}
+
+#[cfg(feature = "serde")]
+fn no_augment<T>() -> Option<Augment<T>> {
+    None
 }
 
 use Colour::*;
-use Insertion::*;
-use Node::*;
-use Removal::*;
+use Slot::*;
+
+impl<T> Innards<T> {
+    fn new(value: T) -> Innards<T> {
+        Innards {
+            value,
+            r_child: NIL,
+            l_child: NIL,
+            parent: NIL,
+            size: 1 | RED_BIT, // all newly inserted values are red
+        }
+    }
+
+    fn colour(&self) -> Colour {
+        if self.size & RED_BIT == 0 {
+            Black
+        } else {
+            Red
+        }
+    }
 
-impl std::fmt::Display for Colour {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Red => write!(f, "R"),
-            Black => write!(f, "B"),
-            DBlack => write!(f, "D"),
+    fn set_colour(&mut self, colour: Colour) {
+        match colour {
+            Red => self.size |= RED_BIT,
+            Black => self.size &= !RED_BIT,
         }
     }
+
+    fn size(&self) -> Index {
+        self.size & !RED_BIT
+    }
+
+    fn set_size(&mut self, size: Index) {
+        self.size = size | (self.size & RED_BIT);
+    }
 }
 
 impl<T> Innards<T> {
-    pub fn is_black(&self) -> bool {
-        matches!(self.colour, Black)
+    // splits the value from the rest of the node
+    #[cfg(feature = "set")]
+    fn with_value<U>(self, value: U) -> (Innards<U>, T) {
+        let n = Innards {
+            value,
+            r_child: self.r_child,
+            l_child: self.l_child,
+            parent: self.parent,
+            size: self.size,
+        };
+        (n, self.value)
     }
-    pub fn is_red(&self) -> bool {
-        matches!(self.colour, Red)
+}
+
+// with the "chunked" feature the collections look up their
+// values through chunk::Arena, which uses only the structural
+// methods of this one
+#[cfg_attr(feature = "chunked", allow(dead_code))]
+impl<T> Arena<T> {
+    pub fn new() -> Arena<T> {
+        Arena {
+            slots: Vec::new(),
+            root: NIL,
+            free: NIL,
+            last: NIL,
+            augment: None,
+        }
     }
 
-    pub fn is_double_black(&self) -> bool {
-        matches!(self.colour, DBlack)
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.root = NIL;
+        self.free = NIL;
+        self.last = NIL;
     }
 
-    pub fn colour(&self) -> Colour {
-        self.colour
+    #[cfg(any(feature = "set", feature = "chunked", test))]
+    pub fn root(&self) -> Index {
+        self.root
     }
 
-    pub fn value(&self) -> &T {
-        &self.value
+    // panicing accessors only used internally very carefully,
+    // they should only ever be given indices of occupied slots
+    fn node(&self, at: Index) -> &Innards<T> {
+        match &self.slots[pos(at)] {
+            Occupied(n) => n,
+            Vacant(_) => panic!("Attempted to access a vacant node"),
+        }
+    }
+    fn node_mut(&mut self, at: Index) -> &mut Innards<T> {
+        match &mut self.slots[pos(at)] {
+            Occupied(n) => n,
+            Vacant(_) => panic!("Attempted to access a vacant node"),
+        }
     }
 
-    pub fn swap_colour(&mut self) {
-        self.colour = match self.colour {
-            Red => Black,
-            Black => Red,
-            DBlack => Black,
+    // places a new red node into the arena, reusing
+    // a vacant slot if one is available
+    fn alloc(&mut self, value: T) -> Index {
+        let node = Occupied(Innards::new(value));
+        if self.free != NIL {
+            let at = self.free;
+            self.free = match m_replace(&mut self.slots[pos(at)], node) {
+                Vacant(next) => next,
+                Occupied(_) => panic!("Free list references an occupied node"),
+            };
+            at
+        } else {
+            let at = self.slots.len();
+            if at >= pos(RED_BIT) {
+                panic!("Exceeded the maximum number of nodes in a tree");
+            }
+            self.slots.push(node);
+            index(at)
         }
     }
-}
 
-impl<T> Node<T> {
-    pub fn new(val: T) -> Node<T> {
-        Internal(Innards {
-            value: val,
-            colour: Red, // all newly inserted values are red
-            r_child: Box::new(Leaf(Black)),
-            l_child: Box::new(Leaf(Black)),
-        })
+    // reserves room for at least `additional` more nodes
+    // without assuming any vacant slots will be reused
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), AllocError> {
+        match self.slots.len().checked_add(additional) {
+            Some(n) if n <= pos(RED_BIT) => self
+                .slots
+                .try_reserve(additional)
+                .map_err(AllocError::ReserveFailed),
+            _ => Err(AllocError::CapacityOverflow),
+        }
     }
 
-    // method used for testing
-    #[cfg(test)]
-    pub fn new_black(val: T) -> Node<T> {
-        Internal(Innards {
-            value: val,
-            colour: Black, // all newly inserted values are red
-            r_child: Box::new(Leaf(Black)),
-            l_child: Box::new(Leaf(Black)),
-        })
+    // makes sure `n` more values than are held now can be
+    // placed without allocating, counting the vacant slots
+    // left by removed values towards them
+    pub fn reserve_nodes(&mut self, n: usize) {
+        let vacant = self.slots.len() - self.size(self.root);
+        self.slots.reserve(n.saturating_sub(vacant));
     }
 
-    // convenience functions so matches don't appear everywhere
-    pub fn is_black(&self) -> bool {
-        match self {
-            Internal(n) => n.is_black(),
-            Leaf(c) => *c == Black,
+    // moves the nodes into the lowest slots so the vacant ones
+    // can be dropped, then gives the unused memory back
+    pub fn shrink_to_fit(&mut self) {
+        let len = self.size(self.root);
+        let mut from = self.slots.len();
+        for to in 0..len {
+            if let Occupied(_) = self.slots[to] {
+                continue;
+            }
+            // there are as many nodes past `len` as there
+            // are vacancies before it, so one will be found
+            from -= 1;
+            while let Vacant(_) = self.slots[from] {
+                from -= 1;
+            }
+            self.relocate(index(from), index(to));
         }
+        self.slots.truncate(len);
+        self.slots.shrink_to_fit();
+        self.free = NIL;
     }
-    pub fn is_red(&self) -> bool {
-        match self {
-            Internal(n) => n.is_red(),
-            _ => false,
+
+    // moves the node at `from` into the vacant slot `to`,
+    // leaving the tree's shape as it was
+    fn relocate(&mut self, from: Index, to: Index) {
+        let node = m_replace(&mut self.slots[pos(from)], Vacant(NIL));
+        self.slots[pos(to)] = node;
+        let n = self.node(to);
+        let (parent, left, right) = (n.parent, n.l_child, n.r_child);
+        if parent == NIL {
+            self.root = to;
+        } else {
+            let right_child = self.get_right(parent) == from;
+            self.set_child(parent, right_child, to);
         }
-    }
-    pub fn is_double_black(&self) -> bool {
-        match self {
-            Internal(n) => n.is_double_black(),
-            Leaf(c) => *c == DBlack,
+        for child in [left, right].iter().copied() {
+            if child != NIL {
+                self.node_mut(child).parent = to;
+            }
+        }
+        if self.last == from {
+            self.last = to;
         }
     }
-    pub fn is_leaf(&self) -> bool {
-        matches!(self, Leaf(_))
+
+    // makes sure the next alloc won't need to allocate
+    pub fn try_make_room(&mut self) -> Result<(), AllocError> {
+        if self.free != NIL {
+            Ok(())
+        } else {
+            self.try_reserve(1)
+        }
     }
 
-    pub fn colour(&self) -> Colour {
-        match self {
-            Internal(n) => n.colour,
-            Leaf(c) => *c,
+    // removes the node from the arena and returns its value
+    fn release(&mut self, at: Index) -> T {
+        match m_replace(&mut self.slots[pos(at)], Vacant(self.free)) {
+            Occupied(n) => {
+                self.free = at;
+                n.value
+            }
+            Vacant(_) => panic!("Attempted to release a vacant node"),
         }
     }
 
-    pub fn value(&self) -> Option<&T> {
-        match self {
-            Internal(n) => Some(&n.value),
-            Leaf(_) => None,
+    // convenience functions so lookups don't appear everywhere;
+    // leaves are treated as black nodes without a value whose
+    // children are themselves
+    pub fn is_black(&self, at: Index) -> bool {
+        self.colour(at) == Black
+    }
+    pub fn is_red(&self, at: Index) -> bool {
+        self.colour(at) == Red
+    }
+
+    pub fn colour(&self, at: Index) -> Colour {
+        if at == NIL {
+            Black
+        } else {
+            self.node(at).colour()
         }
     }
 
-    #[cfg(feature = "map")]
-    pub fn value_mut(&mut self) -> Option<&mut T> {
-        match self {
-            Internal(n) => Some(&mut n.value),
-            Leaf(_) => None,
+    pub fn value(&self, at: Index) -> Option<&T> {
+        if at == NIL {
+            None
+        } else {
+            Some(&self.node(at).value)
         }
     }
 
-    pub fn swap_colour(&mut self) {
-        if let Internal(n) = self {
-            n.swap_colour();
-        } // leaves always black
+    #[cfg(any(feature = "set", feature = "queue"))]
+    pub fn value_mut(&mut self, at: Index) -> Option<&mut T> {
+        if at == NIL {
+            None
+        } else {
+            Some(&mut self.node_mut(at).value)
+        }
     }
-    fn black(&mut self) {
-        match self {
-            Internal(n) => n.colour = Black,
-            Leaf(c) => *c = Black,
+
+    pub fn get_left(&self, at: Index) -> Index {
+        if at == NIL {
+            NIL
+        } else {
+            self.node(at).l_child
         }
     }
-    fn red(&mut self) {
-        match self {
-            Internal(n) => n.colour = Red,
-            Leaf(c) => *c = Red,
+
+    pub fn get_right(&self, at: Index) -> Index {
+        if at == NIL {
+            NIL
+        } else {
+            self.node(at).r_child
         }
     }
-    fn double_black(&mut self) {
-        match self {
-            Internal(n) => n.colour = DBlack,
-            Leaf(c) => *c = DBlack,
+
+    pub fn get_parent(&self, at: Index) -> Index {
+        if at == NIL {
+            NIL
+        } else {
+            self.node(at).parent
         }
     }
 
-    pub fn get_left(&self) -> &Node<T> {
-        match self {
-            Internal(n) => &n.l_child,
-            Leaf(_) => self,
+    // the number of nodes in the subtree rooted at `at`
+    pub fn size(&self, at: Index) -> usize {
+        if at == NIL {
+            0
+        } else {
+            pos(self.node(at).size())
         }
     }
 
-    pub fn get_right(&self) -> &Node<T> {
-        match self {
-            Internal(n) => &n.r_child,
-            Leaf(_) => self,
+    // recounts the nodes beneath `at` from the sizes of its children
+    fn resize(&mut self, at: Index) {
+        let size = 1 + self.size(self.get_left(at)) + self.size(self.get_right(at));
+        self.node_mut(at).set_size(index(size));
+        if let Some(augment) = self.augment {
+            augment(self, at);
         }
     }
 
-    pub fn get_left_mut(&mut self) -> &mut Node<T> {
-        match self {
-            Internal(n) => &mut n.l_child,
-            Leaf(_) => self,
+    #[cfg(any(feature = "map", feature = "chunked"))]
+    pub fn set_augment(&mut self, augment: Augment<T>) {
+        self.augment = Some(augment);
+    }
+
+    // recounts the nodes beneath `at` and each of its ancestors
+    pub fn resize_up(&mut self, mut at: Index) {
+        while at != NIL {
+            self.resize(at);
+            at = self.get_parent(at);
         }
     }
 
-    pub fn get_right_mut(&mut self) -> &mut Node<T> {
-        match self {
-            Internal(n) => &mut n.r_child,
-            Leaf(_) => self,
+    // the left-most (right=false) or right-most (right=true)
+    // node of the subtree rooted at `at`
+    pub fn outermost(&self, at: Index, right: bool) -> Index {
+        let mut cur = at;
+        while self.child(cur, right) != NIL {
+            cur = self.child(cur, right);
         }
+        cur
+    }
+
+    #[cfg(any(feature = "set", feature = "queue"))]
+    pub fn first(&self) -> Index {
+        self.outermost(self.root, false)
     }
 
-    // panicing operators only used internally very carefully
-    // they are essentially used for convenience and to make
-    // code look nicer while working with certain guarantees
-    // (i.e., their use should never actually cause a panic)
-    fn innards(&mut self) -> &mut Innards<T> {
-        match self {
-            Internal(n) => n,
-            Leaf(_) => panic!("Attempted to extract details of leaf node"),
+    #[cfg(any(feature = "set", feature = "queue"))]
+    pub fn last(&self) -> Index {
+        self.outermost(self.root, true)
+    }
+
+    // the in-order successor (forward=true) or predecessor
+    // (forward=false) of `at`, found by walking down into the
+    // next subtree or else up to the first ancestor that lies
+    // in that direction; amortised O(1) over a full traversal
+    #[cfg(any(feature = "set", feature = "queue"))]
+    pub fn step(&self, at: Index, forward: bool) -> Index {
+        let next = self.child(at, forward);
+        if next != NIL {
+            return self.outermost(next, !forward);
+        }
+        let mut cur = at;
+        let mut parent = self.get_parent(cur);
+        while parent != NIL && self.child(parent, forward) == cur {
+            cur = parent;
+            parent = self.get_parent(cur);
         }
+        parent
     }
-    fn gut(self) -> Innards<T> {
-        match self {
-            Internal(n) => n,
-            Leaf(_) => panic!("Attempted to extract details of leaf node"),
+
+    // the successor of the value removed from `at`, given the
+    // successor `next` it had beforehand; a removal leaves the
+    // other values' nodes where they are
+    #[cfg(feature = "set")]
+    pub fn next_after_removal(&self, _at: Index, next: Index) -> Index {
+        next
+    }
+
+    pub fn swap_colour(&mut self, at: Index) {
+        if at != NIL {
+            self.node_mut(at).size ^= RED_BIT;
+        } // leaves always black
+    }
+    fn black(&mut self, at: Index) {
+        if at != NIL {
+            self.node_mut(at).set_colour(Black);
         }
     }
+    fn red(&mut self, at: Index) {
+        self.node_mut(at).set_colour(Red);
+    }
 
     // true gets the right child, false left
-    fn child(&mut self, right: bool) -> &mut Node<T> {
-        match self {
-            Internal(n) => {
-                if right {
-                    n.r_child.deref_mut()
-                } else {
-                    n.l_child.deref_mut()
-                }
-            }
-            Leaf(_) => panic!("Attempted to get child of leaf"),
+    fn child(&self, at: Index, right: bool) -> Index {
+        if right {
+            self.get_right(at)
+        } else {
+            self.get_left(at)
         }
     }
-    fn peek_child(&self, right: bool) -> &Node<T> {
-        match self {
-            Internal(n) => {
-                if right {
-                    n.r_child.deref()
-                } else {
-                    n.l_child.deref()
-                }
-            }
-            Leaf(_) => panic!("Attempted to get child of leaf"),
+    fn set_child(&mut self, at: Index, right: bool, child: Index) {
+        let n = self.node_mut(at);
+        if right {
+            n.r_child = child;
+        } else {
+            n.l_child = child;
+        }
+        if child != NIL {
+            self.node_mut(child).parent = at;
         }
     }
-    fn child_safe(&mut self, right: bool) -> &mut Node<T> {
-        match self {
-            Internal(n) => {
-                if right {
-                    n.r_child.deref_mut()
-                } else {
-                    n.l_child.deref_mut()
-                }
-            }
-            Leaf(_) => self,
+    fn set_root(&mut self, root: Index) {
+        self.root = root;
+        if root != NIL {
+            self.node_mut(root).parent = NIL;
+        }
+    }
+
+    // puts `with` in the position `at` currently holds
+    // relative to its parent
+    fn transplant(&mut self, at: Index, with: Index) {
+        let parent = self.get_parent(at);
+        if parent == NIL {
+            self.set_root(with);
+        } else {
+            let right = self.get_right(parent) == at;
+            self.set_child(parent, right, with);
         }
     }
 
     /*
     visual of this operation (for right=true, mirror for right=false)
 
-       self                 c
+       at                   c
        /  \               /   \
-      a    b    --->     a    self
-     / \  / \           / \  /   \
-          c                       b
+      a    b    --->     at    b
+          /             /
+         c             a
+
+    returns the index of the new local root (c)
     */
-    fn inner_switcheroo(&mut self, right: bool) {
-        let mut tmp = Leaf(Black);
-        let mut l_child_tmp = Leaf(Black);
-        let mut r_child_tmp = Leaf(Black);
-        m_swap(&mut tmp, self.child(right).child(!right));
-        m_swap(&mut l_child_tmp, tmp.child(false));
-        m_swap(&mut r_child_tmp, tmp.child(true));
-        m_swap(tmp.child(right), self.child(right));
-        m_swap(tmp.child(!right), self);
-        m_swap(&mut tmp, self);
-        m_swap(self.child(false).child(true), &mut l_child_tmp);
-        m_swap(self.child(true).child(false), &mut r_child_tmp);
+    fn inner_switcheroo(&mut self, at: Index, right: bool) -> Index {
+        self.outer_switcheroo(self.child(at, right), !right);
+        self.outer_switcheroo(at, right)
     }
 
     /*
     visual of this operation (for right=true, mirror for right=false)
 
-       self                 b
+       at                   b
        /  \               /  \
-      a    b    --->   self   c
+      a    b    --->    at    c
      / \  / \          / \   / \
              c        a
+
+    returns the index of the new local root (b)
     */
-    fn outer_switcheroo(&mut self, right: bool) {
-        let mut tmp = Leaf(Black);
-        let mut child_tmp = Leaf(Black);
-        m_swap(&mut tmp, self.child(right));
-        m_swap(tmp.child(!right), self);
-        m_swap(self, &mut child_tmp);
-        m_swap(&mut tmp, self);
-        m_swap(self.child(!right).child(right), &mut child_tmp);
-    }
-
-    // reorders nodes when required upon insertion
-    fn insert_switcheroo(&mut self, right: bool, inner: bool, recolour: bool) -> Insertion<T> {
-        if recolour {
-            // doesn't move anything, simply recolours
-            self.swap_colour();
-            self.child(false).swap_colour();
-            self.child(true).swap_colour();
-            Recoloured
-        } else if inner {
-            // realligns the newly inserted value as the new local root
-            self.inner_switcheroo(right);
-            self.swap_colour();
-            self.child(!right).swap_colour();
-            Success
-        } else {
-            // realigns the parent of the newly inserted value as the new
-            // local root
-            self.outer_switcheroo(right);
-            self.swap_colour();
-            self.child(!right).swap_colour();
-            Success
-        }
+    fn outer_switcheroo(&mut self, at: Index, right: bool) -> Index {
+        let b = self.child(at, right);
+        self.transplant(at, b);
+        self.set_child(at, right, self.child(b, !right));
+        self.set_child(b, !right, at);
+        self.resize(at);
+        self.resize(b);
+        b
     }
 
-    // returns the value if the value was not inserted
-    fn insert_op<P>(&mut self, mut new_v: T, cmp: &P) -> Insertion<T>
-    where
-        P: Fn(&T, &T) -> std::cmp::Ordering,
-    {
-        match self {
-            Internal(n) => {
-                let order = cmp(&n.value, &new_v);
-                let (res, right, recolour) = match order {
-                    Equal => {
-                        m_swap(&mut n.value, &mut new_v); // useful if used like a map
-                        (Replaced(new_v), true, true)
-                    }
-                    Greater => (n.l_child.insert_op(new_v, cmp), false, n.r_child.is_red()),
-                    Less => (n.r_child.insert_op(new_v, cmp), true, n.l_child.is_red()),
-                };
-                match res {
-                    InvalidLeft => self.insert_switcheroo(right, right, recolour),
-                    InvalidRight => self.insert_switcheroo(right, !right, recolour),
-                    Recoloured => {
-                        if self.is_red() && self.child(right).is_red() {
-                            if right {
-                                InvalidRight
-                            } else {
-                                InvalidLeft
-                            }
-                        } else {
-                            Success
-                        }
-                    }
-                    Inserted => {
-                        if self.is_black() {
-                            Success
-                        } else if right {
-                            InvalidRight
-                        } else {
-                            InvalidLeft
-                        }
-                    }
-                    Replaced(v) => Replaced(v),
-                    Success => Success,
-                }
+    // restores the red-black properties after the red node
+    // `at` has been attached in place of a leaf; returns true
+    // if the root had to be made black, adding a black node
+    // to every path through the tree
+    fn insert_fixup(&mut self, mut at: Index) -> bool {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("rebalance").entered();
+        loop {
+            let parent = self.get_parent(at);
+            if self.is_black(parent) {
+                break;
             }
-            Leaf(_) => {
-                *self = Node::new(new_v);
-                Inserted
+            // parent is red, so can't be the root
+            let grand = self.get_parent(parent);
+            let right = self.get_right(grand) == parent;
+            let uncle = self.child(grand, !right);
+            if self.is_red(uncle) {
+                // doesn't move anything, simply recolours
+                // and continues from the grandparent
+                self.swap_colour(grand);
+                self.swap_colour(parent);
+                self.swap_colour(uncle);
+                #[cfg(feature = "tracing")]
+                tracing::trace!(node = grand, "recolour");
+                at = grand;
+                continue;
             }
+            let top = if self.child(parent, right) != at {
+                // realligns the newly inserted value as the new local root
+                self.inner_switcheroo(grand, right)
+            } else {
+                // realigns the parent of the newly inserted value as the new
+                // local root
+                self.outer_switcheroo(grand, right)
+            };
+            #[cfg(feature = "tracing")]
+            tracing::trace!(node = top, "rotate");
+            self.swap_colour(top);
+            self.swap_colour(self.child(top, !right));
+            break;
         }
+        let grew = self.is_red(self.root);
+        self.black(self.root);
+        grew
     }
 
-    // only to be called on the root
+    // returns the value replaced by the insertion, if any
     pub fn insert<P>(&mut self, new_v: T, cmp: &P) -> Option<T>
     where
         P: Fn(&T, &T) -> std::cmp::Ordering,
     {
-        let res = self.insert_op(new_v, cmp);
-        if self.is_red() {
-            self.swap_colour();
+        match self.try_insert(new_v, &|l: &T, r: &T| Some(cmp(l, r))) {
+            Ok(old) => old,
+            Err(_) => unreachable!(),
         }
-        match res {
-            Replaced(v) => Some(v),
-            _ => None,
+    }
+
+    // inserts the value only if no equal value is present,
+    // otherwise handing the value back untouched
+    #[cfg(feature = "set")]
+    pub fn insert_new<P>(&mut self, new_v: T, cmp: &P) -> Option<T>
+    where
+        P: Fn(&T, &T) -> std::cmp::Ordering,
+    {
+        match self.insert_with(new_v, &|l: &T, r: &T| Some(cmp(l, r)), false) {
+            Ok(rejected) => rejected,
+            Err(_) => unreachable!(),
         }
     }
 
-    // https://www.usna.edu/Users/cs/crabbe/SI321/current/red-black/red-black.html
-    // returns true if double black propogates (i.e., if
-    // self is double black after having called this method on it)
-    // should only be called on the PARENT of a
-    // double black node (right is true if the double
-    // black is the right child, false otherwise)
-    fn deletion_switcheroo(&mut self, right: bool) -> bool {
-        let mut was_red = false;
-
-        // unique case
-        if self.child(!right).is_red() {
-            self.outer_switcheroo(!right);
-            self.black();
-            self.child(right).red();
-            self.child(right).deletion_switcheroo(right);
-            if !self.child(right).is_double_black() {
-                return false;
+    // as with insert, but gives up before anything is changed
+    // if the new value can't be compared to one on its path
+    pub fn try_insert<P>(&mut self, new_v: T, cmp: &P) -> Result<Option<T>, CompareError>
+    where
+        P: Fn(&T, &T) -> Option<std::cmp::Ordering>,
+    {
+        self.insert_with(new_v, cmp, true)
+    }
+
+    // when an equal value is present, either swaps the new
+    // value in and returns the old one or returns the new one
+    fn insert_with<P>(
+        &mut self,
+        new_v: T,
+        cmp: &P,
+        replace: bool,
+    ) -> Result<Option<T>, CompareError>
+    where
+        P: Fn(&T, &T) -> Option<std::cmp::Ordering>,
+    {
+        if self.root != NIL {
+            if self.last == NIL {
+                self.last = self.outermost(self.root, true);
+            }
+            // a new greatest value belongs to the right of the
+            // current greatest, so needs no descent to place
+            let last = self.last;
+            if cmp(&self.node(last).value, &new_v).ok_or(CompareError)? == Less {
+                self.insert_at(last, true, new_v);
+                return Ok(None);
             }
         }
-
-        // do switcheroos if required
-        if self.child(!right).child_safe(right).is_red() {
-            self.inner_switcheroo(!right);
-            was_red = true;
-        } else if self.child(!right).child_safe(!right).is_red() {
-            self.outer_switcheroo(!right);
-            was_red = true;
+        let mut parent = NIL;
+        let mut right = false;
+        let mut cur = self.root;
+        while cur != NIL {
+            let n = self.node_mut(cur);
+            right = match cmp(&n.value, &new_v).ok_or(CompareError)? {
+                // useful if used like a map
+                Equal if replace => {
+                    let old = m_replace(&mut n.value, new_v);
+                    if self.augment.is_some() {
+                        self.resize_up(cur);
+                    }
+                    return Ok(Some(old));
+                }
+                Equal => return Ok(Some(new_v)),
+                Greater => false,
+                Less => true,
+            };
+            parent = cur;
+            cur = self.child(cur, right);
         }
+        self.insert_at(parent, right, new_v);
+        Ok(None)
+    }
 
-        // recolour appropriately
-        if was_red {
-            if self.colour() != self.child(right).colour() {
-                self.swap_colour();
-            }
-            self.child(!right).black();
-            self.child(right).black();
-            self.child(right).child_safe(right).black();
-            false
+    // attaches a new node holding the value to the `right`
+    // side of parent, which must be a leaf, or as the root
+    // if parent is NIL; returns the index of the new node
+    pub fn insert_at(&mut self, parent: Index, right: bool, new_v: T) -> Index {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("insert").entered();
+        let at = self.alloc(new_v);
+        if parent == NIL {
+            self.set_root(at);
+            self.last = at;
         } else {
-            self.child(right).black();
-            self.child(!right).red();
-            if self.is_red() {
-                self.black();
-                false
-            } else {
-                self.double_black();
-                true
+            self.set_child(parent, right, at);
+            if right && parent == self.last {
+                self.last = at;
             }
         }
+        self.resize_up(at);
+        self.insert_fixup(at);
+        #[cfg(feature = "debug_invariants")]
+        self.verify();
+        at
     }
 
-    // either swap the left-most right descendant, or just
-    // swap the immediate left child if the right child
-    // is a leaf
-    fn swap_innermost_descendant(&mut self) -> Removal<T> {
-        let mut tmp = Leaf(Black);
-        let mut doubled = false;
-        if !self.get_right().is_leaf() {
-            let mut innermost = self.get_right_mut();
-            while !innermost.get_left().is_leaf() {
-                innermost = innermost.get_left_mut();
+    // places the value straight before the value at `next`,
+    // or after the greatest value if `next` is NIL; returns
+    // the index of the new node
+    #[cfg(feature = "set")]
+    pub fn insert_before(&mut self, next: Index, new_v: T) -> Index {
+        if next == NIL {
+            let last = self.last();
+            return self.insert_at(last, true, new_v);
+        }
+        match self.get_left(next) {
+            NIL => self.insert_at(next, false, new_v),
+            left => {
+                let prev = self.outermost(left, true);
+                self.insert_at(prev, true, new_v)
             }
-            m_swap(&mut tmp, innermost.get_right_mut());
-            if innermost.is_black() {
-                if tmp.is_black() {
-                    tmp.double_black();
-                    doubled = true;
-                } else {
-                    tmp.black()
-                }
+        }
+    }
+
+    // finds the node holding a value equal to val, or else
+    // the place it would be inserted as (parent, right)
+    #[cfg(any(feature = "set", feature = "queue"))]
+    pub fn locate<K, P>(&self, val: &K, cmp: &P) -> Result<Index, (Index, bool)>
+    where
+        P: Fn(&K, &T) -> std::cmp::Ordering,
+    {
+        let mut parent = NIL;
+        let mut right = false;
+        let mut cur = self.root;
+        while cur != NIL {
+            let n = self.node(cur);
+            right = match cmp(val, &n.value) {
+                Equal => return Ok(cur),
+                Less => false,
+                Greater => true,
+            };
+            parent = cur;
+            cur = self.child(cur, right);
+        }
+        Err((parent, right))
+    }
+
+    // https://www.usna.edu/Users/cs/crabbe/SI321/current/red-black/red-black.html
+    // restores the red-black properties after the child on the
+    // `right` side of `parent` (possibly a leaf) has been left one
+    // black node short by a removal, working up the tree for as
+    // long as the shortfall propogates
+    fn remove_fixup(&mut self, mut parent: Index, mut right: bool) {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("rebalance").entered();
+        while parent != NIL {
+            // unique case, after which the parent is red
+            // so this can't propogate further
+            if self.is_red(self.child(parent, !right)) {
+                let top = self.outer_switcheroo(parent, !right);
+                #[cfg(feature = "tracing")]
+                tracing::trace!(node = top, "rotate");
+                self.black(top);
+                self.red(parent);
             }
-            m_swap(&mut tmp, innermost);
-            m_swap(&mut self.innards().value, &mut tmp.innards().value);
-        } else {
-            m_swap(self.get_left_mut(), &mut tmp);
-            if self.is_black() {
-                if tmp.is_black() {
-                    tmp.double_black();
-                    doubled = true;
-                } else {
-                    tmp.black();
+
+            // do switcheroos if required
+            let sibling = self.child(parent, !right);
+            let top = if self.is_red(self.child(sibling, right)) {
+                self.inner_switcheroo(parent, !right)
+            } else if self.is_red(self.child(sibling, !right)) {
+                self.outer_switcheroo(parent, !right)
+            } else {
+                // recolour appropriately
+                self.red(sibling);
+                #[cfg(feature = "tracing")]
+                tracing::trace!(node = sibling, "recolour");
+                if self.is_red(parent) {
+                    self.black(parent);
+                    return;
                 }
+                let grand = self.get_parent(parent);
+                right = self.get_right(grand) == parent;
+                parent = grand;
+                continue;
+            };
+
+            #[cfg(feature = "tracing")]
+            tracing::trace!(node = top, "rotate");
+            // recolour appropriately
+            if self.colour(top) != self.colour(parent) {
+                self.swap_colour(top);
             }
-            m_swap(&mut tmp, self);
+            self.black(self.child(top, !right));
+            self.black(parent);
+            return;
+        }
+    }
+
+    // removes the node `at` from the tree, replacing it with
+    // its in-order successor if it has two children, or with
+    // its only child otherwise
+    pub fn remove_at(&mut self, at: Index) -> T {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("remove").entered();
+        if at == self.last {
+            self.last = NIL;
         }
-        if doubled {
-            Doubled(tmp.gut().value)
+        let left = self.get_left(at);
+        let right = self.get_right(at);
+        // the colour lost from the tree, the node that takes
+        // the unlinked node's place, and that node's new parent
+        // and side
+        let (lost, replacement, parent, side) = if left == NIL || right == NIL {
+            let replacement = if left == NIL { right } else { left };
+            let parent = self.get_parent(at);
+            let side = self.get_right(parent) == at;
+            self.transplant(at, replacement);
+            (self.colour(at), replacement, parent, side)
         } else {
-            Removed(tmp.gut().value)
+            let succ = self.outermost(right, false);
+            let replacement = self.get_right(succ);
+            let (parent, side) = if succ == right {
+                (succ, true)
+            } else {
+                let parent = self.get_parent(succ);
+                self.transplant(succ, replacement);
+                self.set_child(succ, true, right);
+                (parent, false)
+            };
+            self.transplant(at, succ);
+            self.set_child(succ, false, left);
+            let lost = self.colour(succ);
+            let colour = self.colour(at);
+            self.node_mut(succ).set_colour(colour);
+            (lost, replacement, parent, side)
+        };
+        self.resize_up(parent);
+        if lost == Black {
+            if self.is_red(replacement) {
+                self.black(replacement);
+            } else {
+                self.remove_fixup(parent, side);
+            }
         }
+        let v = self.release(at);
+        #[cfg(feature = "debug_invariants")]
+        self.verify();
+        v
     }
 
-    fn bring_double_up_root(&mut self) -> bool {
-        if self.get_right().is_double_black() {
-            self.deletion_switcheroo(true)
+    pub fn pop(&mut self, back: bool) -> Option<T> {
+        let at = self.outermost(self.root, back);
+        if at == NIL {
+            None
         } else {
-            self.get_right_mut().bring_double_up()
+            Some(self.remove_at(at))
         }
     }
-    fn bring_double_up(&mut self) -> bool {
-        if self.get_left().is_double_black() {
-            self.deletion_switcheroo(false)
+
+    // as with insertion, returns the removed value if found
+    pub fn remove<K, P>(&mut self, val: &K, cmp: &P) -> Option<T>
+    where
+        P: Fn(&K, &T) -> std::cmp::Ordering,
+    {
+        let at = self.find(val, cmp);
+        if at == NIL {
+            None
         } else {
-            self.get_left_mut().bring_double_up()
+            Some(self.remove_at(at))
         }
     }
 
-    fn remove_result_step(&mut self, res: Removal<T>, right: bool) -> Removal<T> {
-        match res {
-            Match => self.swap_innermost_descendant(),
-            Doubled(n) => {
-                let doubled = if self.child(right).is_double_black()
-                    || self.child(right).bring_double_up_root()
-                {
-                    self.deletion_switcheroo(right)
-                } else {
-                    false
-                };
-                if doubled {
-                    Doubled(n)
-                } else {
-                    Removed(n)
-                }
+    pub fn find<K, P>(&self, val: &K, cmp: &P) -> Index
+    where
+        P: Fn(&K, &T) -> std::cmp::Ordering,
+    {
+        match self.try_find(val, &|l: &K, r: &T| Some(cmp(l, r))) {
+            Ok(at) => at,
+            Err(_) => unreachable!(),
+        }
+    }
+
+    fn try_find<K, P>(&self, val: &K, cmp: &P) -> Result<Index, CompareError>
+    where
+        P: Fn(&K, &T) -> Option<std::cmp::Ordering>,
+    {
+        let mut cur = self.root;
+        while cur != NIL {
+            let n = self.node(cur);
+            match cmp(val, &n.value).ok_or(CompareError)? {
+                Equal => break,
+                Less => cur = n.l_child,
+                Greater => cur = n.r_child,
             }
-            Removed(n) => Removed(n),
-            NotFound => NotFound,
         }
+        Ok(cur)
     }
 
-    fn remove_op<K, P>(&mut self, val: &K, cmp: &P) -> Removal<T>
+    pub fn get<K, P>(&self, val: &K, cmp: &P) -> Option<&T>
     where
         P: Fn(&K, &T) -> std::cmp::Ordering,
     {
-        match self {
-            Internal(n) => {
-                let order = cmp(val, &n.value);
-                let (res, right) = match order {
-                    Equal => (Match, true),
-                    Less => (n.l_child.remove_op(val, cmp), false),
-                    Greater => (n.r_child.remove_op(val, cmp), true),
-                };
-                self.remove_result_step(res, right)
+        self.value(self.find(val, cmp))
+    }
+
+    #[cfg(feature = "set")]
+    pub fn try_get<K, P>(&self, val: &K, cmp: &P) -> Result<Option<&T>, CompareError>
+    where
+        P: Fn(&K, &T) -> Option<std::cmp::Ordering>,
+    {
+        Ok(self.value(self.try_find(val, cmp)?))
+    }
+
+    #[cfg(feature = "map")]
+    pub fn get_mut<K, P>(&mut self, val: &K, cmp: &P) -> Option<&mut T>
+    where
+        P: Fn(&K, &T) -> std::cmp::Ordering,
+    {
+        self.value_mut(self.find(val, cmp))
+    }
+
+    pub fn peek(&self, back: bool) -> Option<&T> {
+        self.value(self.outermost(self.root, back))
+    }
+
+    // removes the values keep returns false for, visiting them
+    // in order and leaving the nodes of the rest where they are;
+    // returns the number of values removed
+    #[cfg(any(feature = "set", feature = "queue"))]
+    pub fn retain<F>(&mut self, mut keep: F) -> usize
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let mut removed = 0;
+        let mut cur = self.first();
+        while cur != NIL {
+            // the successor's node is unaffected by the removal
+            let next = self.step(cur, true);
+            if !keep(&mut self.node_mut(cur).value) {
+                self.remove_at(cur);
+                removed += 1;
             }
-            Leaf(_) => NotFound,
+            cur = next;
         }
+        removed
     }
 
-    fn pop_op(&mut self, back: bool) -> Removal<T> {
-        let mut cur = self;
-        while !cur.is_leaf() {
-            if cur.child(back).is_leaf() {
-                return cur.remove_result_step(Match, true);
+    // the node holding the nth smallest value, or NIL
+    // if there are no more than n values
+    #[cfg(any(feature = "set", feature = "queue"))]
+    pub fn nth(&self, mut n: usize) -> Index {
+        let mut cur = self.root;
+        while cur != NIL {
+            let left = self.size(self.get_left(cur));
+            if n < left {
+                cur = self.get_left(cur);
+            } else if n == left {
+                break;
             } else {
-                cur = cur.child(back);
+                n -= left + 1;
+                cur = self.get_right(cur);
             }
         }
-        NotFound
+        cur
     }
 
-    pub fn pop(&mut self, back: bool) -> Option<T> {
-        match self.pop_op(back) {
-            NotFound => None,
-            Removed(v) => Some(v),
-            Doubled(v) => {
-                self.swap_colour();
-                Some(v)
+    // the number of values less than the one held by `at`
+    #[cfg(any(feature = "set", feature = "queue"))]
+    pub fn rank(&self, at: Index) -> usize {
+        let mut rank = self.size(self.get_left(at));
+        let mut cur = at;
+        let mut parent = self.get_parent(cur);
+        while parent != NIL {
+            if self.get_right(parent) == cur {
+                rank += self.size(self.get_left(parent)) + 1;
             }
-            // uhh, shouldn't ever happen if I've coded it right
-            _ => panic!("Returned invalid option, tree structure damaged"),
+            cur = parent;
+            parent = self.get_parent(cur);
         }
+        rank
     }
 
-    // as with insertion, this should only be called on the root
-    pub fn remove<K, P>(&mut self, val: &K, cmp: &P) -> Option<T>
+    // f gives the ordering of each value relative to the target
+    #[cfg(feature = "set")]
+    pub fn search_by<F>(&self, mut f: F) -> Option<&T>
     where
-        P: Fn(&K, &T) -> std::cmp::Ordering,
+        F: FnMut(&T) -> std::cmp::Ordering,
     {
-        match self.remove_op(val, cmp) {
-            NotFound => None,
-            Removed(v) => Some(v),
-            Doubled(v) => {
-                self.swap_colour();
-                Some(v)
+        let mut cur = self.root;
+        while cur != NIL {
+            let n = self.node(cur);
+            match f(&n.value) {
+                Equal => return Some(&n.value),
+                Less => cur = n.r_child,
+                Greater => cur = n.l_child,
             }
-            // uhh, shouldn't ever happen if I've coded it right
-            _ => panic!("Returned invalid option, tree structure damaged"),
         }
+        None
     }
 
-    pub fn get<K, P>(&self, val: &K, cmp: &P) -> Option<&T>
+    // the first value for which pred is false, given pred
+    // holds for a prefix of the values and not the rest
+    #[cfg(any(feature = "set", feature = "queue"))]
+    pub fn partition_point<P>(&self, mut pred: P) -> Index
     where
-        P: Fn(&K, &T) -> std::cmp::Ordering,
+        P: FnMut(&T) -> bool,
     {
-        let mut cur = self;
-        while !cur.is_leaf() {
-            let cur_val = cur.value();
-            let order = cmp(val, cur_val.unwrap());
-            match order {
-                Equal => return cur_val,
-                Less => cur = cur.get_left(),
-                Greater => cur = cur.get_right(),
+        let mut found = NIL;
+        let mut cur = self.root;
+        while cur != NIL {
+            let n = self.node(cur);
+            if pred(&n.value) {
+                cur = n.r_child;
+            } else {
+                found = cur;
+                cur = n.l_child;
             }
         }
-        match cur {
-            Internal(n) => Some(&n.value),
-            _ => None,
-        }
+        found
     }
 
+    // an iterator of mutable references to the values from
+    // `from` up to but not including `to`, in order
     #[cfg(feature = "map")]
-    pub fn get_mut<K, P>(&mut self, val: &K, cmp: &P) -> Option<&mut T>
+    pub fn values_mut(&mut self, from: Index, to: Index) -> ValuesMut<'_, T> {
+        let (back, remaining) = if from == NIL || from == to {
+            (NIL, 0)
+        } else if to == NIL {
+            (self.last(), self.size(self.root) - self.rank(from))
+        } else {
+            (self.step(to, false), self.rank(to) - self.rank(from))
+        };
+        let mut parts = BTreeMap::new();
+        parts.insert(0, Part::Slots(&mut self.slots[..]));
+        ValuesMut {
+            parts,
+            next: from,
+            back,
+            remaining,
+        }
+    }
+
+    // replaces every value with the result of f, called on
+    // the values in order, leaving the structure untouched
+    #[cfg(feature = "set")]
+    pub fn map_values<U, F>(self, mut f: F) -> Arena<U>
     where
-        P: Fn(&K, &T) -> std::cmp::Ordering,
+        F: FnMut(T) -> U,
     {
-        let mut cur = self;
-        while !cur.is_leaf() {
-            let cur_val = cur.value().unwrap();
-            let order = cmp(val, cur_val);
-            match order {
-                Equal => return cur.value_mut(),
-                Less => cur = cur.get_left_mut(),
-                Greater => cur = cur.get_right_mut(),
+        let mut order = Vec::with_capacity(self.slots.len());
+        let mut cur = self.first();
+        while cur != NIL {
+            order.push(cur);
+            cur = self.step(cur, true);
+        }
+        let mut values = Vec::with_capacity(self.slots.len());
+        let shells: Vec<Slot<()>> = self
+            .slots
+            .into_iter()
+            .map(|s| match s {
+                Occupied(n) => {
+                    let (shell, v) = n.with_value(());
+                    values.push(Some(v));
+                    Occupied(shell)
+                }
+                Vacant(next) => {
+                    values.push(None);
+                    Vacant(next)
+                }
+            })
+            .collect();
+        let mut mapped: Vec<Option<U>> = values.iter().map(|_| None).collect();
+        for at in order {
+            mapped[pos(at)] = Some(f(values[pos(at)].take().unwrap()));
+        }
+        Arena {
+            slots: shells
+                .into_iter()
+                .zip(mapped)
+                .map(|(s, v)| match s {
+                    Occupied(n) => Occupied(n.with_value(v.unwrap()).0),
+                    Vacant(next) => Vacant(next),
+                })
+                .collect(),
+            root: self.root,
+            free: self.free,
+            last: self.last,
+            augment: None,
+        }
+    }
+
+    // passes each value to f in order, leaving the arena empty
+    // but with the room it had; each node taken is left vacant
+    // linking to its parent, so the walk can still climb back
+    // past the nodes it has already taken
+    #[cfg(feature = "set")]
+    pub fn drain_ordered<F: FnMut(T)>(&mut self, mut f: F) {
+        let mut at = self.first();
+        while at != NIL {
+            let right = self.child(at, true);
+            let parent = self.get_parent(at);
+            if let Occupied(n) = m_replace(&mut self.slots[pos(at)], Vacant(parent)) {
+                f(n.value);
+            }
+            if right != NIL {
+                at = self.outermost(right, false);
+                continue;
+            }
+            at = parent;
+            while at != NIL {
+                match self.slots[pos(at)] {
+                    Vacant(up) => at = up,
+                    Occupied(_) => break,
+                }
             }
         }
-        match cur {
-            Internal(n) => Some(&mut n.value),
-            _ => None,
+        self.clear();
+    }
+
+    // consumes the arena, returning all values in order
+    #[cfg(any(feature = "set", feature = "queue"))]
+    pub fn into_ordered(self) -> Vec<T> {
+        let mut order = Vec::new();
+        let mut cur = self.first();
+        while cur != NIL {
+            order.push(cur);
+            cur = self.step(cur, true);
         }
+        let mut values = self
+            .slots
+            .into_iter()
+            .map(|s| match s {
+                Occupied(n) => Some(n.value),
+                Vacant(_) => None,
+            })
+            .collect::<Vec<Option<T>>>();
+        order
+            .into_iter()
+            .map(|at| values[pos(at)].take().unwrap())
+            .collect()
     }
 
-    pub fn peek(&self, back: bool) -> Option<&T> {
-        let mut cur = self;
-        while !cur.is_leaf() {
-            if !cur.peek_child(back).is_leaf() {
-                cur = cur.peek_child(back);
-            } else {
-                break;
+    // builds a balanced tree from values already in order in
+    // linear time; every level is black except the deepest one
+    // when it isn't full, which is red
+    #[cfg(any(feature = "set", feature = "queue"))]
+    pub fn from_sorted(values: Vec<T>) -> Result<Arena<T>, AllocError> {
+        let len = values.len();
+        if len >= pos(RED_BIT) {
+            return Err(AllocError::CapacityOverflow);
+        }
+        let mut arena = Arena {
+            slots: values
+                .into_iter()
+                .map(|v| Occupied(Innards::new(v)))
+                .collect(),
+            root: NIL,
+            free: NIL,
+            last: NIL,
+            augment: None,
+        };
+        let ids: Vec<Index> = (0..len).map(index).collect();
+        arena.root = arena.link_sorted(&ids, NIL, 0, red_depth(len));
+        #[cfg(feature = "debug_invariants")]
+        arena.verify();
+        Ok(arena)
+    }
+
+    // links up the nodes at ids, which hold values in order,
+    // beneath parent, returning the root of the subtree
+    #[cfg(any(feature = "set", feature = "queue"))]
+    fn link_sorted(
+        &mut self,
+        ids: &[Index],
+        parent: Index,
+        depth: usize,
+        red_depth: usize,
+    ) -> Index {
+        if ids.is_empty() {
+            return NIL;
+        }
+        let mid = ids.len() / 2;
+        let at = ids[mid];
+        let l_child = self.link_sorted(&ids[..mid], at, depth + 1, red_depth);
+        let r_child = self.link_sorted(&ids[mid + 1..], at, depth + 1, red_depth);
+        let n = self.node_mut(at);
+        n.l_child = l_child;
+        n.r_child = r_child;
+        n.parent = parent;
+        n.set_colour(if depth == red_depth { Red } else { Black });
+        self.resize(at);
+        at
+    }
+
+    // places values already in order into the arena as a
+    // balanced tree of their own, as from_sorted does, and
+    // returns its root and black height
+    #[cfg(feature = "queue")]
+    fn build<I: IntoIterator<Item = T>>(&mut self, values: I) -> (Index, usize) {
+        let ids: Vec<Index> = values.into_iter().map(|v| self.alloc(v)).collect();
+        let root = self.link_sorted(&ids, NIL, 0, red_depth(ids.len()));
+        (root, self.black_height(root))
+    }
+
+    // the number of black nodes on each path from `at`
+    // down to a leaf, counting `at` itself
+    #[cfg(feature = "queue")]
+    fn black_height(&self, mut at: Index) -> usize {
+        let mut height = 0;
+        while at != NIL {
+            height += self.is_black(at) as usize;
+            at = self.get_left(at);
+        }
+        height
+    }
+
+    // joins the detached trees rooted at `left` and `right`,
+    // of black heights `lh` and `rh`, with the detached node
+    // `mid` between them, where every value of `left` comes
+    // before mid's and every value of `right` after it;
+    // returns the root and black height of the result. Takes
+    // time in proportion to the difference in black heights
+    #[cfg(feature = "queue")]
+    fn join(
+        &mut self,
+        left: Index,
+        mut lh: usize,
+        mid: Index,
+        right: Index,
+        mut rh: usize,
+    ) -> (Index, usize) {
+        // a red root can always be made black
+        if self.is_red(left) {
+            self.black(left);
+            lh += 1;
+        }
+        if self.is_red(right) {
+            self.black(right);
+            rh += 1;
+        }
+        if lh == rh {
+            self.set_child(mid, false, left);
+            self.set_child(mid, true, right);
+            self.node_mut(mid).parent = NIL;
+            self.black(mid);
+            self.resize(mid);
+            return (mid, lh + 1);
+        }
+        // mid goes down the facing side of the taller tree as
+        // a red node, above the first black node no taller than
+        // the shorter tree, which becomes mid's other child
+        let side = lh > rh;
+        let (tall, mut height, short, sh) = if side {
+            (left, lh, right, rh)
+        } else {
+            (right, rh, left, lh)
+        };
+        let mut parent = NIL;
+        let mut cur = tall;
+        while self.is_red(cur) || height > sh {
+            height -= self.is_black(cur) as usize;
+            parent = cur;
+            cur = self.child(cur, side);
+        }
+        self.set_child(parent, side, mid);
+        self.set_child(mid, !side, cur);
+        self.set_child(mid, side, short);
+        self.red(mid);
+        self.resize_up(mid);
+        // the fixup works on the tree at the root
+        self.root = tall;
+        let grew = self.insert_fixup(mid);
+        (self.root, lh.max(rh) + grew as usize)
+    }
+
+    // splits the detached tree rooted at path[0], of black
+    // height h, into the trees of the nodes before path's
+    // last node and of the rest, where path leads from the
+    // root down to that node; returns the root and black
+    // height of each
+    #[cfg(feature = "queue")]
+    fn split_path(&mut self, path: &[Index], h: usize) -> ((Index, usize), (Index, usize)) {
+        let at = path[0];
+        let (left, right) = (self.get_left(at), self.get_right(at));
+        let ch = h - self.is_black(at) as usize;
+        for child in [left, right].iter().copied() {
+            if child != NIL {
+                self.node_mut(child).parent = NIL;
             }
         }
-        match cur {
-            Internal(n) => Some(&n.value),
-            _ => None,
+        match path.get(1) {
+            // the first node of the split goes to the right
+            // along with everything after it
+            None => ((left, ch), self.join(NIL, 0, at, right, ch)),
+            Some(&next) if next == left => {
+                let (before, after) = self.split_path(&path[1..], ch);
+                (before, self.join(after.0, after.1, at, right, ch))
+            }
+            Some(_) => {
+                let (before, after) = self.split_path(&path[1..], ch);
+                (self.join(left, ch, at, before.0, before.1), after)
+            }
+        }
+    }
+
+    // moves the values of the detached tree rooted at
+    // `at` out of the arena, in order
+    #[cfg(feature = "queue")]
+    fn extract(&mut self, at: Index) -> Vec<T> {
+        let mut order = Vec::with_capacity(self.size(at));
+        let mut cur = self.outermost(at, false);
+        while cur != NIL {
+            order.push(cur);
+            cur = self.step(cur, true);
+        }
+        order.into_iter().map(|at| self.release(at)).collect()
+    }
+
+    // splits the tree before the node `first`, leaving the
+    // nodes before it here and returning an arena holding it
+    // and the nodes after it. The tree is cut by joining the
+    // subtrees hanging off the path down to `first` in
+    // O(log n) time, and then the smaller of the two parts is
+    // moved into an arena of its own in linear time
+    #[cfg(feature = "queue")]
+    pub fn split_off(&mut self, first: Index) -> Arena<T> {
+        let mut other = Arena::new();
+        other.augment = self.augment;
+        if first == NIL {
+            return other;
+        }
+        let mut path = vec![first];
+        while let Some(&at) = path.last().filter(|&&at| at != self.root) {
+            path.push(self.get_parent(at));
+        }
+        path.reverse();
+        let height = self.black_height(self.root);
+        let ((before, _), (after, _)) = self.split_path(&path, height);
+        self.black(before);
+        self.black(after);
+        self.last = NIL;
+        let (kept, moved) = if self.size(after) <= self.size(before) {
+            (before, after)
+        } else {
+            (after, before)
+        };
+        self.set_root(kept);
+        let values = self.extract(moved);
+        let (root, _) = other.build(values);
+        other.set_root(root);
+        if moved == before {
+            std::mem::swap(self, &mut other);
+        }
+        #[cfg(feature = "debug_invariants")]
+        {
+            self.verify();
+            other.verify();
+        }
+        other
+    }
+
+    // moves the nodes of other into this tree, where all of
+    // them come after (after=true) or before (after=false)
+    // those already here. They are built into a tree of their
+    // own, which is joined to this one in O(m + log n) time
+    // for m values moved
+    #[cfg(feature = "queue")]
+    pub fn append(&mut self, other: Arena<T>, after: bool) {
+        let mut values = other.into_ordered();
+        if values.is_empty() {
+            return;
+        }
+        let mid = if after {
+            values.remove(0)
+        } else {
+            values.pop().unwrap()
+        };
+        let mid = self.alloc(mid);
+        let (rest, rh) = self.build(values);
+        let (root, h) = (self.root, self.black_height(self.root));
+        let (root, _) = if after {
+            self.join(root, h, mid, rest, rh)
+        } else {
+            self.join(rest, rh, mid, root, h)
+        };
+        self.set_root(root);
+        self.last = NIL;
+        #[cfg(feature = "debug_invariants")]
+        self.verify();
+    }
+
+    // the tree drawn level by level, for debugging
+    pub fn levels(&self) -> Vec<String>
+    where
+        T: std::fmt::Debug,
+    {
+        let mut levels = Vec::new();
+        write_to_level(self, self.root, "".to_string(), 0, &mut levels);
+        levels
+    }
+
+    // panics, drawing the shape of the tree, unless the
+    // links, sizes and colours of every node hold up
+    #[cfg(feature = "debug_invariants")]
+    pub fn verify(&self) {
+        let res = if self.is_red(self.root) {
+            Err(format!("red root {}", self.root))
+        } else if self.last != NIL && self.last != self.outermost(self.root, true) {
+            Err(format!("node {} is not the last", self.last))
+        } else {
+            self.verify_below(self.root, NIL).map(|_| ())
+        };
+        if let Err(e) = res {
+            panic!(
+                "red-black invariant violated: {}\n{}",
+                e,
+                self.shape().join("\n")
+            );
+        }
+    }
+
+    // returns the black height of the subtree at `at`
+    #[cfg(feature = "debug_invariants")]
+    fn verify_below(&self, at: Index, parent: Index) -> Result<usize, String> {
+        if at == NIL {
+            return Ok(1);
+        }
+        let (left, right) = (self.get_left(at), self.get_right(at));
+        if self.get_parent(at) != parent {
+            return Err(format!("node {} not linked to its parent {}", at, parent));
+        }
+        if self.is_red(at) && (self.is_red(left) || self.is_red(right)) {
+            return Err(format!("red node {} has a red child", at));
+        }
+        let l_height = self.verify_below(left, at)?;
+        let r_height = self.verify_below(right, at)?;
+        if l_height != r_height {
+            return Err(format!("unequal black heights beneath node {}", at));
+        }
+        if self.size(at) != 1 + self.size(left) + self.size(right) {
+            return Err(format!("node {} has the wrong size", at));
+        }
+        Ok(l_height + self.is_black(at) as usize)
+    }
+
+    // the tree drawn level by level as node:colour/size,
+    // for when the values themselves can't be shown
+    #[cfg(feature = "debug_invariants")]
+    pub fn shape(&self) -> Vec<String> {
+        let mut levels = Vec::new();
+        let mut level = vec![self.root];
+        while level.iter().any(|&at| at != NIL) {
+            let mut below = Vec::new();
+            let entries: Vec<String> = level
+                .iter()
+                .map(|&at| {
+                    if at == NIL {
+                        return "___".to_string();
+                    }
+                    below.push(self.get_left(at));
+                    below.push(self.get_right(at));
+                    format!("{}:{}/{}", at, self.colour(at), self.size(at))
+                })
+                .collect();
+            levels.push(entries.join(" "));
+            level = below;
+        }
+        levels
+    }
+}
+
+// a run of the slots of an arena iterated over mutably, or
+// one whose value has been handed out, keeping the links it
+// had so that the walk can still pass through it
+#[cfg(feature = "map")]
+enum Part<'a, T> {
+    Slots(&'a mut [Slot<T>]),
+    Taken { parent: Index, left: Index, right: Index },
+}
+
+// walks the values in order from either end as the shared
+// iterators do, splitting the slots apart as it hands each
+// value out so that none is borrowed twice
+#[cfg(feature = "map")]
+pub struct ValuesMut<'a, T> {
+    // keyed by the position of the first slot in each
+    parts: BTreeMap<usize, Part<'a, T>>,
+    next: Index,
+    back: Index,
+    remaining: usize,
+}
+
+#[cfg(feature = "map")]
+impl<'a, T> ValuesMut<'a, T> {
+    fn part(&self, at: Index) -> (&usize, &Part<'a, T>) {
+        match self.parts.range(..=pos(at)).next_back() {
+            Some(part) => part,
+            None => panic!("Attempted to access a node outside the arena"),
+        }
+    }
+
+    fn node(&self, at: Index) -> &Innards<T> {
+        match self.part(at) {
+            (start, Part::Slots(slots)) => match &slots[pos(at) - start] {
+                Occupied(n) => n,
+                Vacant(_) => panic!("Attempted to access a vacant node"),
+            },
+            _ => panic!("Attempted to access a node already handed out"),
+        }
+    }
+
+    fn child(&self, at: Index, right: bool) -> Index {
+        match self.part(at) {
+            (_, Part::Taken { left, right: r, .. }) => {
+                if right {
+                    *r
+                } else {
+                    *left
+                }
+            }
+            _ => {
+                let n = self.node(at);
+                if right {
+                    n.r_child
+                } else {
+                    n.l_child
+                }
+            }
+        }
+    }
+
+    fn parent(&self, at: Index) -> Index {
+        match self.part(at) {
+            (_, Part::Taken { parent, .. }) => *parent,
+            _ => self.node(at).parent,
+        }
+    }
+
+    // as Arena::step, following the links kept for the
+    // nodes whose values have already been handed out
+    fn step(&self, at: Index, forward: bool) -> Index {
+        let mut cur = self.child(at, forward);
+        if cur != NIL {
+            let mut next = self.child(cur, !forward);
+            while next != NIL {
+                cur = next;
+                next = self.child(cur, !forward);
+            }
+            return cur;
+        }
+        cur = at;
+        let mut parent = self.parent(cur);
+        while parent != NIL && self.child(parent, forward) == cur {
+            cur = parent;
+            parent = self.parent(cur);
+        }
+        parent
+    }
+
+    // splits the slot for `at` out of its run, leaving
+    // its links behind, and hands out its value
+    fn take(&mut self, at: Index) -> &'a mut T {
+        let start = *self.part(at).0;
+        let at = pos(at);
+        let slots = match self.parts.remove(&start) {
+            Some(Part::Slots(slots)) => slots,
+            _ => panic!("Attempted to access a node already handed out"),
+        };
+        let (head, rest) = slots.split_at_mut(at - start);
+        let (slot, tail) = rest.split_at_mut(1);
+        if !head.is_empty() {
+            self.parts.insert(start, Part::Slots(head));
+        }
+        if !tail.is_empty() {
+            self.parts.insert(at + 1, Part::Slots(tail));
+        }
+        match slot {
+            [Occupied(n)] => {
+                let Innards {
+                    value,
+                    parent,
+                    l_child,
+                    r_child,
+                    ..
+                } = n;
+                let taken = Part::Taken {
+                    parent: *parent,
+                    left: *l_child,
+                    right: *r_child,
+                };
+                self.parts.insert(at, taken);
+                value
+            }
+            _ => panic!("Attempted to access a vacant node"),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.remaining
+    }
+
+    // passes f each value not yet handed out, in order
+    pub fn for_each_remaining<F: FnMut(&T)>(&self, mut f: F) {
+        let mut at = self.next;
+        for i in 0..self.remaining {
+            if i > 0 {
+                at = self.step(at, true);
+            }
+            f(&self.node(at).value);
+        }
+    }
+}
+
+#[cfg(feature = "map")]
+impl<'a, T> Iterator for ValuesMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<&'a mut T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let at = self.next;
+        let value = self.take(at);
+        self.remaining -= 1;
+        if self.remaining > 0 {
+            self.next = self.step(at, true);
+        }
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+#[cfg(feature = "map")]
+impl<'a, T> DoubleEndedIterator for ValuesMut<'a, T> {
+    fn next_back(&mut self) -> Option<&'a mut T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let at = self.back;
+        let value = self.take(at);
+        self.remaining -= 1;
+        if self.remaining > 0 {
+            self.back = self.step(at, false);
+        }
+        Some(value)
+    }
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Arena::new()
+    }
+}
+
+// views used for testing, allowing the tests to walk
+// (and mess with) the structure of the tree directly
+#[cfg(all(test, feature = "set", not(feature = "chunked")))]
+pub struct NodeView<'a, T> {
+    nodes: &'a Arena<T>,
+    at: Index,
+}
+
+#[cfg(all(test, feature = "set", not(feature = "chunked")))]
+impl<'a, T> NodeView<'a, T> {
+    pub fn get_left(&self) -> NodeView<'a, T> {
+        NodeView {
+            nodes: self.nodes,
+            at: self.nodes.get_left(self.at),
+        }
+    }
+    pub fn get_right(&self) -> NodeView<'a, T> {
+        NodeView {
+            nodes: self.nodes,
+            at: self.nodes.get_right(self.at),
+        }
+    }
+    pub fn value(&self) -> Option<&'a T> {
+        self.nodes.value(self.at)
+    }
+    pub fn colour(&self) -> Colour {
+        self.nodes.colour(self.at)
+    }
+}
+
+#[cfg(all(test, feature = "set", not(feature = "chunked")))]
+// the structure tests that reach for these build trees the
+// debug_invariants checks would reject
+#[cfg_attr(feature = "debug_invariants", allow(dead_code))]
+pub struct NodeViewMut<'a, T> {
+    nodes: &'a mut Arena<T>,
+    parent: Index,
+    right: bool,
+    at: Index,
+}
+
+#[cfg(all(test, feature = "set", not(feature = "chunked")))]
+#[cfg_attr(feature = "debug_invariants", allow(dead_code))]
+impl<'a, T> NodeViewMut<'a, T> {
+    pub fn get_left_mut(self) -> NodeViewMut<'a, T> {
+        let at = self.nodes.get_left(self.at);
+        NodeViewMut {
+            nodes: self.nodes,
+            parent: self.at,
+            right: false,
+            at,
+        }
+    }
+    pub fn get_right_mut(self) -> NodeViewMut<'a, T> {
+        let at = self.nodes.get_right(self.at);
+        NodeViewMut {
+            nodes: self.nodes,
+            parent: self.at,
+            right: true,
+            at,
+        }
+    }
+    pub fn swap_colour(&mut self) {
+        self.nodes.swap_colour(self.at);
+    }
+    // replaces the subtree here with a single black node
+    pub fn set_black(self, val: T) {
+        let at = self.nodes.alloc(val);
+        self.nodes.black(at);
+        if self.parent == NIL {
+            self.nodes.set_root(at);
+        } else {
+            self.nodes.set_child(self.parent, self.right, at);
+            self.nodes.resize_up(self.parent);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "set", not(feature = "chunked")))]
+impl<T> Arena<T> {
+    // the number of slots (occupied or vacant) in the arena
+    pub fn allocated(&self) -> usize {
+        self.slots.len()
+    }
+    // the number of slots the arena can hold without allocating
+    pub fn capacity(&self) -> usize {
+        self.slots.capacity()
+    }
+    pub fn view(&self) -> NodeView<'_, T> {
+        NodeView {
+            nodes: self,
+            at: self.root,
+        }
+    }
+    pub fn view_mut(&mut self) -> NodeViewMut<'_, T> {
+        let at = self.root;
+        NodeViewMut {
+            nodes: self,
+            parent: NIL,
+            right: false,
+            at,
         }
     }
 }
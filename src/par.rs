@@ -0,0 +1,94 @@
+#[cfg(feature = "map")]
+use crate::mapper::Mapper;
+#[cfg(feature = "map")]
+use crate::RBMap;
+use crate::{Comparator, NaturalOrder, RBTree};
+
+use rayon::prelude::*;
+use std::cmp::Ordering::Equal;
+
+// collapses each run of equal values in a sorted vec
+// into its last value, as inserting them in turn would
+fn keep_last<T, F>(sorted: Vec<T>, eq: F) -> Vec<T>
+where
+    F: Fn(&T, &T) -> bool,
+{
+    let mut values: Vec<T> = Vec::with_capacity(sorted.len());
+    for v in sorted {
+        match values.last_mut() {
+            Some(last) if eq(last, &v) => *last = v,
+            _ => values.push(v),
+        }
+    }
+    values
+}
+
+impl<T: PartialOrd + Clone + Send + Sync> RBTree<T> {
+    /// Creates and returns a new RBTree holding the elements
+    /// of `values`, which may be in any order. The elements
+    /// are sorted in parallel and the tree is then built in
+    /// linear time, rather than inserting each in turn. Where
+    /// `values` holds equal elements the last one is kept, as
+    /// it would be by `insert`.
+    /// # Panics:
+    /// Panics if two of the elements can't be compared
+    /// (`partial_cmp` returns None, as it does for `f64::NAN`).
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let values: Vec<u32> = (0..1000).rev().chain(0..10).collect();
+    /// let t = RBTree::par_from_slice(&values);
+    /// assert_eq!(t.len(), 1000);
+    /// assert!(t.iter().copied().eq(0..1000));
+    /// ```
+    pub fn par_from_slice(values: &[T]) -> RBTree<T> {
+        let mut sorted: Vec<T> = values.par_iter().cloned().collect();
+        // the sort is stable, so equal elements
+        // keep the order they were given in
+        sorted.par_sort_by(|l, r| NaturalOrder.compare(l, r));
+        RBTree::from_sorted_vec(
+            keep_last(sorted, |l, r| NaturalOrder.compare(l, r) == Equal),
+            NaturalOrder,
+        )
+    }
+}
+
+#[cfg(feature = "map")]
+impl<K, V> RBMap<K, V>
+where
+    K: PartialOrd + Clone + Send + Sync,
+    V: Clone + Send + Sync,
+{
+    /// Creates and returns a new RBMap holding the key-value
+    /// pairs of `pairs`, which may be in any order. The pairs
+    /// are sorted by key in parallel and the map is then built
+    /// in linear time, rather than inserting each in turn.
+    /// Where keys are repeated the last value given for them
+    /// is kept, as it would be by `insert`.
+    /// # Panics:
+    /// Panics if two of the keys can't be compared
+    /// (`partial_cmp` returns None, as it does for `f64::NAN`).
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let pairs = vec!((3, 'c'), (1, 'a'), (2, 'b'), (1, 'z'));
+    /// let map = RBMap::par_from_pairs(&pairs);
+    /// assert_eq!(map.len(), 3);
+    /// assert_eq!(map.get(&1), Some(&'z'));
+    /// assert!(map.keys().copied().eq(1..=3));
+    /// ```
+    pub fn par_from_pairs(pairs: &[(K, V)]) -> RBMap<K, V> {
+        let mut sorted: Vec<(K, V)> = pairs.par_iter().cloned().collect();
+        sorted.par_sort_by(|l, r| NaturalOrder.compare(&l.0, &r.0));
+        let entries = keep_last(sorted, |l, r| NaturalOrder.compare(&l.0, &r.0) == Equal)
+            .into_iter()
+            .map(|(k, v)| Mapper::new(k, Some(v)))
+            .collect();
+        RBMap {
+            map: RBTree::from_sorted_vec(entries, NaturalOrder),
+            observers: None,
+        }
+    }
+}
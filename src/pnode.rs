@@ -0,0 +1,404 @@
+use crate::colour::Colour::{self, Black, Red};
+use std::cmp::Ordering::{self, Equal, Greater, Less};
+use std::fmt::Debug;
+use std::slice::Iter as Steps;
+use std::sync::Arc;
+
+// nodes of the persistent trees are shared between versions
+// of a tree; every change copies the nodes it needs from the
+// root to the change that are shared with another version,
+// reuses those that aren't, and leaves everything else alone
+pub type Link<T> = Option<Arc<PNode<T>>>;
+
+#[derive(Clone)]
+pub struct PNode<T> {
+    value: T,
+    colour: Colour,
+    left: Link<T>,
+    right: Link<T>,
+}
+
+impl<T> PNode<T> {
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    #[cfg(test)]
+    pub fn colour(&self) -> Colour {
+        self.colour
+    }
+
+    #[cfg(test)]
+    pub fn left(&self) -> &Link<T> {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Link<T> {
+        &self.right
+    }
+}
+
+fn node<T>(colour: Colour, left: Link<T>, value: T, right: Link<T>) -> Link<T> {
+    Some(Arc::new(PNode {
+        value,
+        colour,
+        left,
+        right,
+    }))
+}
+
+// takes ownership of the node behind link, copying
+// it only if another version still refers to it
+fn open<T: Clone>(link: Link<T>) -> PNode<T> {
+    match link {
+        Some(n) => Arc::try_unwrap(n).unwrap_or_else(|n| (*n).clone()),
+        None => panic!("Persistent tree structure damaged"),
+    }
+}
+
+fn is_red<T>(link: &Link<T>) -> bool {
+    matches!(link, Some(n) if n.colour == Red)
+}
+
+// leaves don't count as black here
+fn is_black<T>(link: &Link<T>) -> bool {
+    matches!(link, Some(n) if n.colour == Black)
+}
+
+fn left_of<T>(link: &Link<T>) -> &Link<T> {
+    &link.as_ref().unwrap().left
+}
+
+fn right_of<T>(link: &Link<T>) -> &Link<T> {
+    &link.as_ref().unwrap().right
+}
+
+fn paint<T: Clone>(mut link: Link<T>, colour: Colour) -> Link<T> {
+    if let Some(n) = link.as_mut() {
+        if n.colour != colour {
+            Arc::make_mut(n).colour = colour;
+        }
+    }
+    link
+}
+
+// the following follow Kahrs' "Red-black trees with types"
+// (Journal of Functional Programming, 2001)
+
+// removes a red-red violation directly beneath a black node
+fn balance<T: Clone>(l: Link<T>, v: T, r: Link<T>) -> Link<T> {
+    if is_red(&l) && is_red(&r) {
+        return node(Red, paint(l, Black), v, paint(r, Black));
+    }
+    if is_red(&l) {
+        if is_red(left_of(&l)) {
+            let ln = open(l);
+            return node(
+                Red,
+                paint(ln.left, Black),
+                ln.value,
+                node(Black, ln.right, v, r),
+            );
+        }
+        if is_red(right_of(&l)) {
+            let ln = open(l);
+            let lrn = open(ln.right);
+            return node(
+                Red,
+                node(Black, ln.left, ln.value, lrn.left),
+                lrn.value,
+                node(Black, lrn.right, v, r),
+            );
+        }
+    }
+    if is_red(&r) {
+        if is_red(right_of(&r)) {
+            let rn = open(r);
+            return node(
+                Red,
+                node(Black, l, v, rn.left),
+                rn.value,
+                paint(rn.right, Black),
+            );
+        }
+        if is_red(left_of(&r)) {
+            let rn = open(r);
+            let rln = open(rn.left);
+            return node(
+                Red,
+                node(Black, l, v, rln.left),
+                rln.value,
+                node(Black, rln.right, rn.value, rn.right),
+            );
+        }
+    }
+    node(Black, l, v, r)
+}
+
+// turns a black node red, used when its subtree needs
+// to lose one black node to match a sibling's removal
+fn sub1<T: Clone>(link: Link<T>) -> Link<T> {
+    if !is_black(&link) {
+        panic!("Persistent tree structure damaged");
+    }
+    paint(link, Red)
+}
+
+// rebalances after the left subtree lost a black node
+fn bal_left<T: Clone>(l: Link<T>, v: T, r: Link<T>) -> Link<T> {
+    if is_red(&l) {
+        return node(Red, paint(l, Black), v, r);
+    }
+    if is_black(&r) {
+        return balance(l, v, paint(r, Red));
+    }
+    if !is_red(&r) || !is_black(left_of(&r)) {
+        panic!("Persistent tree structure damaged");
+    }
+    let rn = open(r);
+    let rln = open(rn.left);
+    node(
+        Red,
+        node(Black, l, v, rln.left),
+        rln.value,
+        balance(rln.right, rn.value, sub1(rn.right)),
+    )
+}
+
+// rebalances after the right subtree lost a black node
+fn bal_right<T: Clone>(l: Link<T>, v: T, r: Link<T>) -> Link<T> {
+    if is_red(&r) {
+        return node(Red, l, v, paint(r, Black));
+    }
+    if is_black(&l) {
+        return balance(paint(l, Red), v, r);
+    }
+    if !is_red(&l) || !is_black(right_of(&l)) {
+        panic!("Persistent tree structure damaged");
+    }
+    let ln = open(l);
+    let lrn = open(ln.right);
+    node(
+        Red,
+        balance(sub1(ln.left), ln.value, lrn.left),
+        lrn.value,
+        node(Black, lrn.right, v, r),
+    )
+}
+
+// joins two subtrees whose values are all ordered before/after
+// one another and which have the same black height
+fn app<T: Clone>(a: Link<T>, b: Link<T>) -> Link<T> {
+    if a.is_none() {
+        return b;
+    }
+    if b.is_none() {
+        return a;
+    }
+    if is_red(&a) && is_red(&b) {
+        let (an, bn) = (open(a), open(b));
+        let bc = app(an.right, bn.left);
+        if is_red(&bc) {
+            let bcn = open(bc);
+            node(
+                Red,
+                node(Red, an.left, an.value, bcn.left),
+                bcn.value,
+                node(Red, bcn.right, bn.value, bn.right),
+            )
+        } else {
+            node(Red, an.left, an.value, node(Red, bc, bn.value, bn.right))
+        }
+    } else if is_black(&a) && is_black(&b) {
+        let (an, bn) = (open(a), open(b));
+        let bc = app(an.right, bn.left);
+        if is_red(&bc) {
+            let bcn = open(bc);
+            node(
+                Red,
+                node(Black, an.left, an.value, bcn.left),
+                bcn.value,
+                node(Black, bcn.right, bn.value, bn.right),
+            )
+        } else {
+            bal_left(an.left, an.value, node(Black, bc, bn.value, bn.right))
+        }
+    } else if is_red(&b) {
+        let bn = open(b);
+        node(Red, app(a, bn.left), bn.value, bn.right)
+    } else {
+        let an = open(a);
+        node(Red, an.left, an.value, app(an.right, b))
+    }
+}
+
+// the next step of a path found by `path`
+fn step(steps: &mut Steps<'_, Ordering>) -> Ordering {
+    match steps.next() {
+        Some(o) => *o,
+        None => panic!("Persistent tree structure damaged"),
+    }
+}
+
+fn ins<T: Clone>(link: Link<T>, v: T, steps: &mut Steps<'_, Ordering>) -> (Link<T>, Option<T>) {
+    if link.is_none() {
+        return (node(Red, None, v, None), None);
+    }
+    let n = open(link);
+    match step(steps) {
+        Equal => (node(n.colour, n.left, v, n.right), Some(n.value)),
+        Less => {
+            let (l, old) = ins(n.left, v, steps);
+            if n.colour == Black {
+                (balance(l, n.value, n.right), old)
+            } else {
+                (node(Red, l, n.value, n.right), old)
+            }
+        }
+        Greater => {
+            let (r, old) = ins(n.right, v, steps);
+            if n.colour == Black {
+                (balance(n.left, n.value, r), old)
+            } else {
+                (node(Red, n.left, n.value, r), old)
+            }
+        }
+    }
+}
+
+// unlike ins, del assumes the value is present
+fn del<T: Clone>(link: Link<T>, steps: &mut Steps<'_, Ordering>) -> (Link<T>, T) {
+    let n = open(link);
+    match step(steps) {
+        Less => {
+            let black = is_black(&n.left);
+            let (l, removed) = del(n.left, steps);
+            if black {
+                (bal_left(l, n.value, n.right), removed)
+            } else {
+                (node(Red, l, n.value, n.right), removed)
+            }
+        }
+        Greater => {
+            let black = is_black(&n.right);
+            let (r, removed) = del(n.right, steps);
+            if black {
+                (bal_right(n.left, n.value, r), removed)
+            } else {
+                (node(Red, n.left, n.value, r), removed)
+            }
+        }
+        Equal => (app(n.left, n.right), n.value),
+    }
+}
+
+// the way down from the root to where val is (the last step
+// being Equal) or would go, found without touching the tree
+// so that a comparison that panics leaves it as it was
+pub fn path<T, K, P>(root: &Link<T>, val: &K, cmp: &P) -> Vec<Ordering>
+where
+    P: Fn(&K, &T) -> Ordering,
+{
+    let mut steps = Vec::new();
+    let mut cur = root;
+    while let Some(n) = cur {
+        let step = cmp(val, &n.value);
+        steps.push(step);
+        cur = match step {
+            Equal => break,
+            Less => &n.left,
+            Greater => &n.right,
+        };
+    }
+    steps
+}
+
+// follows the path to v without comparing again, returning
+// the new root and the value replaced, if any
+pub fn insert<T: Clone>(root: Link<T>, v: T, path: &[Ordering]) -> (Link<T>, Option<T>) {
+    let (root, old) = ins(root, v, &mut path.iter());
+    (paint(root, Black), old)
+}
+
+// should only be given a path ending at the value
+pub fn remove<T: Clone>(root: Link<T>, path: &[Ordering]) -> (Link<T>, T) {
+    let (root, removed) = del(root, &mut path.iter());
+    (paint(root, Black), removed)
+}
+
+pub fn get<'a, T, K, P>(root: &'a Link<T>, val: &K, cmp: &P) -> Option<&'a T>
+where
+    P: Fn(&K, &T) -> std::cmp::Ordering,
+{
+    let mut cur = root;
+    while let Some(n) = cur {
+        match cmp(val, &n.value) {
+            Equal => return Some(&n.value),
+            Less => cur = &n.left,
+            Greater => cur = &n.right,
+        }
+    }
+    None
+}
+
+// copies any shared nodes on the way down, so
+// should only be called if the value is known
+// to be present
+pub fn get_mut<'a, T, K, P>(root: &'a mut Link<T>, val: &K, cmp: &P) -> Option<&'a mut T>
+where
+    T: Clone,
+    P: Fn(&K, &T) -> std::cmp::Ordering,
+{
+    let mut cur = root;
+    while let Some(n) = cur {
+        let n = Arc::make_mut(n);
+        match cmp(val, &n.value) {
+            Equal => return Some(&mut n.value),
+            Less => cur = &mut n.left,
+            Greater => cur = &mut n.right,
+        }
+    }
+    None
+}
+
+pub fn peek<T>(root: &Link<T>, back: bool) -> Option<&T> {
+    let mut cur = root.as_ref()?;
+    loop {
+        let next = if back { &cur.right } else { &cur.left };
+        match next {
+            Some(n) => cur = n,
+            None => return Some(&cur.value),
+        }
+    }
+}
+
+// inserts into stack start and all left children
+// of start down to the leaf
+pub fn insert_left_down<'a, T>(start: &'a Link<T>, stack: &mut Vec<&'a PNode<T>>) {
+    let mut cur = start;
+    while let Some(n) = cur {
+        stack.push(n);
+        cur = &n.left;
+    }
+}
+
+pub fn write_to_level<T: Debug>(
+    cur: &Link<T>,
+    from_str: String,
+    level: usize,
+    levels: &mut Vec<String>,
+) {
+    let entry = match cur {
+        Some(n) => format!("{}{}:{:?}", from_str, n.colour, n.value),
+        None => format!("{}___", from_str),
+    };
+    if levels.len() <= level {
+        levels.push(entry);
+    } else {
+        levels[level] += &format!(" {}", entry);
+    }
+    if let Some(n) = cur {
+        write_to_level(&n.left, format!("{:?}->", n.value), level + 1, levels);
+        write_to_level(&n.right, format!("{:?}->", n.value), level + 1, levels);
+    }
+}
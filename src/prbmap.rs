@@ -0,0 +1,434 @@
+use crate::mapper::Mapper;
+use crate::prbtree;
+use crate::{PersistentRBMap, PersistentRBTree};
+
+use std::fmt::{Debug, Display, Formatter, Result};
+use std::iter::{ExactSizeIterator, FromIterator, FusedIterator};
+
+impl<K: PartialOrd + Debug, V: Debug> Debug for PersistentRBMap<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{:?}", self.map)
+    }
+}
+
+impl<K: PartialOrd + Debug, V: Debug> Display for PersistentRBMap<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{:?}", self.iter().collect::<Vec<(&K, &V)>>())
+    }
+}
+
+impl<K: PartialOrd, V> Clone for PersistentRBMap<K, V> {
+    fn clone(&self) -> Self {
+        PersistentRBMap {
+            map: self.map.clone(),
+        }
+    }
+}
+
+impl<K: PartialOrd, V> PersistentRBMap<K, V> {
+    /// Creates and returns a new, empty PersistentRBMap.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBMap;
+    ///
+    /// let map = PersistentRBMap::new();
+    /// let map2 = map.insert("Hello", "World");
+    /// assert!(map.get(&"Hello").is_none());
+    /// assert_eq!(map2.get(&"Hello").unwrap(), &"World");
+    /// ```
+    pub fn new() -> PersistentRBMap<K, V> {
+        PersistentRBMap {
+            map: PersistentRBTree::new(),
+        }
+    }
+
+    /// Returns the number of entries in this
+    /// version of the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBMap;
+    ///
+    /// let map = PersistentRBMap::new().insert(1, 'a').insert(2, 'b');
+    /// assert_eq!(map.len(), 2);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns true if this version of the map
+    /// contains no entries, false otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBMap;
+    ///
+    /// let map = PersistentRBMap::new();
+    /// assert!(map.is_empty());
+    /// assert!(!map.insert(1, 'a').is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns true if the map contains an entry
+    /// for key, false otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBMap;
+    ///
+    /// let map = PersistentRBMap::new().insert(1, 'a');
+    /// assert!(map.contains_key(&1));
+    /// assert!(!map.contains_key(&2));
+    /// ```
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns an option containing a reference
+    /// to the value associated with this key,
+    /// or none if this key does not have an
+    /// associated value.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBMap;
+    ///
+    /// let map = PersistentRBMap::new().insert("Hello", "world");
+    /// assert_eq!(map.get(&"Hello").unwrap(), &"world");
+    /// assert!(map.get(&"Foo").is_none());
+    /// ```
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.map.get(&Mapper::new(key, None)).map(|v| v.as_ref())
+    }
+
+    /// Returns an option containing a reference
+    /// to the key-value pair associated with this
+    /// key, or none if this key does not have an
+    /// associated value.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBMap;
+    ///
+    /// let map = PersistentRBMap::new().insert("Hello", "world");
+    /// assert_eq!(map.get_pair(&"Hello").unwrap(), (&"Hello", &"world"));
+    /// ```
+    pub fn get_pair(&self, key: &K) -> Option<(&K, &V)> {
+        self.map.get(&Mapper::new(key, None)).map(|v| v.pair())
+    }
+
+    /// Returns an option containing a reference to the
+    /// key-value pair with the smallest key, or None
+    /// if the map is empty.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBMap;
+    ///
+    /// let map = PersistentRBMap::new().insert(5, 'a').insert(2, 'b');
+    /// assert_eq!(map.peek_pair().unwrap(), (&2, &'b'));
+    /// ```
+    pub fn peek_pair(&self) -> Option<(&K, &V)> {
+        self.map.peek().map(|v| v.pair())
+    }
+
+    /// Returns an option containing a reference to the
+    /// key-value pair with the largest key, or None
+    /// if the map is empty.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBMap;
+    ///
+    /// let map = PersistentRBMap::new().insert(5, 'a').insert(2, 'b');
+    /// assert_eq!(map.peek_pair_back().unwrap(), (&5, &'a'));
+    /// ```
+    pub fn peek_pair_back(&self) -> Option<(&K, &V)> {
+        self.map.peek_back().map(|v| v.pair())
+    }
+
+    /// Returns an iterator over the key-value pairs
+    /// of this version of the map, in the keys'
+    /// PartialOrd order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBMap;
+    ///
+    /// let map = PersistentRBMap::new().insert(3, 'c').insert(1, 'a');
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), vec!((&1, &'a'), (&3, &'c')));
+    /// ```
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.map.iter(),
+        }
+    }
+
+    /// Returns an iterator over the keys of this
+    /// version of the map in their PartialOrd order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBMap;
+    ///
+    /// let map = PersistentRBMap::new().insert(3, 'c').insert(1, 'a');
+    /// assert_eq!(map.keys().collect::<Vec<_>>(), vec!(&1, &3));
+    /// ```
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys {
+            inner: self.map.iter(),
+        }
+    }
+
+    /// Returns an iterator over the values of this
+    /// version of the map, ordered by their keys.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBMap;
+    ///
+    /// let map = PersistentRBMap::new().insert(3, 'c').insert(1, 'a');
+    /// assert_eq!(map.values().collect::<Vec<_>>(), vec!(&'a', &'c'));
+    /// ```
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values {
+            inner: self.map.iter(),
+        }
+    }
+
+    /// Returns true if both maps are the same version,
+    /// i.e. one was cloned from the other with no changes
+    /// made since. This is a constant time check and may
+    /// return false for maps with equal contents.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBMap;
+    ///
+    /// let m1 = PersistentRBMap::new().insert(1, 'a');
+    /// let m2 = m1.clone();
+    /// assert!(m1.ptr_eq(&m2));
+    /// assert!(!m1.ptr_eq(&m1.insert(1, 'b')));
+    /// ```
+    pub fn ptr_eq(&self, other: &PersistentRBMap<K, V>) -> bool {
+        self.map.ptr_eq(&other.map)
+    }
+}
+
+impl<K: PartialOrd + Clone, V: Clone> PersistentRBMap<K, V> {
+    /// Returns a new version of the map with `key` associated
+    /// to `val`, replacing any previous association. This
+    /// version is left unchanged.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBMap;
+    ///
+    /// let m1 = PersistentRBMap::new().insert(1, 'a');
+    /// let m2 = m1.insert(1, 'b');
+    /// assert_eq!(m1.get(&1).unwrap(), &'a');
+    /// assert_eq!(m2.get(&1).unwrap(), &'b');
+    /// ```
+    pub fn insert(&self, key: K, val: V) -> PersistentRBMap<K, V> {
+        PersistentRBMap {
+            map: self.map.insert(Mapper::new(key, Some(val))),
+        }
+    }
+
+    /// Returns a new version of the map without the entry
+    /// for `key`. This version is left unchanged.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBMap;
+    ///
+    /// let m1 = PersistentRBMap::new().insert(1, 'a').insert(2, 'b');
+    /// let m2 = m1.remove(&1);
+    /// assert!(m1.contains_key(&1));
+    /// assert!(!m2.contains_key(&1));
+    /// assert_eq!(m2.len(), 1);
+    /// ```
+    pub fn remove(&self, key: &K) -> PersistentRBMap<K, V> {
+        PersistentRBMap {
+            map: self.map.remove(&Mapper::new(key, None)),
+        }
+    }
+
+    /// Associates `val` with `key` in this version of the
+    /// map, copying only the nodes on the way to the entry
+    /// that are shared with another version. Returns the
+    /// previously associated key-value pair if one existed.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBMap;
+    ///
+    /// let mut map = PersistentRBMap::new();
+    /// assert_eq!(map.insert_in_place(1, 'a'), None);
+    /// let snapshot = map.clone();
+    /// assert_eq!(map.insert_in_place(1, 'b'), Some((1, 'a')));
+    /// assert_eq!(snapshot.get(&1).unwrap(), &'a');
+    /// assert_eq!(map.get(&1).unwrap(), &'b');
+    /// ```
+    pub fn insert_in_place(&mut self, key: K, val: V) -> Option<(K, V)> {
+        self.map
+            .replace_in_place(Mapper::new(key, Some(val)))
+            .map(|v| v.consume())
+    }
+
+    /// Removes the entry for `key` from this version of the
+    /// map as `insert_in_place` does, returning the value
+    /// that was associated with it if it existed.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBMap;
+    ///
+    /// let mut map = PersistentRBMap::new().insert(2, 4);
+    /// let snapshot = map.clone();
+    /// assert_eq!(map.remove_in_place(&2).unwrap(), 4);
+    /// assert!(map.remove_in_place(&2).is_none());
+    /// assert_eq!(snapshot.get(&2).unwrap(), &4);
+    /// ```
+    pub fn remove_in_place(&mut self, key: &K) -> Option<V> {
+        self.map
+            .take_in_place(&Mapper::new(key, None))
+            .map(|v| v.consume().1)
+    }
+
+    /// Returns an option containing a mutable reference
+    /// to the value associated with this key, or none if
+    /// this key does not have an associated value. Any nodes
+    /// on the way to the entry that are shared with another
+    /// version of the map are copied first, so other
+    /// versions never see the change.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBMap;
+    ///
+    /// let mut map = PersistentRBMap::new().insert(1, 1);
+    /// let snapshot = map.clone();
+    /// *map.get_mut(&1).unwrap() += 1;
+    /// assert_eq!(map.get(&1).unwrap(), &2);
+    /// assert_eq!(snapshot.get(&1).unwrap(), &1);
+    /// ```
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.map
+            .get_mut(&Mapper::new(key, None))
+            .map(|v| v.as_mut())
+    }
+}
+
+impl<K: PartialOrd, V> Default for PersistentRBMap<K, V> {
+    fn default() -> Self {
+        PersistentRBMap::new()
+    }
+}
+
+impl<K: PartialOrd + Clone, V: Clone> FromIterator<(K, V)> for PersistentRBMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = PersistentRBMap::new();
+        for (k, v) in iter {
+            map.insert_in_place(k, v);
+        }
+        map
+    }
+}
+
+impl<'a, K: PartialOrd, V> IntoIterator for &'a PersistentRBMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a, K: PartialOrd, V> {
+    inner: prbtree::Iter<'a, Mapper<K, V>>,
+}
+
+impl<'a, K: PartialOrd, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        self.inner.next().map(|v| v.pair())
+    }
+}
+
+impl<'a, K: PartialOrd, V> ExactSizeIterator for Iter<'a, K, V> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+impl<'a, K: PartialOrd, V> FusedIterator for Iter<'a, K, V> {}
+
+impl<'a, K: PartialOrd, V> Clone for Iter<'a, K, V> {
+    fn clone(&self) -> Self {
+        Iter {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K: PartialOrd + Debug, V: Debug> Debug for Iter<'a, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+pub struct Keys<'a, K: PartialOrd, V> {
+    inner: prbtree::Iter<'a, Mapper<K, V>>,
+}
+
+impl<'a, K: PartialOrd, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.inner.next().map(|v| v.key())
+    }
+}
+
+impl<'a, K: PartialOrd, V> ExactSizeIterator for Keys<'a, K, V> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+impl<'a, K: PartialOrd, V> FusedIterator for Keys<'a, K, V> {}
+
+impl<'a, K: PartialOrd, V> Clone for Keys<'a, K, V> {
+    fn clone(&self) -> Self {
+        Keys {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K: PartialOrd + Debug, V> Debug for Keys<'a, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+pub struct Values<'a, K: PartialOrd, V> {
+    inner: prbtree::Iter<'a, Mapper<K, V>>,
+}
+
+impl<'a, K: PartialOrd, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<&'a V> {
+        self.inner.next().map(|v| v.as_ref())
+    }
+}
+
+impl<'a, K: PartialOrd, V> ExactSizeIterator for Values<'a, K, V> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+impl<'a, K: PartialOrd, V> FusedIterator for Values<'a, K, V> {}
+
+impl<'a, K: PartialOrd, V> Clone for Values<'a, K, V> {
+    fn clone(&self) -> Self {
+        Values {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K: PartialOrd, V: Debug> Debug for Values<'a, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
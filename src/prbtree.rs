@@ -0,0 +1,397 @@
+use crate::pnode::{self, insert_left_down, write_to_level, PNode};
+use crate::{Comparator, NaturalOrder, PersistentRBTree};
+
+use std::cmp::Ordering::Equal;
+use std::fmt::{Debug, Display, Formatter, Result};
+use std::iter::{ExactSizeIterator, FromIterator, FusedIterator};
+use std::sync::Arc;
+
+impl<T: PartialOrd + Debug> Debug for PersistentRBTree<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let mut levels = Vec::new();
+        write_to_level(&self.root, "".to_string(), 0, &mut levels);
+        let mut f_string = "".to_string();
+        for i in 0..levels.len() {
+            f_string += &levels[i];
+            if i != levels.len() - 1 {
+                f_string += "\n";
+            }
+        }
+        write!(f, "{}", f_string)
+    }
+}
+
+impl<T: PartialOrd + Debug> Display for PersistentRBTree<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{:?}", self.ordered())
+    }
+}
+
+// cloning only copies the root pointer, so
+// no bound on T is needed
+impl<T: PartialOrd> Clone for PersistentRBTree<T> {
+    fn clone(&self) -> Self {
+        PersistentRBTree {
+            root: self.root.clone(),
+            contained: self.contained,
+        }
+    }
+}
+
+impl<T: PartialOrd> PersistentRBTree<T> {
+    /// Creates and returns a new, empty PersistentRBTree.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBTree;
+    ///
+    /// let t = PersistentRBTree::new();
+    /// let t2 = t.insert(3);
+    /// assert!(t.is_empty());
+    /// assert_eq!(t2.len(), 1);
+    /// ```
+    pub fn new() -> PersistentRBTree<T> {
+        PersistentRBTree {
+            root: None,
+            contained: 0,
+        }
+    }
+
+    /// Returns the number of elements contained
+    /// in this version of the tree.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBTree;
+    ///
+    /// let t = PersistentRBTree::new().insert(3).insert(1);
+    /// assert_eq!(t.len(), 2);
+    /// assert_eq!(t.remove(&3).len(), 1);
+    /// assert_eq!(t.len(), 2);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.contained
+    }
+
+    /// Returns true if there are no items
+    /// present in this version of the tree,
+    /// false otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBTree;
+    ///
+    /// let t = PersistentRBTree::new();
+    /// assert!(t.is_empty());
+    /// assert!(!t.insert(3).is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns true if the tree contains the
+    /// specified item, false otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBTree;
+    ///
+    /// let t = PersistentRBTree::new().insert(2);
+    /// assert!(!t.contains(&3));
+    /// assert!(t.contains(&2));
+    /// ```
+    pub fn contains(&self, val: &T) -> bool {
+        self.get(val).is_some()
+    }
+
+    /// Returns the item specified if contained,
+    /// None otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBTree;
+    ///
+    /// let t = PersistentRBTree::new().insert(1);
+    /// assert_eq!(*t.get(&1).unwrap(), 1);
+    /// assert_eq!(t.get(&2), None);
+    /// ```
+    pub fn get<K: PartialOrd<T>>(&self, val: &K) -> Option<&T> {
+        pnode::get(&self.root, val, &|l: &K, r: &T| NaturalOrder.compare(l, r))
+    }
+
+    /// Peeks the smallest item in the tree if any
+    /// elements are present, or None otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBTree;
+    ///
+    /// let t: PersistentRBTree<_> = vec!(2, 1, 3).into_iter().collect();
+    /// assert_eq!(*t.peek().unwrap(), 1);
+    /// ```
+    pub fn peek(&self) -> Option<&T> {
+        pnode::peek(&self.root, false)
+    }
+
+    /// Peeks the largest item in the tree if any
+    /// elements are present, or None otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBTree;
+    ///
+    /// let t: PersistentRBTree<_> = vec!(2, 1, 3).into_iter().collect();
+    /// assert_eq!(*t.peek_back().unwrap(), 3);
+    /// ```
+    pub fn peek_back(&self) -> Option<&T> {
+        pnode::peek(&self.root, true)
+    }
+
+    /// Returns a vector presenting the contained
+    /// elements of the tree in their PartialOrd order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBTree;
+    ///
+    /// let t = PersistentRBTree::new().insert(3).insert(1).insert(2);
+    /// assert_eq!(t.ordered(), vec!(&1, &2, &3));
+    /// ```
+    pub fn ordered(&self) -> Vec<&T> {
+        self.iter().collect()
+    }
+
+    /// Returns an iterator over the elements
+    /// contained in this version of the tree.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBTree;
+    ///
+    /// let t = PersistentRBTree::new().insert(3).insert(1).insert(5);
+    /// assert_eq!(t.iter().collect::<Vec<&usize>>(), vec!(&1, &3, &5));
+    /// ```
+    pub fn iter(&self) -> Iter<'_, T> {
+        let mut stack = Vec::new();
+        insert_left_down(&self.root, &mut stack);
+        Iter {
+            remaining: self.len(),
+            stack,
+        }
+    }
+
+    /// Returns true if both trees are the same version,
+    /// i.e. one was cloned from the other with no changes
+    /// made since. This is a constant time check and may
+    /// return false for trees with equal contents.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBTree;
+    ///
+    /// let t1 = PersistentRBTree::new().insert(1);
+    /// let t2 = t1.clone();
+    /// let t3 = t1.insert(2);
+    /// assert!(t1.ptr_eq(&t2));
+    /// assert!(!t1.ptr_eq(&t3));
+    /// ```
+    pub fn ptr_eq(&self, other: &PersistentRBTree<T>) -> bool {
+        match (&self.root, &other.root) {
+            (Some(l), Some(r)) => Arc::ptr_eq(l, r),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: PartialOrd + Clone> PersistentRBTree<T> {
+    /// Returns a new version of the tree that also contains
+    /// `val`, replacing any equal element. This version is
+    /// left unchanged, and all subtrees not on the path to
+    /// the new element are shared between the two.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBTree;
+    ///
+    /// let t1 = PersistentRBTree::new().insert(1);
+    /// let t2 = t1.insert(2);
+    /// assert_eq!(t1.ordered(), vec!(&1));
+    /// assert_eq!(t2.ordered(), vec!(&1, &2));
+    /// ```
+    pub fn insert(&self, val: T) -> PersistentRBTree<T> {
+        let mut tree = self.clone();
+        tree.insert_in_place(val);
+        tree
+    }
+
+    /// Returns a new version of the tree without the
+    /// item matching `val`. This version is left unchanged,
+    /// and if the item was not contained the returned tree
+    /// is the same version as this one.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBTree;
+    ///
+    /// let t1 = PersistentRBTree::new().insert(1).insert(2);
+    /// let t2 = t1.remove(&2);
+    /// assert_eq!(t1.ordered(), vec!(&1, &2));
+    /// assert_eq!(t2.ordered(), vec!(&1));
+    /// assert!(t2.remove(&2).ptr_eq(&t2));
+    /// ```
+    pub fn remove<K: PartialOrd<T>>(&self, val: &K) -> PersistentRBTree<T> {
+        let mut tree = self.clone();
+        tree.take_in_place(val);
+        tree
+    }
+
+    /// Inserts a new element into this version of the tree,
+    /// replacing any equal element. Returns true if this item
+    /// was not already in the tree, and false otherwise.
+    ///
+    /// Only the nodes on the path to the new element that
+    /// are shared with another version are copied, so
+    /// mutating a tree that hasn't been cloned copies
+    /// nothing, while the first change to each path of
+    /// a fresh clone copies just that path. The place for
+    /// the element is found before anything is changed,
+    /// so a comparison that panics leaves the tree as it was.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBTree;
+    ///
+    /// let mut t1 = PersistentRBTree::new();
+    /// assert!(t1.insert_in_place(1));
+    /// let snapshot = t1.clone();
+    /// assert!(t1.insert_in_place(2));
+    /// assert!(!t1.insert_in_place(2));
+    /// assert_eq!(snapshot.ordered(), vec!(&1));
+    /// assert_eq!(t1.ordered(), vec!(&1, &2));
+    /// ```
+    pub fn insert_in_place(&mut self, val: T) -> bool {
+        self.replace_in_place(val).is_none()
+    }
+
+    /// Inserts a new element into this version of the tree
+    /// as `insert_in_place` does. Returns None if this item
+    /// was not already in the tree, and the previously
+    /// contained item otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBTree;
+    ///
+    /// let mut t = PersistentRBTree::new();
+    /// assert_eq!(t.replace_in_place("Hello".to_string()), None);
+    /// assert_eq!(t.replace_in_place("Hello".to_string()), Some("Hello".to_string()));
+    /// ```
+    pub fn replace_in_place(&mut self, val: T) -> Option<T> {
+        let path = pnode::path(&self.root, &val, &|l: &T, r: &T| NaturalOrder.compare(l, r));
+        let (root, old) = pnode::insert(self.root.take(), val, &path);
+        self.root = root;
+        if old.is_none() {
+            self.contained += 1;
+        }
+        old
+    }
+
+    /// Removes an item from this version of the tree,
+    /// copying only those nodes shared with another version
+    /// as `insert_in_place` does. Returns the matching item
+    /// if it was contained in the tree, None otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBTree;
+    ///
+    /// let mut t1 = PersistentRBTree::new().insert(4).insert(2);
+    /// let snapshot = t1.clone();
+    /// assert_eq!(t1.take_in_place(&2).unwrap(), 2);
+    /// assert_eq!(t1.take_in_place(&2), None);
+    /// assert_eq!(t1.len(), 1);
+    /// assert_eq!(snapshot.len(), 2);
+    /// ```
+    pub fn take_in_place<K: PartialOrd<T>>(&mut self, val: &K) -> Option<T> {
+        let path = pnode::path(&self.root, val, &|l: &K, r: &T| NaturalOrder.compare(l, r));
+        if path.last() != Some(&Equal) {
+            return None;
+        }
+        let (root, removed) = pnode::remove(self.root.take(), &path);
+        self.root = root;
+        self.contained -= 1;
+        Some(removed)
+    }
+
+    /// Removes an item from this version of the tree as
+    /// `take_in_place` does. Returns true if it was
+    /// contained in the tree, false otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::PersistentRBTree;
+    ///
+    /// let mut t = PersistentRBTree::new().insert(4).insert(2);
+    /// assert!(t.remove_in_place(&2));
+    /// assert!(!t.remove_in_place(&2));
+    /// ```
+    pub fn remove_in_place<K: PartialOrd<T>>(&mut self, val: &K) -> bool {
+        self.take_in_place(val).is_some()
+    }
+
+    pub(crate) fn get_mut<K: PartialOrd<T>>(&mut self, val: &K) -> Option<&mut T> {
+        self.get(val)?;
+        pnode::get_mut(&mut self.root, val, &|l: &K, r: &T| {
+            NaturalOrder.compare(l, r)
+        })
+    }
+}
+
+impl<T: PartialOrd> Default for PersistentRBTree<T> {
+    fn default() -> Self {
+        PersistentRBTree::new()
+    }
+}
+
+impl<T: PartialOrd + Clone> FromIterator<T> for PersistentRBTree<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = PersistentRBTree::new();
+        for i in iter {
+            tree.insert_in_place(i);
+        }
+        tree
+    }
+}
+
+pub struct Iter<'a, T> {
+    remaining: usize,
+    stack: Vec<&'a PNode<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let next = self.stack.pop()?;
+        insert_left_down(next.right(), &mut self.stack);
+        self.remaining -= 1;
+        Some(next.value())
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {
+    fn len(&self) -> usize {
+        self.remaining
+    }
+}
+
+impl<'a, T> FusedIterator for Iter<'a, T> {}
+
+impl<'a, T> Clone for Iter<'a, T> {
+    fn clone(&self) -> Self {
+        Iter {
+            remaining: self.remaining,
+            stack: self.stack.clone(),
+        }
+    }
+}
+
+impl<'a, T: Debug> Debug for Iter<'a, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, T: PartialOrd> IntoIterator for &'a PersistentRBTree<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
@@ -0,0 +1,347 @@
+use crate::mapper::Mapper;
+use crate::rbmap;
+use crate::{RBMap, RangeMap};
+
+use std::cmp::Ordering::Less;
+use std::fmt::{Debug, Formatter, Result};
+use std::iter::{ExactSizeIterator, FromIterator, FusedIterator};
+use std::ops::Range;
+
+impl<K: PartialOrd + Debug, V: Debug> Debug for RangeMap<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: PartialOrd, V> RangeMap<K, V> {
+    /// Creates and returns a new, empty RangeMap.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RangeMap;
+    ///
+    /// let mut map = RangeMap::new();
+    /// map.insert(0..10, "low");
+    /// assert_eq!(map.get(&5), Some(&"low"));
+    /// ```
+    pub fn new() -> RangeMap<K, V> {
+        RangeMap {
+            ranges: RBMap::new(),
+        }
+    }
+
+    /// Removes all ranges from the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RangeMap;
+    ///
+    /// let mut map = RangeMap::new();
+    /// map.insert(0..10, 'a');
+    /// map.clear();
+    /// assert!(map.is_empty());
+    /// ```
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+    }
+
+    /// Returns the number of ranges in the map, once
+    /// neighbouring ranges mapped to equal values
+    /// have been joined together.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RangeMap;
+    ///
+    /// let mut map = RangeMap::new();
+    /// map.insert(0..5, 'a');
+    /// map.insert(5..10, 'a');
+    /// map.insert(20..30, 'b');
+    /// assert_eq!(map.len(), 2);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    /// Returns true if no keys are covered
+    /// by the map, false otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RangeMap;
+    ///
+    /// let mut map = RangeMap::new();
+    /// assert!(map.is_empty());
+    /// map.insert(0..1, ());
+    /// assert!(!map.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Returns the range covering `key` and the value it
+    /// is mapped to, or None if no range covers `key`.
+    /// Takes O(log n) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RangeMap;
+    ///
+    /// let mut map = RangeMap::new();
+    /// map.insert(10..20, 'a');
+    /// assert_eq!(map.get_key_value(&15), Some((&10..&20, &'a')));
+    /// assert_eq!(map.get_key_value(&20), None);
+    /// ```
+    pub fn get_key_value(&self, key: &K) -> Option<(Range<&K>, &V)> {
+        // the ranges don't overlap, so their ends are in the same
+        // order as their starts; the first to end after `key` is
+        // the only one that might cover it
+        let covering = self
+            .ranges
+            .map
+            .partition_point(|m: &Mapper<K, (K, V)>| m.as_ref().0 <= *key)?;
+        let (start, (end, val)) = covering.pair();
+        if *start <= *key {
+            Some((start..end, val))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value of the range covering `key`,
+    /// or None if no range covers it. Takes O(log n) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RangeMap;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut owners = RangeMap::new();
+    /// owners.insert(Ipv4Addr::new(10, 0, 0, 0)..Ipv4Addr::new(11, 0, 0, 0), "private");
+    /// owners.insert(Ipv4Addr::new(8, 8, 8, 0)..Ipv4Addr::new(8, 8, 9, 0), "dns");
+    /// assert_eq!(owners.get(&Ipv4Addr::new(10, 1, 2, 3)), Some(&"private"));
+    /// assert_eq!(owners.get(&Ipv4Addr::new(8, 8, 8, 8)), Some(&"dns"));
+    /// assert_eq!(owners.get(&Ipv4Addr::new(1, 1, 1, 1)), None);
+    /// ```
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.get_key_value(key).map(|(_, val)| val)
+    }
+
+    /// Returns true if a range of the map covers `key`,
+    /// false otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RangeMap;
+    ///
+    /// let mut map = RangeMap::new();
+    /// map.insert(1.0..2.5, ());
+    /// assert!(map.contains_key(&1.0));
+    /// assert!(!map.contains_key(&2.5));
+    /// ```
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get_key_value(key).is_some()
+    }
+
+    /// Returns an iterator over the ranges of the map
+    /// and their values, in order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RangeMap;
+    ///
+    /// let mut map = RangeMap::new();
+    /// map.insert(5..8, 'b');
+    /// map.insert(0..3, 'a');
+    /// let mut ranges = map.iter();
+    /// assert_eq!(ranges.next(), Some((&0..&3, &'a')));
+    /// assert_eq!(ranges.next(), Some((&5..&8, &'b')));
+    /// assert_eq!(ranges.next(), None);
+    /// ```
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            iter: self.ranges.iter(),
+        }
+    }
+
+    // the starts of the ranges reaching `start` or
+    // beyond that begin at or before `end`, in order
+    fn starts_between(&self, start: &K, end: &K) -> Vec<K>
+    where
+        K: Clone,
+    {
+        let first = self
+            .ranges
+            .map
+            .partition_point(|m: &Mapper<K, (K, V)>| m.as_ref().0 < *start);
+        match first {
+            Some(m) => self
+                .ranges
+                .iter_from(m.key())
+                .take_while(|(s, _)| **s <= *end)
+                .map(|(s, _)| s.clone())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl<K: PartialOrd + Clone, V: PartialEq + Clone> RangeMap<K, V> {
+    /// Maps every key within `range` to `val`, replacing
+    /// whatever the keys were mapped to before. Ranges it
+    /// overlaps are cut back (or split in two) to make room,
+    /// and it is joined with any range it overlaps or meets
+    /// that is mapped to an equal value. Does nothing if
+    /// `range` is empty.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RangeMap;
+    ///
+    /// let mut map = RangeMap::new();
+    /// map.insert(0..10, 'a');
+    /// map.insert(4..6, 'b');
+    /// assert_eq!(
+    ///     map.iter().collect::<Vec<_>>(),
+    ///     vec!((&0..&4, &'a'), (&4..&6, &'b'), (&6..&10, &'a'))
+    /// );
+    ///
+    /// map.insert(4..6, 'a');
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), vec!((&0..&10, &'a')));
+    /// ```
+    pub fn insert(&mut self, range: Range<K>, val: V) {
+        if range.start.partial_cmp(&range.end) != Some(Less) {
+            return;
+        }
+        let Range { mut start, mut end } = range;
+        for s in self.starts_between(&start, &end) {
+            let (s, (e, old)) = self.ranges.remove_entry(&s).unwrap();
+            if old == val {
+                // an equal neighbour is absorbed, whether
+                // it overlaps the new range or just meets it
+                if s < start {
+                    start = s;
+                }
+                if e > end {
+                    end = e;
+                }
+                continue;
+            }
+            if e > end {
+                self.ranges.insert(end.clone(), (e, old.clone()));
+            }
+            if s < start {
+                self.ranges.insert(s, (start.clone(), old));
+            }
+        }
+        self.ranges.insert(start, (end, val));
+    }
+
+    /// Unmaps every key within `range`, cutting back
+    /// (or splitting in two) the ranges it overlaps.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RangeMap;
+    ///
+    /// let mut map = RangeMap::new();
+    /// map.insert(0..10, 'a');
+    /// map.remove(3..5);
+    /// assert_eq!(map.get(&4), None);
+    /// assert_eq!(
+    ///     map.iter().collect::<Vec<_>>(),
+    ///     vec!((&0..&3, &'a'), (&5..&10, &'a'))
+    /// );
+    /// ```
+    pub fn remove(&mut self, range: Range<K>) {
+        if range.start.partial_cmp(&range.end) != Some(Less) {
+            return;
+        }
+        let Range { start, end } = range;
+        for s in self.starts_between(&start, &end) {
+            // ranges that only meet this one are left alone
+            match self.ranges.get(&s) {
+                Some((e, _)) if *e > start && s < end => {}
+                _ => continue,
+            }
+            let (s, (e, old)) = self.ranges.remove_entry(&s).unwrap();
+            if e > end {
+                self.ranges.insert(end.clone(), (e, old.clone()));
+            }
+            if s < start {
+                self.ranges.insert(s, (start.clone(), old));
+            }
+        }
+    }
+}
+
+impl<K: PartialOrd, V> Default for RangeMap<K, V> {
+    fn default() -> Self {
+        RangeMap::new()
+    }
+}
+
+impl<K: PartialOrd, V: PartialEq> PartialEq for RangeMap<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<K: PartialOrd + Clone, V: PartialEq + Clone> FromIterator<(Range<K>, V)> for RangeMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (Range<K>, V)>>(iter: I) -> Self {
+        let mut map = RangeMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: PartialOrd + Clone, V: PartialEq + Clone> Extend<(Range<K>, V)> for RangeMap<K, V> {
+    fn extend<I: IntoIterator<Item = (Range<K>, V)>>(&mut self, iter: I) {
+        for (range, val) in iter {
+            self.insert(range, val);
+        }
+    }
+}
+
+impl<'a, K: PartialOrd, V> IntoIterator for &'a RangeMap<K, V> {
+    type Item = (Range<&'a K>, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+/// An iterator over the ranges of a RangeMap
+/// and their values, in order.
+pub struct Iter<'a, K: PartialOrd, V> {
+    iter: rbmap::Iter<'a, K, (K, V)>,
+}
+
+impl<'a, K: PartialOrd, V> Iterator for Iter<'a, K, V> {
+    type Item = (Range<&'a K>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter
+            .next()
+            .map(|(start, (end, val))| (start..end, val))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, K: PartialOrd, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter
+            .next_back()
+            .map(|(start, (end, val))| (start..end, val))
+    }
+}
+
+impl<'a, K: PartialOrd, V> ExactSizeIterator for Iter<'a, K, V> {
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+impl<'a, K: PartialOrd, V> FusedIterator for Iter<'a, K, V> {}
+
+impl<'a, K: PartialOrd, V> Clone for Iter<'a, K, V> {
+    fn clone(&self) -> Self {
+        Iter {
+            iter: self.iter.clone(),
+        }
+    }
+}
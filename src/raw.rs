@@ -0,0 +1,750 @@
+#[cfg(feature = "chunked")]
+pub(crate) use crate::chunk::Arena;
+#[cfg(all(feature = "chunked", feature = "map"))]
+pub(crate) use crate::chunk::ValuesMut;
+#[cfg(not(feature = "chunked"))]
+pub(crate) use crate::node::Arena;
+#[cfg(all(not(feature = "chunked"), feature = "map"))]
+pub(crate) use crate::node::ValuesMut;
+use crate::node::{Index, NIL};
+use crate::{AllocError, Comparator};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering::{Equal, Greater, Less};
+use std::fmt::{Debug, Formatter, Result};
+use std::iter::{ExactSizeIterator, FusedIterator};
+use std::ops::Bound::{Excluded, Included, Unbounded};
+use std::ops::RangeBounds;
+
+// the nodes, element count and ordering shared by RBTree
+// and RBQueue (and, through RBTree, the maps); the wrappers
+// decide what insert, take etc. mean for their callers but
+// leave the bookkeeping of the tree to this
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "T: Serialize",
+        deserialize = "T: Deserialize<'de>, C: Default"
+    ))
+)]
+pub(crate) struct RawTree<T, C> {
+    pub(crate) nodes: Arena<T>,
+    pub(crate) contained: usize,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) cmp: C,
+}
+
+impl<T: Debug, C> Debug for RawTree<T, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let levels = self.nodes.levels();
+        let mut f_string = "".to_string();
+        for i in 0..levels.len() {
+            f_string += &levels[i];
+            if i != levels.len() - 1 {
+                f_string += "\n";
+            }
+        }
+        write!(f, "{}", f_string)
+    }
+}
+
+impl<T, C> RawTree<T, C> {
+    pub(crate) fn new(cmp: C) -> RawTree<T, C> {
+        RawTree {
+            nodes: Arena::new(),
+            contained: 0,
+            cmp,
+        }
+    }
+
+    // replaces the contents with values already in strictly
+    // ascending order, building the tree in linear time
+    pub(crate) fn rebuild(&mut self, values: Vec<T>) {
+        self.contained = values.len();
+        self.nodes =
+            Arena::from_sorted(values).expect("Exceeded the maximum number of nodes in a tree");
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.contained
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.nodes.clear();
+        self.contained = 0;
+    }
+
+    pub(crate) fn try_reserve(&mut self, additional: usize) -> std::result::Result<(), AllocError> {
+        self.nodes.try_reserve(additional)
+    }
+
+    pub(crate) fn reserve_nodes(&mut self, n: usize) {
+        self.nodes.reserve_nodes(n);
+    }
+
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+    }
+
+    pub(crate) fn pop(&mut self, back: bool) -> Option<T> {
+        let v = self.nodes.pop(back)?;
+        self.contained -= 1;
+        Some(v)
+    }
+
+    pub(crate) fn peek(&self, back: bool) -> Option<&T> {
+        self.nodes.peek(back)
+    }
+
+    pub(crate) fn ordered(&self) -> Vec<&T> {
+        self.iter().collect()
+    }
+
+    pub(crate) fn iter(&self) -> Iter<'_, T> {
+        Iter::new(&self.nodes, self.contained)
+    }
+
+    pub(crate) fn retain<F: FnMut(&mut T) -> bool>(&mut self, keep: F) {
+        self.contained -= self.nodes.retain(keep);
+    }
+
+    // the first node in the range and the first node after it
+    pub(crate) fn range_ends<K, R>(&self, range: &R) -> (Index, Index)
+    where
+        R: RangeBounds<K>,
+        C: Comparator<K, T>,
+    {
+        let cmp = &self.cmp;
+        let before_start = |v: &T| match range.start_bound() {
+            Included(s) => cmp.compare(s, v) == Greater,
+            Excluded(s) => cmp.compare(s, v) != Less,
+            Unbounded => false,
+        };
+        let before_end = |v: &T| match range.end_bound() {
+            Included(e) => cmp.compare(e, v) != Less,
+            Excluded(e) => cmp.compare(e, v) == Greater,
+            Unbounded => true,
+        };
+        let next = self.nodes.partition_point(before_start);
+        match self.nodes.value(next) {
+            // the range is empty unless its first element
+            // comes before its end
+            Some(v) if before_end(v) => (next, self.nodes.partition_point(before_end)),
+            _ => (NIL, NIL),
+        }
+    }
+
+    pub(crate) fn range<K, R>(&self, range: R) -> Range<'_, T>
+    where
+        R: RangeBounds<K>,
+        C: Comparator<K, T>,
+    {
+        let (next, end) = self.range_ends(&range);
+        Range {
+            nodes: &self.nodes,
+            next,
+            end,
+        }
+    }
+
+    // the values at the positions within the
+    // range, in the tree's order
+    #[cfg(feature = "set")]
+    pub(crate) fn positions<R: RangeBounds<usize>>(&self, range: R) -> Range<'_, T> {
+        let start = match range.start_bound() {
+            Included(s) => *s,
+            Excluded(s) => s.saturating_add(1),
+            Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Included(e) => e.saturating_add(1),
+            Excluded(e) => *e,
+            Unbounded => self.contained,
+        };
+        let (next, end) = if start < end.min(self.contained) {
+            (self.nodes.nth(start), self.nodes.nth(end))
+        } else {
+            (NIL, NIL)
+        };
+        Range {
+            nodes: &self.nodes,
+            next,
+            end,
+        }
+    }
+
+    // inserts the value, handing back the equal
+    // value it replaced, if there was one
+    pub(crate) fn insert(&mut self, val: T) -> Option<T>
+    where
+        C: Comparator<T>,
+    {
+        let cmp = &self.cmp;
+        let old = self.nodes.insert(val, &|l: &T, r: &T| cmp.compare(l, r));
+        if old.is_none() {
+            self.contained += 1;
+        }
+        self.verify();
+        old
+    }
+
+    pub(crate) fn get<K>(&self, val: &K) -> Option<&T>
+    where
+        C: Comparator<K, T>,
+    {
+        self.nodes.get(val, &|l: &K, r: &T| self.cmp.compare(l, r))
+    }
+
+    pub(crate) fn take<K>(&mut self, val: &K) -> Option<T>
+    where
+        C: Comparator<K, T>,
+    {
+        let cmp = &self.cmp;
+        let v = self.nodes.remove(val, &|l: &K, r: &T| cmp.compare(l, r))?;
+        self.contained -= 1;
+        Some(v)
+    }
+
+    // removes the values equal to any of the keys, which
+    // come in the tree's order, walking both side by side
+    #[cfg(feature = "set")]
+    pub(crate) fn remove_sorted<K, I>(&mut self, keys: I) -> usize
+    where
+        I: IntoIterator<Item = K>,
+        C: Comparator<K, T>,
+    {
+        let mut keys = keys.into_iter().peekable();
+        let mut found = Vec::new();
+        let mut cur = self.nodes.first();
+        while cur != NIL {
+            // the rest of the tree is left alone once
+            // the keys run out
+            let key = match keys.peek() {
+                Some(key) => key,
+                None => break,
+            };
+            match self.cmp.compare(key, self.nodes.value(cur).unwrap()) {
+                Less => {
+                    keys.next();
+                }
+                Greater => cur = self.nodes.step(cur, true),
+                Equal => {
+                    found.push(cur);
+                    cur = self.nodes.step(cur, true);
+                    keys.next();
+                }
+            }
+        }
+        self.remove_found(found)
+    }
+
+    // removes the values at the given places, which come in
+    // order; they are found before any is removed, so that a
+    // panicking comparator leaves the tree as it was
+    #[cfg(feature = "set")]
+    pub(crate) fn remove_found(&mut self, found: Vec<Index>) -> usize {
+        // a removal can shift the values after it
+        // in a chunk, but not those before it
+        for at in found.iter().rev() {
+            self.nodes.remove_at(*at);
+        }
+        self.contained -= found.len();
+        found.len()
+    }
+
+    // with the "debug_invariants" feature, panics unless the
+    // values are in order and there are as many as counted;
+    // the arena checks its own structure on every change, and
+    // removals can't put the remaining values out of order
+    #[inline]
+    pub(crate) fn verify(&self)
+    where
+        C: Comparator<T>,
+    {
+        #[cfg(feature = "debug_invariants")]
+        {
+            let mut seen = 0;
+            let mut prev = None;
+            let mut cur = self.nodes.first();
+            while let Some(v) = self.nodes.value(cur) {
+                if let Some(p) = prev {
+                    if self.cmp.compare(p, v) == Greater {
+                        panic!(
+                            "ordering invariant violated: value {} out of order\n{}",
+                            seen,
+                            self.nodes.shape().join("\n")
+                        );
+                    }
+                }
+                prev = Some(v);
+                seen += 1;
+                cur = self.nodes.step(cur, true);
+            }
+            if seen != self.contained {
+                panic!(
+                    "count invariant violated: {} values counted as {}\n{}",
+                    seen,
+                    self.contained,
+                    self.nodes.shape().join("\n")
+                );
+            }
+        }
+    }
+
+    // finds the node holding the value, or else where it
+    // would be inserted
+    #[cfg(any(feature = "set", feature = "queue"))]
+    pub(crate) fn locate<K>(&self, val: &K) -> std::result::Result<Index, (Index, bool)>
+    where
+        C: Comparator<K, T>,
+    {
+        self.nodes
+            .locate(val, &|l: &K, r: &T| self.cmp.compare(l, r))
+    }
+
+    pub(crate) fn difference<'a>(&'a self, other: &'a RawTree<T, C>) -> Difference<'a, T, C> {
+        let mut iterl = self.iter();
+        let mut iterr = other.iter();
+        Difference {
+            nextl: iterl.next(),
+            nextr: iterr.next(),
+            left: iterl,
+            right: iterr,
+            cmp: &self.cmp,
+        }
+    }
+
+    #[cfg(feature = "set")]
+    pub(crate) fn symmetric_difference<'a>(
+        &'a self,
+        other: &'a RawTree<T, C>,
+    ) -> SymmetricDifference<'a, T, C> {
+        let mut iterl = self.iter();
+        let mut iterr = other.iter();
+        SymmetricDifference {
+            nextl: iterl.next(),
+            nextr: iterr.next(),
+            left: iterl,
+            right: iterr,
+            cmp: &self.cmp,
+        }
+    }
+
+    pub(crate) fn intersection<'a>(&'a self, other: &'a RawTree<T, C>) -> Intersection<'a, T, C> {
+        let mut iterl = self.iter();
+        let mut iterr = other.iter();
+        Intersection {
+            nextl: iterl.next(),
+            nextr: iterr.next(),
+            left: iterl,
+            right: iterr,
+            cmp: &self.cmp,
+        }
+    }
+
+    pub(crate) fn union<'a>(&'a self, other: &'a RawTree<T, C>) -> Union<'a, T, C> {
+        let mut iterl = self.iter();
+        let mut iterr = other.iter();
+        Union {
+            nextl: iterl.next(),
+            nextr: iterr.next(),
+            left: iterl,
+            right: iterr,
+            cmp: &self.cmp,
+        }
+    }
+}
+
+pub struct Iter<'a, T> {
+    remaining: usize,
+    nodes: &'a Arena<T>,
+    next: Index,
+    back: Index,
+}
+
+impl<'a, T> Iter<'a, T> {
+    pub(crate) fn new(nodes: &'a Arena<T>, remaining: usize) -> Iter<'a, T> {
+        Iter {
+            remaining,
+            nodes,
+            next: nodes.first(),
+            back: nodes.last(),
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let next = self.nodes.value(self.next)?;
+        self.remaining -= 1;
+        self.next = self.nodes.step(self.next, true);
+        Some(next)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+/// Provides the trait DoubleEndedIterator for Iter<'a, T>,
+/// visiting elements from the back of the queue
+/// # Example:
+/// ```
+/// # #[cfg(feature = "queue")] {
+/// use rb_tree::RBQueue;
+///
+/// let mut t = RBQueue::<i8>::default();
+/// t.extend(vec!(2, 1, 4, 3));
+///
+/// assert_eq!(t.iter().rev().collect::<Vec<_>>(), vec!(&4, &3, &2, &1));
+/// let mut iterator = t.iter();
+/// assert_eq!(iterator.next(), Some(&1));
+/// assert_eq!(iterator.next_back(), Some(&4));
+/// assert_eq!(iterator.len(), 2);
+/// # }
+/// ```
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let back = self.nodes.value(self.back)?;
+        self.remaining -= 1;
+        self.back = self.nodes.step(self.back, false);
+        Some(back)
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {
+    fn len(&self) -> usize {
+        self.remaining
+    }
+}
+
+impl<'a, T> FusedIterator for Iter<'a, T> {}
+
+impl<'a, T> Clone for Iter<'a, T> {
+    fn clone(&self) -> Self {
+        Iter {
+            remaining: self.remaining,
+            nodes: self.nodes,
+            next: self.next,
+            back: self.back,
+        }
+    }
+}
+
+impl<'a, T: Debug> Debug for Iter<'a, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+pub struct Range<'a, T> {
+    nodes: &'a Arena<T>,
+    next: Index,
+    end: Index,
+}
+
+impl<'a, T> Iterator for Range<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.next == self.end {
+            return None;
+        }
+        let next = self.nodes.value(self.next)?;
+        self.next = self.nodes.step(self.next, true);
+        Some(next)
+    }
+}
+
+impl<'a, T> FusedIterator for Range<'a, T> {}
+
+impl<'a, T> Clone for Range<'a, T> {
+    fn clone(&self) -> Self {
+        Range {
+            nodes: self.nodes,
+            next: self.next,
+            end: self.end,
+        }
+    }
+}
+
+impl<'a, T: Debug> Debug for Range<'a, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+pub struct Difference<'a, T, C> {
+    nextl: Option<&'a T>,
+    nextr: Option<&'a T>,
+    left: Iter<'a, T>,
+    right: Iter<'a, T>,
+    cmp: &'a C,
+}
+
+impl<'a, T, C: Comparator<T>> Iterator for Difference<'a, T, C> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        // select and store the next next
+        let mut res = None;
+        'left: while let Some(vl) = self.nextl {
+            self.nextl = self.left.next();
+            while let Some(vr) = self.nextr {
+                match self.cmp.compare(vl, vr) {
+                    Less => {
+                        res = Some(vl);
+                        break 'left;
+                    }
+                    Equal => {
+                        self.nextr = self.right.next();
+                        continue 'left;
+                    }
+                    Greater => {
+                        self.nextr = self.right.next();
+                    }
+                }
+            }
+            res = Some(vl);
+            break; // don't want to skip values
+        }
+
+        // return the current next value
+        res
+    }
+}
+
+impl<'a, T, C: Comparator<T>> FusedIterator for Difference<'a, T, C> {}
+
+impl<'a, T, C> Clone for Difference<'a, T, C> {
+    fn clone(&self) -> Self {
+        Difference {
+            nextl: self.nextl,
+            nextr: self.nextr,
+            left: self.left.clone(),
+            right: self.right.clone(),
+            cmp: self.cmp,
+        }
+    }
+}
+
+impl<'a, T: Debug, C: Comparator<T>> Debug for Difference<'a, T, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+#[cfg(feature = "set")]
+pub struct SymmetricDifference<'a, T, C> {
+    nextl: Option<&'a T>,
+    nextr: Option<&'a T>,
+    left: Iter<'a, T>,
+    right: Iter<'a, T>,
+    cmp: &'a C,
+}
+
+#[cfg(feature = "set")]
+impl<'a, T, C: Comparator<T>> Iterator for SymmetricDifference<'a, T, C> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        // select and store the next next
+        let mut res = None;
+        'left: while let Some(vl) = self.nextl {
+            if let Some(vr) = self.nextr {
+                match self.cmp.compare(vl, vr) {
+                    Less => {
+                        self.nextl = self.left.next();
+                        res = Some(vl);
+                        break 'left;
+                    }
+                    Equal => {
+                        self.nextl = self.left.next();
+                        self.nextr = self.right.next();
+                        continue 'left;
+                    }
+                    Greater => {
+                        self.nextr = self.right.next();
+                        res = Some(vr);
+                        break 'left;
+                    }
+                }
+            }
+
+            // don't want to skip values
+            self.nextl = self.left.next();
+            res = Some(vl);
+            break;
+        }
+        if res.is_none() {
+            res = self.nextr;
+            self.nextr = self.right.next();
+        }
+
+        // return the current next value
+        res
+    }
+}
+
+#[cfg(feature = "set")]
+impl<'a, T, C: Comparator<T>> FusedIterator for SymmetricDifference<'a, T, C> {}
+
+#[cfg(feature = "set")]
+impl<'a, T, C> Clone for SymmetricDifference<'a, T, C> {
+    fn clone(&self) -> Self {
+        SymmetricDifference {
+            nextl: self.nextl,
+            nextr: self.nextr,
+            left: self.left.clone(),
+            right: self.right.clone(),
+            cmp: self.cmp,
+        }
+    }
+}
+
+#[cfg(feature = "set")]
+impl<'a, T: Debug, C: Comparator<T>> Debug for SymmetricDifference<'a, T, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+pub struct Intersection<'a, T, C> {
+    nextl: Option<&'a T>,
+    nextr: Option<&'a T>,
+    left: Iter<'a, T>,
+    right: Iter<'a, T>,
+    cmp: &'a C,
+}
+
+impl<'a, T, C: Comparator<T>> Iterator for Intersection<'a, T, C> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        // select and store the next next
+        let mut res = None;
+        'left: while let Some(vl) = self.nextl {
+            while let Some(vr) = self.nextr {
+                match self.cmp.compare(vl, vr) {
+                    Less => {
+                        self.nextl = self.left.next();
+                        continue 'left;
+                    }
+                    Equal => {
+                        self.nextr = self.right.next();
+                        self.nextl = self.left.next();
+                        res = Some(vl);
+                        break 'left;
+                    }
+                    Greater => {
+                        self.nextr = self.right.next();
+                    }
+                }
+            }
+            break; // don't bother iterating the remaining lefts
+        }
+
+        // return the current next value
+        res
+    }
+}
+
+impl<'a, T, C: Comparator<T>> FusedIterator for Intersection<'a, T, C> {}
+
+impl<'a, T, C> Clone for Intersection<'a, T, C> {
+    fn clone(&self) -> Self {
+        Intersection {
+            nextl: self.nextl,
+            nextr: self.nextr,
+            left: self.left.clone(),
+            right: self.right.clone(),
+            cmp: self.cmp,
+        }
+    }
+}
+
+impl<'a, T: Debug, C: Comparator<T>> Debug for Intersection<'a, T, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+pub struct Union<'a, T, C> {
+    nextl: Option<&'a T>,
+    nextr: Option<&'a T>,
+    left: Iter<'a, T>,
+    right: Iter<'a, T>,
+    cmp: &'a C,
+}
+
+impl<'a, T, C: Comparator<T>> Iterator for Union<'a, T, C> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        // select and store the next next
+        let mut res = None;
+        let mut need_next = true;
+        if let Some(vl) = self.nextl {
+            if let Some(vr) = self.nextr {
+                match self.cmp.compare(vl, vr) {
+                    Less => {
+                        self.nextl = self.left.next();
+                        res = Some(vl);
+                    }
+                    Equal => {
+                        self.nextr = self.right.next();
+                        self.nextl = self.left.next();
+                        res = Some(vl);
+                    }
+                    Greater => {
+                        self.nextr = self.right.next();
+                        res = Some(vr);
+                    }
+                }
+                need_next = false;
+            }
+            if need_next {
+                self.nextl = self.left.next();
+                res = Some(vl);
+            }
+        }
+        if res.is_none() {
+            res = self.nextr;
+            self.nextr = self.right.next();
+        }
+
+        // return the current next value
+        res
+    }
+}
+
+impl<'a, T, C: Comparator<T>> FusedIterator for Union<'a, T, C> {}
+
+impl<'a, T, C> Clone for Union<'a, T, C> {
+    fn clone(&self) -> Self {
+        Union {
+            nextl: self.nextl,
+            nextr: self.nextr,
+            left: self.left.clone(),
+            right: self.right.clone(),
+            cmp: self.cmp,
+        }
+    }
+}
+
+impl<'a, T: Debug, C: Comparator<T>> Debug for Union<'a, T, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
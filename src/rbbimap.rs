@@ -0,0 +1,321 @@
+use crate::rbmap;
+use crate::{RBBiMap, RBMap};
+
+use std::borrow::Borrow;
+use std::fmt::{Debug, Formatter, Result};
+use std::iter::FromIterator;
+
+/// The pairs removed from an RBBiMap to make way
+/// for a new pair, as returned by `RBBiMap::insert`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Overwritten<L, R> {
+    /// No pair shared either value with the new pair.
+    Neither,
+    /// The pair that had the new pair's left value.
+    Left(L, R),
+    /// The pair that had the new pair's right value.
+    Right(L, R),
+    /// The pair was already in the map.
+    Pair(L, R),
+    /// The pair that had the new pair's left value,
+    /// followed by the one that had its right value.
+    Both((L, R), (L, R)),
+}
+
+impl<L: PartialOrd + Debug, R: PartialOrd + Debug> Debug for RBBiMap<L, R> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_map().entries(self.left.iter()).finish()
+    }
+}
+
+impl<L: PartialOrd + Clone, R: PartialOrd + Clone> RBBiMap<L, R> {
+    /// Creates and returns a new, empty RBBiMap.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBBiMap;
+    ///
+    /// let mut ports = RBBiMap::new();
+    /// ports.insert("http", 80);
+    /// ports.insert("ssh", 22);
+    /// assert_eq!(ports.get_by_left(&"ssh"), Some(&22));
+    /// assert_eq!(ports.get_by_right(&80), Some(&"http"));
+    /// ```
+    pub fn new() -> RBBiMap<L, R> {
+        RBBiMap {
+            left: RBMap::new(),
+            right: RBMap::new(),
+        }
+    }
+
+    /// Clears all pairs from the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBBiMap;
+    ///
+    /// let mut map = RBBiMap::new();
+    /// map.insert(1, 'a');
+    /// map.clear();
+    /// assert!(map.is_empty());
+    /// ```
+    pub fn clear(&mut self) {
+        self.left.clear();
+        self.right.clear();
+    }
+
+    /// Inserts the pair, first removing any pairs that
+    /// share either of its values so that each value
+    /// stays associated with exactly one other. Returns
+    /// the pairs that were removed.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBBiMap;
+    /// use rb_tree::rbbimap::Overwritten;
+    ///
+    /// let mut map = RBBiMap::new();
+    /// assert_eq!(map.insert(1, 'a'), Overwritten::Neither);
+    /// assert_eq!(map.insert(2, 'b'), Overwritten::Neither);
+    /// assert_eq!(map.insert(1, 'a'), Overwritten::Pair(1, 'a'));
+    /// assert_eq!(map.insert(1, 'c'), Overwritten::Left(1, 'a'));
+    /// assert_eq!(map.insert(3, 'b'), Overwritten::Right(2, 'b'));
+    /// assert_eq!(map.insert(1, 'b'), Overwritten::Both((1, 'c'), (3, 'b')));
+    /// assert_eq!(map.len(), 1);
+    /// ```
+    pub fn insert(&mut self, left: L, right: R) -> Overwritten<L, R> {
+        let by_left = self.remove_by_left(&left);
+        let by_right = self.remove_by_right(&right);
+        let overwritten = match (by_left, by_right) {
+            (None, None) => Overwritten::Neither,
+            (Some((l, r)), None) if r == right => Overwritten::Pair(l, r),
+            (Some((l, r)), None) => Overwritten::Left(l, r),
+            (None, Some((l, r))) => Overwritten::Right(l, r),
+            (Some(by_left), Some(by_right)) => Overwritten::Both(by_left, by_right),
+        };
+        self.link(left, right);
+        overwritten
+    }
+
+    /// Inserts the pair only if neither of its values is
+    /// already in the map, otherwise handing it back.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBBiMap;
+    ///
+    /// let mut map = RBBiMap::new();
+    /// assert_eq!(map.insert_no_overwrite(1, 'a'), Ok(()));
+    /// assert_eq!(map.insert_no_overwrite(1, 'b'), Err((1, 'b')));
+    /// assert_eq!(map.insert_no_overwrite(2, 'a'), Err((2, 'a')));
+    /// assert_eq!(map.get_by_left(&1), Some(&'a'));
+    /// ```
+    pub fn insert_no_overwrite(&mut self, left: L, right: R) -> std::result::Result<(), (L, R)> {
+        if self.left.contains_key(&left) || self.right.contains_key(&right) {
+            Err((left, right))
+        } else {
+            self.link(left, right);
+            Ok(())
+        }
+    }
+
+    /// Returns the right value paired with the
+    /// given left value, if any.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBBiMap;
+    ///
+    /// let mut map = RBBiMap::new();
+    /// map.insert("one".to_string(), 1);
+    /// assert_eq!(map.get_by_left("one"), Some(&1));
+    /// assert_eq!(map.get_by_left("two"), None);
+    /// ```
+    pub fn get_by_left<Q: PartialOrd + ?Sized>(&self, left: &Q) -> Option<&R>
+    where
+        L: Borrow<Q>,
+    {
+        self.left.get(left)
+    }
+
+    /// Returns the left value paired with the
+    /// given right value, if any.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBBiMap;
+    ///
+    /// let mut map = RBBiMap::new();
+    /// map.insert(1, "one".to_string());
+    /// assert_eq!(map.get_by_right("one"), Some(&1));
+    /// assert_eq!(map.get_by_right("two"), None);
+    /// ```
+    pub fn get_by_right<Q: PartialOrd + ?Sized>(&self, right: &Q) -> Option<&L>
+    where
+        R: Borrow<Q>,
+    {
+        self.right.get(right)
+    }
+
+    /// Returns true if the left value is in the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBBiMap;
+    ///
+    /// let mut map = RBBiMap::new();
+    /// map.insert(1, 'a');
+    /// assert!(map.contains_left(&1));
+    /// assert!(!map.contains_left(&2));
+    /// ```
+    pub fn contains_left<Q: PartialOrd + ?Sized>(&self, left: &Q) -> bool
+    where
+        L: Borrow<Q>,
+    {
+        self.left.contains_key(left)
+    }
+
+    /// Returns true if the right value is in the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBBiMap;
+    ///
+    /// let mut map = RBBiMap::new();
+    /// map.insert(1, 'a');
+    /// assert!(map.contains_right(&'a'));
+    /// assert!(!map.contains_right(&'b'));
+    /// ```
+    pub fn contains_right<Q: PartialOrd + ?Sized>(&self, right: &Q) -> bool
+    where
+        R: Borrow<Q>,
+    {
+        self.right.contains_key(right)
+    }
+
+    /// Removes the pair with the given left
+    /// value, returning it if there was one.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBBiMap;
+    ///
+    /// let mut map = RBBiMap::new();
+    /// map.insert(1, 'a');
+    /// assert_eq!(map.remove_by_left(&1), Some((1, 'a')));
+    /// assert!(!map.contains_right(&'a'));
+    /// assert_eq!(map.remove_by_left(&1), None);
+    /// ```
+    pub fn remove_by_left<Q: PartialOrd + ?Sized>(&mut self, left: &Q) -> Option<(L, R)>
+    where
+        L: Borrow<Q>,
+    {
+        let (l, r) = self.left.remove_entry(left)?;
+        self.right.remove(&r);
+        Some((l, r))
+    }
+
+    /// Removes the pair with the given right
+    /// value, returning it if there was one.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBBiMap;
+    ///
+    /// let mut map = RBBiMap::new();
+    /// map.insert(1, 'a');
+    /// assert_eq!(map.remove_by_right(&'a'), Some((1, 'a')));
+    /// assert!(!map.contains_left(&1));
+    /// assert_eq!(map.remove_by_right(&'a'), None);
+    /// ```
+    pub fn remove_by_right<Q: PartialOrd + ?Sized>(&mut self, right: &Q) -> Option<(L, R)>
+    where
+        R: Borrow<Q>,
+    {
+        let (r, l) = self.right.remove_entry(right)?;
+        self.left.remove(&l);
+        Some((l, r))
+    }
+
+    /// Returns the number of pairs in the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBBiMap;
+    ///
+    /// let mut map = RBBiMap::new();
+    /// map.insert(1, 'a');
+    /// map.insert(2, 'b');
+    /// assert_eq!(map.len(), 2);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.left.len()
+    }
+
+    /// Returns true if there are no pairs in the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBBiMap;
+    ///
+    /// let mut map = RBBiMap::new();
+    /// assert!(map.is_empty());
+    /// map.insert(1, 'a');
+    /// assert!(!map.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.left.is_empty()
+    }
+
+    /// An iterator that visits every pair
+    /// in their left value's partialord order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBBiMap;
+    ///
+    /// let mut map = RBBiMap::new();
+    /// map.insert(2, 'a');
+    /// map.insert(1, 'b');
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), vec!((&1, &'b'), (&2, &'a')));
+    /// ```
+    pub fn iter(&self) -> rbmap::Iter<'_, L, R> {
+        self.left.iter()
+    }
+
+    /// An iterator that visits every pair, with its right
+    /// value first, in their right value's partialord order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBBiMap;
+    ///
+    /// let mut map = RBBiMap::new();
+    /// map.insert(2, 'a');
+    /// map.insert(1, 'b');
+    /// assert_eq!(map.iter_by_right().collect::<Vec<_>>(), vec!((&'a', &2), (&'b', &1)));
+    /// ```
+    pub fn iter_by_right(&self) -> rbmap::Iter<'_, R, L> {
+        self.right.iter()
+    }
+
+    // adds a pair neither of whose values is in the map
+    fn link(&mut self, left: L, right: R) {
+        self.left.insert(left.clone(), right.clone());
+        self.right.insert(right, left);
+    }
+}
+
+impl<L: PartialOrd + Clone, R: PartialOrd + Clone> Default for RBBiMap<L, R> {
+    fn default() -> Self {
+        RBBiMap::new()
+    }
+}
+
+impl<L: PartialOrd + Clone, R: PartialOrd + Clone> FromIterator<(L, R)> for RBBiMap<L, R> {
+    fn from_iter<I: IntoIterator<Item = (L, R)>>(iter: I) -> Self {
+        let mut map = RBBiMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<L: PartialOrd + Clone, R: PartialOrd + Clone> Extend<(L, R)> for RBBiMap<L, R> {
+    fn extend<I: IntoIterator<Item = (L, R)>>(&mut self, iter: I) {
+        for (left, right) in iter {
+            self.insert(left, right);
+        }
+    }
+}
+
+impl<L: PartialOrd, R: PartialOrd> PartialEq for RBBiMap<L, R> {
+    fn eq(&self, other: &RBBiMap<L, R>) -> bool {
+        self.left.len() == other.left.len() && self.left.iter().eq(other.left.iter())
+    }
+}
@@ -1,23 +1,28 @@
-use crate::helpers::write_to_level;
-use crate::mapper::Mapper;
+use crate::mapper::{KeyRef, Mapper};
+use crate::node::{Index, NIL};
+use crate::raw::RawTree;
 use crate::rbtree;
-use crate::{RBMap, RBTree};
+use crate::{Comparator, Error, NaturalOrder, RBMap, RBTree, ReplaceKeyError, UnsortedError};
 
+use std::borrow::Borrow;
+use std::cmp::Ordering::{Equal, Greater};
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{Debug, Display, Formatter, Result};
-use std::iter::{ExactSizeIterator, FromIterator, FusedIterator};
+use std::hash::{BuildHasher, Hash};
+use std::iter::{DoubleEndedIterator, ExactSizeIterator, FromIterator, FusedIterator};
+use std::ops::Bound::{self, Excluded, Included, Unbounded};
+use std::ops::RangeBounds;
+
+#[cfg(feature = "serde")]
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+#[cfg(feature = "serde")]
+use serde::ser::{Serialize, Serializer};
+#[cfg(feature = "serde")]
+use std::marker::PhantomData;
 
 impl<K: PartialOrd + Debug, V: Debug> Debug for RBMap<K, V> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        let mut levels = Vec::new();
-        write_to_level(&self.map.root, "".to_string(), 0, &mut levels);
-        let mut f_string = "".to_string();
-        for i in 0..levels.len() {
-            f_string += &levels[i];
-            if i != levels.len() - 1 {
-                f_string += "\n";
-            }
-        }
-        write!(f, "{}", f_string)
+        f.debug_map().entries(self.iter()).finish()
     }
 }
 
@@ -27,6 +32,64 @@ impl<K: PartialOrd + Debug, V: Debug> Display for RBMap<K, V> {
     }
 }
 
+/// Clones the pairs of the map, but not the observers
+/// registered with it, which go on watching only the
+/// original.
+/// # Example:
+/// ```
+/// use rb_tree::RBMap;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+///
+/// let seen = Arc::new(AtomicUsize::new(0));
+/// let counter = seen.clone();
+/// let mut map = RBMap::new();
+/// map.on_insert(move |_, _| {
+///     counter.fetch_add(1, Ordering::Relaxed);
+/// });
+/// map.insert(1, 'a');
+/// let mut copy = map.clone();
+/// copy.insert(2, 'b');
+/// assert_eq!(copy.len(), 2);
+/// assert_eq!(seen.load(Ordering::Relaxed), 1);
+/// ```
+impl<K: PartialOrd + Clone, V: Clone> Clone for RBMap<K, V> {
+    fn clone(&self) -> Self {
+        RBMap {
+            map: self.map.clone(),
+            observers: None,
+        }
+    }
+}
+
+impl<K: PartialOrd + Debug, V: Debug> RBMap<K, V> {
+    /// Returns a level-by-level description of the
+    /// underlying tree, listing each entry with the
+    /// colour of its node and the key of its parent.
+    /// Meant for debugging the tree itself; `Debug`
+    /// formats the map as its entries. With the `chunked`
+    /// feature each node lists the entries of its chunk.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map = RBMap::new();
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// assert_eq!(format!("{:?}", map), r#"{1: "a", 2: "b"}"#);
+    /// # #[cfg(not(feature = "chunked"))]
+    /// assert_eq!(
+    ///     map.dump_structure(),
+    ///     "B:[2: Some(\"b\")]\n\
+    ///      [2: Some(\"b\")]->R:[1: Some(\"a\")] [2: Some(\"b\")]->___\n\
+    ///      [1: Some(\"a\")]->___ [1: Some(\"a\")]->___"
+    /// );
+    /// ```
+    pub fn dump_structure(&self) -> String {
+        self.map.raw.nodes.levels().join("\n")
+    }
+}
+
 impl<K: PartialOrd, V> RBMap<K, V> {
     /// Creates and returns a new, empty RBMap
     /// # Example:
@@ -38,7 +101,52 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// assert_eq!(map.remove(&"Hello").unwrap(), "World");
     /// ```
     pub fn new() -> RBMap<K, V> {
-        RBMap { map: RBTree::new() }
+        RBMap {
+            map: RBTree::new(),
+            observers: None,
+        }
+    }
+
+    /// Builds a map in linear time from key-value pairs
+    /// whose keys are in ascending order, such as those
+    /// of a previously saved map. Where a key is repeated
+    /// the last value given for it is kept. Returns an
+    /// error holding the position of the first pair whose
+    /// key is less than (or can't be compared with) the key
+    /// before it.
+    /// # Example:
+    /// ```
+    /// use rb_tree::{RBMap, UnsortedError};
+    ///
+    /// let map = RBMap::from_sorted_iter(vec!((1, 'a'), (2, 'b'), (2, 'c'))).unwrap();
+    /// assert_eq!(map.get(&2), Some(&'c'));
+    /// assert_eq!(map.len(), 2);
+    ///
+    /// let err = RBMap::from_sorted_iter(vec!((2, 'b'), (1, 'a'))).unwrap_err();
+    /// assert_eq!(err, UnsortedError { position: 1 });
+    /// ```
+    pub fn from_sorted_iter<I>(iter: I) -> std::result::Result<RBMap<K, V>, UnsortedError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut entries: Vec<Mapper<K, V>> = Vec::new();
+        for (position, (key, val)) in iter.into_iter().enumerate() {
+            if let Some(last) = entries.last_mut() {
+                match key.partial_cmp(last.key()) {
+                    Some(Greater) => {}
+                    Some(Equal) => {
+                        *last.val_mut() = Some(val);
+                        continue;
+                    }
+                    _ => return Err(UnsortedError { position }),
+                }
+            }
+            entries.push(Mapper::new(key, Some(val)));
+        }
+        Ok(RBMap {
+            map: RBTree::from_sorted_vec(entries, NaturalOrder),
+            observers: None,
+        })
     }
 
     /// Creates an RBTree set of the keys
@@ -56,11 +164,9 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// assert!(!kset.contains(&&"Bar"));
     /// ```
     pub fn keyset(&self) -> RBTree<&K> {
-        let mut keys = RBTree::new();
-        for key in self.keys() {
-            keys.insert(key);
-        }
-        keys
+        // the keys are already in order, so the
+        // set is built in linear time
+        RBTree::from_sorted_vec(self.keys().collect(), NaturalOrder)
     }
 
     /// Creates a set from the keys in this
@@ -78,11 +184,15 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// assert!(!kset.contains(&"Bar"));
     /// ```
     pub fn into_keyset(self) -> RBTree<K> {
-        let mut kset = RBTree::new();
-        for (key, _) in self.into_iter() {
-            kset.insert(key);
-        }
-        kset
+        let keys = self
+            .map
+            .raw
+            .nodes
+            .into_ordered()
+            .into_iter()
+            .map(|m| m.consume().0)
+            .collect();
+        RBTree::from_sorted_vec(keys, NaturalOrder)
     }
 
     /// Clears all entries from the RBMap
@@ -99,7 +209,8 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// assert!(map.remove(&"Hello").is_none());
     /// ```
     pub fn clear(&mut self) {
-        self.map = RBTree::new();
+        let old = std::mem::replace(&mut self.map, RBTree::new());
+        self.removed_all(&old);
     }
 
     /// Returns true if the map contains an entry
@@ -113,8 +224,11 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// map.insert("Hello", "world");
     /// assert!(map.contains_key(&"Hello"));
     /// ```
-    pub fn contains_key(&self, key: &K) -> bool {
-        match self.map.get(&Mapper::new(key, None)) {
+    pub fn contains_key<Q: PartialOrd + ?Sized>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+    {
+        match self.map.get(&KeyRef(key)) {
             None => false,
             Some(v) => v.is_some(),
         }
@@ -138,13 +252,15 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     pub fn drain(&mut self) -> Drain<K, V> {
         let mut rep = RBTree::new();
         std::mem::swap(&mut self.map, &mut rep);
+        self.removed_all(&rep);
         Drain { tree: rep }
     }
 
     /// Returns an option containing a reference
     /// to the value associated with this key,
     /// or none if this key does not have an associated
-    /// value.
+    /// value. As with the other lookups, the key may be
+    /// any borrowed form of the map's key type.
     /// # Example:
     /// ```
     /// use rb_tree::RBMap;
@@ -153,9 +269,16 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// assert!(map.get(&"Hello").is_none());
     /// map.insert("Hello", "world");
     /// assert_eq!(map.get(&"Hello").unwrap(), &"world");
+    ///
+    /// let mut owned = RBMap::new();
+    /// owned.insert("Hello".to_string(), 1);
+    /// assert_eq!(owned.get("Hello"), Some(&1));
     /// ```
-    pub fn get(&self, key: &K) -> Option<&V> {
-        self.map.get(&Mapper::new(key, None)).map(|v| v.as_ref())
+    pub fn get<Q: PartialOrd + ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        self.map.get(&KeyRef(key)).map(|v| v.as_ref())
     }
 
     /// Returns an option containing a reference
@@ -171,10 +294,11 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// map.insert("Hello", "world");
     /// assert_eq!(map.get_pair(&"Hello").unwrap(), (&"Hello", &"world"));
     /// ```
-    pub fn get_pair(&self, key: &K) -> Option<(&K, &V)> {
-        self.map
-            .get(&Mapper::new(key, None))
-            .map(|v| (v.key(), v.as_ref()))
+    pub fn get_pair<Q: PartialOrd + ?Sized>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+    {
+        self.map.get(&KeyRef(key)).map(|v| (v.key(), v.as_ref()))
     }
 
     /// Returns an option containing a reference
@@ -191,10 +315,11 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// map.insert("Hello", "world");
     /// assert_eq!(map.get_pair(&"Hello").unwrap(), (&"Hello", &"world"));
     /// ```
-    pub fn get_pair_mut(&mut self, key: &K) -> Option<(&K, &mut V)> {
-        self.map
-            .get_mut(&Mapper::new(key, None))
-            .map(|v| v.mut_pair())
+    pub fn get_pair_mut<Q: PartialOrd + ?Sized>(&mut self, key: &Q) -> Option<(&K, &mut V)>
+    where
+        K: Borrow<Q>,
+    {
+        self.map.get_mut(&KeyRef(key)).map(|v| v.mut_pair())
     }
 
     /// Returns an option containing a mutable
@@ -211,10 +336,140 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// *map.get_mut(&"Hello").unwrap() = "world!";
     /// assert_eq!(map.get(&"Hello").unwrap(), &"world!");
     /// ```
-    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+    pub fn get_mut<Q: PartialOrd + ?Sized>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+    {
+        self.map.get_mut(&KeyRef(key)).map(|v| v.as_mut())
+    }
+
+    /// Moves the value associated with `old` so that it is
+    /// associated with `new` instead, without touching the
+    /// value itself. Fails, leaving the map unchanged, if
+    /// `old` has no value or `new` already has one (unless
+    /// the two keys are equal, in which case the stored key
+    /// is swapped for `new`).
+    /// # Example:
+    /// ```
+    /// use rb_tree::{RBMap, ReplaceKeyError};
+    ///
+    /// let mut map = RBMap::new();
+    /// map.insert("draft", vec!(1, 2, 3));
+    /// map.insert("final", vec!());
+    /// assert_eq!(map.replace_key(&"draft", "v1"), Ok(()));
+    /// assert_eq!(map.get(&"v1"), Some(&vec!(1, 2, 3)));
+    /// assert_eq!(map.get(&"draft"), None);
+    ///
+    /// assert_eq!(map.replace_key(&"v1", "final"), Err(ReplaceKeyError::Occupied("final")));
+    /// assert_eq!(map.replace_key(&"draft", "v2"), Err(ReplaceKeyError::NotFound("v2")));
+    /// ```
+    pub fn replace_key<Q: PartialOrd + ?Sized>(
+        &mut self,
+        old: &Q,
+        new: K,
+    ) -> std::result::Result<(), ReplaceKeyError<K>>
+    where
+        K: Borrow<Q>,
+    {
+        let old_at = match self.map.raw.locate(&KeyRef(old)) {
+            Ok(at) => at,
+            Err(_) => return Err(ReplaceKeyError::NotFound(new)),
+        };
+        match self.map.raw.locate(&KeyRef(&new)) {
+            Ok(at) if at == old_at => {
+                let mapper = self.map.raw.nodes.value_mut(at).unwrap();
+                let old = std::mem::replace(mapper.key_mut(), new);
+                notify(&mut self.observers, true, &old, mapper.as_ref());
+                self.inserted(at);
+                Ok(())
+            }
+            Ok(_) => Err(ReplaceKeyError::Occupied(new)),
+            Err(_) => {
+                let (old, val) = self.map.raw.nodes.remove_at(old_at).consume();
+                notify(&mut self.observers, true, &old, &val);
+                // the removal may have moved the new key's place
+                let (parent, right) = self.map.raw.locate(&KeyRef(&new)).unwrap_err();
+                let at = self
+                    .map
+                    .raw
+                    .nodes
+                    .insert_at(parent, right, Mapper::new(new, Some(val)));
+                self.inserted(at);
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the position of the key in the map's
+    /// ordering, i.e. the number of keys less than it,
+    /// or None if the key has no associated value.
+    /// Takes O(log n) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let map: RBMap<_, _> = vec!(("c", 3), ("a", 1), ("b", 2)).into_iter().collect();
+    /// assert_eq!(map.rank(&"a"), Some(0));
+    /// assert_eq!(map.rank(&"c"), Some(2));
+    /// assert_eq!(map.rank(&"d"), None);
+    /// ```
+    pub fn rank<Q: PartialOrd + ?Sized>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+    {
+        self.map
+            .raw
+            .locate(&KeyRef(key))
+            .ok()
+            .map(|at| self.map.raw.nodes.rank(at))
+    }
+
+    /// Returns the key-value pair with the nth smallest
+    /// key (counting from 0), or None if the map has no
+    /// more than n entries. Takes O(log n) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let map: RBMap<_, _> = (0..10).map(|k| (k * 2, k)).collect();
+    /// assert_eq!(map.nth(0), Some((&0, &0)));
+    /// assert_eq!(map.nth(4), Some((&8, &4)));
+    /// assert_eq!(map.nth(10), None);
+    /// ```
+    pub fn nth(&self, n: usize) -> Option<(&K, &V)> {
         self.map
-            .get_mut(&Mapper::new(key, None))
-            .map(|v| v.as_mut())
+            .raw
+            .nodes
+            .value(self.map.raw.nodes.nth(n))
+            .map(Mapper::pair)
+    }
+
+    /// Removes and returns the key-value pair with the nth
+    /// smallest key (counting from 0), or None if the map has
+    /// no more than n entries. Takes O(log n) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// // a leaderboard keeping only its top three scores
+    /// let mut board: RBMap<_, _> = vec!((90, "ann"), (75, "bo"), (82, "cy"), (60, "di"))
+    ///     .into_iter()
+    ///     .collect();
+    /// while board.len() > 3 {
+    ///     board.remove_nth(0);
+    /// }
+    /// assert_eq!(board.remove_nth(1), Some((82, "cy")));
+    /// assert_eq!(board.remove_nth(2), None);
+    /// assert_eq!(board.len(), 2);
+    /// ```
+    pub fn remove_nth(&mut self, n: usize) -> Option<(K, V)> {
+        let at = self.map.raw.nodes.nth(n);
+        if at == NIL {
+            return None;
+        }
+        self.map.raw.contained -= 1;
+        let pair = self.map.raw.nodes.remove_at(at).consume();
+        Some(self.removed(pair))
     }
 
     /// Returns an option containing a reference to the
@@ -301,6 +556,91 @@ impl<K: PartialOrd, V> RBMap<K, V> {
         self.map.peek_back().map(|v| v.pair())
     }
 
+    /// Returns an option containing a mutable reference to
+    /// the value associated with the key that has the smallest
+    /// `PartialOrd` value, allowing it to be updated in place.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map = RBMap::new();
+    /// assert_eq!(map.peek_mut(), None);
+    ///
+    /// map.insert(5, vec!("Hello"));
+    /// map.insert(2, vec!("World"));
+    ///
+    /// map.peek_mut().unwrap().push("Foo");
+    /// assert_eq!(map.get(&2).unwrap(), &vec!("World", "Foo"));
+    /// ```
+    pub fn peek_mut(&mut self) -> Option<&mut V> {
+        self.outer_mut(false).map(|v| v.as_mut())
+    }
+
+    /// Returns an option containing a mutable reference to
+    /// the value associated with the key that has the largest
+    /// `PartialOrd` value, allowing it to be updated in place.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map = RBMap::new();
+    /// assert_eq!(map.peek_back_mut(), None);
+    ///
+    /// map.insert(5, 1);
+    /// map.insert(2, 1);
+    ///
+    /// *map.peek_back_mut().unwrap() += 1;
+    /// assert_eq!(map.get(&5).unwrap(), &2);
+    /// ```
+    pub fn peek_back_mut(&mut self) -> Option<&mut V> {
+        self.outer_mut(true).map(|v| v.as_mut())
+    }
+
+    /// Returns an option containing a pair with a reference to the
+    /// key with the smallest `PartialOrd` value and a mutable
+    /// reference to its associated value.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map = RBMap::new();
+    /// assert_eq!(map.peek_pair_mut(), None);
+    ///
+    /// // a scheduler bumping the retry count of its next job
+    /// map.insert(30, ("backup", 0));
+    /// map.insert(10, ("email", 0));
+    ///
+    /// let (due, job) = map.peek_pair_mut().unwrap();
+    /// assert_eq!(*due, 10);
+    /// job.1 += 1;
+    /// assert_eq!(map.peek_pair().unwrap(), (&10, &("email", 1)));
+    /// ```
+    pub fn peek_pair_mut(&mut self) -> Option<(&K, &mut V)> {
+        self.outer_mut(false).map(|v| v.mut_pair())
+    }
+
+    /// Returns an option containing a pair with a reference to the
+    /// key with the largest `PartialOrd` value and a mutable
+    /// reference to its associated value.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map = RBMap::new();
+    /// assert_eq!(map.peek_pair_back_mut(), None);
+    ///
+    /// map.insert(5, "Hello");
+    /// map.insert(7, "Foo");
+    ///
+    /// let (k, v) = map.peek_pair_back_mut().unwrap();
+    /// assert_eq!(*k, 7);
+    /// *v = "Bar";
+    /// assert_eq!(map.peek_back().unwrap(), &"Bar");
+    /// ```
+    pub fn peek_pair_back_mut(&mut self) -> Option<(&K, &mut V)> {
+        self.outer_mut(true).map(|v| v.mut_pair())
+    }
+
     /// Inserts a value to associate with the given key
     /// into the map, returning the previously-stored key-value
     /// pair if one existed, None otherwise.
@@ -314,9 +654,189 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// assert_eq!(map.len(), 2);
     /// ```
     pub fn insert(&mut self, key: K, val: V) -> Option<(K, V)> {
+        if self.observers.is_none() {
+            return self
+                .map
+                .replace(Mapper::new(key, Some(val)))
+                .map(|v| v.consume());
+        }
+        // the observers need to know where the pair was put
+        match self.map.raw.locate(&KeyRef(&key)) {
+            Ok(at) => {
+                let mapper = self.map.raw.nodes.value_mut(at).unwrap();
+                let old = std::mem::replace(mapper, Mapper::new(key, Some(val)));
+                let old = self.removed(old.consume());
+                self.inserted(at);
+                Some(old)
+            }
+            Err((parent, right)) => {
+                self.link(parent, right, key, val);
+                None
+            }
+        }
+    }
+
+    /// Tries to reserve room for at least `additional`
+    /// more pairs, so that inserting them will not need
+    /// to allocate. Returns an error rather than aborting
+    /// if the memory could not be allocated.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map = RBMap::new();
+    /// map.try_reserve(10).expect("out of memory");
+    /// (0..10).for_each(|v| {map.insert(v, v);});
+    /// assert!(map.try_reserve(usize::MAX).is_err());
+    /// ```
+    pub fn try_reserve(&mut self, additional: usize) -> std::result::Result<(), Error> {
+        self.map.try_reserve(additional)
+    }
+
+    /// Makes sure at least `n` more pairs than the map now
+    /// holds can be inserted without allocating. The nodes
+    /// of removed pairs are kept and reused for later
+    /// insertions, and count towards `n`, so a map that is
+    /// reserved for its working size will not allocate while
+    /// pairs are inserted and removed at a steady rate.
+    /// # Panics:
+    /// Panics if the new capacity overflows `usize`.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map = RBMap::new();
+    /// map.reserve_nodes(10);
+    /// for k in 0..100 {
+    ///     map.insert(k, k * 2);
+    ///     map.remove(&(k - 10));
+    /// }
+    /// assert_eq!(map.len(), 10);
+    /// ```
+    pub fn reserve_nodes(&mut self, n: usize) {
+        self.map.reserve_nodes(n);
+    }
+
+    /// Gives the memory held for the nodes of removed pairs
+    /// back to the allocator, so that a map which has shrunk
+    /// after holding many pairs doesn't keep the memory it
+    /// needed at its largest. Moves the remaining nodes
+    /// together, taking O(n) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map: RBMap<u32, u32> = (0..10_000).map(|k| (k, k * 2)).collect();
+    /// map.retain(|k, _| *k >= 9_990);
+    /// map.shrink_to_fit();
+    /// assert_eq!(map.get(&9_995), Some(&19_990));
+    /// assert_eq!(map.len(), 10);
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        self.map.shrink_to_fit();
+    }
+
+    /// Inserts a key-value pair as `insert` does, but returns
+    /// an error rather than aborting if room for the pair could
+    /// not be allocated, in which case the pair is dropped and
+    /// the map is left unchanged. This may fail even when the
+    /// key is already contained.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map = RBMap::new();
+    /// assert_eq!(map.try_insert_alloc(1, 'a'), Ok(None));
+    /// assert_eq!(map.try_insert_alloc(1, 'b'), Ok(Some((1, 'a'))));
+    /// ```
+    pub fn try_insert_alloc(
+        &mut self,
+        key: K,
+        val: V,
+    ) -> std::result::Result<Option<(K, V)>, Error> {
+        self.map.raw.nodes.try_make_room()?;
+        Ok(self.insert(key, val))
+    }
+
+    /// Inserts a key-value pair as `insert` does, but returns
+    /// an error rather than panicking if `key` could not be
+    /// compared to the keys already in the map, in which case
+    /// the pair is dropped and the map is left unchanged.
+    /// # Example:
+    /// ```
+    /// use rb_tree::{Error, RBMap};
+    ///
+    /// let mut map = RBMap::new();
+    /// assert_eq!(map.try_insert(1.0, 'a'), Ok(None));
+    /// assert_eq!(map.try_insert(f64::NAN, 'b'), Err(Error::IncomparableElement));
+    /// assert_eq!(map.len(), 1);
+    /// ```
+    pub fn try_insert(&mut self, key: K, val: V) -> std::result::Result<Option<(K, V)>, Error> {
+        if self.observers.is_some() {
+            // a key that can be compared along its search path
+            // can be placed by insert without panicking
+            self.try_get(&key)?;
+            return Ok(self.insert(key, val));
+        }
+        self.map
+            .raw
+            .nodes
+            .try_insert(
+                Mapper::new(key, Some(val)),
+                &|l: &Mapper<K, V>, r: &Mapper<K, V>| l.partial_cmp(r),
+            )
+            .map(|old| match old {
+                Some(v) => Some(v.consume()),
+                None => {
+                    self.map.raw.contained += 1;
+                    self.map.raw.verify();
+                    None
+                }
+            })
+            .map_err(Error::from)
+    }
+
+    /// Inserts a key-value pair only if `key` has no value
+    /// associated with it yet, returning `Error::OccupiedKey`
+    /// if it does. Fails as `try_insert` does if `key` could
+    /// not be compared to the keys already in the map. On
+    /// failure the pair is dropped and the map is left as it was.
+    /// # Example:
+    /// ```
+    /// use rb_tree::{Error, RBMap};
+    ///
+    /// let mut map = RBMap::new();
+    /// assert_eq!(map.try_insert_new(1.0, 'a'), Ok(()));
+    /// assert_eq!(map.try_insert_new(1.0, 'b'), Err(Error::OccupiedKey));
+    /// assert_eq!(map.try_insert_new(f64::NAN, 'c'), Err(Error::IncomparableElement));
+    /// assert_eq!(map.get(&1.0), Some(&'a'));
+    /// ```
+    pub fn try_insert_new(&mut self, key: K, val: V) -> std::result::Result<(), Error> {
+        if self.try_get(&key)?.is_some() {
+            return Err(Error::OccupiedKey);
+        }
+        self.try_insert(key, val).map(|_| ())
+    }
+
+    /// Returns the value associated with `key`, as `get`
+    /// does, but returns an error rather than panicking if
+    /// `key` could not be compared to the keys of the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::{Error, RBMap};
+    ///
+    /// let mut map = RBMap::new();
+    /// map.insert(1.0, 'a');
+    /// assert_eq!(map.try_get(&1.0), Ok(Some(&'a')));
+    /// assert_eq!(map.try_get(&f64::NAN), Err(Error::IncomparableElement));
+    /// ```
+    pub fn try_get<Q: PartialOrd + ?Sized>(&self, key: &Q) -> std::result::Result<Option<&V>, Error>
+    where
+        K: Borrow<Q>,
+    {
         self.map
-            .replace(Mapper::new(key, Some(val)))
-            .map(|v| v.consume())
+            .try_get(&KeyRef(key))
+            .map(|v| v.map(|v| v.as_ref()))
     }
 
     /// Returns true if there are no key-value pairs
@@ -365,10 +885,12 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// map.insert(2, 4);
     /// assert_eq!(map.remove(&2).unwrap(), 4);
     /// ```
-    pub fn remove(&mut self, key: &K) -> Option<V> {
-        self.map
-            .take(&Mapper::new(key, None))
-            .map(|v| v.consume().1)
+    pub fn remove<Q: PartialOrd + ?Sized>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+    {
+        let pair = self.map.take(&KeyRef(key))?.consume();
+        Some(self.removed(pair).1)
     }
 
     /// Removes the key-value pair associated with key,
@@ -383,8 +905,12 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// map.insert(2, 4);
     /// assert_eq!(map.remove_entry(&2).unwrap(), (2, 4));
     /// ```
-    pub fn remove_entry(&mut self, key: &K) -> Option<(K, V)> {
-        self.map.take(&Mapper::new(key, None)).map(|v| v.consume())
+    pub fn remove_entry<Q: PartialOrd + ?Sized>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+    {
+        let pair = self.map.take(&KeyRef(key))?.consume();
+        Some(self.removed(pair))
     }
 
     /// Removes the pair associated with the key that has the smallest
@@ -405,7 +931,7 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// assert_eq!(map.pop().unwrap(), "Hello");
     /// ```
     pub fn pop(&mut self) -> Option<V> {
-        self.map.pop().map(|v| v.consume().1)
+        self.pop_pair().map(|(_, v)| v)
     }
 
     /// Removes the pair associated with the key that has the largest
@@ -426,7 +952,7 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// assert_eq!(map.pop_back().unwrap(), "Bar");
     /// ```
     pub fn pop_back(&mut self) -> Option<V> {
-        self.map.pop_back().map(|v| v.consume().1)
+        self.pop_pair_back().map(|(_, v)| v)
     }
 
     /// Removes the pair associated with the key that has the smallest
@@ -447,7 +973,8 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// assert_eq!(map.pop_pair().unwrap(), (5, "Hello"));
     /// ```
     pub fn pop_pair(&mut self) -> Option<(K, V)> {
-        self.map.pop().map(|v| v.consume())
+        let pair = self.map.pop()?.consume();
+        Some(self.removed(pair))
     }
 
     /// Removes the pair associated with the key that has the smallest
@@ -468,7 +995,76 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// assert_eq!(map.pop_pair_back().unwrap(), (6, "Bar"));
     /// ```
     pub fn pop_pair_back(&mut self) -> Option<(K, V)> {
-        self.map.pop_back().map(|v| v.consume())
+        let pair = self.map.pop_back()?.consume();
+        Some(self.removed(pair))
+    }
+
+    /// Removes and returns the pair with the smallest key
+    /// if the given predicate holds for it, leaving the map
+    /// unchanged otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut deadlines = RBMap::new();
+    /// deadlines.insert(10, "email");
+    /// deadlines.insert(30, "backup");
+    ///
+    /// let now = 20;
+    /// assert_eq!(deadlines.pop_pair_if(|due, _| *due <= now), Some((10, "email")));
+    /// assert_eq!(deadlines.pop_pair_if(|due, _| *due <= now), None);
+    /// assert_eq!(deadlines.len(), 1);
+    /// ```
+    pub fn pop_pair_if<F: FnOnce(&K, &V) -> bool>(&mut self, pred: F) -> Option<(K, V)> {
+        self.pop_outer_if(false, pred)
+    }
+
+    /// Removes and returns the pair with the largest key
+    /// if the given predicate holds for it, leaving the map
+    /// unchanged otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut scores = RBMap::new();
+    /// scores.insert(72, "bo");
+    /// scores.insert(95, "ann");
+    ///
+    /// assert_eq!(scores.pop_pair_back_if(|_, name| *name == "bo"), None);
+    /// assert_eq!(scores.pop_pair_back_if(|score, _| *score > 90), Some((95, "ann")));
+    /// assert_eq!(scores.len(), 1);
+    /// ```
+    pub fn pop_pair_back_if<F: FnOnce(&K, &V) -> bool>(&mut self, pred: F) -> Option<(K, V)> {
+        self.pop_outer_if(true, pred)
+    }
+
+    /// Returns an iterator that removes and yields pairs from
+    /// the front of the map (smallest key first) for as long
+    /// as the given predicate holds for them. Pairs are only
+    /// removed as the iterator is advanced, so the first pair
+    /// the predicate fails for, and everything after it, stays
+    /// in the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// // jobs keyed by the time they are due
+    /// let mut deadlines = RBMap::new();
+    /// deadlines.insert(15, "flush");
+    /// deadlines.insert(30, "backup");
+    /// deadlines.insert(10, "email");
+    ///
+    /// let now = 20;
+    /// let due: Vec<_> = deadlines.pop_pair_while(|due, _| *due <= now).collect();
+    /// assert_eq!(due, vec!((10, "email"), (15, "flush")));
+    /// assert_eq!(deadlines.len(), 1);
+    /// ```
+    pub fn pop_pair_while<F: FnMut(&K, &V) -> bool>(&mut self, pred: F) -> PopWhile<'_, K, V, F> {
+        PopWhile {
+            map: self,
+            pred,
+            done: false,
+        }
     }
 
     /// Removes all key-value pairs that do not return true for the
@@ -488,17 +1084,133 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// assert_eq!(pairs.next(), None);
     /// ```
     pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut logic: F) {
-        let mut rep = RBMap::new();
-        for (key, mut val) in self.drain() {
-            if logic(&key, &mut val) {
-                rep.insert(key, val);
+        let observers = &mut self.observers;
+        self.map.raw.contained -= self.map.raw.nodes.retain(|m| {
+            let (key, val) = m.mut_pair();
+            let keep = logic(key, val);
+            if !keep {
+                notify(observers, true, key, val);
             }
+            keep
+        });
+    }
+
+    /// Keeps only the pairs whose keys are in the given
+    /// set, removing the rest. The map and set are walked
+    /// side by side in order, taking O(n + m) time rather
+    /// than a lookup in the set for every key.
+    /// # Example:
+    /// ```
+    /// use rb_tree::{RBMap, RBTree};
+    ///
+    /// let mut stock: RBMap<_, _> = vec!(("apple", 3), ("kiwi", 0), ("pear", 5)).into_iter().collect();
+    /// let wanted: RBTree<_> = vec!("apple", "fig", "pear").into_iter().collect();
+    /// stock.retain_keys(&wanted);
+    /// assert_eq!(stock.keys().collect::<Vec<_>>(), vec!(&"apple", &"pear"));
+    /// ```
+    pub fn retain_keys(&mut self, keys: &RBTree<K>) {
+        self.filter_keys(keys, true);
+    }
+
+    /// Removes the pairs whose keys are in the given set,
+    /// keeping the rest. The map and set are walked side
+    /// by side in order, taking O(n + m) time rather than
+    /// a lookup in the set for every key.
+    /// # Example:
+    /// ```
+    /// use rb_tree::{RBMap, RBTree};
+    ///
+    /// let mut sessions: RBMap<_, _> = (1..=5).map(|id| (id, "active")).collect();
+    /// let expired: RBTree<_> = vec!(2, 4, 9).into_iter().collect();
+    /// sessions.remove_keys(&expired);
+    /// assert_eq!(sessions.keys().collect::<Vec<_>>(), vec!(&1, &3, &5));
+    /// ```
+    pub fn remove_keys(&mut self, keys: &RBTree<K>) {
+        self.filter_keys(keys, false);
+    }
+
+    /// Passes each value, in its key's order, to `f` by value,
+    /// keeping the value `f` returns in its place or removing
+    /// the pair if it returns None. This both transforms and
+    /// filters the map in a single pass without moving the
+    /// pairs that are kept.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut leases: RBMap<_, _> = vec!(("a", 3), ("b", 1), ("c", 2)).into_iter().collect();
+    /// // counts each lease down, dropping those that expire
+    /// leases.retain_map(|_, ttl| if ttl > 1 { Some(ttl - 1) } else { None });
+    ///
+    /// assert_eq!(leases.get(&"a"), Some(&2));
+    /// assert_eq!(leases.get(&"b"), None);
+    /// assert_eq!(leases.get(&"c"), Some(&1));
+    /// ```
+    pub fn retain_map<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, V) -> Option<V>,
+    {
+        let mut cur = self.map.raw.nodes.first();
+        while cur != NIL {
+            let next = self.map.raw.nodes.step(cur, true);
+            // as in update, the guard removes the pair if its
+            // value is not put back, even if f panics
+            let taken = Taken { map: self, at: cur };
+            let mapper = taken.map.map.raw.nodes.value_mut(cur).unwrap();
+            let val = mapper.val_mut().take().unwrap();
+            notify(&mut taken.map.observers, true, mapper.key(), &val);
+            let new = f(mapper.key(), val);
+            let kept = new.is_some();
+            *taken.map.map.raw.nodes.value_mut(cur).unwrap().val_mut() = new;
+            drop(taken);
+            cur = if kept {
+                self.inserted(cur);
+                next
+            } else {
+                self.map.raw.nodes.next_after_removal(cur, next)
+            };
+        }
+    }
+
+    /// Consumes the map, producing a map of the pairs `f`
+    /// returns a value for, with that value. Each value is
+    /// passed to `f` in its key's order, and the resulting
+    /// map is built in linear time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let map: RBMap<_, _> = vec!((1, "4"), (2, "x"), (3, "16")).into_iter().collect();
+    /// let parsed = map.filter_map_values(|_, v| v.parse::<u32>().ok());
+    /// assert_eq!(parsed.get(&1), Some(&4));
+    /// assert_eq!(parsed.get(&2), None);
+    /// assert_eq!(parsed.len(), 2);
+    /// ```
+    pub fn filter_map_values<W, F>(self, mut f: F) -> RBMap<K, W>
+    where
+        F: FnMut(&K, V) -> Option<W>,
+    {
+        let entries = self
+            .map
+            .raw
+            .nodes
+            .into_ordered()
+            .into_iter()
+            .filter_map(|m| {
+                let (key, val) = m.consume();
+                f(&key, val).map(|w| Mapper::new(key, Some(w)))
+            })
+            .collect();
+        RBMap {
+            map: RBTree::from_sorted_vec(entries, NaturalOrder),
+            observers: None,
         }
-        std::mem::swap(self, &mut rep);
     }
 
     /// An iterator that visits all key-value
     /// pairs in their key's partialord order.
+    /// It can also be walked from the largest
+    /// key down using `rev`.
     /// # Example:
     /// ```
     /// use rb_tree::RBMap;
@@ -513,11 +1225,13 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// assert_eq!(pairs.next().unwrap(), (&2, &4));
     /// assert_eq!(pairs.next().unwrap(), (&3, &9));
     /// assert_eq!(pairs.next(), None);
+    ///
+    /// let mut pairs = map.iter().rev();
+    /// assert_eq!(pairs.next().unwrap(), (&3, &9));
     /// ```
-    pub fn iter(&self) -> Iter<K, V> {
+    pub fn iter(&self) -> Iter<'_, K, V> {
         Iter {
-            pos: 0,
-            ordered: self.ordered(),
+            iter: self.map.iter(),
         }
     }
 
@@ -541,9 +1255,9 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// assert_eq!(pairs.next().unwrap(), (&3, &18));
     /// assert_eq!(pairs.next(), None);
     /// ```
-    pub fn iter_mut(&mut self) -> IterMut<K, V> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
         IterMut {
-            iter: self.map.iter(),
+            iter: self.map.iter_mut(),
         }
     }
 
@@ -564,10 +1278,9 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// assert_eq!(*vals.next().unwrap(), 9);
     /// assert_eq!(vals.next(), None);
     /// ```
-    pub fn values(&self) -> Values<K, V> {
+    pub fn values(&self) -> Values<'_, K, V> {
         Values {
-            pos: 0,
-            ordered: self.ordered(),
+            iter: self.map.iter(),
         }
     }
 
@@ -591,13 +1304,181 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// assert_eq!(*vals.next().unwrap(), 18);
     /// assert_eq!(vals.next(), None);
     /// ```
-    pub fn values_mut(&mut self) -> ValuesMut<K, V> {
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
         ValuesMut {
             iter: self.iter_mut(),
         }
     }
 
-    /// An iterator that visits all keys
+    /// Returns an iterator over the key-value pairs
+    /// whose keys fall within the given range, in
+    /// their key's partialord order. The iterator is
+    /// empty if the range's start comes after its end.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let map: RBMap<_, _> = (0..10).map(|k| (k, k * 10)).collect();
+    /// let mut range = map.range(4..6);
+    /// assert_eq!(range.next(), Some((&4, &40)));
+    /// assert_eq!(range.next(), Some((&5, &50)));
+    /// assert_eq!(range.next(), None);
+    /// assert_eq!(map.range(..=2).count(), 3);
+    /// ```
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: PartialOrd + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        Range {
+            inner: self.map.range((
+                mapper_bound(range.start_bound()),
+                mapper_bound(range.end_bound()),
+            )),
+        }
+    }
+
+    /// Returns an iterator over the key-value pairs whose
+    /// keys' positions in the map's ordering (counting from
+    /// 0) fall within the given range, in order. Finding the
+    /// first pair takes O(log n) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// // the second page of a leaderboard, ten to a page
+    /// let board: RBMap<_, _> = (0..100).map(|score| (score, score % 7)).collect();
+    /// let page: Vec<_> = board.select_range(10..20).map(|(score, _)| *score).collect();
+    /// assert_eq!(page, (10..20).collect::<Vec<_>>());
+    /// assert_eq!(board.select_range(95..).count(), 5);
+    /// ```
+    pub fn select_range<R: RangeBounds<usize>>(&self, range: R) -> Range<'_, K, V> {
+        Range {
+            inner: self.map.select_range(range),
+        }
+    }
+
+    /// Returns an iterator over the key-value pairs
+    /// from the first whose key is not less than `key`
+    /// onwards, in their key's partialord order. Finding
+    /// the start takes O(log n) time, so a map can be
+    /// paged through from the last key seen.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let map: RBMap<_, _> = (0..100).map(|k| (k * 2, k)).collect();
+    /// let page: Vec<_> = map.iter_from(&51).take(3).collect();
+    /// assert_eq!(page, vec!((&52, &26), (&54, &27), (&56, &28)));
+    ///
+    /// // continuing after the last key of the page
+    /// let (last, _) = page[2];
+    /// let next: Vec<_> = map.iter_from(last).skip(1).take(1).collect();
+    /// assert_eq!(next, vec!((&58, &29)));
+    /// ```
+    pub fn iter_from<Q: PartialOrd + ?Sized>(&self, key: &Q) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+    {
+        self.range((Included(key), Unbounded))
+    }
+
+    /// Returns an iterator over the keys that fall
+    /// within the given range, in their partialord order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let map: RBMap<_, _> = (0..10).map(|k| (k, k * 10)).collect();
+    /// assert_eq!(map.keys_range(7..).collect::<Vec<_>>(), vec!(&7, &8, &9));
+    /// ```
+    pub fn keys_range<Q, R>(&self, range: R) -> KeysRange<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: PartialOrd + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        KeysRange {
+            inner: self.range(range),
+        }
+    }
+
+    /// Returns an iterator over the values whose keys
+    /// fall within the given range, in their key's
+    /// partialord order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let map: RBMap<_, _> = (0..10).map(|k| (k, k * 10)).collect();
+    /// assert_eq!(map.values_range(..2).collect::<Vec<_>>(), vec!(&0, &10));
+    /// ```
+    pub fn values_range<Q, R>(&self, range: R) -> ValuesRange<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: PartialOrd + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        ValuesRange {
+            inner: self.range(range),
+        }
+    }
+
+    /// Returns the number of entries whose keys fall
+    /// within the given range, in O(log n) time rather
+    /// than by visiting them.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let map: RBMap<_, _> = (0..100).map(|k| (k, ())).collect();
+    /// assert_eq!(map.count_range(10..20), 10);
+    /// assert_eq!(map.count_range(90..), 10);
+    /// assert_eq!(map.count_range(..=0), 1);
+    /// assert_eq!(map.count_range(200..), 0);
+    /// ```
+    pub fn count_range<Q, R>(&self, range: R) -> usize
+    where
+        K: Borrow<Q>,
+        Q: PartialOrd + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        self.map.count_range((
+            mapper_bound(range.start_bound()),
+            mapper_bound(range.end_bound()),
+        ))
+    }
+
+    /// Returns an iterator over the key-value pairs
+    /// whose keys fall within the given range, in
+    /// their key's partialord order, presenting the
+    /// values as mutable.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map: RBMap<_, _> = (0..10).map(|k| (k, k * 10)).collect();
+    /// map.range_mut(4..=5).for_each(|(_, v)| *v += 1);
+    /// assert_eq!(map.get(&3), Some(&30));
+    /// assert_eq!(map.get(&4), Some(&41));
+    /// assert_eq!(map.get(&5), Some(&51));
+    /// ```
+    pub fn range_mut<Q, R>(&mut self, range: R) -> RangeMut<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: PartialOrd + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        RangeMut {
+            inner: self.map.range_mut((
+                mapper_bound(range.start_bound()),
+                mapper_bound(range.end_bound()),
+            )),
+        }
+    }
+
+    /// An iterator that visits all keys
     /// in their partialord order.
     /// # Example:
     /// ```
@@ -614,13 +1495,33 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// assert_eq!(*keys.next().unwrap(), 3);
     /// assert_eq!(keys.next(), None);
     /// ```
-    pub fn keys(&self) -> Keys<K, V> {
+    pub fn keys(&self) -> Keys<'_, K, V> {
         Keys {
-            pos: 0,
-            ordered: self.ordered(),
+            iter: self.map.iter(),
         }
     }
 
+    /// Returns the value associated with the key, first
+    /// associating the result of `f` with it if it has no
+    /// value, in a single descent of the tree.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut index: RBMap<String, Vec<usize>> = RBMap::new();
+    /// for (pos, word) in "to be or not to be".split(' ').enumerate() {
+    ///     index.get_or_insert_with(word.to_string(), Vec::new).push(pos);
+    /// }
+    /// assert_eq!(index.get("be"), Some(&vec!(1, 5)));
+    /// ```
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let at = match self.map.raw.locate(&KeyRef(&key)) {
+            Ok(at) => at,
+            Err((parent, right)) => self.link(parent, right, key, f()),
+        };
+        self.map.raw.nodes.value_mut(at).unwrap().as_mut()
+    }
+
     /// Provides an interface for ensuring values
     /// are allocated to the given key.
     /// # Example:
@@ -633,11 +1534,389 @@ impl<K: PartialOrd, V> RBMap<K, V> {
     /// *val = 3;
     /// assert_eq!(*map.get(&1).unwrap(), 3);
     /// ```
-    pub fn entry(&mut self, key: K) -> Entry<K, V> {
-        Entry { map: self, key }
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        match self.map.raw.locate(&KeyRef(&key)) {
+            Ok(at) => Entry::Occupied(OccupiedEntry { map: self, at }),
+            Err((parent, right)) => Entry::Vacant(VacantEntry {
+                map: self,
+                key,
+                parent,
+                right,
+            }),
+        }
+    }
+
+    /// Provides an interface for ensuring values are allocated
+    /// to the given key, like `entry`, but only creating an
+    /// owned key (with `to_owned`) if a value is actually
+    /// inserted for it. This avoids building a key, such as
+    /// a `String`, just to look up a value that is already there.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut counts: RBMap<String, usize> = RBMap::new();
+    /// for word in "the cat and the hat".split(' ') {
+    ///     *counts.entry_ref(word).or_insert(0) += 1;
+    /// }
+    /// assert_eq!(counts.get("the"), Some(&2));
+    /// assert_eq!(counts.get("cat"), Some(&1));
+    /// ```
+    pub fn entry_ref<'b, Q>(&mut self, key: &'b Q) -> EntryRef<'_, 'b, K, Q, V>
+    where
+        K: Borrow<Q>,
+        Q: PartialOrd + ToOwned<Owned = K> + ?Sized,
+    {
+        match self.map.raw.locate(&KeyRef(key)) {
+            Ok(at) => EntryRef::Occupied(OccupiedEntry { map: self, at }),
+            Err((parent, right)) => EntryRef::Vacant(VacantEntryRef {
+                map: self,
+                key,
+                parent,
+                right,
+            }),
+        }
+    }
+
+    /// Returns the entry with the smallest key, or None
+    /// if the map is empty, so it can be inspected and then
+    /// mutated or removed without searching for it again.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map: RBMap<_, _> = (1..=3).map(|k| (k, k * 10)).collect();
+    /// let first = map.first_entry().unwrap();
+    /// if *first.get() < 20 {
+    ///     first.remove();
+    /// }
+    /// assert_eq!(map.peek_pair(), Some((&2, &20)));
+    /// ```
+    pub fn first_entry(&mut self) -> Option<OccupiedEntry<'_, K, V>> {
+        self.outer_entry(false)
+    }
+
+    /// Returns the entry with the largest key, or None
+    /// if the map is empty, so it can be inspected and then
+    /// mutated or removed without searching for it again.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map: RBMap<_, _> = (1..=3).map(|k| (k, k * 10)).collect();
+    /// *map.last_entry().unwrap().get_mut() += 1;
+    /// assert_eq!(map.peek_pair_back(), Some((&3, &31)));
+    /// ```
+    pub fn last_entry(&mut self) -> Option<OccupiedEntry<'_, K, V>> {
+        self.outer_entry(true)
+    }
+
+    /// Updates the value associated with the key in
+    /// a single descent of the tree. `f` is given the
+    /// current value, if any, and returns the value
+    /// to associate with the key, or None to remove
+    /// it from the map. Returns the value now associated
+    /// with the key, if any.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map = RBMap::new();
+    /// map.update("a", |v| Some(v.unwrap_or(0) + 1));
+    /// map.update("a", |v| Some(v.unwrap_or(0) + 1));
+    /// assert_eq!(map.get(&"a"), Some(&2));
+    /// assert_eq!(map.update("a", |_| None), None);
+    /// assert!(map.is_empty());
+    /// ```
+    pub fn update<F>(&mut self, key: K, f: F) -> Option<&mut V>
+    where
+        F: FnOnce(Option<V>) -> Option<V>,
+    {
+        match self.map.raw.locate(&KeyRef(&key)) {
+            Ok(at) => {
+                // the entry is removed when the guard is dropped
+                // unless its value is put back, even if f panics
+                let taken = Taken { map: self, at };
+                let mapper = taken.map.map.raw.nodes.value_mut(at).unwrap();
+                let old = mapper.val_mut().take().unwrap();
+                notify(&mut taken.map.observers, true, mapper.key(), &old);
+                let new = f(Some(old));
+                let kept = new.is_some();
+                *taken.map.map.raw.nodes.value_mut(at).unwrap().val_mut() = new;
+                drop(taken);
+                if kept {
+                    self.inserted(at);
+                    Some(self.map.raw.nodes.value_mut(at).unwrap().as_mut())
+                } else {
+                    None
+                }
+            }
+            Err((parent, right)) => {
+                let val = f(None)?;
+                let at = self.link(parent, right, key, val);
+                Some(self.map.raw.nodes.value_mut(at).unwrap().as_mut())
+            }
+        }
+    }
+
+    /// Consumes the map, producing a map with the same
+    /// keys whose values are the results of `f` applied
+    /// to the values of this map, in their key's order.
+    /// The tree is reused as is rather than rebuilt.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let map: RBMap<_, _> = (0..3).map(|k| (k, k * 10)).collect();
+    /// let strings = map.map_values(|v| v.to_string());
+    /// assert_eq!(strings.get(&2), Some(&"20".to_string()));
+    /// ```
+    pub fn map_values<W, F>(self, mut f: F) -> RBMap<K, W>
+    where
+        F: FnMut(V) -> W,
+    {
+        RBMap {
+            map: RBTree {
+                raw: RawTree {
+                    contained: self.map.raw.contained,
+                    nodes: self.map.raw.nodes.map_values(|m| m.map_val(&mut f)),
+                    cmp: self.map.raw.cmp,
+                },
+            },
+            observers: None,
+        }
+    }
+
+    /// Moves all the entries of `other` into this map.
+    /// Where a key is present in both maps, `f` is given
+    /// the key, this map's value and `other`'s value, and
+    /// returns the value to keep.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut counts = RBMap::new();
+    /// counts.insert("a", 2);
+    /// counts.insert("b", 1);
+    /// let mut shard = RBMap::new();
+    /// shard.insert("b", 3);
+    /// shard.insert("c", 1);
+    /// counts.merge_with(shard, |_, l, r| l + r);
+    /// assert_eq!(counts.get(&"a"), Some(&2));
+    /// assert_eq!(counts.get(&"b"), Some(&4));
+    /// assert_eq!(counts.get(&"c"), Some(&1));
+    /// ```
+    pub fn merge_with<F>(&mut self, other: RBMap<K, V>, mut f: F)
+    where
+        F: FnMut(&K, V, V) -> V,
+    {
+        for (key, val) in other {
+            match self.map.raw.locate(&KeyRef(&key)) {
+                Ok(at) => {
+                    // removes the entry if f panics
+                    let taken = Taken { map: self, at };
+                    let mapper = taken.map.map.raw.nodes.value_mut(at).unwrap();
+                    let old = mapper.val_mut().take().unwrap();
+                    notify(&mut taken.map.observers, true, mapper.key(), &old);
+                    let new = f(mapper.key(), old, val);
+                    *mapper.val_mut() = Some(new);
+                    drop(taken);
+                    self.inserted(at);
+                }
+                Err((parent, right)) => {
+                    self.link(parent, right, key, val);
+                }
+            }
+        }
+    }
+
+    /// Registers `f` to be called with each pair placed
+    /// in the map, once it is in place, so that something
+    /// layered on the map, such as a secondary index, can be
+    /// kept in step with it. A pair whose value is replaced,
+    /// whether by `insert`, an entry, `update` or the like,
+    /// is reported as removed (with its old key and value)
+    /// and then inserted. Changes made through a mutable
+    /// reference to a value, such as those `get_mut` or
+    /// `iter_mut` give out, are not reported. Observers are
+    /// not cloned with the map, nor told of the pairs a map
+    /// is consumed or dropped with.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// // a secondary index from each city to the users in it
+    /// let by_city = Arc::new(Mutex::new(RBMap::<&str, Vec<u32>>::new()));
+    /// let mut users = RBMap::new();
+    /// let index = by_city.clone();
+    /// users.on_insert(move |id: &u32, city: &&str| {
+    ///     index.lock().unwrap().entry(*city).or_default().push(*id);
+    /// });
+    /// let index = by_city.clone();
+    /// users.on_remove(move |id, city| {
+    ///     let mut index = index.lock().unwrap();
+    ///     index.get_mut(city).unwrap().retain(|other| other != id);
+    /// });
+    ///
+    /// users.insert(1, "paris");
+    /// users.insert(2, "oslo");
+    /// users.insert(1, "oslo");
+    /// assert_eq!(by_city.lock().unwrap().get(&"oslo"), Some(&vec!(2, 1)));
+    /// users.remove(&2);
+    /// assert_eq!(by_city.lock().unwrap().get(&"oslo"), Some(&vec!(1)));
+    /// assert_eq!(by_city.lock().unwrap().get(&"paris"), Some(&vec!()));
+    /// ```
+    pub fn on_insert<F>(&mut self, f: F)
+    where
+        F: FnMut(&K, &V) + Send + Sync + 'static,
+    {
+        self.observers
+            .get_or_insert_with(Default::default)
+            .inserted
+            .push(Box::new(f));
+    }
+
+    /// Registers `f` to be called with each pair taken
+    /// out of the map, once it has been, whether it is
+    /// handed back (as by `remove` or `pop`) or dropped
+    /// (as by `retain` or `clear`). `drain` reports all the
+    /// pairs when called. See `on_insert` for the changes
+    /// that are and aren't reported.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let evicted = Arc::new(Mutex::new(Vec::new()));
+    /// let log = evicted.clone();
+    /// let mut cache: RBMap<_, _> = (1..=5).map(|k| (k, k * k)).collect();
+    /// cache.on_remove(move |k, v| log.lock().unwrap().push((*k, *v)));
+    /// cache.retain(|k, _| k % 2 == 1);
+    /// cache.pop();
+    /// assert_eq!(*evicted.lock().unwrap(), vec!((2, 4), (4, 16), (1, 1)));
+    /// ```
+    pub fn on_remove<F>(&mut self, f: F)
+    where
+        F: FnMut(&K, &V) + Send + Sync + 'static,
+    {
+        self.observers
+            .get_or_insert_with(Default::default)
+            .removed
+            .push(Box::new(f));
+    }
+
+    /// Unregisters every observer added with `on_insert`
+    /// or `on_remove`.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// let seen = Arc::new(AtomicUsize::new(0));
+    /// let counter = seen.clone();
+    /// let mut map = RBMap::new();
+    /// map.on_insert(move |_, _| {
+    ///     counter.fetch_add(1, Ordering::Relaxed);
+    /// });
+    /// map.insert(1, 'a');
+    /// map.clear_observers();
+    /// map.insert(2, 'b');
+    /// assert_eq!(seen.load(Ordering::Relaxed), 1);
+    /// ```
+    pub fn clear_observers(&mut self) {
+        self.observers = None;
     }
 
     // internal helper methods
+    // adds a pair where a search for its key ended
+    fn link(&mut self, parent: Index, right: bool, key: K, val: V) -> Index {
+        self.map.raw.contained += 1;
+        let at = self
+            .map
+            .raw
+            .nodes
+            .insert_at(parent, right, Mapper::new(key, Some(val)));
+        self.map.raw.verify();
+        self.inserted(at);
+        at
+    }
+
+    // tells the observers of the pair just placed at `at`
+    fn inserted(&mut self, at: Index) {
+        if self.observers.is_some() {
+            let (key, val) = self.map.raw.nodes.value(at).unwrap().pair();
+            notify(&mut self.observers, false, key, val);
+        }
+    }
+
+    // tells the observers of a pair just taken out
+    // of the map, handing it back
+    fn removed(&mut self, pair: (K, V)) -> (K, V) {
+        notify(&mut self.observers, true, &pair.0, &pair.1);
+        pair
+    }
+
+    // tells the observers of every pair of a tree
+    // just taken from the map
+    fn removed_all(&mut self, old: &RBTree<Mapper<K, V>>) {
+        if self.observers.is_some() {
+            for (key, val) in old.iter().map(|m| m.pair()) {
+                notify(&mut self.observers, true, key, val);
+            }
+        }
+    }
+
+    fn outer_entry(&mut self, back: bool) -> Option<OccupiedEntry<'_, K, V>> {
+        let nodes = &self.map.raw.nodes;
+        let at = if back { nodes.last() } else { nodes.first() };
+        if at == NIL {
+            return None;
+        }
+        Some(OccupiedEntry { map: self, at })
+    }
+
+    // keeps the pairs whose keys are in `keys` if `present`
+    // is true, or those whose keys aren't otherwise
+    fn filter_keys(&mut self, keys: &RBTree<K>, present: bool) {
+        let mut keys = keys.iter().peekable();
+        let observers = &mut self.observers;
+        self.map.raw.contained -= self.map.raw.nodes.retain(|m| {
+            let key = m.key();
+            while keys.next_if(|k| *k < key).is_some() {}
+            let keep = (keys.peek() == Some(&key)) == present;
+            if !keep {
+                notify(observers, true, key, m.as_ref());
+            }
+            keep
+        });
+    }
+
+    fn pop_outer_if<F: FnOnce(&K, &V) -> bool>(&mut self, back: bool, pred: F) -> Option<(K, V)> {
+        let nodes = &self.map.raw.nodes;
+        let at = if back { nodes.last() } else { nodes.first() };
+        if at == NIL {
+            return None;
+        }
+        let (key, val) = self.map.raw.nodes.value(at).unwrap().pair();
+        if !pred(key, val) {
+            return None;
+        }
+        self.map.raw.contained -= 1;
+        let pair = self.map.raw.nodes.remove_at(at).consume();
+        Some(self.removed(pair))
+    }
+
+    fn outer_mut(&mut self, back: bool) -> Option<&mut Mapper<K, V>> {
+        let nodes = &self.map.raw.nodes;
+        let at = if back { nodes.last() } else { nodes.first() };
+        if at == NIL {
+            return None;
+        }
+        self.map.raw.nodes.value_mut(at)
+    }
+
     fn ordered(&self) -> Vec<(&K, &V)> {
         self.map.iter().map(|m| (m.key(), m.as_ref())).collect()
     }
@@ -659,11 +1938,7 @@ impl<K: PartialOrd, V: PartialOrd> RBMap<K, V> {
     /// assert!(!vset.contains(&&"Foo"));
     /// ```
     pub fn valueset(&self) -> RBTree<&V> {
-        let mut values = RBTree::new();
-        for value in self.values() {
-            values.insert(value);
-        }
-        values
+        set_of(self.values().collect())
     }
 
     /// Creates a set of keys and a set of values
@@ -687,13 +1962,15 @@ impl<K: PartialOrd, V: PartialOrd> RBMap<K, V> {
     /// assert!(!vset.contains(&"Foo"));
     /// ```
     pub fn into_sets(self) -> (RBTree<K>, RBTree<V>) {
-        let mut kset = RBTree::new();
-        let mut vset = RBTree::new();
-        for (key, value) in self.into_iter() {
-            kset.insert(key);
-            vset.insert(value);
-        }
-        (kset, vset)
+        let (keys, values) = self
+            .map
+            .raw
+            .nodes
+            .into_ordered()
+            .into_iter()
+            .map(Mapper::consume)
+            .unzip();
+        (RBTree::from_sorted_vec(keys, NaturalOrder), set_of(values))
     }
 
     /// Creates an RBTree set from the values
@@ -711,20 +1988,85 @@ impl<K: PartialOrd, V: PartialOrd> RBMap<K, V> {
     /// assert!(!vset.contains(&"Foo"));
     /// ```
     pub fn into_valueset(self) -> RBTree<V> {
-        let mut vset = RBTree::new();
-        for (_, value) in self.into_iter() {
-            vset.insert(value);
-        }
-        vset
+        set_of(
+            self.map
+                .raw
+                .nodes
+                .into_ordered()
+                .into_iter()
+                .map(|m| m.consume().1)
+                .collect(),
+        )
     }
 }
 
+// builds a set of values given in any order by sorting
+// them rather than inserting them one by one, keeping
+// the last of any equal values as insertion would
+fn set_of<T: PartialOrd>(mut values: Vec<T>) -> RBTree<T> {
+    values.sort_by(|l, r| NaturalOrder.compare(l, r));
+    values.dedup_by(|later, kept| {
+        let equal = NaturalOrder.compare(later, kept) == Equal;
+        if equal {
+            std::mem::swap(later, kept);
+        }
+        equal
+    });
+    RBTree::from_sorted_vec(values, NaturalOrder)
+}
+
 impl<K: PartialOrd, V> Default for RBMap<K, V> {
     fn default() -> Self {
         RBMap::new()
     }
 }
 
+/// Serialises the map as a map of its pairs, in key
+/// order, so that a map with string keys appears as
+/// an object in formats like JSON.
+#[cfg(feature = "serde")]
+impl<K: PartialOrd + Serialize, V: Serialize> Serialize for RBMap<K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+/// Deserialises the map from any map of pairs, whatever
+/// order they are in; where a key appears more than once
+/// the last of its values is kept.
+#[cfg(feature = "serde")]
+impl<'de, K: PartialOrd + Deserialize<'de>, V: Deserialize<'de>> Deserialize<'de> for RBMap<K, V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        deserializer.deserialize_map(MapVisitor(PhantomData))
+    }
+}
+
+#[cfg(feature = "serde")]
+struct MapVisitor<K, V>(PhantomData<fn() -> (K, V)>);
+
+#[cfg(feature = "serde")]
+impl<'de, K: PartialOrd + Deserialize<'de>, V: Deserialize<'de>> Visitor<'de> for MapVisitor<K, V> {
+    type Value = RBMap<K, V>;
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> Result {
+        f.write_str("a map")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(
+        self,
+        mut access: A,
+    ) -> std::result::Result<RBMap<K, V>, A::Error> {
+        // pairs that arrive in order, as they do from a
+        // serialised RBMap, are each appended in O(1) time
+        let mut map = RBMap::new();
+        while let Some((key, val)) = access.next_entry()? {
+            map.insert(key, val);
+        }
+        Ok(map)
+    }
+}
+
+#[derive(Clone)]
 pub struct IntoIter<K: PartialOrd, V> {
     tree: RBTree<Mapper<K, V>>,
 }
@@ -764,6 +2106,14 @@ impl<K: PartialOrd, V> ExactSizeIterator for IntoIter<K, V> {
 
 impl<K: PartialOrd, V> FusedIterator for IntoIter<K, V> {}
 
+impl<K: PartialOrd + Debug, V: Debug> Debug for IntoIter<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list()
+            .entries(self.tree.iter().map(|m| m.pair()))
+            .finish()
+    }
+}
+
 impl<K: PartialOrd, V> IntoIterator for RBMap<K, V> {
     type Item = (K, V);
     type IntoIter = IntoIter<K, V>;
@@ -791,97 +2141,217 @@ impl<K: PartialOrd, V> Extend<(K, V)> for RBMap<K, V> {
     }
 }
 
-impl<'a, K: PartialOrd + Copy + 'a, V: Copy + 'a> Extend<(&'a K, &'a V)> for RBMap<K, V> {
+/// Clones each pair into the map.
+/// # Example:
+/// ```
+/// use rb_tree::RBMap;
+///
+/// let mut defaults = RBMap::new();
+/// defaults.insert("editor".to_string(), vec!("vi".to_string()));
+/// let mut config = RBMap::new();
+/// config.extend(defaults.iter());
+/// assert_eq!(config.get("editor"), Some(&vec!("vi".to_string())));
+/// ```
+impl<'a, K: PartialOrd + Clone + 'a, V: Clone + 'a> Extend<(&'a K, &'a V)> for RBMap<K, V> {
     fn extend<I: IntoIterator<Item = (&'a K, &'a V)>>(&mut self, iter: I) {
-        for (&key, &val) in iter {
-            self.insert(key, val);
+        for (key, val) in iter {
+            self.insert(key.clone(), val.clone());
+        }
+    }
+}
+
+/// Builds the map in linear time, since the
+/// entries of a BTreeMap are already in order.
+/// Assumes the `Ord` and `PartialOrd` orderings
+/// of the keys agree.
+/// # Example:
+/// ```
+/// use rb_tree::RBMap;
+/// use std::collections::BTreeMap;
+///
+/// let b: BTreeMap<_, _> = (0..5).map(|k| (k, k * k)).collect();
+/// let map = RBMap::from(b);
+/// assert_eq!(map.get(&3), Some(&9));
+/// assert_eq!(map.len(), 5);
+/// ```
+impl<K: Ord, V> From<BTreeMap<K, V>> for RBMap<K, V> {
+    fn from(map: BTreeMap<K, V>) -> Self {
+        let entries = map
+            .into_iter()
+            .map(|(key, val)| Mapper::new(key, Some(val)))
+            .collect();
+        RBMap {
+            map: RBTree::from_sorted_vec(entries, NaturalOrder),
+            observers: None,
         }
     }
 }
 
-// this should be fine to do since only one
-// borrow can occur when mutable
+impl<K: PartialOrd, V, S> From<HashMap<K, V, S>> for RBMap<K, V> {
+    fn from(map: HashMap<K, V, S>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+/// Converts the map back into a std map, as do
+/// the conversions into HashMap.
+/// # Example:
+/// ```
+/// use rb_tree::RBMap;
+/// use std::collections::{BTreeMap, HashMap};
+///
+/// let map: RBMap<_, _> = vec!((2, "b"), (1, "a")).into_iter().collect();
+/// let b = BTreeMap::from(map.clone());
+/// assert_eq!(b.into_iter().collect::<Vec<_>>(), vec!((1, "a"), (2, "b")));
+/// let h: HashMap<_, _> = map.into();
+/// assert_eq!(h[&2], "b");
+/// ```
+impl<K: Ord, V> From<RBMap<K, V>> for BTreeMap<K, V> {
+    fn from(map: RBMap<K, V>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl<K: PartialOrd + Hash + Eq, V, S: BuildHasher + Default> From<RBMap<K, V>>
+    for HashMap<K, V, S>
+{
+    fn from(map: RBMap<K, V>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
 pub struct Iter<'a, K: PartialOrd, V> {
-    pos: usize,
-    ordered: Vec<(&'a K, &'a V)>,
+    iter: rbtree::Iter<'a, Mapper<K, V>>,
 }
 
 impl<'a, K: PartialOrd, V> Iterator for Iter<'a, K, V> {
     type Item = (&'a K, &'a V);
 
     fn next(&mut self) -> Option<(&'a K, &'a V)> {
-        match self.ordered.get(self.pos) {
-            Some(v) => {
-                self.pos += 1;
-                Some(*v)
-            }
-            None => None,
-        }
+        self.iter.next().map(Mapper::pair)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, K: PartialOrd, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        self.iter.next_back().map(Mapper::pair)
     }
 }
 
 impl<'a, K: PartialOrd, V> ExactSizeIterator for Iter<'a, K, V> {
     fn len(&self) -> usize {
-        self.ordered.len() - self.pos
+        self.iter.len()
     }
 }
 
 impl<'a, K: PartialOrd, V> FusedIterator for Iter<'a, K, V> {}
 
+impl<'a, K: PartialOrd, V> Clone for Iter<'a, K, V> {
+    fn clone(&self) -> Self {
+        Iter {
+            iter: self.iter.clone(),
+        }
+    }
+}
+
+impl<'a, K: PartialOrd + Debug, V: Debug> Debug for Iter<'a, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
 pub struct Keys<'a, K: PartialOrd, V> {
-    pos: usize,
-    ordered: Vec<(&'a K, &'a V)>,
+    iter: rbtree::Iter<'a, Mapper<K, V>>,
 }
 
 impl<'a, K: PartialOrd, V> Iterator for Keys<'a, K, V> {
     type Item = &'a K;
 
     fn next(&mut self) -> Option<&'a K> {
-        match self.ordered.get(self.pos) {
-            Some(v) => {
-                self.pos += 1;
-                Some(v.0)
-            }
-            None => None,
-        }
+        self.iter.next().map(Mapper::key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, K: PartialOrd, V> DoubleEndedIterator for Keys<'a, K, V> {
+    fn next_back(&mut self) -> Option<&'a K> {
+        self.iter.next_back().map(Mapper::key)
     }
 }
 
 impl<'a, K: PartialOrd, V> ExactSizeIterator for Keys<'a, K, V> {
     fn len(&self) -> usize {
-        self.ordered.len() - self.pos
+        self.iter.len()
     }
 }
 
 impl<'a, K: PartialOrd, V> FusedIterator for Keys<'a, K, V> {}
 
+impl<'a, K: PartialOrd, V> Clone for Keys<'a, K, V> {
+    fn clone(&self) -> Self {
+        Keys {
+            iter: self.iter.clone(),
+        }
+    }
+}
+
+impl<'a, K: PartialOrd + Debug, V> Debug for Keys<'a, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
 pub struct Values<'a, K: PartialOrd, V> {
-    pos: usize,
-    ordered: Vec<(&'a K, &'a V)>,
+    iter: rbtree::Iter<'a, Mapper<K, V>>,
 }
 
 impl<'a, K: PartialOrd, V> Iterator for Values<'a, K, V> {
     type Item = &'a V;
 
     fn next(&mut self) -> Option<&'a V> {
-        match self.ordered.get(self.pos) {
-            Some(v) => {
-                self.pos += 1;
-                Some(v.1)
-            }
-            None => None,
-        }
+        self.iter.next().map(Mapper::as_ref)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, K: PartialOrd, V> DoubleEndedIterator for Values<'a, K, V> {
+    fn next_back(&mut self) -> Option<&'a V> {
+        self.iter.next_back().map(Mapper::as_ref)
     }
 }
 
 impl<'a, K: PartialOrd, V> ExactSizeIterator for Values<'a, K, V> {
     fn len(&self) -> usize {
-        self.ordered.len() - self.pos
+        self.iter.len()
     }
 }
 
 impl<'a, K: PartialOrd, V> FusedIterator for Values<'a, K, V> {}
 
+impl<'a, K: PartialOrd, V> Clone for Values<'a, K, V> {
+    fn clone(&self) -> Self {
+        Values {
+            iter: self.iter.clone(),
+        }
+    }
+}
+
+impl<'a, K: PartialOrd, V: Debug> Debug for Values<'a, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
 pub struct ValuesMut<'a, K: PartialOrd, V> {
     iter: IterMut<'a, K, V>,
 }
@@ -897,6 +2367,12 @@ impl<'a, K: PartialOrd, V> Iterator for ValuesMut<'a, K, V> {
     }
 }
 
+impl<'a, K: PartialOrd, V> DoubleEndedIterator for ValuesMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<&'a mut V> {
+        self.iter.next_back().map(|v| v.1)
+    }
+}
+
 impl<'a, K: PartialOrd, V> ExactSizeIterator for ValuesMut<'a, K, V> {
     fn len(&self) -> usize {
         self.iter.len()
@@ -905,25 +2381,35 @@ impl<'a, K: PartialOrd, V> ExactSizeIterator for ValuesMut<'a, K, V> {
 
 impl<'a, K: PartialOrd, V> FusedIterator for ValuesMut<'a, K, V> {}
 
+impl<'a, K: PartialOrd, V: Debug> Debug for ValuesMut<'a, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let mut list = f.debug_list();
+        self.iter.iter.for_each_remaining(|m| {
+            list.entry(m.as_ref());
+        });
+        list.finish()
+    }
+}
+
 pub struct IterMut<'a, K: PartialOrd, V> {
-    iter: rbtree::Iter<'a, Mapper<K, V>>,
+    iter: rbtree::IterMut<'a, Mapper<K, V>>,
 }
 
 impl<'a, K: PartialOrd, V> Iterator for IterMut<'a, K, V> {
     type Item = (&'a K, &'a mut V);
 
     fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
-        let next = self.iter.next();
-        match next {
-            Some(iv) => {
-                let v = unsafe {
-                    let ptr = iv as *const Mapper<K, V>;
-                    &mut *(ptr as *mut Mapper<K, V>)
-                };
-                Some(v.mut_pair())
-            }
-            None => None,
-        }
+        self.iter.next().map(Mapper::mut_pair)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, K: PartialOrd, V> DoubleEndedIterator for IterMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a mut V)> {
+        self.iter.next_back().map(Mapper::mut_pair)
     }
 }
 
@@ -935,6 +2421,17 @@ impl<'a, K: PartialOrd, V> ExactSizeIterator for IterMut<'a, K, V> {
 
 impl<'a, K: PartialOrd, V> FusedIterator for IterMut<'a, K, V> {}
 
+impl<'a, K: PartialOrd + Debug, V: Debug> Debug for IterMut<'a, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let mut list = f.debug_list();
+        self.iter.for_each_remaining(|m| {
+            list.entry(&m.pair());
+        });
+        list.finish()
+    }
+}
+
+#[derive(Clone)]
 pub struct Drain<K: PartialOrd, V> {
     tree: RBTree<Mapper<K, V>>,
 }
@@ -955,66 +2452,701 @@ impl<K: PartialOrd, V> ExactSizeIterator for Drain<K, V> {
 
 impl<K: PartialOrd, V> FusedIterator for Drain<K, V> {}
 
-pub struct Entry<'a, K: PartialOrd, V> {
+impl<K: PartialOrd + Debug, V: Debug> Debug for Drain<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list()
+            .entries(self.tree.iter().map(|m| m.pair()))
+            .finish()
+    }
+}
+
+pub struct PopWhile<'a, K: PartialOrd, V, F: FnMut(&K, &V) -> bool> {
+    map: &'a mut RBMap<K, V>,
+    pred: F,
+    done: bool,
+}
+
+impl<'a, K: PartialOrd, V, F: FnMut(&K, &V) -> bool> Iterator for PopWhile<'a, K, V, F> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        if self.done {
+            return None;
+        }
+        match self.map.peek_pair() {
+            Some((k, v)) if (self.pred)(k, v) => self.map.pop_pair(),
+            _ => {
+                self.done = true;
+                None
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            (0, Some(0))
+        } else {
+            (0, Some(self.map.len()))
+        }
+    }
+}
+
+impl<'a, K: PartialOrd, V, F: FnMut(&K, &V) -> bool> FusedIterator for PopWhile<'a, K, V, F> {}
+
+impl<'a, K: PartialOrd + Debug, V: Debug, F: FnMut(&K, &V) -> bool> Debug
+    for PopWhile<'a, K, V, F>
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_struct("PopWhile")
+            .field("map", &self.map)
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+// turns a bound on keys into one that can be compared
+// against the entries of the underlying tree
+fn mapper_bound<Q: ?Sized>(bound: Bound<&Q>) -> Bound<KeyRef<'_, Q>> {
+    match bound {
+        Included(k) => Included(KeyRef(k)),
+        Excluded(k) => Excluded(KeyRef(k)),
+        Unbounded => Unbounded,
+    }
+}
+
+pub struct Range<'a, K: PartialOrd, V> {
+    inner: rbtree::Range<'a, Mapper<K, V>>,
+}
+
+impl<'a, K: PartialOrd, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        self.inner.next().map(|v| v.pair())
+    }
+}
+
+impl<'a, K: PartialOrd, V> FusedIterator for Range<'a, K, V> {}
+
+impl<'a, K: PartialOrd, V> Clone for Range<'a, K, V> {
+    fn clone(&self) -> Self {
+        Range {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K: PartialOrd + Debug, V: Debug> Debug for Range<'a, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+pub struct KeysRange<'a, K: PartialOrd, V> {
+    inner: Range<'a, K, V>,
+}
+
+impl<'a, K: PartialOrd, V> Iterator for KeysRange<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.inner.next().map(|p| p.0)
+    }
+}
+
+impl<'a, K: PartialOrd, V> FusedIterator for KeysRange<'a, K, V> {}
+
+impl<'a, K: PartialOrd, V> Clone for KeysRange<'a, K, V> {
+    fn clone(&self) -> Self {
+        KeysRange {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K: PartialOrd + Debug, V> Debug for KeysRange<'a, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+pub struct ValuesRange<'a, K: PartialOrd, V> {
+    inner: Range<'a, K, V>,
+}
+
+impl<'a, K: PartialOrd, V> Iterator for ValuesRange<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<&'a V> {
+        self.inner.next().map(|p| p.1)
+    }
+}
+
+impl<'a, K: PartialOrd, V> FusedIterator for ValuesRange<'a, K, V> {}
+
+impl<'a, K: PartialOrd, V> Clone for ValuesRange<'a, K, V> {
+    fn clone(&self) -> Self {
+        ValuesRange {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K: PartialOrd, V: Debug> Debug for ValuesRange<'a, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+pub struct RangeMut<'a, K: PartialOrd, V> {
+    inner: rbtree::IterMut<'a, Mapper<K, V>>,
+}
+
+impl<'a, K: PartialOrd, V> Iterator for RangeMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        self.inner.next().map(Mapper::mut_pair)
+    }
+}
+
+impl<'a, K: PartialOrd, V> FusedIterator for RangeMut<'a, K, V> {}
+
+impl<'a, K: PartialOrd + Debug, V: Debug> Debug for RangeMut<'a, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let mut list = f.debug_list();
+        self.inner.for_each_remaining(|m| {
+            list.entry(&m.pair());
+        });
+        list.finish()
+    }
+}
+
+// a callback registered with on_insert or on_remove
+type Observer<K, V> = Box<dyn FnMut(&K, &V) + Send + Sync>;
+
+// the callbacks registered with an RBMap
+pub(crate) struct Observers<K, V> {
+    inserted: Vec<Observer<K, V>>,
+    removed: Vec<Observer<K, V>>,
+}
+
+impl<K, V> Default for Observers<K, V> {
+    fn default() -> Self {
+        Observers {
+            inserted: Vec::new(),
+            removed: Vec::new(),
+        }
+    }
+}
+
+// calls the observers of a map with a pair that
+// was removed from it if `removed`, or inserted
+fn notify<K, V>(observers: &mut Option<Box<Observers<K, V>>>, removed: bool, key: &K, val: &V) {
+    if let Some(observers) = observers {
+        let called = if removed {
+            &mut observers.removed
+        } else {
+            &mut observers.inserted
+        };
+        called.iter_mut().for_each(|f| f(key, val));
+    }
+}
+
+// removes the entry at `at` when dropped if
+// it has been left without a value
+struct Taken<'a, K: PartialOrd, V> {
+    map: &'a mut RBMap<K, V>,
+    at: Index,
+}
+
+impl<'a, K: PartialOrd, V> Drop for Taken<'a, K, V> {
+    fn drop(&mut self) {
+        let nodes = &mut self.map.map.raw.nodes;
+        if !nodes.value(self.at).unwrap().is_some() {
+            nodes.remove_at(self.at);
+            self.map.map.raw.contained -= 1;
+        }
+    }
+}
+
+/// A view into a single entry of an RBMap, which may
+/// either be vacant or occupied. Returned by `RBMap::entry`.
+pub enum Entry<'a, K: PartialOrd, V> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+/// An entry of an RBMap whose key has a value.
+pub struct OccupiedEntry<'a, K: PartialOrd, V> {
+    map: &'a mut RBMap<K, V>,
+    at: Index,
+}
+
+/// An entry of an RBMap whose key has no value,
+/// holding the place the key will be inserted.
+pub struct VacantEntry<'a, K: PartialOrd, V> {
     map: &'a mut RBMap<K, V>,
     key: K,
+    parent: Index,
+    right: bool,
 }
 
 /// Follows a similar implementation to std::collections::HashMap,
 /// in terms of behaviour, only differs in types used.
 /// For further detail about any given method, please refer
 /// to the documentation of HashMap::Entry.
-/// For the time being only copyable keys can utilise
-/// these methods
-impl<'a, K: PartialOrd + Copy, V> Entry<'a, K, V> {
+impl<'a, K: PartialOrd, V> Entry<'a, K, V> {
+    /// Sets the value of the entry, keeping the key
+    /// already in the map if there is one, and returns
+    /// the key and value.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map = RBMap::new();
+    /// map.insert("a", 1);
+    /// assert_eq!(map.entry("a").insert(2), (&"a", &mut 2));
+    /// assert_eq!(map.entry("b").insert(3), (&"b", &mut 3));
+    /// ```
     pub fn insert(self, val: V) -> (&'a K, &'a mut V) {
-        match self.map.remove_entry(&self.key) {
-            Some((k, _)) => {
-                self.map.insert(k, val);
-            }
-            None => {
-                self.map.insert(self.key, val);
+        let (map, at) = match self {
+            Entry::Occupied(mut e) => {
+                e.insert(val);
+                (e.map, e.at)
             }
-        }
-        self.map.get_pair_mut(&self.key).unwrap()
+            Entry::Vacant(e) => e.insert_at(val),
+        };
+        map.map.raw.nodes.value_mut(at).unwrap().mut_pair()
     }
 
-    pub fn and_modify<F>(self, f: F) -> Entry<'a, K, V>
+    /// Modifies the value of an occupied entry,
+    /// leaving a vacant entry as it is.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map = RBMap::new();
+    /// map.entry(1).and_modify(|v| *v += 1).or_insert(0);
+    /// map.entry(1).and_modify(|v| *v += 1).or_insert(0);
+    /// assert_eq!(map.get(&1), Some(&1));
+    /// ```
+    pub fn and_modify<F>(mut self, f: F) -> Entry<'a, K, V>
     where
         F: FnOnce(&mut V),
     {
-        if let Some(v) = self.map.get_mut(&self.key).as_mut() {
-            f(*v);
+        if let Entry::Occupied(e) = &mut self {
+            f(e.get_mut());
         }
         self
     }
 
+    /// Inserts the given value if the entry is vacant,
+    /// and returns the entry's value.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map = RBMap::new();
+    /// *map.entry("a".to_string()).or_insert(1) += 10;
+    /// assert_eq!(map.get(&"a".to_string()), Some(&11));
+    /// ```
     pub fn or_insert(self, default: V) -> &'a mut V {
-        if !self.map.contains_key(&self.key) {
-            self.map.insert(self.key, default);
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(default),
         }
-        self.map.get_mut(&self.key).unwrap()
     }
 
+    /// Inserts the result of `default` if the entry is
+    /// vacant, and returns the entry's value.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map = RBMap::new();
+    /// map.entry(1).or_insert_with(Vec::new).push(2);
+    /// assert_eq!(map.get(&1), Some(&vec!(2)));
+    /// ```
     pub fn or_insert_with<F>(self, default: F) -> &'a mut V
     where
         F: FnOnce() -> V,
     {
-        if !self.map.contains_key(&self.key) {
-            self.map.insert(self.key, default());
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(default()),
+        }
+    }
+
+    /// Inserts the result of `default`, which is given
+    /// the entry's key, if the entry is vacant, and
+    /// returns the entry's value.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map = RBMap::new();
+    /// let len = map.entry("four".to_string()).or_insert_with_key(|k| k.len());
+    /// assert_eq!(*len, 4);
+    /// ```
+    pub fn or_insert_with_key<F>(self, default: F) -> &'a mut V
+    where
+        F: FnOnce(&K) -> V,
+    {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => {
+                let val = default(&e.key);
+                e.insert(val)
+            }
+        }
+    }
+
+    /// Returns the key of the entry, which is the key
+    /// already in the map if the entry is occupied.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map: RBMap<&str, usize> = RBMap::new();
+    /// assert_eq!(map.entry("a").key(), &"a");
+    /// ```
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(e) => e.key(),
+            Entry::Vacant(e) => e.key(),
         }
-        self.map.get_mut(&self.key).unwrap()
     }
 }
 
-impl<'a, K: PartialOrd + Copy, V: Default> Entry<'a, K, V> {
-    pub fn or_default<F>(self) -> &'a mut V
+impl<'a, K: PartialOrd, V: Default> Entry<'a, K, V> {
+    /// Inserts the default value if the entry is
+    /// vacant, and returns the entry's value.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map: RBMap<&str, usize> = RBMap::new();
+    /// *map.entry("a").or_default() += 1;
+    /// assert_eq!(map.get(&"a"), Some(&1));
+    /// ```
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K: PartialOrd, V> OccupiedEntry<'a, K, V> {
+    fn mapper(&self) -> &Mapper<K, V> {
+        self.map.map.raw.nodes.value(self.at).unwrap()
+    }
+
+    fn mapper_mut(&mut self) -> &mut Mapper<K, V> {
+        self.map.map.raw.nodes.value_mut(self.at).unwrap()
+    }
+
+    /// Returns the key of the entry.
+    pub fn key(&self) -> &K {
+        self.mapper().key()
+    }
+
+    /// Returns the value of the entry.
+    pub fn get(&self) -> &V {
+        self.mapper().as_ref()
+    }
+
+    /// Returns the value of the entry as mutable.
+    pub fn get_mut(&mut self) -> &mut V {
+        self.mapper_mut().as_mut()
+    }
+
+    /// Returns the value of the entry as mutable
+    /// for as long as the map is borrowed.
+    pub fn into_mut(self) -> &'a mut V {
+        self.map.map.raw.nodes.value_mut(self.at).unwrap().as_mut()
+    }
+
+    /// Sets the value of the entry, returning the
+    /// previous value.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    /// use rb_tree::rbmap::Entry;
+    ///
+    /// let mut map = RBMap::new();
+    /// map.insert(1, 'a');
+    /// if let Entry::Occupied(mut e) = map.entry(1) {
+    ///     assert_eq!(e.insert('b'), 'a');
+    /// }
+    /// assert_eq!(map.get(&1), Some(&'b'));
+    /// ```
+    pub fn insert(&mut self, val: V) -> V {
+        let old = std::mem::replace(self.get_mut(), val);
+        let key = self.map.map.raw.nodes.value(self.at).unwrap().key();
+        notify(&mut self.map.observers, true, key, &old);
+        self.map.inserted(self.at);
+        old
+    }
+
+    /// Removes the entry from the map, returning
+    /// its value.
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Removes the entry from the map, returning
+    /// its key and value.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    /// use rb_tree::rbmap::Entry;
+    ///
+    /// let mut map = RBMap::new();
+    /// map.insert(1, 'a');
+    /// if let Entry::Occupied(e) = map.entry(1) {
+    ///     assert_eq!(e.remove_entry(), (1, 'a'));
+    /// }
+    /// assert!(map.is_empty());
+    /// ```
+    pub fn remove_entry(self) -> (K, V) {
+        self.map.map.raw.contained -= 1;
+        let pair = self.map.map.raw.nodes.remove_at(self.at).consume();
+        self.map.removed(pair)
+    }
+}
+
+impl<'a, K: PartialOrd, V> VacantEntry<'a, K, V> {
+    /// Returns the key that would be used
+    /// when inserting a value.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Takes back ownership of the key.
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts the value under the entry's key,
+    /// returning the value as mutable.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    /// use rb_tree::rbmap::Entry;
+    ///
+    /// let mut map = RBMap::new();
+    /// if let Entry::Vacant(e) = map.entry("a".to_string()) {
+    ///     *e.insert(1) += 1;
+    /// }
+    /// assert_eq!(map.get(&"a".to_string()), Some(&2));
+    /// ```
+    pub fn insert(self, val: V) -> &'a mut V {
+        let (map, at) = self.insert_at(val);
+        map.map.raw.nodes.value_mut(at).unwrap().as_mut()
+    }
+
+    fn insert_at(self, val: V) -> (&'a mut RBMap<K, V>, Index) {
+        let at = self.map.link(self.parent, self.right, self.key, val);
+        (self.map, at)
+    }
+}
+
+/// A view into a single entry of an RBMap, which may either
+/// be vacant or occupied, that borrows its key until a value
+/// is inserted. Returned by `RBMap::entry_ref`.
+pub enum EntryRef<'a, 'b, K: PartialOrd + Borrow<Q>, Q: ToOwned<Owned = K> + ?Sized, V> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntryRef<'a, 'b, K, Q, V>),
+}
+
+/// An entry of an RBMap whose key has no value, holding
+/// a reference to the key and the place it will be inserted.
+pub struct VacantEntryRef<'a, 'b, K: PartialOrd + Borrow<Q>, Q: ToOwned<Owned = K> + ?Sized, V> {
+    map: &'a mut RBMap<K, V>,
+    key: &'b Q,
+    parent: Index,
+    right: bool,
+}
+
+impl<'a, 'b, K, Q, V> EntryRef<'a, 'b, K, Q, V>
+where
+    K: PartialOrd + Borrow<Q>,
+    Q: ToOwned<Owned = K> + ?Sized,
+{
+    /// Sets the value of the entry, keeping the key
+    /// already in the map if there is one, and returns
+    /// the key and value.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map: RBMap<String, u32> = RBMap::new();
+    /// map.entry_ref("a").insert(1);
+    /// let (k, v) = map.entry_ref("a").insert(2);
+    /// assert_eq!((k.as_str(), *v), ("a", 2));
+    /// ```
+    pub fn insert(self, val: V) -> (&'a K, &'a mut V) {
+        let (map, at) = match self {
+            EntryRef::Occupied(mut e) => {
+                e.insert(val);
+                (e.map, e.at)
+            }
+            EntryRef::Vacant(e) => e.insert_at(val),
+        };
+        map.map.raw.nodes.value_mut(at).unwrap().mut_pair()
+    }
+
+    /// Modifies the value of an occupied entry,
+    /// leaving a vacant entry as it is.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map: RBMap<String, u32> = RBMap::new();
+    /// map.entry_ref("a").and_modify(|v| *v += 1).or_insert(0);
+    /// map.entry_ref("a").and_modify(|v| *v += 1).or_insert(0);
+    /// assert_eq!(map.get("a"), Some(&1));
+    /// ```
+    pub fn and_modify<F>(mut self, f: F) -> EntryRef<'a, 'b, K, Q, V>
+    where
+        F: FnOnce(&mut V),
+    {
+        if let EntryRef::Occupied(e) = &mut self {
+            f(e.get_mut());
+        }
+        self
+    }
+
+    /// Inserts the given value if the entry is vacant,
+    /// and returns the entry's value.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map: RBMap<String, u32> = RBMap::new();
+    /// *map.entry_ref("a").or_insert(1) += 10;
+    /// assert_eq!(map.get("a"), Some(&11));
+    /// ```
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            EntryRef::Occupied(e) => e.into_mut(),
+            EntryRef::Vacant(e) => e.insert(default),
+        }
+    }
+
+    /// Inserts the result of `default` if the entry is
+    /// vacant, and returns the entry's value.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map: RBMap<String, Vec<u32>> = RBMap::new();
+    /// map.entry_ref("a").or_insert_with(Vec::new).push(2);
+    /// assert_eq!(map.get("a"), Some(&vec!(2)));
+    /// ```
+    pub fn or_insert_with<F>(self, default: F) -> &'a mut V
     where
         F: FnOnce() -> V,
     {
-        if !self.map.contains_key(&self.key) {
-            self.map.insert(self.key, V::default());
+        match self {
+            EntryRef::Occupied(e) => e.into_mut(),
+            EntryRef::Vacant(e) => e.insert(default()),
+        }
+    }
+
+    /// Inserts the result of `default`, which is given
+    /// the borrowed key, if the entry is vacant, and
+    /// returns the entry's value.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map: RBMap<String, usize> = RBMap::new();
+    /// let len = map.entry_ref("four").or_insert_with_key(|k| k.len());
+    /// assert_eq!(*len, 4);
+    /// ```
+    pub fn or_insert_with_key<F>(self, default: F) -> &'a mut V
+    where
+        F: FnOnce(&Q) -> V,
+    {
+        match self {
+            EntryRef::Occupied(e) => e.into_mut(),
+            EntryRef::Vacant(e) => {
+                let val = default(e.key);
+                e.insert(val)
+            }
         }
-        self.map.get_mut(&self.key).unwrap()
+    }
+
+    /// Returns the key of the entry, which is the key
+    /// already in the map if the entry is occupied.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map: RBMap<String, usize> = RBMap::new();
+    /// assert_eq!(map.entry_ref("a").key(), "a");
+    /// ```
+    pub fn key(&self) -> &Q {
+        match self {
+            EntryRef::Occupied(e) => e.key().borrow(),
+            EntryRef::Vacant(e) => e.key(),
+        }
+    }
+}
+
+impl<'a, 'b, K, Q, V: Default> EntryRef<'a, 'b, K, Q, V>
+where
+    K: PartialOrd + Borrow<Q>,
+    Q: ToOwned<Owned = K> + ?Sized,
+{
+    /// Inserts the default value if the entry is
+    /// vacant, and returns the entry's value.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    ///
+    /// let mut map: RBMap<String, usize> = RBMap::new();
+    /// *map.entry_ref("a").or_default() += 1;
+    /// assert_eq!(map.get("a"), Some(&1));
+    /// ```
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, 'b, K, Q, V> VacantEntryRef<'a, 'b, K, Q, V>
+where
+    K: PartialOrd + Borrow<Q>,
+    Q: ToOwned<Owned = K> + ?Sized,
+{
+    /// Returns the key that would be used
+    /// when inserting a value.
+    pub fn key(&self) -> &'b Q {
+        self.key
+    }
+
+    /// Inserts the value under an owned copy of the
+    /// entry's key, returning the value as mutable.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMap;
+    /// use rb_tree::rbmap::EntryRef;
+    ///
+    /// let mut map: RBMap<String, u32> = RBMap::new();
+    /// if let EntryRef::Vacant(e) = map.entry_ref("a") {
+    ///     *e.insert(1) += 1;
+    /// }
+    /// assert_eq!(map.get("a"), Some(&2));
+    /// ```
+    pub fn insert(self, val: V) -> &'a mut V {
+        let (map, at) = self.insert_at(val);
+        map.map.raw.nodes.value_mut(at).unwrap().as_mut()
+    }
+
+    fn insert_at(self, val: V) -> (&'a mut RBMap<K, V>, Index) {
+        let at = self
+            .map
+            .link(self.parent, self.right, self.key.to_owned(), val);
+        (self.map, at)
     }
 }
@@ -0,0 +1,399 @@
+use crate::rbmap;
+use crate::{RBMap, RBMultiMap};
+
+use std::borrow::Borrow;
+use std::fmt::{Debug, Formatter, Result};
+use std::iter::{ExactSizeIterator, FromIterator, FusedIterator};
+
+impl<K: PartialOrd + Debug, V: Debug> Debug for RBMultiMap<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_map().entries(self.groups()).finish()
+    }
+}
+
+impl<K: PartialOrd, V> RBMultiMap<K, V> {
+    /// Creates and returns a new, empty RBMultiMap
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMultiMap;
+    ///
+    /// let mut map = RBMultiMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("a", 2);
+    /// assert_eq!(map.get_all(&"a"), &[1, 2]);
+    /// ```
+    pub fn new() -> RBMultiMap<K, V> {
+        RBMultiMap {
+            map: RBMap::new(),
+            contained: 0,
+        }
+    }
+
+    /// Clears all entries from the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMultiMap;
+    ///
+    /// let mut map = RBMultiMap::new();
+    /// map.insert("a", 1);
+    /// map.clear();
+    /// assert!(map.is_empty());
+    /// ```
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.contained = 0;
+    }
+
+    /// Adds a value to those associated with the key,
+    /// after any already associated with it.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMultiMap;
+    ///
+    /// let mut map = RBMultiMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(1, "a");
+    /// map.insert(0, "b");
+    /// assert_eq!(map.len(), 3);
+    /// assert_eq!(map.get_all(&1), &["a", "a"]);
+    /// ```
+    pub fn insert(&mut self, key: K, val: V) {
+        self.map.entry(key).or_default().push(val);
+        self.contained += 1;
+    }
+
+    /// Returns true if the key has any values
+    /// associated with it.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMultiMap;
+    ///
+    /// let mut map = RBMultiMap::new();
+    /// assert!(!map.contains_key(&1));
+    /// map.insert(1, "a");
+    /// assert!(map.contains_key(&1));
+    /// ```
+    pub fn contains_key<Q: PartialOrd + ?Sized>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+    {
+        self.map.contains_key(key)
+    }
+
+    /// Returns the first value associated with
+    /// the key, if any.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMultiMap;
+    ///
+    /// let mut map = RBMultiMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(1, "b");
+    /// assert_eq!(map.get(&1), Some(&"a"));
+    /// assert_eq!(map.get(&2), None);
+    /// ```
+    pub fn get<Q: PartialOrd + ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        self.get_all(key).first()
+    }
+
+    /// Returns all the values associated with the
+    /// key in the order they were inserted, which is
+    /// empty if there are none.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMultiMap;
+    ///
+    /// let mut map = RBMultiMap::new();
+    /// map.insert("fruit", "apple");
+    /// map.insert("veg", "leek");
+    /// map.insert("fruit", "pear");
+    /// assert_eq!(map.get_all("fruit"), &["apple", "pear"]);
+    /// assert!(map.get_all("nuts").is_empty());
+    /// ```
+    pub fn get_all<Q: PartialOrd + ?Sized>(&self, key: &Q) -> &[V]
+    where
+        K: Borrow<Q>,
+    {
+        match self.map.get(key) {
+            Some(vals) => vals,
+            None => &[],
+        }
+    }
+
+    /// Removes and returns the first value associated
+    /// with the key, if any, removing the key once it
+    /// has no values left.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMultiMap;
+    ///
+    /// let mut map = RBMultiMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(1, "b");
+    /// assert_eq!(map.remove_one(&1), Some("a"));
+    /// assert_eq!(map.remove_one(&1), Some("b"));
+    /// assert_eq!(map.remove_one(&1), None);
+    /// assert!(!map.contains_key(&1));
+    /// ```
+    pub fn remove_one<Q: PartialOrd + ?Sized>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+    {
+        let vals = self.map.get_mut(key)?;
+        let val = vals.remove(0);
+        if vals.is_empty() {
+            self.map.remove(key);
+        }
+        self.contained -= 1;
+        Some(val)
+    }
+
+    /// Removes the key, returning all the values
+    /// that were associated with it in the order
+    /// they were inserted.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMultiMap;
+    ///
+    /// let mut map = RBMultiMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(1, "b");
+    /// assert_eq!(map.remove_all(&1), vec!("a", "b"));
+    /// assert!(map.remove_all(&1).is_empty());
+    /// ```
+    pub fn remove_all<Q: PartialOrd + ?Sized>(&mut self, key: &Q) -> Vec<V>
+    where
+        K: Borrow<Q>,
+    {
+        let vals = self.map.remove(key).unwrap_or_default();
+        self.contained -= vals.len();
+        vals
+    }
+
+    /// Returns the total number of values in the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMultiMap;
+    ///
+    /// let mut map = RBMultiMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(1, "b");
+    /// assert_eq!(map.len(), 2);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.contained
+    }
+
+    /// Returns the number of distinct keys in the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMultiMap;
+    ///
+    /// let mut map = RBMultiMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(1, "b");
+    /// assert_eq!(map.keys_len(), 1);
+    /// ```
+    pub fn keys_len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns true if there are no values in the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMultiMap;
+    ///
+    /// let mut map = RBMultiMap::new();
+    /// assert!(map.is_empty());
+    /// map.insert(1, "a");
+    /// assert!(!map.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.contained == 0
+    }
+
+    /// An iterator that visits every key-value pair,
+    /// in their key's partialord order and then in the
+    /// order the values of each key were inserted.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMultiMap;
+    ///
+    /// let mut map = RBMultiMap::new();
+    /// map.insert(2, "c");
+    /// map.insert(1, "a");
+    /// map.insert(1, "b");
+    /// let pairs: Vec<_> = map.iter().collect();
+    /// assert_eq!(pairs, vec!((&1, &"a"), (&1, &"b"), (&2, &"c")));
+    /// ```
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            groups: self.groups(),
+            current: None,
+            remaining: self.contained,
+        }
+    }
+
+    /// An iterator that visits each key with all
+    /// of its values, in the key's partialord order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMultiMap;
+    ///
+    /// let mut map = RBMultiMap::new();
+    /// map.insert("b", 3);
+    /// map.insert("a", 1);
+    /// map.insert("a", 2);
+    /// let mut groups = map.groups();
+    /// assert_eq!(groups.next(), Some((&"a", &[1, 2][..])));
+    /// assert_eq!(groups.next(), Some((&"b", &[3][..])));
+    /// assert_eq!(groups.next(), None);
+    /// ```
+    pub fn groups(&self) -> Groups<'_, K, V> {
+        Groups {
+            inner: self.map.iter(),
+        }
+    }
+
+    /// An iterator that visits each distinct key
+    /// in their partialord order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBMultiMap;
+    ///
+    /// let mut map = RBMultiMap::new();
+    /// map.insert(2, "c");
+    /// map.insert(1, "a");
+    /// map.insert(1, "b");
+    /// assert_eq!(map.keys().collect::<Vec<_>>(), vec!(&1, &2));
+    /// ```
+    pub fn keys(&self) -> rbmap::Keys<'_, K, Vec<V>> {
+        self.map.keys()
+    }
+}
+
+impl<K: PartialOrd, V> Default for RBMultiMap<K, V> {
+    fn default() -> Self {
+        RBMultiMap::new()
+    }
+}
+
+impl<K: PartialOrd, V> FromIterator<(K, V)> for RBMultiMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = RBMultiMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: PartialOrd, V> Extend<(K, V)> for RBMultiMap<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, val) in iter {
+            self.insert(key, val);
+        }
+    }
+}
+
+impl<K: PartialOrd + PartialEq, V: PartialEq> PartialEq for RBMultiMap<K, V> {
+    fn eq(&self, other: &RBMultiMap<K, V>) -> bool {
+        self.len() == other.len() && self.groups().eq(other.groups())
+    }
+}
+
+pub struct Groups<'a, K: PartialOrd, V> {
+    inner: rbmap::Iter<'a, K, Vec<V>>,
+}
+
+impl<'a, K: PartialOrd, V> Iterator for Groups<'a, K, V> {
+    type Item = (&'a K, &'a [V]);
+
+    fn next(&mut self) -> Option<(&'a K, &'a [V])> {
+        self.inner.next().map(|(k, vals)| (k, vals.as_slice()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K: PartialOrd, V> DoubleEndedIterator for Groups<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a [V])> {
+        self.inner.next_back().map(|(k, vals)| (k, vals.as_slice()))
+    }
+}
+
+impl<'a, K: PartialOrd, V> ExactSizeIterator for Groups<'a, K, V> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+impl<'a, K: PartialOrd, V> FusedIterator for Groups<'a, K, V> {}
+
+impl<'a, K: PartialOrd, V> Clone for Groups<'a, K, V> {
+    fn clone(&self) -> Self {
+        Groups {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K: PartialOrd + Debug, V: Debug> Debug for Groups<'a, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+pub struct Iter<'a, K: PartialOrd, V> {
+    groups: Groups<'a, K, V>,
+    current: Option<(&'a K, std::slice::Iter<'a, V>)>,
+    remaining: usize,
+}
+
+impl<'a, K: PartialOrd, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some((key, vals)) = &mut self.current {
+                if let Some(val) = vals.next() {
+                    self.remaining -= 1;
+                    return Some((*key, val));
+                }
+            }
+            let (key, vals) = self.groups.next()?;
+            self.current = Some((key, vals.iter()));
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, K: PartialOrd, V> ExactSizeIterator for Iter<'a, K, V> {
+    fn len(&self) -> usize {
+        self.remaining
+    }
+}
+
+impl<'a, K: PartialOrd, V> FusedIterator for Iter<'a, K, V> {}
+
+impl<'a, K: PartialOrd, V> Clone for Iter<'a, K, V> {
+    fn clone(&self) -> Self {
+        Iter {
+            groups: self.groups.clone(),
+            current: self.current.clone(),
+            remaining: self.remaining,
+        }
+    }
+}
+
+impl<'a, K: PartialOrd + Debug, V: Debug> Debug for Iter<'a, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
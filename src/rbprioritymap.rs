@@ -0,0 +1,311 @@
+use crate::rbqueue;
+use crate::{Comparator, NaturalOrder, RBMap, RBPriorityMap, RBQueue};
+
+use std::borrow::Borrow;
+use std::fmt::{Debug, Formatter, Result};
+use std::iter::{ExactSizeIterator, FromIterator, FusedIterator};
+
+impl<K: PartialOrd + Debug, P: PartialOrd + Debug> Debug for RBPriorityMap<K, P> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_map()
+            .entries(self.queue.iter().map(|(p, k)| (k, p)))
+            .finish()
+    }
+}
+
+impl<K: PartialOrd + Clone, P: PartialOrd + Clone> RBPriorityMap<K, P> {
+    /// Creates and returns a new, empty RBPriorityMap.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBPriorityMap;
+    ///
+    /// let mut tasks = RBPriorityMap::new();
+    /// tasks.push("write", 2);
+    /// tasks.push("test", 1);
+    /// assert_eq!(tasks.pop(), Some(("test", 1)));
+    /// ```
+    pub fn new() -> RBPriorityMap<K, P> {
+        RBPriorityMap {
+            queue: RBQueue::default(),
+            priorities: RBMap::new(),
+        }
+    }
+
+    /// Clears all keys from the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBPriorityMap;
+    ///
+    /// let mut map = RBPriorityMap::new();
+    /// map.push('a', 1);
+    /// map.clear();
+    /// assert!(map.is_empty());
+    /// ```
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.priorities.clear();
+    }
+
+    /// Adds the key with the given priority, returning
+    /// the key's previous priority if it was already in
+    /// the map, in which case its priority is changed.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBPriorityMap;
+    ///
+    /// let mut map = RBPriorityMap::new();
+    /// assert_eq!(map.push('a', 5), None);
+    /// assert_eq!(map.push('a', 3), Some(5));
+    /// assert_eq!(map.len(), 1);
+    /// assert_eq!(map.get_priority(&'a'), Some(&3));
+    /// ```
+    pub fn push(&mut self, key: K, priority: P) -> Option<P> {
+        let old = self.remove(&key);
+        self.queue.insert((priority.clone(), key.clone()));
+        self.priorities.insert(key, priority);
+        old
+    }
+
+    /// Removes and returns the key with the best
+    /// (lowest) priority, along with that priority.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBPriorityMap;
+    ///
+    /// let mut map = RBPriorityMap::new();
+    /// map.push("b", 2);
+    /// map.push("a", 2);
+    /// map.push("c", 1);
+    /// assert_eq!(map.pop(), Some(("c", 1)));
+    /// // equal priorities are ordered by key
+    /// assert_eq!(map.pop(), Some(("a", 2)));
+    /// assert_eq!(map.pop(), Some(("b", 2)));
+    /// assert_eq!(map.pop(), None);
+    /// ```
+    pub fn pop(&mut self) -> Option<(K, P)> {
+        let (priority, key) = self.queue.pop()?;
+        self.priorities.remove(&key);
+        Some((key, priority))
+    }
+
+    /// Returns the key with the best (lowest) priority,
+    /// along with that priority, without removing it.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBPriorityMap;
+    ///
+    /// let mut map = RBPriorityMap::new();
+    /// map.push("b", 2);
+    /// map.push("c", 1);
+    /// assert_eq!(map.peek(), Some((&"c", &1)));
+    /// assert_eq!(map.len(), 2);
+    /// ```
+    pub fn peek(&self) -> Option<(&K, &P)> {
+        self.queue.peek().map(|(p, k)| (k, p))
+    }
+
+    /// Returns the priority of the key, if
+    /// it is in the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBPriorityMap;
+    ///
+    /// let mut map = RBPriorityMap::new();
+    /// map.push("a".to_string(), 4);
+    /// assert_eq!(map.get_priority("a"), Some(&4));
+    /// assert_eq!(map.get_priority("b"), None);
+    /// ```
+    pub fn get_priority<Q: PartialOrd + ?Sized>(&self, key: &Q) -> Option<&P>
+    where
+        K: Borrow<Q>,
+    {
+        self.priorities.get(key)
+    }
+
+    /// Changes the priority of a key already in the map,
+    /// returning its previous priority, or returns None
+    /// and leaves the map unchanged if the key is not in
+    /// the map. Takes O(log n) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBPriorityMap;
+    ///
+    /// let mut map = RBPriorityMap::new();
+    /// map.push('a', 5);
+    /// map.push('b', 3);
+    /// assert_eq!(map.change_priority(&'a', 1), Some(5));
+    /// assert_eq!(map.change_priority(&'c', 1), None);
+    /// assert_eq!(map.pop(), Some(('a', 1)));
+    /// ```
+    pub fn change_priority<Q: PartialOrd + ?Sized>(&mut self, key: &Q, priority: P) -> Option<P>
+    where
+        K: Borrow<Q>,
+    {
+        let (old, key) = self.take(key)?;
+        self.push(key, priority);
+        Some(old)
+    }
+
+    /// Removes the key from the map, returning its
+    /// priority if it was in the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBPriorityMap;
+    ///
+    /// let mut map = RBPriorityMap::new();
+    /// map.push('a', 5);
+    /// assert_eq!(map.remove(&'a'), Some(5));
+    /// assert_eq!(map.remove(&'a'), None);
+    /// assert!(map.is_empty());
+    /// ```
+    pub fn remove<Q: PartialOrd + ?Sized>(&mut self, key: &Q) -> Option<P>
+    where
+        K: Borrow<Q>,
+    {
+        self.take(key).map(|(priority, _)| priority)
+    }
+
+    /// Returns true if the key is in the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBPriorityMap;
+    ///
+    /// let mut map = RBPriorityMap::new();
+    /// map.push('a', 5);
+    /// assert!(map.contains_key(&'a'));
+    /// assert!(!map.contains_key(&'b'));
+    /// ```
+    pub fn contains_key<Q: PartialOrd + ?Sized>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+    {
+        self.priorities.contains_key(key)
+    }
+
+    /// Returns the number of keys in the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBPriorityMap;
+    ///
+    /// let mut map = RBPriorityMap::new();
+    /// map.push('a', 5);
+    /// map.push('b', 5);
+    /// assert_eq!(map.len(), 2);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.priorities.len()
+    }
+
+    /// Returns true if there are no keys in the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBPriorityMap;
+    ///
+    /// let mut map = RBPriorityMap::new();
+    /// assert!(map.is_empty());
+    /// map.push('a', 5);
+    /// assert!(!map.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.priorities.is_empty()
+    }
+
+    /// An iterator that visits every key and its
+    /// priority, from the best priority to the worst.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBPriorityMap;
+    ///
+    /// let mut map = RBPriorityMap::new();
+    /// map.push('a', 3);
+    /// map.push('b', 1);
+    /// assert_eq!(map.iter().collect::<Vec<_>>(), vec!((&'b', &1), (&'a', &3)));
+    /// ```
+    pub fn iter(&self) -> Iter<'_, K, P> {
+        Iter {
+            pairs: self.queue.iter(),
+        }
+    }
+
+    // removes the key from both the index and the queue,
+    // finding its place in the queue without cloning it
+    fn take<Q: PartialOrd + ?Sized>(&mut self, key: &Q) -> Option<(P, K)>
+    where
+        K: Borrow<Q>,
+    {
+        let priority = self.priorities.remove(key)?;
+        self.queue.take_by(|(p, k)| {
+            NaturalOrder
+                .compare(p, &priority)
+                .then_with(|| NaturalOrder.compare(k.borrow(), key))
+        })
+    }
+}
+
+impl<K: PartialOrd + Clone, P: PartialOrd + Clone> Default for RBPriorityMap<K, P> {
+    fn default() -> Self {
+        RBPriorityMap::new()
+    }
+}
+
+impl<K: PartialOrd + Clone, P: PartialOrd + Clone> FromIterator<(K, P)> for RBPriorityMap<K, P> {
+    fn from_iter<I: IntoIterator<Item = (K, P)>>(iter: I) -> Self {
+        let mut map = RBPriorityMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: PartialOrd + Clone, P: PartialOrd + Clone> Extend<(K, P)> for RBPriorityMap<K, P> {
+    fn extend<I: IntoIterator<Item = (K, P)>>(&mut self, iter: I) {
+        for (key, priority) in iter {
+            self.push(key, priority);
+        }
+    }
+}
+
+impl<K: PartialOrd, P: PartialOrd> PartialEq for RBPriorityMap<K, P> {
+    fn eq(&self, other: &RBPriorityMap<K, P>) -> bool {
+        self.queue.len() == other.queue.len() && self.queue.iter().eq(other.queue.iter())
+    }
+}
+
+pub struct Iter<'a, K, P> {
+    pairs: rbqueue::Iter<'a, (P, K)>,
+}
+
+impl<'a, K, P> Iterator for Iter<'a, K, P> {
+    type Item = (&'a K, &'a P);
+
+    fn next(&mut self) -> Option<(&'a K, &'a P)> {
+        self.pairs.next().map(|(p, k)| (k, p))
+    }
+}
+
+impl<'a, K, P> DoubleEndedIterator for Iter<'a, K, P> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a P)> {
+        self.pairs.next_back().map(|(p, k)| (k, p))
+    }
+}
+
+impl<'a, K, P> ExactSizeIterator for Iter<'a, K, P> {
+    fn len(&self) -> usize {
+        self.pairs.len()
+    }
+}
+
+impl<'a, K, P> FusedIterator for Iter<'a, K, P> {}
+
+impl<'a, K, P> Clone for Iter<'a, K, P> {
+    fn clone(&self) -> Self {
+        Iter {
+            pairs: self.pairs.clone(),
+        }
+    }
+}
+
+impl<'a, K: Debug, P: Debug> Debug for Iter<'a, K, P> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
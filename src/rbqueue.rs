@@ -1,40 +1,50 @@
-use crate::RBQueue;
 #[cfg(feature = "set")]
 use crate::RBTree;
+use crate::{Comparator, Error, MaxQueue, MinQueue, RBQueue, Reversed, TotalOrder};
 
-use crate::helpers::{ordered_insertion, write_to_level};
-use crate::node::Colour::Black;
-use crate::node::Node::Leaf;
+use crate::node::{Index, NIL};
+use crate::raw::RawTree;
+pub use crate::raw::{Difference, Intersection, Iter, Range, Union};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering::{Equal, Greater, Less};
 use std::fmt::{Debug, Display, Formatter, Result};
 use std::iter::{ExactSizeIterator, FusedIterator};
+use std::ops::RangeBounds;
 
-impl<T: Debug, P> Debug for RBQueue<T, P>
+/// What a bounded RBQueue does with an element
+/// inserted while it is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BoundPolicy {
+    /// The new element is dropped.
+    Reject,
+    /// The element at the back of the queue is dropped
+    /// to make room, unless the new element would itself
+    /// be at the back, in which case it is dropped instead.
+    EvictWorst,
+}
+
+impl<T: Debug, C> Debug for RBQueue<T, C>
 where
-    P: Fn(&T, &T) -> std::cmp::Ordering,
+    C: Comparator<T>,
 {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        let mut levels = Vec::new();
-        write_to_level(&self.root, "".to_string(), 0, &mut levels);
-        let mut f_string = "".to_string();
-        for i in 0..levels.len() {
-            f_string += &levels[i];
-            if i != levels.len() - 1 {
-                f_string += "\n";
-            }
-        }
-        write!(f, "{}", f_string)
+        self.raw.fmt(f)
     }
 }
 
-impl<T: Debug, P> Display for RBQueue<T, P>
+impl<T: Debug, C> Display for RBQueue<T, C>
 where
-    P: Fn(&T, &T) -> std::cmp::Ordering,
+    C: Comparator<T>,
 {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         write!(f, "{:?}", self.ordered())
     }
 }
 
+// kept apart from the other methods so that the closure's
+// parameter types can be inferred from the `Fn` bound
 impl<T, P> RBQueue<T, P>
 where
     P: Fn(&T, &T) -> std::cmp::Ordering,
@@ -75,12 +85,122 @@ where
     /// ```
     pub fn new(cmp: P) -> RBQueue<T, P> {
         RBQueue {
-            root: Leaf(Black),
-            contained: 0,
-            cmp,
+            raw: RawTree::new(cmp),
+            bound: None,
+        }
+    }
+}
+
+impl<T, C> RBQueue<T, C>
+where
+    C: Comparator<T>,
+{
+    /// Creates and returns a new RBQueue that will order
+    /// entries using the given `Comparator`. Unlike a closure,
+    /// a comparator type can be named, so the queue can be
+    /// stored in a struct field without boxing, and, for a
+    /// comparator implementing `Default`, the queue can be
+    /// created with `RBQueue::default()`.
+    /// # Example:
+    /// ```
+    /// use rb_tree::{Comparator, RBQueue};
+    /// use std::cmp::Ordering;
+    ///
+    /// #[derive(Default)]
+    /// struct ByDistance;
+    /// impl Comparator<(i32, i32)> for ByDistance {
+    ///     fn compare(&self, l: &(i32, i32), r: &(i32, i32)) -> Ordering {
+    ///         let dist = |p: &(i32, i32)| p.0 * p.0 + p.1 * p.1;
+    ///         dist(l).cmp(&dist(r)).then(l.cmp(r))
+    ///     }
+    /// }
+    ///
+    /// struct Nearest {
+    ///     points: RBQueue<(i32, i32), ByDistance>,
+    /// }
+    ///
+    /// let mut n = Nearest { points: RBQueue::with_comparator(ByDistance) };
+    /// n.points.insert((3, 4));
+    /// n.points.insert((-1, 1));
+    /// assert_eq!(n.points.pop(), Some((-1, 1)));
+    /// ```
+    pub fn with_comparator(cmp: C) -> RBQueue<T, C> {
+        RBQueue {
+            raw: RawTree::new(cmp),
+            bound: None,
         }
     }
 
+    /// Creates and returns a new RBQueue ordered by `cmp`
+    /// that contains the elements of `iter`. The elements
+    /// are sorted and the tree is built from them in one
+    /// go, which is faster than inserting them one by one.
+    /// As with insertion, only the last of any elements
+    /// that `cmp` considers equal is kept.
+    /// # Example:
+    /// ```
+    /// use rb_tree::{RBQueue, Reversed, NaturalOrder};
+    ///
+    /// let mut q = RBQueue::from_iter_with(|l: &i32, r: &i32| r.cmp(l), vec!(3, 9, 1, 9));
+    /// assert_eq!(q.len(), 3);
+    /// assert_eq!(q.pop(), Some(9));
+    ///
+    /// let words = vec!("pear", "fig", "apple");
+    /// let q = RBQueue::from_iter_with(Reversed(NaturalOrder), words);
+    /// assert_eq!(q.ordered(), [&"pear", &"fig", &"apple"]);
+    /// ```
+    pub fn from_iter_with<I: IntoIterator<Item = T>>(cmp: C, iter: I) -> RBQueue<T, C> {
+        let mut values: Vec<T> = iter.into_iter().collect();
+        values.sort_by(|l, r| cmp.compare(l, r));
+        values.dedup_by(|later, kept| {
+            let equal = cmp.compare(later, kept) == std::cmp::Ordering::Equal;
+            if equal {
+                std::mem::swap(later, kept);
+            }
+            equal
+        });
+        let mut raw = RawTree::new(cmp);
+        raw.rebuild(values);
+        RBQueue { raw, bound: None }
+    }
+
+    /// Limits the queue to holding at most `capacity`
+    /// elements, with `policy` deciding what happens when
+    /// an element is inserted into a full queue. Any
+    /// elements beyond the bound are removed from the
+    /// back of the queue.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    /// use rb_tree::rbqueue::BoundPolicy;
+    ///
+    /// let mut q = RBQueue::new(|l: &i32, r| r.cmp(l)).bounded(2, BoundPolicy::EvictWorst);
+    /// q.extend(vec!(4, 9, 1, 7));
+    /// assert_eq!(q.ordered(), [&9, &7]);
+    /// ```
+    pub fn bounded(mut self, capacity: usize, policy: BoundPolicy) -> RBQueue<T, C> {
+        self.bound = Some((capacity, policy));
+        while self.len() > capacity {
+            self.pop_back();
+        }
+        self
+    }
+
+    /// Returns the most elements the queue may
+    /// hold, or None if it is unbounded.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    /// use rb_tree::rbqueue::BoundPolicy;
+    ///
+    /// let q = RBQueue::<u8>::default();
+    /// assert_eq!(q.bound(), None);
+    /// assert_eq!(q.bounded(3, BoundPolicy::Reject).bound(), Some(3));
+    /// ```
+    pub fn bound(&self) -> Option<usize> {
+        self.bound.map(|(capacity, _)| capacity)
+    }
+
     /// Clears all entries from the queue.
     /// # Example:
     /// ```
@@ -94,8 +214,7 @@ where
     /// assert!(!q.contains(&2));
     /// ```
     pub fn clear(&mut self) {
-        self.root = Leaf(Black);
-        self.contained = 0;
+        self.raw.clear();
     }
 
     /// Clears the queue and returns all values
@@ -122,6 +241,54 @@ where
         Drain { ordered: vec }
     }
 
+    /// Removes the elements at the front of the queue for
+    /// as long as `pred` holds for them, returning them as
+    /// an iterator in their order. The elements are removed
+    /// when this is called, not as the iterator is used.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// let mut q = RBQueue::<u32>::default();
+    /// q.extend(vec!(8, 2, 5, 11, 3));
+    /// let small = q.drain_while(|v| *v < 6);
+    /// assert_eq!(small.collect::<Vec<_>>(), vec!(2, 3, 5));
+    /// assert_eq!(q.ordered(), [&8, &11]);
+    /// ```
+    pub fn drain_while<F: FnMut(&T) -> bool>(&mut self, mut pred: F) -> Drain<T> {
+        let count = self.iter().take_while(|v| pred(v)).count();
+        let mut vec = self.pop_batch(count);
+        vec.reverse();
+        Drain { ordered: vec }
+    }
+
+    /// Removes every element ordered before or equal to
+    /// `bound`, returning them as an iterator in their order.
+    /// For a queue of timers ordered by when they are due,
+    /// this takes every timer due by a given time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// let mut timers = RBQueue::<(u64, &str)>::default();
+    /// timers.insert((30, "flush"));
+    /// timers.insert((10, "ping"));
+    /// timers.insert((20, "retry"));
+    /// let due = timers.drain_up_to(&(20, "~"));
+    /// assert_eq!(due.map(|(_, t)| t).collect::<Vec<_>>(), vec!("ping", "retry"));
+    /// assert_eq!(timers.len(), 1);
+    /// ```
+    pub fn drain_up_to(&mut self, bound: &T) -> Drain<T> {
+        let cmp = &self.raw.cmp;
+        let count = self
+            .iter()
+            .take_while(|v| cmp.compare(v, bound) != std::cmp::Ordering::Greater)
+            .count();
+        let mut vec = self.pop_batch(count);
+        vec.reverse();
+        Drain { ordered: vec }
+    }
+
     /// Returns a vector presenting the contained
     /// elements of the RBQueue in the order by which
     /// they are prioritised (that is, in the in-order
@@ -138,9 +305,7 @@ where
     /// assert_eq!(*order[1], 2);
     /// ```
     pub fn ordered(&self) -> Vec<&T> {
-        let mut order = Vec::with_capacity(self.len());
-        ordered_insertion(&self.root, &mut order);
-        order
+        self.raw.ordered()
     }
 
     /// Returns the number of elements contained
@@ -158,7 +323,7 @@ where
     /// assert_eq!(t.len(), 2);
     /// ```
     pub fn len(&self) -> usize {
-        self.contained
+        self.raw.len()
     }
 
     /// Returns true if there are no items
@@ -178,7 +343,10 @@ where
 
     /// Inserts a new element into the RBQueue.
     /// Returns true if this item was not already
-    /// in the tree, and false otherwise.
+    /// in the tree, and false otherwise. If the queue
+    /// is bounded and full, the item is only added
+    /// as its `BoundPolicy` allows, and false is
+    /// returned if it was not.
     /// # Example:
     /// ```
     /// use rb_tree::RBQueue;
@@ -188,19 +356,104 @@ where
     /// assert_eq!(t.insert("Hello".to_string()), false);
     /// ```
     pub fn insert(&mut self, val: T) -> bool {
-        match self.root.insert(val, &self.cmp) {
-            Some(_) => false,
-            None => {
-                self.contained += 1;
-                true
-            }
+        if !self.make_room(&val) {
+            return false;
         }
+        self.raw.insert(val).is_none()
+    }
+
+    /// Tries to reserve room for at least `additional`
+    /// more elements, so that inserting them will not need
+    /// to allocate. Returns an error rather than aborting
+    /// if the memory could not be allocated.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// let mut q = RBQueue::new(|l: &i32, r| l.cmp(r));
+    /// q.try_reserve(10).expect("out of memory");
+    /// (0..10).for_each(|v| {q.insert(v);});
+    /// assert!(q.try_reserve(usize::MAX).is_err());
+    /// ```
+    pub fn try_reserve(&mut self, additional: usize) -> std::result::Result<(), Error> {
+        self.raw.try_reserve(additional).map_err(Error::from)
+    }
+
+    /// Makes sure at least `n` more elements than the queue
+    /// now holds can be inserted without allocating. The
+    /// nodes of popped or removed elements are kept and
+    /// reused for later insertions, and count towards `n`,
+    /// so a queue that is reserved for its working size will
+    /// not allocate while elements are pushed and popped at
+    /// a steady rate, even across calls to `clear`.
+    /// # Panics:
+    /// Panics if the new capacity overflows `usize`.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// let mut q = RBQueue::new(|l: &i32, r| l.cmp(r));
+    /// q.reserve_nodes(10);
+    /// for v in 0..100 {
+    ///     q.insert(v);
+    ///     if q.len() > 10 {
+    ///         q.pop();
+    ///     }
+    /// }
+    /// assert_eq!(q.peek(), Some(&90));
+    /// ```
+    pub fn reserve_nodes(&mut self, n: usize) {
+        self.raw.reserve_nodes(n);
+    }
+
+    /// Gives the memory held for the nodes of popped or
+    /// removed elements back to the allocator, so that a
+    /// queue which has drained after a burst doesn't keep
+    /// the memory it needed at its largest. Moves the
+    /// remaining nodes together, taking O(n) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// let mut q = RBQueue::new(|l: &u32, r| r.cmp(l));
+    /// for v in 0..10_000 {
+    ///     q.insert(v);
+    /// }
+    /// while q.len() > 3 {
+    ///     q.pop();
+    /// }
+    /// q.shrink_to_fit();
+    /// assert_eq!(q.pop(), Some(2));
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        self.raw.shrink_to_fit();
+    }
+
+    /// Inserts a new element into the RBQueue as `insert`
+    /// does, but returns an error rather than aborting if
+    /// room for the element could not be allocated, in
+    /// which case `val` is dropped and the queue is left
+    /// unchanged. This may fail even when an equal element
+    /// is already contained.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// let mut q = RBQueue::new(|l: &i32, r| l.cmp(r));
+    /// assert_eq!(q.try_insert_alloc(1), Ok(true));
+    /// assert_eq!(q.try_insert_alloc(1), Ok(false));
+    /// ```
+    pub fn try_insert_alloc(&mut self, val: T) -> std::result::Result<bool, Error> {
+        self.raw.nodes.try_make_room()?;
+        Ok(self.insert(val))
     }
 
     /// Inserts a new element into the RBQueue.
     /// Returns None if this item was not already
     /// in the tree, and the previously contained
-    /// item otherwise.
+    /// item otherwise. If the queue is bounded and
+    /// full, an item the `BoundPolicy` does not
+    /// allow to be added is dropped.
     /// # Example:
     /// ```
     /// use rb_tree::RBQueue;
@@ -210,13 +463,10 @@ where
     /// assert_eq!(t.replace("Hello".to_string()), Some("Hello".to_string()));
     /// ```
     pub fn replace(&mut self, val: T) -> Option<T> {
-        match self.root.insert(val, &self.cmp) {
-            Some(v) => Some(v),
-            None => {
-                self.contained += 1;
-                None
-            }
+        if !self.make_room(&val) {
+            return None;
         }
+        self.raw.insert(val)
     }
 
     /// Returns true if the tree contains the
@@ -246,12 +496,46 @@ where
     /// assert_eq!(t.get(&2), None);
     /// ```
     pub fn get(&self, val: &T) -> Option<&T> {
-        self.root.get(val, &self.cmp)
+        self.raw.get(val)
     }
 
-    // pub fn at(&self, index: usize) -> Option<&T> {
+    /// Returns the element at the given position in the
+    /// queue's order (counting from 0, the front of the
+    /// queue), or None if the queue has no more than
+    /// `index` elements. Takes O(log n) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// // deadlines in ms, soonest first
+    /// let mut deadlines = RBQueue::<u32>::default();
+    /// deadlines.extend((1..=100).map(|d| d * 10));
+    /// let p95 = deadlines.at(deadlines.len() * 95 / 100 - 1);
+    /// assert_eq!(p95, Some(&950));
+    /// assert_eq!(deadlines.at(0), deadlines.peek());
+    /// assert_eq!(deadlines.at(100), None);
+    /// ```
+    pub fn at(&self, index: usize) -> Option<&T> {
+        let nodes = &self.raw.nodes;
+        nodes.value(nodes.nth(index))
+    }
 
-    // }
+    /// Returns the position of the element in the queue's
+    /// order, i.e. the number of elements before it, or
+    /// None if it is not in the queue. Takes O(log n) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// let mut q = RBQueue::new(|l: &i32, r| r.cmp(l));
+    /// q.extend(vec!(4, 9, 1, 7));
+    /// assert_eq!(q.rank(&9), Some(0));
+    /// assert_eq!(q.rank(&4), Some(2));
+    /// assert_eq!(q.rank(&5), None);
+    /// ```
+    pub fn rank(&self, val: &T) -> Option<usize> {
+        self.raw.locate(val).ok().map(|at| self.raw.nodes.rank(at))
+    }
 
     /// Removes an item the tree. Returns the matching item
     /// if it was contained in the tree, None otherwise.
@@ -267,13 +551,54 @@ where
     /// assert_eq!(t.take(&2), None);
     /// ```
     pub fn take(&mut self, val: &T) -> Option<T> {
-        match self.root.remove(val, &self.cmp) {
-            Some(v) => {
-                self.contained -= 1;
-                Some(v)
-            }
-            None => None,
-        }
+        self.raw.take(val)
+    }
+
+    /// Returns the element for which `probe` returns
+    /// Equal, where `probe` gives how an element compares
+    /// to the one sought in the queue's order, or None if
+    /// there is no such element. This lets an element be
+    /// found by the part of it the queue is ordered on,
+    /// without building a whole element to search with.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// struct Job {
+    ///     id: u32,
+    ///     payload: Vec<u8>,
+    /// }
+    ///
+    /// let mut jobs = RBQueue::new(|l: &Job, r: &Job| l.id.cmp(&r.id));
+    /// jobs.insert(Job { id: 7, payload: vec!(1, 2) });
+    /// jobs.insert(Job { id: 3, payload: vec!() });
+    /// let job = jobs.get_by(|j| j.id.cmp(&7)).unwrap();
+    /// assert_eq!(job.payload, vec!(1, 2));
+    /// assert!(jobs.get_by(|j| j.id.cmp(&5)).is_none());
+    /// ```
+    pub fn get_by<F: Fn(&T) -> std::cmp::Ordering>(&self, probe: F) -> Option<&T> {
+        let at = self.locate_by(probe)?;
+        self.raw.nodes.value(at)
+    }
+
+    /// Removes and returns the element for which `probe`
+    /// returns Equal, as found by `get_by`, or returns
+    /// None if there is no such element.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// let mut q = RBQueue::new(|l: &(u32, &str), r: &(u32, &str)| l.0.cmp(&r.0));
+    /// q.insert((2, "two"));
+    /// q.insert((5, "five"));
+    /// assert_eq!(q.take_by(|v| v.0.cmp(&2)), Some((2, "two")));
+    /// assert_eq!(q.take_by(|v| v.0.cmp(&2)), None);
+    /// assert_eq!(q.len(), 1);
+    /// ```
+    pub fn take_by<F: Fn(&T) -> std::cmp::Ordering>(&mut self, probe: F) -> Option<T> {
+        let at = self.locate_by(probe)?;
+        self.raw.contained -= 1;
+        Some(self.raw.nodes.remove_at(at))
     }
 
     /// Removes an item the tree. Returns true
@@ -290,9 +615,57 @@ where
     /// assert_eq!(t.remove(&2), false);
     /// ```
     pub fn remove(&mut self, val: &T) -> bool {
-        match self.root.remove(val, &self.cmp) {
-            Some(_) => {
-                self.contained -= 1;
+        self.raw.take(val).is_some()
+    }
+
+    /// Replaces the element equal to `old` with `new`,
+    /// moving it to the place `new` belongs in the queue
+    /// (the decrease-key operation used by searches such as
+    /// Dijkstra's). Returns false, leaving the queue as it
+    /// was, if no element is equal to `old`. As with
+    /// `insert`, an element already equal to `new` is
+    /// replaced by it.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// // (distance, node) pairs, nearest first
+    /// let mut q = RBQueue::min();
+    /// q.insert((7, 'a'));
+    /// q.insert((4, 'b'));
+    /// assert!(q.change_priority(&(7, 'a'), (2, 'a')));
+    /// assert_eq!(q.pop(), Some((2, 'a')));
+    /// assert!(!q.change_priority(&(7, 'a'), (1, 'a')));
+    /// ```
+    pub fn change_priority(&mut self, old: &T, new: T) -> bool {
+        if !self.remove(old) {
+            return false;
+        }
+        self.insert(new);
+        true
+    }
+
+    /// Applies `f` to the element equal to `old`, then moves
+    /// it to the place it now belongs in the queue. Returns
+    /// false if no element is equal to `old`. The element
+    /// is out of the queue while `f` runs, so it is lost if
+    /// `f` panics. As with `insert`, an element already
+    /// equal to the modified one is replaced by it.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// let mut q = RBQueue::max();
+    /// q.insert((3, "write report"));
+    /// q.insert((5, "fix outage"));
+    /// assert!(q.modify(&(3, "write report"), |task| task.0 = 9));
+    /// assert_eq!(q.pop(), Some((9, "write report")));
+    /// ```
+    pub fn modify<F: FnOnce(&mut T)>(&mut self, old: &T, f: F) -> bool {
+        match self.take(old) {
+            Some(mut val) => {
+                f(&mut val);
+                self.insert(val);
                 true
             }
             None => false,
@@ -313,15 +686,172 @@ where
     /// assert_eq!(t.pop().unwrap(), 1);
     /// ```
     pub fn pop(&mut self) -> Option<T> {
-        match self.root.pop(false) {
-            Some(v) => {
-                self.contained -= 1;
-                Some(v)
+        self.raw.pop(false)
+    }
+
+    /// Inserts `val` and then pops the front of the queue,
+    /// as a single operation. If `val` would be at the
+    /// front, it is returned straight away, and if it would
+    /// take the front's place it replaces the front without
+    /// the tree needing to be rebalanced.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// // keeping the three largest values seen
+    /// let mut top = RBQueue::<u32>::default();
+    /// top.extend(vec!(4, 8, 6));
+    /// assert_eq!(top.push_pop(2), 2);
+    /// assert_eq!(top.push_pop(7), 4);
+    /// assert_eq!(top.push_pop(10), 6);
+    /// assert_eq!(top.ordered(), [&7, &8, &10]);
+    /// ```
+    pub fn push_pop(&mut self, val: T) -> T {
+        let front = self.raw.nodes.first();
+        match self
+            .raw
+            .nodes
+            .value(front)
+            .map(|v| self.raw.cmp.compare(&val, v))
+        {
+            Some(Greater) => {}
+            Some(Equal) => {
+                // val would have replaced the front
+                self.pop();
+                return val;
+            }
+            _ => return val,
+        }
+        match self.replace_front(val) {
+            Ok(v) => v,
+            Err(val) => {
+                let v = self.pop().unwrap();
+                self.insert(val);
+                v
             }
-            None => None,
         }
     }
 
+    /// Pops the front of the queue and then inserts `val`,
+    /// as a single operation. Returns the old front, the
+    /// element that was first before `val` went in, or
+    /// None if the queue was empty; `val` itself is always
+    /// kept. If `val` would take the front's place, it
+    /// replaces the front without the tree needing to be
+    /// rebalanced. This is what heaps often call `replace`,
+    /// a name `RBQueue::replace` already has for swapping
+    /// out an equal element.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// let mut q = RBQueue::<u32>::default();
+    /// assert_eq!(q.pop_push(5), None);
+    /// q.insert(9);
+    /// assert_eq!(q.pop_push(2), Some(5));
+    /// // 2 was the front, and is handed back even though
+    /// // 12 goes in behind 9
+    /// assert_eq!(q.pop_push(12), Some(2));
+    /// assert_eq!(q.ordered(), [&9, &12]);
+    /// ```
+    pub fn pop_push(&mut self, val: T) -> Option<T> {
+        if self.is_empty() {
+            self.insert(val);
+            return None;
+        }
+        match self.replace_front(val) {
+            Ok(v) => Some(v),
+            Err(val) => {
+                let v = self.pop();
+                self.insert(val);
+                v
+            }
+        }
+    }
+
+    /// Removes and returns every element the queue's
+    /// comparator ranks equal to the front, in priority
+    /// order, or nothing if the queue is empty. As an
+    /// element inserted equal to one already held replaces
+    /// it, this is the front alone unless the comparator
+    /// ranks elements equal that it doesn't rank equal to
+    /// each other; `pop_equal_by` takes groups that tie on
+    /// less than the whole ordering.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// let mut q = RBQueue::<(u32, char), _>::new(|l, r| l.0.cmp(&r.0));
+    /// q.extend(vec!((2, 'a'), (1, 'b'), (1, 'c')));
+    /// assert_eq!(q.pop_equal(), vec!((1, 'c')));
+    /// assert_eq!(q.pop_equal(), vec!((2, 'a')));
+    /// assert!(q.pop_equal().is_empty());
+    /// ```
+    pub fn pop_equal(&mut self) -> Vec<T> {
+        let count = self.tied(|front, v| self.raw.cmp.compare(front, v) == Equal);
+        self.pop_batch(count)
+    }
+
+    /// Removes and returns, in priority order, the front
+    /// of the queue and every element after it for which
+    /// `same` returns true when given the front and that
+    /// element, stopping at the first it returns false for.
+    /// `same` should compare less than the whole ordering,
+    /// such as the time of events ordered by time and id,
+    /// as no two elements the queue's comparator ranks
+    /// equal are both kept (see `pop_equal`).
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// // events as (time, id)
+    /// let mut events = RBQueue::<(u32, u32)>::default();
+    /// events.extend(vec!((5, 1), (3, 7), (3, 2), (9, 4), (3, 5)));
+    /// let now = events.pop_equal_by(|front, e| front.0 == e.0);
+    /// assert_eq!(now, vec!((3, 2), (3, 5), (3, 7)));
+    /// assert_eq!(events.pop_equal_by(|front, e| front.0 == e.0), vec!((5, 1)));
+    /// ```
+    pub fn pop_equal_by<F: FnMut(&T, &T) -> bool>(&mut self, same: F) -> Vec<T> {
+        let count = self.tied(same);
+        self.pop_batch(count)
+    }
+
+    // the number of elements from the front on that `same`
+    // holds for with the front, which always counts itself
+    fn tied<F: FnMut(&T, &T) -> bool>(&self, mut same: F) -> usize {
+        match self.peek() {
+            Some(front) => self.iter().take_while(|v| same(front, v)).count().max(1),
+            None => 0,
+        }
+    }
+
+    /// Removes and returns, in priority order, the `n`
+    /// items at the front of the queue, or every item if
+    /// there are fewer than `n`. Large batches are taken
+    /// by rebuilding the tree from the items that remain
+    /// in linear time, rather than rebalancing it after
+    /// each item is removed.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// let mut q = RBQueue::<u32>::default();
+    /// q.extend(vec!(7, 3, 9, 1, 5));
+    /// assert_eq!(q.pop_batch(2), vec!(1, 3));
+    /// assert_eq!(q.pop_batch(5), vec!(5, 7, 9));
+    /// assert!(q.pop_batch(1).is_empty());
+    /// ```
+    pub fn pop_batch(&mut self, n: usize) -> Vec<T> {
+        if n <= self.len() / 2 {
+            return (0..n).filter_map(|_| self.pop()).collect();
+        }
+        let n = n.min(self.len());
+        let mut batch = std::mem::take(&mut self.raw.nodes).into_ordered();
+        let rest = batch.split_off(n);
+        self.raw.rebuild(rest);
+        batch
+    }
+
     /// Peeks the item at the front of the priority
     /// queue that the RBQueue represents if any elements
     /// are present, or None otherwise.
@@ -336,7 +866,7 @@ where
     /// assert_eq!(*t.peek().unwrap(), 1);
     /// ```
     pub fn peek(&self) -> Option<&T> {
-        self.root.peek(false)
+        self.raw.peek(false)
     }
 
     /// Removes the item at the back of the priority
@@ -353,13 +883,7 @@ where
     /// assert_eq!(t.pop_back().unwrap(), 3);
     /// ```
     pub fn pop_back(&mut self) -> Option<T> {
-        match self.root.pop(true) {
-            Some(v) => {
-                self.contained -= 1;
-                Some(v)
-            }
-            None => None,
-        }
+        self.raw.pop(true)
     }
 
     /// Peeks the item at the back of the priority
@@ -376,7 +900,7 @@ where
     /// assert_eq!(*t.peek_back().unwrap(), 3);
     /// ```
     pub fn peek_back(&self) -> Option<&T> {
-        self.root.peek(true)
+        self.raw.peek(true)
     }
 
     /// Returns an iterator over the elements
@@ -391,11 +915,216 @@ where
     /// t.insert(5);
     /// assert_eq!(t.iter().collect::<Vec<&i8>>(), vec!(&1, &3, &5));
     /// ```
-    pub fn iter(&self) -> Iter<T> {
-        Iter {
-            pos: 0,
-            ordered: self.ordered(),
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.raw.iter()
+    }
+
+    /// Moves all the elements of `other` into this queue,
+    /// which keeps its own comparator. Where an element of
+    /// `other` is equal to one in this queue, it replaces it,
+    /// as it would if inserted. When every element of one
+    /// queue comes before every element of the other, the
+    /// two trees are joined without searching this queue's
+    /// tree, taking O(m + log n) time for the m elements of
+    /// `other`. Otherwise the two queues are merged in linear
+    /// time, unless `other` is small enough that inserting
+    /// its elements is faster.
+    /// A bounded queue inserts the elements one by one.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// let mut mine = RBQueue::<u32>::default();
+    /// mine.extend(vec!(1, 5, 9));
+    /// let mut stolen = RBQueue::default();
+    /// stolen.extend(vec!(2, 5, 12));
+    /// mine.append(stolen);
+    /// assert_eq!(mine.ordered(), [&1, &2, &5, &9, &12]);
+    /// ```
+    pub fn append(&mut self, other: RBQueue<T, C>) {
+        let (first, last) = match (other.peek(), other.peek_back()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return,
+        };
+        if self.bound.is_some() {
+            self.extend(other);
+            return;
         }
+        let after = match self.peek_back() {
+            Some(back) => self.raw.cmp.compare(back, first) == Less,
+            None => true,
+        };
+        let before = match self.peek() {
+            Some(front) => !after && self.raw.cmp.compare(last, front) == Less,
+            None => false,
+        };
+        if after || before {
+            // the new elements all go past one end of the
+            // tree, so the two trees can be joined
+            self.raw.contained += other.len();
+            self.raw.nodes.append(other.raw.nodes, after);
+        } else if other.len() * 4 < self.len() {
+            self.extend(other);
+        } else {
+            let mut merged = Vec::with_capacity(self.len() + other.len());
+            let mut mine = std::mem::take(&mut self.raw.nodes)
+                .into_ordered()
+                .into_iter()
+                .peekable();
+            let mut theirs = other.raw.nodes.into_ordered().into_iter().peekable();
+            while let (Some(l), Some(r)) = (mine.peek(), theirs.peek()) {
+                match self.raw.cmp.compare(l, r) {
+                    Less => merged.push(mine.next().unwrap()),
+                    Equal => {
+                        mine.next();
+                        merged.push(theirs.next().unwrap());
+                    }
+                    Greater => merged.push(theirs.next().unwrap()),
+                }
+            }
+            merged.extend(mine);
+            merged.extend(theirs);
+            self.raw.rebuild(merged);
+        }
+        self.raw.verify();
+    }
+
+    /// Splits the queue in two at `at`, leaving the elements
+    /// ordered before it in this queue and returning a new
+    /// queue, with a copy of this queue's comparator, holding
+    /// those ordered after or equal to it. The new queue is
+    /// not bounded, whether or not this one is.
+    ///
+    /// Splitting m of the n elements off takes O(log n +
+    /// min(m, n - m)) time, not O(log n): the tree is cut in
+    /// two along the search path in O(log n) time, but each
+    /// queue keeps its elements in storage of its own, so
+    /// whichever part is smaller must then be moved out.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// // requests by priority, most urgent first
+    /// let mut urgent = RBQueue::<(u8, &str)>::default();
+    /// urgent.extend(vec!((1, "pay"), (7, "report"), (3, "login"), (9, "digest")));
+    /// // under load, shed all but the most urgent to the background
+    /// let background = urgent.split_off(&(5, ""));
+    /// assert_eq!(urgent.ordered(), [&(1, "pay"), &(3, "login")]);
+    /// assert_eq!(background.ordered(), [&(7, "report"), &(9, "digest")]);
+    /// ```
+    pub fn split_off(&mut self, at: &T) -> RBQueue<T, C>
+    where
+        C: Clone,
+    {
+        let cmp = &self.raw.cmp;
+        let first = self
+            .raw
+            .nodes
+            .partition_point(|v| cmp.compare(v, at) == Less);
+        let kept = if first == NIL {
+            self.len()
+        } else {
+            self.raw.nodes.rank(first)
+        };
+        // the comparator is done with, so nothing
+        // can panic while the tree is being cut
+        let mut other = RBQueue {
+            raw: RawTree::new(self.raw.cmp.clone()),
+            bound: None,
+        };
+        other.raw.nodes = self.raw.nodes.split_off(first);
+        other.raw.contained = self.len() - kept;
+        self.raw.contained = kept;
+        self.raw.verify();
+        other.raw.verify();
+        other
+    }
+
+    /// Returns an iterator over the elements of this
+    /// queue that are not in `other`, in this queue's
+    /// order. Both queues must be ordered the same way.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// // queues must share their closure's type to be compared
+    /// let desc = |l: &i32, r: &i32| r.cmp(l);
+    /// let mut q1 = RBQueue::new(desc);
+    /// let mut q2 = RBQueue::new(desc);
+    /// q1.extend(0..3);
+    /// q2.extend(2..5);
+    /// assert_eq!(q1.difference(&q2).collect::<Vec<_>>(), vec!(&1, &0));
+    /// assert_eq!(q2.difference(&q1).collect::<Vec<_>>(), vec!(&4, &3));
+    /// ```
+    pub fn difference<'a>(&'a self, other: &'a RBQueue<T, C>) -> Difference<'a, T, C> {
+        self.raw.difference(&other.raw)
+    }
+
+    /// Returns an iterator over the elements that are
+    /// in both this queue and `other`, in this queue's
+    /// order. Both queues must be ordered the same way.
+    /// Where elements are equal, the one from this
+    /// queue is given.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// // queues must share their closure's type to be compared
+    /// let desc = |l: &i32, r: &i32| r.cmp(l);
+    /// let mut q1 = RBQueue::new(desc);
+    /// let mut q2 = RBQueue::new(desc);
+    /// q1.extend(0..3);
+    /// q2.extend(1..5);
+    /// assert_eq!(q1.intersection(&q2).collect::<Vec<_>>(), vec!(&2, &1));
+    /// ```
+    pub fn intersection<'a>(&'a self, other: &'a RBQueue<T, C>) -> Intersection<'a, T, C> {
+        self.raw.intersection(&other.raw)
+    }
+
+    /// Returns an iterator over the elements that are in
+    /// either this queue or `other`, in this queue's order.
+    /// Both queues must be ordered the same way. Where
+    /// elements are equal, the one from this queue is given.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// // queues must share their closure's type to be compared
+    /// let desc = |l: &i32, r: &i32| r.cmp(l);
+    /// let mut q1 = RBQueue::new(desc);
+    /// let mut q2 = RBQueue::new(desc);
+    /// q1.extend(0..3);
+    /// q2.extend(2..5);
+    /// assert_eq!(q1.union(&q2).collect::<Vec<_>>(), vec!(&4, &3, &2, &1, &0));
+    /// ```
+    pub fn union<'a>(&'a self, other: &'a RBQueue<T, C>) -> Union<'a, T, C> {
+        self.raw.union(&other.raw)
+    }
+
+    /// Returns an iterator over the elements of the queue
+    /// whose priority falls within the given range, in the
+    /// queue's order. The bounds are compared using the
+    /// queue's comparator, so for a queue that pops its
+    /// largest element first the range's start is the
+    /// higher bound. The iterator is empty if the range's
+    /// start comes after its end.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBQueue;
+    ///
+    /// // timers as (due time, name), soonest first
+    /// let mut timers = RBQueue::<(u64, &str)>::default();
+    /// timers.insert((12, "ping"));
+    /// timers.insert((3, "flush"));
+    /// timers.insert((17, "retry"));
+    /// timers.insert((8, "sync"));
+    /// let now = 5;
+    /// let soon = timers.range((now, "")..(now + 10, ""));
+    /// assert_eq!(soon.map(|(_, n)| *n).collect::<Vec<_>>(), vec!("sync", "ping"));
+    /// assert_eq!(timers.len(), 4);
+    /// ```
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> Range<'_, T> {
+        self.raw.range(range)
     }
 
     /// Retains in this RBQueue only those values for which
@@ -410,22 +1139,56 @@ where
     /// assert_eq!(t.iter().collect::<Vec<&usize>>(), vec!(&0, &2, &4, &6, &8));
     /// ```
     pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
-        let mut tmp = Vec::with_capacity(self.len());
-        while let Some(v) = self.pop() {
-            if f(&v) {
-                tmp.push(v);
-            }
+        self.raw.retain(|v| f(v));
+    }
+
+    // swaps val in for the front of a non-empty queue if it
+    // would still come before everything else, handing it
+    // back otherwise
+    fn replace_front(&mut self, val: T) -> std::result::Result<T, T> {
+        let nodes = &mut self.raw.nodes;
+        let front = nodes.first();
+        let second = nodes.step(front, true);
+        match nodes.value(second) {
+            Some(v) if self.raw.cmp.compare(&val, v) != Less => Err(val),
+            _ => Ok(std::mem::replace(nodes.value_mut(front).unwrap(), val)),
+        }
+    }
+
+    // the node for which probe gives Equal, if any
+    fn locate_by<F: Fn(&T) -> std::cmp::Ordering>(&self, probe: F) -> Option<Index> {
+        self.raw
+            .nodes
+            .locate(&(), &|_: &(), v: &T| probe(v).reverse())
+            .ok()
+    }
+
+    // checks whether val may be inserted under the queue's
+    // bound, evicting the back element if that makes room
+    fn make_room(&mut self, val: &T) -> bool {
+        let (capacity, policy) = match self.bound {
+            Some(bound) => bound,
+            None => return true,
+        };
+        if self.len() < capacity || self.contains(val) {
+            return true;
         }
-        while let Some(v) = tmp.pop() {
-            self.insert(v);
+        match (policy, self.peek_back()) {
+            (BoundPolicy::EvictWorst, Some(back))
+                if self.raw.cmp.compare(val, back) == std::cmp::Ordering::Less =>
+            {
+                self.pop_back();
+                true
+            }
+            _ => false,
         }
     }
 }
 
-impl<T, P> RBQueue<T, P>
+impl<T, C> RBQueue<T, C>
 where
     T: PartialOrd,
-    P: Fn(&T, &T) -> std::cmp::Ordering,
+    C: Comparator<T>,
 {
     /// Turns this queue into a set (RBTree)
     /// # Example:
@@ -456,15 +1219,157 @@ where
     }
 }
 
+impl<T: Ord> RBQueue<T, TotalOrder> {
+    /// Creates and returns a new, empty queue that
+    /// pops its smallest element first.
+    /// # Example:
+    /// ```
+    /// use rb_tree::{MinQueue, RBQueue};
+    ///
+    /// struct Scheduler {
+    ///     due: MinQueue<u64>,
+    /// }
+    ///
+    /// let mut s = Scheduler { due: RBQueue::min() };
+    /// s.due.insert(30);
+    /// s.due.insert(10);
+    /// assert_eq!(s.due.pop(), Some(10));
+    /// ```
+    pub fn min() -> MinQueue<T> {
+        RBQueue::with_comparator(TotalOrder)
+    }
+}
+
+impl<T: Ord> RBQueue<T, Reversed<TotalOrder>> {
+    /// Creates and returns a new, empty queue that
+    /// pops its largest element first.
+    /// # Example:
+    /// ```
+    /// use rb_tree::{MaxQueue, RBQueue};
+    ///
+    /// let mut scores: MaxQueue<u32> = RBQueue::max();
+    /// scores.insert(72);
+    /// scores.insert(95);
+    /// assert_eq!(scores.pop(), Some(95));
+    /// ```
+    pub fn max() -> MaxQueue<T> {
+        RBQueue::with_comparator(Reversed(TotalOrder))
+    }
+}
+
+impl<T, C: Comparator<T> + Default> RBQueue<T, C> {
+    /// Creates and returns a new, empty queue ordered by the
+    /// default instance of its comparator that holds at most
+    /// `n` elements. When the queue is full, `policy` decides
+    /// whether a new element is rejected or replaces the
+    /// element at the back of the queue, so with
+    /// `BoundPolicy::EvictWorst` the queue keeps the `n`
+    /// elements that come first in its order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::{MaxQueue, RBQueue};
+    /// use rb_tree::rbqueue::BoundPolicy;
+    ///
+    /// let mut top: MaxQueue<u32> = RBQueue::with_capacity_bounded(3, BoundPolicy::EvictWorst);
+    /// top.extend(vec!(5, 80, 13, 42, 7, 99));
+    /// assert_eq!(top.ordered(), [&99, &80, &42]);
+    ///
+    /// let mut first: RBQueue<u32> = RBQueue::with_capacity_bounded(2, BoundPolicy::Reject);
+    /// assert!(first.insert(5));
+    /// assert!(first.insert(8));
+    /// assert!(!first.insert(1));
+    /// assert_eq!(first.ordered(), [&5, &8]);
+    /// ```
+    pub fn with_capacity_bounded(n: usize, policy: BoundPolicy) -> RBQueue<T, C> {
+        RBQueue::with_comparator(C::default()).bounded(n, policy)
+    }
+}
+
+/// Creates an empty queue ordered by the default
+/// instance of its comparator.
+/// # Example:
+/// ```
+/// use rb_tree::{RBQueue, Reversed, NaturalOrder};
+///
+/// let mut q: RBQueue<u32> = RBQueue::default();
+/// q.insert(2);
+/// q.insert(1);
+/// assert_eq!(q.pop(), Some(1));
+///
+/// let mut rev: RBQueue<u32, Reversed<NaturalOrder>> = RBQueue::default();
+/// rev.insert(1);
+/// rev.insert(2);
+/// assert_eq!(rev.pop(), Some(2));
+/// ```
+impl<T, C: Comparator<T> + Default> Default for RBQueue<T, C> {
+    fn default() -> Self {
+        RBQueue::with_comparator(C::default())
+    }
+}
+
+/// Compares the elements of two queues in their
+/// order, so queues ordered by different comparators
+/// (even different closures) are equal if they hold
+/// equal elements in the same order.
+/// # Example:
+/// ```
+/// use rb_tree::RBQueue;
+///
+/// let mut q1 = RBQueue::new(|l: &i32, r: &i32| l.cmp(r));
+/// let mut q2 = RBQueue::<i32>::default();
+/// q1.extend(vec!(3, 1, 2));
+/// q2.extend(vec!(2, 3, 1));
+/// assert!(q1 == q2);
+/// q2.pop();
+/// assert!(q1 != q2);
+///
+/// let mut rev = RBQueue::new(|l: &i32, r: &i32| r.cmp(l));
+/// rev.extend(vec!(1, 2, 3));
+/// assert!(q1 != rev);
+/// ```
+impl<T, C, D> PartialEq<RBQueue<T, D>> for RBQueue<T, C>
+where
+    T: PartialEq,
+    C: Comparator<T>,
+    D: Comparator<T>,
+{
+    fn eq(&self, other: &RBQueue<T, D>) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq, C: Comparator<T>> Eq for RBQueue<T, C> {}
+
+#[derive(Clone)]
 pub struct IntoIter<T> {
-    order: Vec<T>,
+    order: std::vec::IntoIter<T>,
 }
 
 impl<T> Iterator for IntoIter<T> {
     type Item = T;
 
     fn next(&mut self) -> Option<T> {
-        self.order.pop()
+        self.order.next()
+    }
+}
+
+/// Provides the trait DoubleEndedIterator for IntoIter<T>,
+/// taking elements from the back of the queue
+/// # Example:
+/// ```
+/// use rb_tree::RBQueue;
+///
+/// let mut t = RBQueue::<i8>::default();
+/// t.extend(vec!(2, 1, 4, 3));
+///
+/// let mut iterator = t.into_iter();
+/// assert_eq!(iterator.next_back(), Some(4));
+/// assert_eq!(iterator.next(), Some(1));
+/// assert_eq!(iterator.rev().collect::<Vec<_>>(), vec!(3, 2));
+/// ```
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        self.order.next_back()
     }
 }
 
@@ -491,25 +1396,29 @@ impl<T> ExactSizeIterator for IntoIter<T> {
 
 impl<T> FusedIterator for IntoIter<T> {}
 
-impl<T, P> IntoIterator for RBQueue<T, P>
+impl<T: Debug> Debug for IntoIter<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.order.as_slice()).finish()
+    }
+}
+
+impl<T, C> IntoIterator for RBQueue<T, C>
 where
-    P: Fn(&T, &T) -> std::cmp::Ordering,
+    C: Comparator<T>,
 {
     type Item = T;
     type IntoIter = IntoIter<T>;
 
-    fn into_iter(mut self) -> IntoIter<T> {
-        let mut vec = Vec::with_capacity(self.len());
-        while let Some(v) = self.pop_back() {
-            vec.push(v);
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter {
+            order: self.raw.nodes.into_ordered().into_iter(),
         }
-        IntoIter { order: vec }
     }
 }
 
-impl<T, P> Extend<T> for RBQueue<T, P>
+impl<T, C> Extend<T> for RBQueue<T, C>
 where
-    P: Fn(&T, &T) -> std::cmp::Ordering,
+    C: Comparator<T>,
 {
     fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
         for i in iter {
@@ -518,10 +1427,10 @@ where
     }
 }
 
-impl<'a, T, P> Extend<&'a T> for RBQueue<T, P>
+impl<'a, T, C> Extend<&'a T> for RBQueue<T, C>
 where
     T: Copy + 'a,
-    P: Fn(&T, &T) -> std::cmp::Ordering,
+    C: Comparator<T>,
 {
     fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
         for &i in iter {
@@ -530,6 +1439,7 @@ where
     }
 }
 
+#[derive(Clone)]
 pub struct Drain<T> {
     ordered: Vec<T>,
 }
@@ -550,30 +1460,9 @@ impl<T> ExactSizeIterator for Drain<T> {
 
 impl<T> FusedIterator for Drain<T> {}
 
-pub struct Iter<'a, T> {
-    pos: usize,
-    ordered: Vec<&'a T>,
-}
-
-impl<'a, T> Iterator for Iter<'a, T> {
-    type Item = &'a T;
-
-    fn next(&mut self) -> Option<&'a T> {
-        let ret = self.ordered.get(self.pos);
-        match ret {
-            Some(v) => {
-                self.pos += 1;
-                Some(*v)
-            }
-            None => None,
-        }
-    }
-}
-
-impl<'a, T> ExactSizeIterator for Iter<'a, T> {
-    fn len(&self) -> usize {
-        self.ordered.len() - self.pos
+impl<T: Debug> Debug for Drain<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        // the remaining items are stored back to front
+        f.debug_list().entries(self.ordered.iter().rev()).finish()
     }
 }
-
-impl<'a, T> FusedIterator for Iter<'a, T> {}
@@ -1,34 +1,26 @@
-use crate::helpers::{insert_left_down, ordered_insertion, write_to_level};
-use crate::node::Colour::Black;
-use crate::node::Node;
-use crate::node::Node::Leaf;
+#[cfg(not(feature = "chunked"))]
+pub use crate::colour::Colour;
+use crate::encode::{decode_len, encode_len, initial_capacity, invalid, Encode};
+use crate::node::{Index, NIL};
+use crate::raw::{Arena, RawTree};
+pub use crate::raw::{Difference, Intersection, Iter, Range, SymmetricDifference, Union};
 #[cfg(feature = "queue")]
 use crate::RBQueue;
-use crate::RBTree;
+use crate::{Comparator, Error, FrozenRBTree, NaturalOrder, RBTree};
 
+use std::cmp::Ordering::{Equal, Greater, Less};
 use std::fmt::{Debug, Display, Formatter, Result};
-use std::iter::{ExactSizeIterator, FromIterator, FusedIterator};
+use std::io::{self, Read, Write};
+use std::iter::{ExactSizeIterator, FromIterator, FusedIterator, Sum};
+use std::ops::{Deref, DerefMut, RangeBounds};
 
-fn partial_ord<T, K: PartialOrd<T>>(l: &K, r: &T) -> std::cmp::Ordering {
-    l.partial_cmp(r).unwrap()
-}
-
-impl<T: PartialOrd + Debug> Debug for RBTree<T> {
+impl<T: Debug, C> Debug for RBTree<T, C> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        let mut levels = Vec::new();
-        write_to_level(&self.root, "".to_string(), 0, &mut levels);
-        let mut f_string = "".to_string();
-        for i in 0..levels.len() {
-            f_string += &levels[i];
-            if i != levels.len() - 1 {
-                f_string += "\n";
-            }
-        }
-        write!(f, "{}", f_string)
+        self.raw.fmt(f)
     }
 }
 
-impl<T: PartialOrd + Debug> Display for RBTree<T> {
+impl<T: Debug, C> Display for RBTree<T, C> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         write!(f, "{:?}", self.ordered())
     }
@@ -46,12 +38,44 @@ impl<T: PartialOrd> RBTree<T> {
     /// assert_eq!(t.take(&2).unwrap(), 2);
     /// ```
     pub fn new() -> RBTree<T> {
+        RBTree::with_comparator(NaturalOrder)
+    }
+}
+
+impl<T, C> RBTree<T, C> {
+    /// Creates and returns a new RBTree that orders
+    /// its elements using the given comparator rather
+    /// than their PartialOrd ordering.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// // ordered by the second field, largest first
+    /// let mut t = RBTree::with_comparator(|l: &(char, u8), r: &(char, u8)| r.1.cmp(&l.1));
+    /// t.insert(('a', 1));
+    /// t.insert(('b', 3));
+    /// t.insert(('c', 2));
+    /// assert_eq!(t.ordered(), vec!(&('b', 3), &('c', 2), &('a', 1)));
+    /// ```
+    pub fn with_comparator(cmp: C) -> RBTree<T, C> {
         RBTree {
-            root: Leaf(Black),
-            contained: 0,
+            raw: RawTree::new(cmp),
         }
     }
 
+    /// Returns a reference to the comparator
+    /// used to order the tree's elements.
+    /// # Example:
+    /// ```
+    /// use rb_tree::{NaturalOrder, RBTree};
+    ///
+    /// let t: RBTree<usize> = RBTree::new();
+    /// assert_eq!(t.comparator(), &NaturalOrder);
+    /// ```
+    pub fn comparator(&self) -> &C {
+        &self.raw.cmp
+    }
+
     /// Turns this tree into a queue with the given
     /// the comparison method.
     /// # Example:
@@ -102,8 +126,7 @@ impl<T: PartialOrd> RBTree<T> {
     /// assert!(!tree.contains(&2));
     /// ```
     pub fn clear(&mut self) {
-        self.root = Leaf(Black);
-        self.contained = 0;
+        self.raw.clear();
     }
 
     /// Clears the tree and returns all values
@@ -123,9 +146,10 @@ impl<T: PartialOrd> RBTree<T> {
     /// assert_eq!(tree.len(), 0);
     /// ```
     pub fn drain(&mut self) -> Drain<T> {
-        let mut rep = RBTree::new();
-        std::mem::swap(&mut rep, self);
-        Drain { tree: rep }
+        Drain {
+            nodes: std::mem::take(&mut self.raw.nodes),
+            remaining: std::mem::replace(&mut self.raw.contained, 0),
+        }
     }
 
     /// Returns a vector presenting the contained
@@ -144,9 +168,126 @@ impl<T: PartialOrd> RBTree<T> {
     /// assert_eq!(*order[1], 2);
     /// ```
     pub fn ordered(&self) -> Vec<&T> {
-        let mut order = Vec::new();
-        ordered_insertion(&self.root, &mut order);
-        order
+        self.raw.ordered()
+    }
+
+    /// Appends references to the elements of the RBTree
+    /// to `buf` in order, reusing its capacity rather
+    /// than allocating a new vector as `ordered` does.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let t: RBTree<_> = vec!(3, 1, 2).into_iter().collect();
+    /// let mut buf = Vec::with_capacity(3);
+    /// t.ordered_into(&mut buf);
+    /// assert_eq!(buf, vec!(&1, &2, &3));
+    /// ```
+    pub fn ordered_into<'a>(&'a self, buf: &mut Vec<&'a T>) {
+        buf.extend(self.iter());
+    }
+
+    /// Clears the tree, appending all of its values to
+    /// `buf` in order in a single pass. Both the tree and
+    /// `buf` keep the capacity they have, so neither needs
+    /// to allocate again when reused.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let mut t: RBTree<_> = vec!(3, 1, 2).into_iter().collect();
+    /// let mut buf = vec!(0);
+    /// t.drain_into(&mut buf);
+    /// assert_eq!(buf, vec!(0, 1, 2, 3));
+    /// assert!(t.is_empty());
+    /// ```
+    pub fn drain_into(&mut self, buf: &mut Vec<T>) {
+        buf.reserve(self.len());
+        self.raw.nodes.drain_ordered(|v| buf.push(v));
+        self.raw.contained = 0;
+    }
+
+    /// Consumes the tree, passing each item to `f` in order
+    /// and building a new tree from the values it returns,
+    /// leaving out those for which it returns None. Where the
+    /// values come out in ascending order, as they do when `f`
+    /// preserves the tree's order, the new tree is built in
+    /// linear time rather than inserting each value in turn.
+    /// Otherwise the values are inserted in the order they
+    /// were returned, so where any are equal the last is kept.
+    /// # Panics:
+    /// Panics if two of the values can't be compared
+    /// (`partial_cmp` returns None, as it does for `f64::NAN`).
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let millis: RBTree<u64> = vec!(1500, 250, 4000, 900).into_iter().collect();
+    /// // the whole seconds of those lasting at least one
+    /// let secs = millis.filter_map(|ms| if ms >= 1000 { Some(ms / 1000) } else { None });
+    /// assert_eq!(secs.ordered(), vec!(&1, &4));
+    /// ```
+    pub fn filter_map<U, F>(self, mut f: F) -> RBTree<U>
+    where
+        U: PartialOrd,
+        F: FnMut(T) -> Option<U>,
+    {
+        let mut values: Vec<U> = Vec::new();
+        let mut ascending = true;
+        for v in self.into_iter().filter_map(&mut f) {
+            if ascending {
+                if let Some(last) = values.last() {
+                    ascending = NaturalOrder.compare(last, &v) == Less;
+                }
+            }
+            values.push(v);
+        }
+        if ascending {
+            RBTree::from_sorted_vec(values, NaturalOrder)
+        } else {
+            values.into_iter().collect()
+        }
+    }
+
+    /// Consumes the tree, replacing each item with the value
+    /// `f` returns for it without moving or rebalancing any
+    /// node, so that converting every item takes O(n) time
+    /// and no allocation beyond the new values themselves.
+    /// `f` must preserve the order of the items: where one
+    /// item comes before another in this tree, the value `f`
+    /// returns for it must be less than the other's. This is
+    /// not checked (unless the `debug_invariants` feature is
+    /// enabled), and if it doesn't hold then lookups in the
+    /// new tree may fail to find the values it holds.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let celsius: RBTree<i32> = vec!(20, -5, 100, 37).into_iter().collect();
+    /// let fahrenheit = celsius.map_monotone(|c| c as f64 * 1.8 + 32.0);
+    /// assert_eq!(fahrenheit.ordered(), vec!(&23.0, &68.0, &98.60000000000001, &212.0));
+    /// assert!(fahrenheit.contains(&212.0));
+    /// ```
+    pub fn map_monotone<U, F>(self, f: F) -> RBTree<U>
+    where
+        U: PartialOrd,
+        F: FnMut(T) -> U,
+    {
+        let raw = RawTree {
+            contained: self.raw.contained,
+            nodes: self.raw.nodes.map_values(f),
+            cmp: NaturalOrder,
+        };
+        raw.verify();
+        RBTree { raw }
+    }
+
+    // builds a tree in linear time from values
+    // already in strictly ascending order
+    pub(crate) fn from_sorted_vec(values: Vec<T>, cmp: C) -> RBTree<T, C> {
+        let mut raw = RawTree::new(cmp);
+        raw.rebuild(values);
+        RBTree { raw }
     }
 
     /// Returns the number of elements contained
@@ -164,7 +305,7 @@ impl<T: PartialOrd> RBTree<T> {
     /// assert_eq!(t.len(), 2);
     /// ```
     pub fn len(&self) -> usize {
-        self.contained
+        self.raw.len()
     }
 
     /// Returns true if there are no items
@@ -182,9 +323,346 @@ impl<T: PartialOrd> RBTree<T> {
         self.len() == 0
     }
 
+    /// Tries to reserve room for at least `additional`
+    /// more elements, so that inserting them will not need
+    /// to allocate. Returns an error rather than aborting
+    /// if the memory could not be allocated.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let mut t = RBTree::new();
+    /// t.try_reserve(10).expect("out of memory");
+    /// (0..10).for_each(|v| {t.insert(v);});
+    /// assert!(t.try_reserve(usize::MAX).is_err());
+    /// ```
+    pub fn try_reserve(&mut self, additional: usize) -> std::result::Result<(), Error> {
+        self.raw.try_reserve(additional).map_err(Error::from)
+    }
+
+    /// Makes sure at least `n` more elements than the tree
+    /// now holds can be inserted without allocating. The
+    /// nodes of removed elements are kept and reused for
+    /// later insertions, and count towards `n`, so a tree
+    /// that is reserved for its working size will not
+    /// allocate while elements are inserted and removed at
+    /// a steady rate, even across calls to `clear`.
+    /// # Panics:
+    /// Panics if the new capacity overflows `usize`.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let mut t = RBTree::new();
+    /// t.reserve_nodes(100);
+    /// for v in 0..1000 {
+    ///     t.insert(v);
+    ///     if v >= 100 {
+    ///         t.remove(&(v - 100));
+    ///     }
+    /// }
+    /// assert_eq!(t.len(), 100);
+    /// ```
+    pub fn reserve_nodes(&mut self, n: usize) {
+        self.raw.reserve_nodes(n);
+    }
+
+    /// Gives the memory held for the nodes of removed
+    /// elements back to the allocator, so that a tree which
+    /// has shrunk after holding many elements doesn't keep
+    /// the memory it needed at its largest. Moves the
+    /// remaining nodes together, taking O(n) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let mut t: RBTree<u32> = (0..10_000).collect();
+    /// t.retain(|v| *v < 10);
+    /// t.shrink_to_fit();
+    /// assert!(t.iter().copied().eq(0..10));
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        self.raw.shrink_to_fit();
+    }
+
+    /// Removes the item at the front of the priority
+    /// queue that the RBTree represents if any elements
+    /// are present, or None otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let mut t = RBTree::new();
+    /// t.insert(2);
+    /// t.insert(1);
+    /// t.insert(3);
+    /// assert_eq!(t.pop().unwrap(), 1);
+    /// ```
+    pub fn pop(&mut self) -> Option<T> {
+        self.raw.pop(false)
+    }
+
+    /// Peeks the item at the front of the priority
+    /// queue that the RBTree represents if any elements
+    /// are present, or None otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let mut t = RBTree::new();
+    /// t.insert(2);
+    /// t.insert(1);
+    /// t.insert(3);
+    /// assert_eq!(*t.peek().unwrap(), 1);
+    /// ```
+    pub fn peek(&self) -> Option<&T> {
+        self.raw.peek(false)
+    }
+
+    /// Removes the item at the back of the priority
+    /// queue that the RBTree represents if any elements
+    /// are present, or None otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let mut t = RBTree::new();
+    /// t.insert(2);
+    /// t.insert(1);
+    /// t.insert(3);
+    /// assert_eq!(t.pop_back().unwrap(), 3);
+    /// ```
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.raw.pop(true)
+    }
+
+    /// Peeks the item at the back of the priority
+    /// queue that the RBTree represents if any elements
+    /// are present, or None otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let mut t = RBTree::new();
+    /// t.insert(2);
+    /// t.insert(1);
+    /// t.insert(3);
+    /// assert_eq!(*t.peek_back().unwrap(), 3);
+    /// ```
+    pub fn peek_back(&self) -> Option<&T> {
+        self.raw.peek(true)
+    }
+
+    /// Returns the element for which `f` returns
+    /// `Ordering::Equal`, or None if there isn't one.
+    /// `f` gives the ordering of an element relative to
+    /// the one being searched for, and must agree with
+    /// the ordering of the tree.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let t: RBTree<_> = vec!((1, 'a'), (4, 'b'), (9, 'c')).into_iter().collect();
+    /// assert_eq!(t.search_by(|v| v.0.cmp(&4)), Some(&(4, 'b')));
+    /// assert_eq!(t.search_by(|v| v.0.cmp(&5)), None);
+    /// ```
+    pub fn search_by<F>(&self, f: F) -> Option<&T>
+    where
+        F: FnMut(&T) -> std::cmp::Ordering,
+    {
+        self.raw.nodes.search_by(f)
+    }
+
+    /// Returns the first element for which `pred`
+    /// returns false, or None if it holds for every
+    /// element. `pred` must return true for all of the
+    /// elements before some point in the tree's order
+    /// and false for all of those after it.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let t: RBTree<_> = vec!(10, 20, 30, 40).into_iter().collect();
+    /// assert_eq!(t.partition_point(|&v| v <= 25), Some(&30));
+    /// assert_eq!(t.partition_point(|&v| v < 50), None);
+    /// ```
+    pub fn partition_point<P>(&self, pred: P) -> Option<&T>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        let nodes = &self.raw.nodes;
+        nodes.value(nodes.partition_point(pred))
+    }
+
+    /// Returns an iterator over the elements of the
+    /// tree that fall within the given range, in order.
+    /// The iterator is empty if the range's start comes
+    /// after its end.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let t: RBTree<_> = (0..10).collect();
+    /// assert_eq!(t.range(3..6).collect::<Vec<_>>(), vec!(&3, &4, &5));
+    /// assert_eq!(t.range(..=1).collect::<Vec<_>>(), vec!(&0, &1));
+    /// assert_eq!(t.range(8..).count(), 2);
+    /// ```
+    pub fn range<K, R>(&self, range: R) -> Range<'_, T>
+    where
+        R: RangeBounds<K>,
+        C: Comparator<K, T>,
+    {
+        self.raw.range(range)
+    }
+
+    /// Returns an iterator over the elements whose positions
+    /// in the tree's ordering (counting from 0) fall within
+    /// the given range, in order. Each node knows the size of
+    /// its subtree, so finding the first element takes
+    /// O(log n) time rather than stepping over those before
+    /// it.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let t: RBTree<_> = (0..1000).map(|v| v * 2).collect();
+    /// assert_eq!(t.select_range(100..103).collect::<Vec<_>>(), vec!(&200, &202, &204));
+    /// assert_eq!(t.select_range(998..).count(), 2);
+    /// assert_eq!(t.select_range(..=1).collect::<Vec<_>>(), vec!(&0, &2));
+    /// assert_eq!(t.select_range(1000..2000).next(), None);
+    /// ```
+    pub fn select_range<R: RangeBounds<usize>>(&self, range: R) -> Range<'_, T> {
+        self.raw.positions(range)
+    }
+
+    // mutable iteration is only exposed through maps,
+    // where it can't disturb the order of the tree
+    #[cfg(feature = "map")]
+    pub(crate) fn iter_mut(&mut self) -> IterMut<'_, T> {
+        let first = self.raw.nodes.first();
+        IterMut {
+            values: self.raw.nodes.values_mut(first, NIL),
+        }
+    }
+
+    #[cfg(feature = "map")]
+    pub(crate) fn range_mut<K, R>(&mut self, range: R) -> IterMut<'_, T>
+    where
+        R: RangeBounds<K>,
+        C: Comparator<K, T>,
+    {
+        let (next, end) = self.raw.range_ends(&range);
+        IterMut {
+            values: self.raw.nodes.values_mut(next, end),
+        }
+    }
+
+    // the number of elements in the range, counted from
+    // the ranks of its ends rather than by walking it
+    #[cfg(feature = "map")]
+    pub(crate) fn count_range<K, R>(&self, range: R) -> usize
+    where
+        R: RangeBounds<K>,
+        C: Comparator<K, T>,
+    {
+        let (next, end) = self.raw.range_ends(&range);
+        if next == NIL {
+            return 0;
+        }
+        let end = if end == NIL {
+            self.len()
+        } else {
+            self.raw.nodes.rank(end)
+        };
+        end - self.raw.nodes.rank(next)
+    }
+
+    /// Returns an iterator over the elements
+    /// contained in this RBTree.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let mut t = RBTree::new();
+    /// t.insert(3);
+    /// t.insert(1);
+    /// t.insert(5);
+    /// assert_eq!(t.iter().collect::<Vec<&usize>>(), vec!(&1, &3, &5));
+    /// ```
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.raw.iter()
+    }
+
+    /// Returns a read-only handle to the root node of the
+    /// tree, or None if the tree is empty. Following the
+    /// children of the handle walks the structure of the
+    /// tree, such as to draw it or check its properties.
+    /// Not available with the `chunked` feature, where each
+    /// node holds several elements.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    /// use rb_tree::rbtree::{Colour, NodeRef};
+    ///
+    /// // the number of black nodes on every path
+    /// // from the node down to a leaf
+    /// fn black_height(node: Option<NodeRef<'_, u32>>) -> usize {
+    ///     match node {
+    ///         None => 1,
+    ///         Some(n) => {
+    ///             let height = black_height(n.left());
+    ///             assert_eq!(height, black_height(n.right()));
+    ///             height + (n.colour() == Colour::Black) as usize
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let t: RBTree<u32> = (0..100).collect();
+    /// let root = t.root_ref().unwrap();
+    /// assert_eq!(root.colour(), Colour::Black);
+    /// assert_eq!(root.depth(), 0);
+    /// assert_eq!(root.left().unwrap().depth(), 1);
+    /// assert!(black_height(Some(root)) > 1);
+    /// ```
+    #[cfg(not(feature = "chunked"))]
+    pub fn root_ref(&self) -> Option<NodeRef<'_, T>> {
+        NodeRef::new(&self.raw.nodes, self.raw.nodes.root(), 0)
+    }
+
+    /// Consumes the tree, producing a read-only FrozenRBTree
+    /// holding the same elements in a sorted array. The result
+    /// is `Send + Sync` whenever `T` is, and is well suited to
+    /// serving lookups from many threads once building is done.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    /// use std::sync::Arc;
+    /// use std::thread;
+    ///
+    /// let t: RBTree<usize> = (0..100).collect();
+    /// let frozen = Arc::new(t.freeze());
+    /// let handles = (0..4).map(|i| {
+    ///     let f = frozen.clone();
+    ///     thread::spawn(move || f.contains(&(i * 25)))
+    /// }).collect::<Vec<_>>();
+    /// assert!(handles.into_iter().all(|h| h.join().unwrap()));
+    /// ```
+    pub fn freeze(self) -> FrozenRBTree<T, C> {
+        FrozenRBTree {
+            values: self.raw.nodes.into_ordered().into_boxed_slice(),
+            cmp: self.raw.cmp,
+        }
+    }
+}
+
+impl<T, C: Comparator<T>> RBTree<T, C> {
     /// Inserts a new element into the RBTree.
     /// Returns true if this item was not already
-    /// in the tree, and false otherwise.
+    /// in the tree, and false otherwise. When an
+    /// equal item is already in the tree it is
+    /// replaced by (and dropped in favour of) `val`;
+    /// use `insert_keep_existing` to keep it instead,
+    /// or `insert_replace` to get it back.
     /// # Example:
     /// ```
     /// use rb_tree::RBTree;
@@ -194,13 +672,7 @@ impl<T: PartialOrd> RBTree<T> {
     /// assert_eq!(t.insert("Hello".to_string()), false);
     /// ```
     pub fn insert(&mut self, val: T) -> bool {
-        match self.root.insert(val, &partial_ord) {
-            Some(_) => false,
-            None => {
-                self.contained += 1;
-                true
-            }
-        }
+        self.raw.insert(val).is_none()
     }
 
     /// Inserts a new element into the RBTree.
@@ -216,15 +688,105 @@ impl<T: PartialOrd> RBTree<T> {
     /// assert_eq!(t.replace("Hello".to_string()), Some("Hello".to_string()));
     /// ```
     pub fn replace(&mut self, val: T) -> Option<T> {
-        match self.root.insert(val, &partial_ord) {
+        self.raw.insert(val)
+    }
+
+    /// Inserts a new element into the RBTree only
+    /// if no equal item is already in the tree, which
+    /// is left untouched. Returns None if `val` was
+    /// inserted, and gives `val` back otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let mut t = RBTree::with_comparator(|l: &(u8, char), r: &(u8, char)| l.0.cmp(&r.0));
+    /// assert_eq!(t.insert_keep_existing((1, 'a')), None);
+    /// assert_eq!(t.insert_keep_existing((1, 'b')), Some((1, 'b')));
+    /// assert_eq!(t.get(&(1, ' ')), Some(&(1, 'a')));
+    /// ```
+    pub fn insert_keep_existing(&mut self, val: T) -> Option<T> {
+        let cmp = &self.raw.cmp;
+        match self
+            .raw
+            .nodes
+            .insert_new(val, &|l: &T, r: &T| cmp.compare(l, r))
+        {
             Some(v) => Some(v),
             None => {
-                self.contained += 1;
+                self.raw.contained += 1;
+                self.raw.verify();
                 None
             }
         }
     }
 
+    /// Inserts a new element into the RBTree, replacing
+    /// any equal item already in the tree. Returns the
+    /// replaced item if there was one, and None otherwise.
+    /// This is the same as `replace`.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let mut t = RBTree::with_comparator(|l: &(u8, char), r: &(u8, char)| l.0.cmp(&r.0));
+    /// assert_eq!(t.insert_replace((1, 'a')), None);
+    /// assert_eq!(t.insert_replace((1, 'b')), Some((1, 'a')));
+    /// assert_eq!(t.get(&(1, ' ')), Some(&(1, 'b')));
+    /// ```
+    pub fn insert_replace(&mut self, val: T) -> Option<T> {
+        self.replace(val)
+    }
+
+    /// Inserts a new element into the RBTree as `insert`
+    /// does, but returns an error rather than aborting if
+    /// room for the element could not be allocated, in
+    /// which case `val` is dropped and the tree is left
+    /// unchanged. This may fail even when an equal element
+    /// is already contained.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let mut t = RBTree::new();
+    /// assert_eq!(t.try_insert_alloc(1), Ok(true));
+    /// assert_eq!(t.try_insert_alloc(1), Ok(false));
+    /// ```
+    pub fn try_insert_alloc(&mut self, val: T) -> std::result::Result<bool, Error> {
+        self.raw.nodes.try_make_room()?;
+        Ok(self.insert(val))
+    }
+
+    /// Inserts a new element into the RBTree as `insert`
+    /// does, but returns an error rather than panicking if
+    /// `val` could not be compared to the elements already
+    /// in the tree, in which case `val` is dropped and the
+    /// tree is left unchanged.
+    /// # Example:
+    /// ```
+    /// use rb_tree::{Error, RBTree};
+    ///
+    /// let mut t = RBTree::new();
+    /// assert_eq!(t.try_insert(1.0), Ok(true));
+    /// assert_eq!(t.try_insert(1.0), Ok(false));
+    /// assert_eq!(t.try_insert(f64::NAN), Err(Error::IncomparableElement));
+    /// assert_eq!(t.len(), 1);
+    /// ```
+    pub fn try_insert(&mut self, val: T) -> std::result::Result<bool, Error> {
+        let cmp = &self.raw.cmp;
+        match self
+            .raw
+            .nodes
+            .try_insert(val, &|l: &T, r: &T| cmp.try_compare(l, r))?
+        {
+            Some(_) => Ok(false),
+            None => {
+                self.raw.contained += 1;
+                self.raw.verify();
+                Ok(true)
+            }
+        }
+    }
+
     /// Returns true if the tree contains the
     /// specified item, false otherwise.
     /// # Example:
@@ -236,10 +798,48 @@ impl<T: PartialOrd> RBTree<T> {
     /// assert!(!t.contains(&3));
     /// assert!(t.contains(&2));
     /// ```
-    pub fn contains(&self, val: &T) -> bool {
+    pub fn contains<K>(&self, val: &K) -> bool
+    where
+        C: Comparator<K, T>,
+    {
         self.get(val).is_some()
     }
 
+    /// Returns true if the tree contains every one of
+    /// the items given, false otherwise. The items are
+    /// sorted and then checked in a single pass over the
+    /// tree rather than looked up one at a time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let t: RBTree<_> = (0..10).collect();
+    /// assert!(t.contains_all(vec!(7, 2, 5)));
+    /// assert!(!t.contains_all(vec!(7, 12)));
+    /// ```
+    pub fn contains_all<K, I>(&self, vals: I) -> bool
+    where
+        I: IntoIterator<Item = K>,
+        C: Comparator<K, T> + Comparator<K>,
+    {
+        let mut vals: Vec<K> = vals.into_iter().collect();
+        vals.sort_by(|l, r| Comparator::<K>::compare(&self.raw.cmp, l, r));
+        let mut iter = self.iter().peekable();
+        'vals: for val in vals.iter() {
+            while let Some(&v) = iter.peek() {
+                match Comparator::<K, T>::compare(&self.raw.cmp, val, v) {
+                    Less => return false,
+                    Equal => continue 'vals,
+                    Greater => {
+                        iter.next();
+                    }
+                }
+            }
+            return false;
+        }
+        true
+    }
+
     /// Returns the item specified if contained,
     /// None otherwise.
     /// # Example:
@@ -251,160 +851,223 @@ impl<T: PartialOrd> RBTree<T> {
     /// assert_eq!(*t.get(&1).unwrap(), 1);
     /// assert_eq!(t.get(&2), None);
     /// ```
-    pub fn get<K: PartialOrd<T>>(&self, val: &K) -> Option<&T> {
-        self.root.get(val, &partial_ord)
-    }
-
-    #[cfg(feature = "map")]
-    pub(crate) fn get_mut<K: PartialOrd<T>>(&mut self, val: &K) -> Option<&mut T> {
-        self.root.get_mut(val, &partial_ord)
+    pub fn get<K>(&self, val: &K) -> Option<&T>
+    where
+        C: Comparator<K, T>,
+    {
+        self.raw.get(val)
     }
 
-    /// Removes an item the tree. Returns the matching item
-    /// if it was contained in the tree, None otherwise.
+    /// Looks up each of the keys given, returning an iterator
+    /// over the matching items (or None where there is no
+    /// match) in the order the keys were given. The keys are
+    /// sorted and then resolved in a single pass over the tree,
+    /// which is cheaper than looking each up in turn when there
+    /// are many of them.
     /// # Example:
     /// ```
     /// use rb_tree::RBTree;
     ///
-    /// let mut t = RBTree::new();
-    /// t.insert(4);
-    /// t.insert(2);
-    /// assert_eq!(t.take(&2).unwrap(), 2);
-    /// assert_eq!(t.len(), 1);
-    /// assert_eq!(t.take(&2), None);
+    /// let t: RBTree<_> = (0..100).map(|v| v * 10).collect();
+    /// let found: Vec<_> = t.get_all(vec!(500, 15, 30, 500)).collect();
+    /// assert_eq!(found, vec!(Some(&500), None, Some(&30), Some(&500)));
     /// ```
-    pub fn take<K: PartialOrd<T>>(&mut self, val: &K) -> Option<T> {
-        match self.root.remove(val, &partial_ord) {
-            Some(v) => {
-                self.contained -= 1;
-                Some(v)
-            }
-            None => None,
+    pub fn get_all<K, I>(&self, keys: I) -> GetAll<'_, T>
+    where
+        I: IntoIterator<Item = K>,
+        C: Comparator<K, T> + Comparator<K>,
+    {
+        let cmp = &self.raw.cmp;
+        let mut keys: Vec<(usize, K)> = keys.into_iter().enumerate().collect();
+        keys.sort_by(|(_, l), (_, r)| Comparator::<K>::compare(cmp, l, r));
+        let mut found = vec![None; keys.len()];
+        let mut iter = self.iter().peekable();
+        for (i, key) in keys.iter() {
+            while iter
+                .next_if(|v| Comparator::<K, T>::compare(cmp, key, v) == Greater)
+                .is_some()
+            {}
+            found[*i] = iter
+                .peek()
+                .copied()
+                .filter(|v| Comparator::<K, T>::compare(cmp, key, v) == Equal);
+        }
+        GetAll {
+            found: found.into_iter(),
         }
     }
 
-    /// Removes an item the tree. Returns true
-    /// if it was contained in the tree, false otherwise.
+    /// Returns Ok with the position of the element equal to
+    /// `val` in the tree's ordering (the number of elements
+    /// less than it) if there is one, or else Err with the
+    /// position it would take if it were inserted. Like
+    /// `slice::binary_search`, this answers whether the tree
+    /// holds a value and where it falls in one O(log n) search.
     /// # Example:
     /// ```
     /// use rb_tree::RBTree;
     ///
-    /// let mut t = RBTree::new();
-    /// t.insert(4);
-    /// t.insert(2);
-    /// assert_eq!(t.remove(&2), true);
-    /// assert_eq!(t.len(), 1);
-    /// assert_eq!(t.remove(&2), false);
+    /// let t: RBTree<_> = vec!(10, 40, 20, 30).into_iter().collect();
+    /// assert_eq!(t.binary_search(&30), Ok(2));
+    /// assert_eq!(t.binary_search(&25), Err(2));
+    /// assert_eq!(t.binary_search(&5), Err(0));
+    /// assert_eq!(t.binary_search(&50), Err(4));
     /// ```
-    pub fn remove<K: PartialOrd<T>>(&mut self, val: &K) -> bool {
-        match self.root.remove(val, &partial_ord) {
-            Some(_) => {
-                self.contained -= 1;
-                true
-            }
-            None => false,
+    pub fn binary_search<K>(&self, val: &K) -> std::result::Result<usize, usize>
+    where
+        C: Comparator<K, T>,
+    {
+        let nodes = &self.raw.nodes;
+        match self.raw.locate(val) {
+            Ok(at) => Ok(nodes.rank(at)),
+            Err((at, _)) if at == NIL => Err(0),
+            Err((at, right)) => Err(nodes.rank(at) + right as usize),
         }
     }
 
-    /// Removes the item at the front of the priority
-    /// queue that the RBTree represents if any elements
-    /// are present, or None otherwise.
+    /// Returns the item specified if contained, None
+    /// otherwise, as `get` does, but returns an error rather
+    /// than panicking if `val` could not be compared to the
+    /// elements of the tree.
     /// # Example:
     /// ```
-    /// use rb_tree::RBTree;
+    /// use rb_tree::{Error, RBTree};
     ///
     /// let mut t = RBTree::new();
-    /// t.insert(2);
-    /// t.insert(1);
-    /// t.insert(3);
-    /// assert_eq!(t.pop().unwrap(), 1);
+    /// t.insert(1.0);
+    /// assert_eq!(t.try_get(&1.0), Ok(Some(&1.0)));
+    /// assert_eq!(t.try_get(&2.0), Ok(None));
+    /// assert_eq!(t.try_get(&f64::NAN), Err(Error::IncomparableElement));
     /// ```
-    pub fn pop(&mut self) -> Option<T> {
-        match self.root.pop(false) {
-            Some(v) => {
-                self.contained -= 1;
-                Some(v)
-            }
-            None => None,
-        }
+    pub fn try_get<K>(&self, val: &K) -> std::result::Result<Option<&T>, Error>
+    where
+        C: Comparator<K, T>,
+    {
+        self.raw
+            .nodes
+            .try_get(val, &|l: &K, r: &T| self.raw.cmp.try_compare(l, r))
+            .map_err(Error::from)
     }
 
-    /// Peeks the item at the front of the priority
-    /// queue that the RBTree represents if any elements
-    /// are present, or None otherwise.
+    #[cfg(feature = "map")]
+    pub(crate) fn get_mut<K>(&mut self, val: &K) -> Option<&mut T>
+    where
+        C: Comparator<K, T>,
+    {
+        let cmp = &self.raw.cmp;
+        self.raw
+            .nodes
+            .get_mut(val, &|l: &K, r: &T| cmp.compare(l, r))
+    }
+
+    /// Returns a guard giving mutable access to the item
+    /// specified if contained, None otherwise. Changing
+    /// the item so that it would be ordered differently
+    /// is allowed; when the guard is dropped the item is
+    /// moved to its new place in the tree, replacing any
+    /// equal item already there.
     /// # Example:
     /// ```
     /// use rb_tree::RBTree;
     ///
-    /// let mut t = RBTree::new();
-    /// t.insert(2);
-    /// t.insert(1);
-    /// t.insert(3);
-    /// assert_eq!(*t.peek().unwrap(), 1);
+    /// let mut t = RBTree::with_comparator(|l: &(u8, u8), r: &(u8, u8)| l.0.cmp(&r.0));
+    /// t.insert((1, 0));
+    /// t.insert((2, 0));
+    /// // changing a field the ordering ignores
+    /// t.get_mut_guard(&(1, 0)).unwrap().1 = 5;
+    /// assert_eq!(t.get(&(1, 0)), Some(&(1, 5)));
+    /// // changing the ordering moves the item
+    /// t.get_mut_guard(&(1, 0)).unwrap().0 = 3;
+    /// assert_eq!(t.ordered(), vec!(&(2, 0), &(3, 5)));
     /// ```
-    pub fn peek(&self) -> Option<&T> {
-        self.root.peek(false)
+    pub fn get_mut_guard<K>(&mut self, val: &K) -> Option<RefMut<'_, T, C>>
+    where
+        C: Comparator<K, T>,
+    {
+        let cmp = &self.raw.cmp;
+        let at = self.raw.nodes.find(val, &|l: &K, r: &T| cmp.compare(l, r));
+        if at == NIL {
+            None
+        } else {
+            Some(RefMut { tree: self, at })
+        }
     }
 
-    /// Removes the item at the back of the priority
-    /// queue that the RBTree represents if any elements
-    /// are present, or None otherwise.
+    /// Removes an item the tree. Returns the matching item
+    /// if it was contained in the tree, None otherwise.
     /// # Example:
     /// ```
     /// use rb_tree::RBTree;
     ///
     /// let mut t = RBTree::new();
+    /// t.insert(4);
     /// t.insert(2);
-    /// t.insert(1);
-    /// t.insert(3);
-    /// assert_eq!(t.pop_back().unwrap(), 3);
+    /// assert_eq!(t.take(&2).unwrap(), 2);
+    /// assert_eq!(t.len(), 1);
+    /// assert_eq!(t.take(&2), None);
     /// ```
-    pub fn pop_back(&mut self) -> Option<T> {
-        match self.root.pop(true) {
-            Some(v) => {
-                self.contained -= 1;
-                Some(v)
-            }
-            None => None,
-        }
+    pub fn take<K>(&mut self, val: &K) -> Option<T>
+    where
+        C: Comparator<K, T>,
+    {
+        self.raw.take(val)
     }
 
-    /// Peeks the item at the back of the priority
-    /// queue that the RBTree represents if any elements
-    /// are present, or None otherwise.
+    /// Removes an item the tree. Returns true
+    /// if it was contained in the tree, false otherwise.
     /// # Example:
     /// ```
     /// use rb_tree::RBTree;
     ///
     /// let mut t = RBTree::new();
+    /// t.insert(4);
     /// t.insert(2);
-    /// t.insert(1);
-    /// t.insert(3);
-    /// assert_eq!(*t.peek_back().unwrap(), 3);
+    /// assert_eq!(t.remove(&2), true);
+    /// assert_eq!(t.len(), 1);
+    /// assert_eq!(t.remove(&2), false);
     /// ```
-    pub fn peek_back(&self) -> Option<&T> {
-        self.root.peek(true)
+    pub fn remove<K>(&mut self, val: &K) -> bool
+    where
+        C: Comparator<K, T>,
+    {
+        self.raw.take(val).is_some()
     }
 
-    /// Returns an iterator over the elements
-    /// contained in this RBTree.
+    /// Removes every one of the items given that is
+    /// contained in the tree, returning the number of
+    /// items removed. The items are sorted, and then each
+    /// is looked up in turn when there are few of them
+    /// compared to the tree, or else the tree is walked
+    /// alongside them in a single pass. Either way, the
+    /// elements are removed from the tree where they are.
     /// # Example:
     /// ```
     /// use rb_tree::RBTree;
     ///
-    /// let mut t = RBTree::new();
-    /// t.insert(3);
-    /// t.insert(1);
-    /// t.insert(5);
-    /// assert_eq!(t.iter().collect::<Vec<&usize>>(), vec!(&1, &3, &5));
+    /// let mut t: RBTree<_> = (0..10).collect();
+    /// assert_eq!(t.remove_all(vec!(8, 3, 12, 3)), 2);
+    /// assert_eq!(t.len(), 8);
+    /// assert!(!t.contains(&3));
     /// ```
-    pub fn iter(&self) -> Iter<T> {
-        let mut ordered = Vec::new();
-        insert_left_down(&self.root, &mut ordered);
-        Iter {
-            remaining: self.len(),
-            ordered,
+    pub fn remove_all<K, I>(&mut self, vals: I) -> usize
+    where
+        I: IntoIterator<Item = K>,
+        C: Comparator<K, T> + Comparator<K>,
+    {
+        let cmp = &self.raw.cmp;
+        let mut vals: Vec<K> = vals.into_iter().collect();
+        vals.sort_by(|l, r| Comparator::<K>::compare(cmp, l, r));
+        vals.dedup_by(|l, r| Comparator::<K>::compare(cmp, l, r) == Equal);
+        // a lookup costs about log n steps of the walk
+        let log_n = (usize::BITS - self.len().leading_zeros()) as usize;
+        if vals.len() * log_n >= self.len() {
+            return self.raw.remove_sorted(vals);
         }
+        let found = vals
+            .iter()
+            .filter_map(|v| self.raw.locate(v).ok())
+            .collect();
+        self.raw.remove_found(found)
     }
 
     /// Returns an iterator representing the
@@ -428,15 +1091,8 @@ impl<T: PartialOrd> RBTree<T> {
     ///     vec!(&3, &4)
     /// );
     /// ```
-    pub fn difference<'a>(&'a self, other: &'a RBTree<T>) -> Difference<'a, T> {
-        let mut iterl = self.iter();
-        let mut iterr = other.iter();
-        Difference {
-            nextl: iterl.next(),
-            nextr: iterr.next(),
-            left: iterl,
-            right: iterr,
-        }
+    pub fn difference<'a>(&'a self, other: &'a RBTree<T, C>) -> Difference<'a, T, C> {
+        self.raw.difference(&other.raw)
     }
 
     /// Returns an iterator representing the
@@ -460,15 +1116,11 @@ impl<T: PartialOrd> RBTree<T> {
     ///     vec!(&0, &1, &3, &4)
     /// );
     /// ```
-    pub fn symmetric_difference<'a>(&'a self, other: &'a RBTree<T>) -> SymmetricDifference<'a, T> {
-        let mut iterl = self.iter();
-        let mut iterr = other.iter();
-        SymmetricDifference {
-            nextl: iterl.next(),
-            nextr: iterr.next(),
-            left: iterl,
-            right: iterr,
-        }
+    pub fn symmetric_difference<'a>(
+        &'a self,
+        other: &'a RBTree<T, C>,
+    ) -> SymmetricDifference<'a, T, C> {
+        self.raw.symmetric_difference(&other.raw)
     }
 
     /// Returns an iterator representing the intersection
@@ -487,15 +1139,8 @@ impl<T: PartialOrd> RBTree<T> {
     ///     vec!(&2)
     /// );
     /// ```
-    pub fn intersection<'a>(&'a self, other: &'a RBTree<T>) -> Intersection<'a, T> {
-        let mut iterl = self.iter();
-        let mut iterr = other.iter();
-        Intersection {
-            nextl: iterl.next(),
-            nextr: iterr.next(),
-            left: iterl,
-            right: iterr,
-        }
+    pub fn intersection<'a>(&'a self, other: &'a RBTree<T, C>) -> Intersection<'a, T, C> {
+        self.raw.intersection(&other.raw)
     }
 
     /// Returns an iterator representing the union
@@ -514,15 +1159,8 @@ impl<T: PartialOrd> RBTree<T> {
     ///     vec!(&0, &1, &2, &3, &4)
     /// );
     /// ```
-    pub fn union<'a>(&'a self, other: &'a RBTree<T>) -> Union<'a, T> {
-        let mut iterl = self.iter();
-        let mut iterr = other.iter();
-        Union {
-            nextl: iterl.next(),
-            nextr: iterr.next(),
-            left: iterl,
-            right: iterr,
-        }
+    pub fn union<'a>(&'a self, other: &'a RBTree<T, C>) -> Union<'a, T, C> {
+        self.raw.union(&other.raw)
     }
 
     /// Returns true if this RBTree and another are disjoint,
@@ -540,7 +1178,7 @@ impl<T: PartialOrd> RBTree<T> {
     /// t2.pop(); // remove '2' from t2
     /// assert!(t1.is_disjoint(&t2));
     /// ```
-    pub fn is_disjoint(&self, other: &RBTree<T>) -> bool {
+    pub fn is_disjoint(&self, other: &RBTree<T, C>) -> bool {
         self.intersection(other).next().is_none()
     }
 
@@ -560,8 +1198,24 @@ impl<T: PartialOrd> RBTree<T> {
     /// assert!(!t1.is_subset(&t2));
     /// assert!(t3.is_subset(&t2));
     /// ```
-    pub fn is_subset(&self, other: &RBTree<T>) -> bool {
-        self.intersection(other).count() == self.len()
+    pub fn is_subset(&self, other: &RBTree<T, C>) -> bool {
+        if self.len() > other.len() {
+            return false;
+        }
+        // walk both trees together, stopping at the
+        // first value of self missing from other
+        let mut right = other.iter();
+        'left: for vl in self.iter() {
+            for vr in right.by_ref() {
+                match self.raw.cmp.compare(vl, vr) {
+                    Less => return false,
+                    Equal => continue 'left,
+                    Greater => {}
+                }
+            }
+            return false;
+        }
+        true
     }
 
     /// Returns true if this RBTree is a superset of another,
@@ -580,8 +1234,8 @@ impl<T: PartialOrd> RBTree<T> {
     /// assert!(!t2.is_superset(&t1));
     /// assert!(t2.is_superset(&t3));
     /// ```
-    pub fn is_superset(&self, other: &RBTree<T>) -> bool {
-        other.intersection(self).count() == other.len()
+    pub fn is_superset(&self, other: &RBTree<T, C>) -> bool {
+        other.is_subset(self)
     }
 
     /// Retains in this RBTree only those values for which
@@ -595,42 +1249,267 @@ impl<T: PartialOrd> RBTree<T> {
     /// assert_eq!(t.iter().collect::<Vec<&usize>>(), vec!(&0, &2, &4, &6, &8));
     /// ```
     pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
-        let mut rep = RBTree::new();
-        while let Some(v) = self.pop() {
-            if f(&v) {
-                rep.insert(v);
+        self.raw.retain(|v| f(v));
+    }
+
+    /// Removes the elements equal to any of `keys`, which must
+    /// come in the tree's order, and returns how many were
+    /// removed. The tree and the keys are walked side by side
+    /// in a single pass, which is much cheaper than removing
+    /// each key in turn when there are many of them. Keys that
+    /// come out of order may be skipped over without removing
+    /// their element.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let mut live: RBTree<u32> = (0..100).collect();
+    /// // a sorted batch of ids deleted elsewhere
+    /// let deleted = vec!(3, 10, 11, 57, 200);
+    /// assert_eq!(live.remove_sorted(deleted), 4);
+    /// assert_eq!(live.len(), 96);
+    /// assert!(!live.contains(&10));
+    /// assert!(live.contains(&12));
+    /// ```
+    pub fn remove_sorted<K, I>(&mut self, keys: I) -> usize
+    where
+        I: IntoIterator<Item = K>,
+        C: Comparator<K, T>,
+    {
+        self.raw.remove_sorted(keys)
+    }
+}
+
+impl<T: Encode, C> RBTree<T, C> {
+    /// Writes the elements of the tree to `writer` in
+    /// order, prefixed by their count, so the tree can
+    /// later be rebuilt using `read_from`.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let t: RBTree<u16> = (0..4).collect();
+    /// let mut buf = Vec::new();
+    /// t.write_to(&mut buf).unwrap();
+    /// assert_eq!(buf.len(), 8 + 4 * 2);
+    /// ```
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        encode_len(self.len(), &mut writer)?;
+        self.iter().try_for_each(|v| v.encode(&mut writer))
+    }
+}
+
+impl<T: Encode, C: Comparator<T>> RBTree<T, C> {
+    /// Reads a tree written by `write_to` from `reader`,
+    /// ordering it with the given comparator. The tree is
+    /// built in linear time. Returns an error of kind
+    /// `InvalidData` if the elements read aren't in
+    /// strictly ascending order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::{NaturalOrder, RBTree, Reversed};
+    ///
+    /// let mut t = RBTree::with_comparator(Reversed(NaturalOrder));
+    /// t.extend(vec!(1, 3, 2));
+    /// let mut buf = Vec::new();
+    /// t.write_to(&mut buf).unwrap();
+    /// let read: RBTree<i32, _> = RBTree::read_from_with(&buf[..], Reversed(NaturalOrder)).unwrap();
+    /// assert_eq!(read.ordered(), vec!(&3, &2, &1));
+    /// ```
+    pub fn read_from_with<R: Read>(mut reader: R, cmp: C) -> io::Result<RBTree<T, C>> {
+        let len = decode_len(&mut reader)?;
+        let mut values: Vec<T> = Vec::with_capacity(initial_capacity(len));
+        for _ in 0..len {
+            let v = T::decode(&mut reader)?;
+            if let Some(prev) = values.last() {
+                if cmp.compare(prev, &v) != Less {
+                    return Err(invalid("elements are not in ascending order"));
+                }
             }
+            values.push(v);
         }
-        std::mem::swap(&mut rep, self);
+        let nodes = Arena::from_sorted(values)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut raw = RawTree::new(cmp);
+        raw.nodes = nodes;
+        raw.contained = len;
+        Ok(RBTree { raw })
+    }
+}
+
+impl<T: Encode, C: Comparator<T> + Default> RBTree<T, C> {
+    /// Reads a tree written by `write_to` from `reader`.
+    /// The tree is built in linear time. Returns an error
+    /// of kind `InvalidData` if the elements read aren't
+    /// in strictly ascending order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::RBTree;
+    ///
+    /// let t: RBTree<String> = vec!("b", "a").into_iter().map(String::from).collect();
+    /// let mut buf = Vec::new();
+    /// t.write_to(&mut buf).unwrap();
+    /// let read: RBTree<String> = RBTree::read_from(&buf[..]).unwrap();
+    /// assert_eq!(read.ordered(), vec!("a", "b"));
+    /// ```
+    pub fn read_from<R: Read>(reader: R) -> io::Result<RBTree<T, C>> {
+        RBTree::read_from_with(reader, C::default())
     }
 }
 
 #[cfg(feature = "queue")]
-impl<T, P> From<RBQueue<T, P>> for RBTree<T>
+impl<T, C> From<RBQueue<T, C>> for RBTree<T>
 where
     T: PartialOrd,
-    P: Copy + Fn(&T, &T) -> std::cmp::Ordering,
+    C: Comparator<T>,
 {
-    fn from(q: RBQueue<T, P>) -> Self {
+    fn from(q: RBQueue<T, C>) -> Self {
         q.into_set()
     }
 }
 
-impl<T: PartialOrd> Default for RBTree<T> {
+impl<T, C: Default> Default for RBTree<T, C> {
     fn default() -> Self {
-        RBTree::new()
+        RBTree::with_comparator(C::default())
+    }
+}
+
+/// A read-only handle to a node of an RBTree, giving
+/// its element, colour and depth and handles to its
+/// children. Returned by `RBTree::root_ref`.
+#[cfg(not(feature = "chunked"))]
+pub struct NodeRef<'a, T> {
+    nodes: &'a Arena<T>,
+    at: Index,
+    depth: usize,
+}
+
+#[cfg(not(feature = "chunked"))]
+impl<'a, T> NodeRef<'a, T> {
+    fn new(nodes: &'a Arena<T>, at: Index, depth: usize) -> Option<NodeRef<'a, T>> {
+        if at == NIL {
+            None
+        } else {
+            Some(NodeRef { nodes, at, depth })
+        }
+    }
+
+    /// Returns the element held by the node.
+    pub fn value(&self) -> &'a T {
+        self.nodes.value(self.at).unwrap()
+    }
+
+    /// Returns the colour of the node.
+    pub fn colour(&self) -> Colour {
+        self.nodes.colour(self.at)
+    }
+
+    /// Returns the number of nodes above this one,
+    /// so that the root is at depth 0.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the left child of the node, whose
+    /// elements come before the node's own, or None
+    /// if it has no left child.
+    pub fn left(&self) -> Option<NodeRef<'a, T>> {
+        NodeRef::new(self.nodes, self.nodes.get_left(self.at), self.depth + 1)
+    }
+
+    /// Returns the right child of the node, whose
+    /// elements come after the node's own, or None
+    /// if it has no right child.
+    pub fn right(&self) -> Option<NodeRef<'a, T>> {
+        NodeRef::new(self.nodes, self.nodes.get_right(self.at), self.depth + 1)
+    }
+}
+
+#[cfg(not(feature = "chunked"))]
+impl<'a, T> Clone for NodeRef<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+#[cfg(not(feature = "chunked"))]
+impl<'a, T> Copy for NodeRef<'a, T> {}
+
+#[cfg(not(feature = "chunked"))]
+impl<'a, T: Debug> Debug for NodeRef<'a, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_struct("NodeRef")
+            .field("value", self.value())
+            .field("colour", &self.colour())
+            .field("depth", &self.depth)
+            .finish()
+    }
+}
+
+/// Mutable access to an item of an RBTree, returned
+/// by `RBTree::get_mut_guard`. The item is moved if its
+/// ordering changed once the guard is dropped.
+pub struct RefMut<'a, T, C: Comparator<T>> {
+    tree: &'a mut RBTree<T, C>,
+    at: Index,
+}
+
+impl<'a, T, C: Comparator<T>> Deref for RefMut<'a, T, C> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.tree.raw.nodes.value(self.at).unwrap()
+    }
+}
+
+impl<'a, T, C: Comparator<T>> DerefMut for RefMut<'a, T, C> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.tree.raw.nodes.value_mut(self.at).unwrap()
     }
 }
 
-pub struct IntoIter<T: PartialOrd> {
-    tree: RBTree<T>,
+impl<'a, T, C: Comparator<T>> Drop for RefMut<'a, T, C> {
+    fn drop(&mut self) {
+        // the item is still in place if it's ordered
+        // between its neighbours
+        let nodes = &self.tree.raw.nodes;
+        let cmp = &self.tree.raw.cmp;
+        let val = nodes.value(self.at).unwrap();
+        let after_prev = match nodes.value(nodes.step(self.at, false)) {
+            Some(prev) => cmp.compare(prev, val) == Less,
+            None => true,
+        };
+        let before_next = match nodes.value(nodes.step(self.at, true)) {
+            Some(next) => cmp.compare(val, next) == Less,
+            None => true,
+        };
+        if !(after_prev && before_next) {
+            let val = self.tree.raw.nodes.remove_at(self.at);
+            self.tree.raw.contained -= 1;
+            self.tree.insert(val);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct IntoIter<T> {
+    nodes: Arena<T>,
+    remaining: usize,
+}
+
+impl<T> IntoIter<T> {
+    pub(crate) fn new(nodes: Arena<T>, remaining: usize) -> IntoIter<T> {
+        IntoIter { nodes, remaining }
+    }
 }
 
-impl<T: PartialOrd> Iterator for IntoIter<T> {
+impl<T> Iterator for IntoIter<T> {
     type Item = T;
 
     fn next(&mut self) -> Option<T> {
-        self.tree.pop()
+        let next = self.nodes.pop(false)?;
+        self.remaining -= 1;
+        Some(next)
     }
 }
 
@@ -649,26 +1528,33 @@ impl<T: PartialOrd> Iterator for IntoIter<T> {
 /// let _ = iterator.next();
 /// assert_eq!(iterator.len(), 2);
 /// ```
-impl<T: PartialOrd> ExactSizeIterator for IntoIter<T> {
+impl<T> ExactSizeIterator for IntoIter<T> {
     fn len(&self) -> usize {
-        self.tree.len()
+        self.remaining
     }
 }
 
-impl<T: PartialOrd> FusedIterator for IntoIter<T> {}
+impl<T> FusedIterator for IntoIter<T> {}
+
+impl<T: Debug> Debug for IntoIter<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let remaining = Iter::new(&self.nodes, self.remaining);
+        f.debug_list().entries(remaining).finish()
+    }
+}
 
-impl<T: PartialOrd> IntoIterator for RBTree<T> {
+impl<T, C> IntoIterator for RBTree<T, C> {
     type Item = T;
     type IntoIter = IntoIter<T>;
 
     fn into_iter(self) -> IntoIter<T> {
-        IntoIter { tree: self }
+        IntoIter::new(self.raw.nodes, self.raw.contained)
     }
 }
 
-impl<T: PartialOrd> FromIterator<T> for RBTree<T> {
+impl<T, C: Comparator<T> + Default> FromIterator<T> for RBTree<T, C> {
     fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
-        let mut tree = RBTree::new();
+        let mut tree = RBTree::default();
         for i in iter {
             tree.insert(i);
         }
@@ -676,7 +1562,59 @@ impl<T: PartialOrd> FromIterator<T> for RBTree<T> {
     }
 }
 
-impl<T: PartialOrd> Extend<T> for RBTree<T> {
+/// Unions the trees, which must all be ordered the same
+/// way. The ordered contents of the trees are merged and
+/// the result built in linear time, rather than inserting
+/// each item; where trees share equal items the one from
+/// the earliest tree is kept.
+/// # Example:
+/// ```
+/// use rb_tree::RBTree;
+///
+/// let shards = vec!(vec!(1, 5, 9), vec!(2, 5), vec!(0, 9, 3));
+/// let t: RBTree<_> = shards
+///     .into_iter()
+///     .map(|s| s.into_iter().collect::<RBTree<_>>())
+///     .collect();
+/// assert_eq!(t.ordered(), vec!(&0, &1, &2, &3, &5, &9));
+/// ```
+impl<T, C: Comparator<T> + Default> FromIterator<RBTree<T, C>> for RBTree<T, C> {
+    fn from_iter<I: IntoIterator<Item = RBTree<T, C>>>(iter: I) -> Self {
+        let mut iter = iter.into_iter();
+        let first = match iter.next() {
+            Some(t) => t,
+            None => return RBTree::default(),
+        };
+        let cmp = first.raw.cmp;
+        let mut values = first.raw.nodes.into_ordered();
+        for t in iter {
+            values.extend(t.raw.nodes.into_ordered());
+        }
+        // the sort is stable and merges the already sorted
+        // runs, so equal items stay in order of their trees
+        values.sort_by(|l, r| cmp.compare(l, r));
+        values.dedup_by(|r, l| cmp.compare(l, r) == Equal);
+        RBTree::from_sorted_vec(values, cmp)
+    }
+}
+
+/// Unions the trees, as with collecting them.
+/// # Example:
+/// ```
+/// use rb_tree::RBTree;
+///
+/// let t: RBTree<_> = vec!((0..3).collect::<RBTree<_>>(), (2..5).collect())
+///     .into_iter()
+///     .sum();
+/// assert_eq!(t.len(), 5);
+/// ```
+impl<T, C: Comparator<T> + Default> Sum for RBTree<T, C> {
+    fn sum<I: Iterator<Item = RBTree<T, C>>>(iter: I) -> Self {
+        iter.collect()
+    }
+}
+
+impl<T, C: Comparator<T>> Extend<T> for RBTree<T, C> {
     fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
         for i in iter {
             self.insert(i);
@@ -684,7 +1622,7 @@ impl<T: PartialOrd> Extend<T> for RBTree<T> {
     }
 }
 
-impl<'a, T: PartialOrd + Copy + 'a> Extend<&'a T> for RBTree<T> {
+impl<'a, T: Copy + 'a, C: Comparator<T>> Extend<&'a T> for RBTree<T, C> {
     fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
         for &i in iter {
             self.insert(i);
@@ -692,216 +1630,106 @@ impl<'a, T: PartialOrd + Copy + 'a> Extend<&'a T> for RBTree<T> {
     }
 }
 
-pub struct Drain<T: PartialOrd> {
-    tree: RBTree<T>,
+#[derive(Clone)]
+pub struct Drain<T> {
+    nodes: Arena<T>,
+    remaining: usize,
 }
 
-impl<T: PartialOrd> Iterator for Drain<T> {
+impl<T> Iterator for Drain<T> {
     type Item = T;
 
     fn next(&mut self) -> Option<T> {
-        self.tree.pop()
+        let next = self.nodes.pop(false)?;
+        self.remaining -= 1;
+        Some(next)
     }
 }
 
-impl<T: PartialOrd> ExactSizeIterator for Drain<T> {
+impl<T> ExactSizeIterator for Drain<T> {
     fn len(&self) -> usize {
-        self.tree.len()
+        self.remaining
     }
 }
 
-impl<T: PartialOrd> FusedIterator for Drain<T> {}
+impl<T> FusedIterator for Drain<T> {}
 
-pub struct Iter<'a, T: PartialOrd> {
-    remaining: usize,
-    ordered: Vec<&'a Node<T>>,
+impl<T: Debug> Debug for Drain<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let remaining = Iter::new(&self.nodes, self.remaining);
+        f.debug_list().entries(remaining).finish()
+    }
 }
 
-impl<'a, T: PartialOrd> Iterator for Iter<'a, T> {
-    type Item = &'a T;
+/// An iterator over the results of `RBTree::get_all`,
+/// in the order the keys were given.
+#[derive(Clone, Debug)]
+pub struct GetAll<'a, T> {
+    found: std::vec::IntoIter<Option<&'a T>>,
+}
 
-    fn next(&mut self) -> Option<&'a T> {
-        let next = match self.ordered.pop() {
-            Some(n) => n,
-            None => return None,
-        };
-        self.remaining -= 1;
-        insert_left_down(next.get_right(), &mut self.ordered);
-        Some(next.value().unwrap())
+impl<'a, T> Iterator for GetAll<'a, T> {
+    type Item = Option<&'a T>;
+
+    fn next(&mut self) -> Option<Option<&'a T>> {
+        self.found.next()
     }
-}
 
-impl<'a, T: PartialOrd> ExactSizeIterator for Iter<'a, T> {
-    fn len(&self) -> usize {
-        self.remaining
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.found.size_hint()
     }
 }
 
-impl<'a, T: PartialOrd> FusedIterator for Iter<'a, T> {}
-
-pub struct Difference<'a, T: PartialOrd> {
-    nextl: Option<&'a T>,
-    nextr: Option<&'a T>,
-    left: Iter<'a, T>,
-    right: Iter<'a, T>,
+impl<'a, T> DoubleEndedIterator for GetAll<'a, T> {
+    fn next_back(&mut self) -> Option<Option<&'a T>> {
+        self.found.next_back()
+    }
 }
 
-impl<'a, T: PartialOrd> Iterator for Difference<'a, T> {
-    type Item = &'a T;
-
-    fn next(&mut self) -> Option<&'a T> {
-        // select and store the next next
-        let mut res = None;
-        'left: while let Some(vl) = self.nextl {
-            self.nextl = self.left.next();
-            while let Some(vr) = self.nextr {
-                if vl < vr {
-                    res = Some(vl);
-                    break 'left;
-                } else if vl == vr {
-                    self.nextr = self.right.next();
-                    continue 'left;
-                } else {
-                    self.nextr = self.right.next();
-                }
-            }
-            res = Some(vl);
-            break; // don't want to skip values
-        }
-
-        // return the current next value
-        res
+impl<'a, T> ExactSizeIterator for GetAll<'a, T> {
+    fn len(&self) -> usize {
+        self.found.len()
     }
 }
 
-impl<'a, T: PartialOrd> FusedIterator for Difference<'a, T> {}
+impl<'a, T> FusedIterator for GetAll<'a, T> {}
 
-pub struct SymmetricDifference<'a, T: PartialOrd> {
-    nextl: Option<&'a T>,
-    nextr: Option<&'a T>,
-    left: Iter<'a, T>,
-    right: Iter<'a, T>,
+#[cfg(feature = "map")]
+pub(crate) struct IterMut<'a, T> {
+    values: crate::raw::ValuesMut<'a, T>,
 }
 
-impl<'a, T: PartialOrd> Iterator for SymmetricDifference<'a, T> {
-    type Item = &'a T;
-
-    fn next(&mut self) -> Option<&'a T> {
-        // select and store the next next
-        let mut res = None;
-        'left: while let Some(vl) = self.nextl {
-            if let Some(vr) = self.nextr {
-                if vl < vr {
-                    self.nextl = self.left.next();
-                    res = Some(vl);
-                    break 'left;
-                } else if vl == vr {
-                    self.nextl = self.left.next();
-                    self.nextr = self.right.next();
-                    continue 'left;
-                } else {
-                    self.nextr = self.right.next();
-                    res = Some(vr);
-                    break 'left;
-                }
-            }
-
-            // don't want to skip values
-            self.nextl = self.left.next();
-            res = Some(vl);
-            break;
-        }
-        if res.is_none() {
-            res = self.nextr;
-            self.nextr = self.right.next();
-        }
-
-        // return the current next value
-        res
+#[cfg(feature = "map")]
+impl<'a, T> IterMut<'a, T> {
+    // passes f each item not yet iterated over, in order
+    pub(crate) fn for_each_remaining<F: FnMut(&T)>(&self, f: F) {
+        self.values.for_each_remaining(f);
     }
 }
 
-impl<'a, T: PartialOrd> FusedIterator for SymmetricDifference<'a, T> {}
-
-pub struct Intersection<'a, T: PartialOrd> {
-    nextl: Option<&'a T>,
-    nextr: Option<&'a T>,
-    left: Iter<'a, T>,
-    right: Iter<'a, T>,
-}
-
-impl<'a, T: PartialOrd> Iterator for Intersection<'a, T> {
-    type Item = &'a T;
+#[cfg(feature = "map")]
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
 
-    fn next(&mut self) -> Option<&'a T> {
-        // select and store the next next
-        let mut res = None;
-        'left: while let Some(vl) = self.nextl {
-            while let Some(vr) = self.nextr {
-                if vl < vr {
-                    self.nextl = self.left.next();
-                    continue 'left;
-                } else if vl == vr {
-                    self.nextr = self.right.next();
-                    self.nextl = self.left.next();
-                    res = Some(vl);
-                    break 'left;
-                } else {
-                    self.nextr = self.right.next();
-                }
-            }
-            break; // don't bother iterating the remaining lefts
-        }
+    fn next(&mut self) -> Option<&'a mut T> {
+        self.values.next()
+    }
 
-        // return the current next value
-        res
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.values.size_hint()
     }
 }
 
-impl<'a, T: PartialOrd> FusedIterator for Intersection<'a, T> {}
-
-pub struct Union<'a, T: PartialOrd> {
-    nextl: Option<&'a T>,
-    nextr: Option<&'a T>,
-    left: Iter<'a, T>,
-    right: Iter<'a, T>,
+#[cfg(feature = "map")]
+impl<'a, T> DoubleEndedIterator for IterMut<'a, T> {
+    fn next_back(&mut self) -> Option<&'a mut T> {
+        self.values.next_back()
+    }
 }
 
-impl<'a, T: PartialOrd> Iterator for Union<'a, T> {
-    type Item = &'a T;
-
-    fn next(&mut self) -> Option<&'a T> {
-        // select and store the next next
-        let mut res = None;
-        let mut need_next = true;
-        if let Some(vl) = self.nextl {
-            if let Some(vr) = self.nextr {
-                if vl < vr {
-                    self.nextl = self.left.next();
-                    res = Some(vl);
-                } else if vl == vr {
-                    self.nextr = self.right.next();
-                    self.nextl = self.left.next();
-                    res = Some(vl);
-                } else {
-                    self.nextr = self.right.next();
-                    res = Some(vr);
-                }
-                need_next = false;
-            }
-            if need_next {
-                self.nextl = self.left.next();
-                res = Some(vl);
-            }
-        }
-        if res.is_none() {
-            res = self.nextr;
-            self.nextr = self.right.next();
-        }
-
-        // return the current next value
-        res
+#[cfg(feature = "map")]
+impl<'a, T> ExactSizeIterator for IterMut<'a, T> {
+    fn len(&self) -> usize {
+        self.values.len()
     }
 }
-
-impl<'a, T: PartialOrd> FusedIterator for Union<'a, T> {}
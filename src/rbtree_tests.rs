@@ -1,7 +1,11 @@
-use crate::node::Colour::*;
-use crate::node::Node;
-use crate::{RBMap, RBTree};
+#[cfg(all(feature = "set", not(feature = "chunked")))]
+use crate::colour::Colour::*;
+#[cfg(feature = "map")]
+use crate::RBMap;
+#[cfg(feature = "set")]
+use crate::RBTree;
 
+#[cfg(all(feature = "set", not(feature = "chunked")))]
 #[test]
 fn test_print() {
     let mut t = RBTree::new();
@@ -24,6 +28,7 @@ fn test_print() {
     }
 }
 
+#[cfg(feature = "set")]
 #[test]
 fn test_add_existing() {
     let mut t = RBTree::new();
@@ -38,6 +43,7 @@ fn test_add_existing() {
     }
 }
 
+#[cfg(feature = "set")]
 #[test]
 fn test_ordered() {
     let mut t = RBTree::new();
@@ -55,6 +61,7 @@ fn test_ordered() {
     }
 }
 
+#[cfg(feature = "set")]
 #[test]
 fn test_contains_and_is_empty() {
     let mut t = RBTree::new();
@@ -85,23 +92,30 @@ fn test_contains_and_is_empty() {
 
 // "cases" refer to this document here:
 // https://www.usna.edu/Users/cs/crabbe/SI321/current/red-black/red-black.html
+#[cfg(all(
+    feature = "set",
+    not(any(feature = "chunked", feature = "debug_invariants"))
+))]
 #[test]
 fn test_insertion_case1_left() {
     let mut t = RBTree::new();
     t.insert(2.0);
     t.insert(3.0);
     t.insert(1.0);
-    t.root.get_right_mut().swap_colour(); // simulate the situation
+    t.raw.nodes.view_mut().get_right_mut().swap_colour(); // simulate the situation
     t.insert(0.0);
     println!("{:?}", t);
-    assert_eq!(*t.root.value().unwrap(), 1.0);
-    assert_eq!(t.root.colour(), Black);
-    assert_eq!(*t.root.get_left().value().unwrap(), 0.0);
-    assert_eq!(t.root.get_left().colour(), Red);
-    assert_eq!(*t.root.get_right().value().unwrap(), 2.0);
-    assert_eq!(t.root.get_right().colour(), Red);
-    assert_eq!(*t.root.get_right().get_right().value().unwrap(), 3.0);
-    assert_eq!(t.root.get_right().get_right().colour(), Black);
+    assert_eq!(*t.raw.nodes.view().value().unwrap(), 1.0);
+    assert_eq!(t.raw.nodes.view().colour(), Black);
+    assert_eq!(*t.raw.nodes.view().get_left().value().unwrap(), 0.0);
+    assert_eq!(t.raw.nodes.view().get_left().colour(), Red);
+    assert_eq!(*t.raw.nodes.view().get_right().value().unwrap(), 2.0);
+    assert_eq!(t.raw.nodes.view().get_right().colour(), Red);
+    assert_eq!(
+        *t.raw.nodes.view().get_right().get_right().value().unwrap(),
+        3.0
+    );
+    assert_eq!(t.raw.nodes.view().get_right().get_right().colour(), Black);
     #[cfg(feature = "serde")]
     {
         let t_serde: RBTree<f64> =
@@ -110,23 +124,30 @@ fn test_insertion_case1_left() {
     }
 }
 
+#[cfg(all(
+    feature = "set",
+    not(any(feature = "chunked", feature = "debug_invariants"))
+))]
 #[test]
 fn test_insertion_case1_right() {
     let mut t = RBTree::new();
     t.insert(2.0);
     t.insert(3.0);
     t.insert(1.0);
-    t.root.get_left_mut().swap_colour(); // simulate the situation
+    t.raw.nodes.view_mut().get_left_mut().swap_colour(); // simulate the situation
     t.insert(4.0);
     println!("{:?}", t);
-    assert_eq!(*t.root.value().unwrap(), 3.0);
-    assert_eq!(t.root.colour(), Black);
-    assert_eq!(*t.root.get_right().value().unwrap(), 4.0);
-    assert_eq!(t.root.get_right().colour(), Red);
-    assert_eq!(*t.root.get_left().value().unwrap(), 2.0);
-    assert_eq!(t.root.get_left().colour(), Red);
-    assert_eq!(*t.root.get_left().get_left().value().unwrap(), 1.0);
-    assert_eq!(t.root.get_left().get_left().colour(), Black);
+    assert_eq!(*t.raw.nodes.view().value().unwrap(), 3.0);
+    assert_eq!(t.raw.nodes.view().colour(), Black);
+    assert_eq!(*t.raw.nodes.view().get_right().value().unwrap(), 4.0);
+    assert_eq!(t.raw.nodes.view().get_right().colour(), Red);
+    assert_eq!(*t.raw.nodes.view().get_left().value().unwrap(), 2.0);
+    assert_eq!(t.raw.nodes.view().get_left().colour(), Red);
+    assert_eq!(
+        *t.raw.nodes.view().get_left().get_left().value().unwrap(),
+        1.0
+    );
+    assert_eq!(t.raw.nodes.view().get_left().get_left().colour(), Black);
     #[cfg(feature = "serde")]
     {
         let t_serde: RBTree<f64> =
@@ -135,23 +156,30 @@ fn test_insertion_case1_right() {
     }
 }
 
+#[cfg(all(
+    feature = "set",
+    not(any(feature = "chunked", feature = "debug_invariants"))
+))]
 #[test]
 fn test_insertion_case2_right() {
     let mut t = RBTree::new();
     t.insert(2.0);
     t.insert(3.0);
     t.insert(1.0);
-    t.root.get_left_mut().swap_colour(); // simulate the situation
+    t.raw.nodes.view_mut().get_left_mut().swap_colour(); // simulate the situation
     t.insert(2.5);
     println!("{:?}", t);
-    assert_eq!(*t.root.value().unwrap(), 2.5);
-    assert_eq!(t.root.colour(), Black);
-    assert_eq!(*t.root.get_left().value().unwrap(), 2.0);
-    assert_eq!(t.root.get_right().colour(), Red);
-    assert_eq!(*t.root.get_right().value().unwrap(), 3.0);
-    assert_eq!(t.root.get_left().colour(), Red);
-    assert_eq!(*t.root.get_left().get_left().value().unwrap(), 1.0);
-    assert_eq!(t.root.get_left().get_left().colour(), Black);
+    assert_eq!(*t.raw.nodes.view().value().unwrap(), 2.5);
+    assert_eq!(t.raw.nodes.view().colour(), Black);
+    assert_eq!(*t.raw.nodes.view().get_left().value().unwrap(), 2.0);
+    assert_eq!(t.raw.nodes.view().get_right().colour(), Red);
+    assert_eq!(*t.raw.nodes.view().get_right().value().unwrap(), 3.0);
+    assert_eq!(t.raw.nodes.view().get_left().colour(), Red);
+    assert_eq!(
+        *t.raw.nodes.view().get_left().get_left().value().unwrap(),
+        1.0
+    );
+    assert_eq!(t.raw.nodes.view().get_left().get_left().colour(), Black);
     #[cfg(feature = "serde")]
     {
         let t_serde: RBTree<f64> =
@@ -160,23 +188,30 @@ fn test_insertion_case2_right() {
     }
 }
 
+#[cfg(all(
+    feature = "set",
+    not(any(feature = "chunked", feature = "debug_invariants"))
+))]
 #[test]
 fn test_insertion_case2_left() {
     let mut t = RBTree::new();
     t.insert(2.0);
     t.insert(3.0);
     t.insert(1.0);
-    t.root.get_right_mut().swap_colour(); // simulate the situation
+    t.raw.nodes.view_mut().get_right_mut().swap_colour(); // simulate the situation
     t.insert(1.5);
     println!("{:?}", t);
-    assert_eq!(*t.root.value().unwrap(), 1.5);
-    assert_eq!(t.root.colour(), Black);
-    assert_eq!(*t.root.get_left().value().unwrap(), 1.0);
-    assert_eq!(t.root.get_right().colour(), Red);
-    assert_eq!(*t.root.get_right().value().unwrap(), 2.0);
-    assert_eq!(t.root.get_left().colour(), Red);
-    assert_eq!(*t.root.get_right().get_right().value().unwrap(), 3.0);
-    assert_eq!(t.root.get_right().get_right().colour(), Black);
+    assert_eq!(*t.raw.nodes.view().value().unwrap(), 1.5);
+    assert_eq!(t.raw.nodes.view().colour(), Black);
+    assert_eq!(*t.raw.nodes.view().get_left().value().unwrap(), 1.0);
+    assert_eq!(t.raw.nodes.view().get_right().colour(), Red);
+    assert_eq!(*t.raw.nodes.view().get_right().value().unwrap(), 2.0);
+    assert_eq!(t.raw.nodes.view().get_left().colour(), Red);
+    assert_eq!(
+        *t.raw.nodes.view().get_right().get_right().value().unwrap(),
+        3.0
+    );
+    assert_eq!(t.raw.nodes.view().get_right().get_right().colour(), Black);
     #[cfg(feature = "serde")]
     {
         let t_serde: RBTree<f64> =
@@ -185,6 +220,7 @@ fn test_insertion_case2_left() {
     }
 }
 
+#[cfg(all(feature = "set", not(feature = "chunked")))]
 #[test]
 fn test_insertion_case3_at_root() {
     let mut t = RBTree::new();
@@ -193,14 +229,17 @@ fn test_insertion_case3_at_root() {
     t.insert(1.0);
     t.insert(0.0);
     println!("{:?}", t);
-    assert_eq!(*t.root.value().unwrap(), 2.0);
-    assert_eq!(t.root.colour(), Black);
-    assert_eq!(*t.root.get_left().value().unwrap(), 1.0);
-    assert_eq!(t.root.get_right().colour(), Black);
-    assert_eq!(*t.root.get_right().value().unwrap(), 3.0);
-    assert_eq!(t.root.get_left().colour(), Black);
-    assert_eq!(*t.root.get_left().get_left().value().unwrap(), 0.0);
-    assert_eq!(t.root.get_left().get_left().colour(), Red);
+    assert_eq!(*t.raw.nodes.view().value().unwrap(), 2.0);
+    assert_eq!(t.raw.nodes.view().colour(), Black);
+    assert_eq!(*t.raw.nodes.view().get_left().value().unwrap(), 1.0);
+    assert_eq!(t.raw.nodes.view().get_right().colour(), Black);
+    assert_eq!(*t.raw.nodes.view().get_right().value().unwrap(), 3.0);
+    assert_eq!(t.raw.nodes.view().get_left().colour(), Black);
+    assert_eq!(
+        *t.raw.nodes.view().get_left().get_left().value().unwrap(),
+        0.0
+    );
+    assert_eq!(t.raw.nodes.view().get_left().get_left().colour(), Red);
     #[cfg(feature = "serde")]
     {
         let t_serde: RBTree<f64> =
@@ -209,33 +248,58 @@ fn test_insertion_case3_at_root() {
     }
 }
 
+#[cfg(all(
+    feature = "set",
+    not(any(feature = "chunked", feature = "debug_invariants"))
+))]
 #[test]
 fn test_insertion_case3_not_root() {
     let mut t = RBTree::new();
     t.insert(2.0);
     t.insert(3.0);
     t.insert(1.0);
-    t.root.get_right_mut().swap_colour(); // simulate the situation
+    t.raw.nodes.view_mut().get_right_mut().swap_colour(); // simulate the situation
     t.insert(1.5);
     t.insert(2.5);
     t.insert(4.0);
     t.insert(5.0);
     println!("{:?}", t);
-    assert_eq!(*t.root.value().unwrap(), 1.5);
-    assert_eq!(t.root.colour(), Black);
-    assert_eq!(*t.root.get_left().value().unwrap(), 1.0);
-    assert_eq!(t.root.get_right().colour(), Black);
-    assert_eq!(*t.root.get_right().value().unwrap(), 2.0);
-    assert_eq!(t.root.get_left().colour(), Black);
-    assert_eq!(*t.root.get_right().get_right().value().unwrap(), 3.0);
-    assert_eq!(t.root.get_right().get_right().colour(), Red);
-    assert_eq!(
-        *t.root.get_right().get_right().get_right().value().unwrap(),
+    assert_eq!(*t.raw.nodes.view().value().unwrap(), 1.5);
+    assert_eq!(t.raw.nodes.view().colour(), Black);
+    assert_eq!(*t.raw.nodes.view().get_left().value().unwrap(), 1.0);
+    assert_eq!(t.raw.nodes.view().get_right().colour(), Black);
+    assert_eq!(*t.raw.nodes.view().get_right().value().unwrap(), 2.0);
+    assert_eq!(t.raw.nodes.view().get_left().colour(), Black);
+    assert_eq!(
+        *t.raw.nodes.view().get_right().get_right().value().unwrap(),
+        3.0
+    );
+    assert_eq!(t.raw.nodes.view().get_right().get_right().colour(), Red);
+    assert_eq!(
+        *t.raw
+            .nodes
+            .view()
+            .get_right()
+            .get_right()
+            .get_right()
+            .value()
+            .unwrap(),
         4.0
     );
-    assert_eq!(t.root.get_right().get_right().get_right().colour(), Black);
     assert_eq!(
-        *t.root
+        t.raw
+            .nodes
+            .view()
+            .get_right()
+            .get_right()
+            .get_right()
+            .colour(),
+        Black
+    );
+    assert_eq!(
+        *t.raw
+            .nodes
+            .view()
             .get_right()
             .get_right()
             .get_right()
@@ -245,7 +309,9 @@ fn test_insertion_case3_not_root() {
         5.0
     );
     assert_eq!(
-        t.root
+        t.raw
+            .nodes
+            .view()
             .get_right()
             .get_right()
             .get_right()
@@ -254,10 +320,26 @@ fn test_insertion_case3_not_root() {
         Red
     );
     assert_eq!(
-        *t.root.get_right().get_right().get_left().value().unwrap(),
+        *t.raw
+            .nodes
+            .view()
+            .get_right()
+            .get_right()
+            .get_left()
+            .value()
+            .unwrap(),
         2.5
     );
-    assert_eq!(t.root.get_right().get_right().get_left().colour(), Black);
+    assert_eq!(
+        t.raw
+            .nodes
+            .view()
+            .get_right()
+            .get_right()
+            .get_left()
+            .colour(),
+        Black
+    );
     #[cfg(feature = "serde")]
     {
         let t_serde: RBTree<f64> =
@@ -266,27 +348,44 @@ fn test_insertion_case3_not_root() {
     }
 }
 
+#[cfg(all(
+    feature = "set",
+    not(any(feature = "chunked", feature = "debug_invariants"))
+))]
 #[test]
 fn test_insertion_transfer_children() {
     let mut t = RBTree::new();
     t.insert(2.0);
     t.insert(3.0);
     t.insert(1.0);
-    t.root.get_right_mut().swap_colour(); // simulate the situation
-    *t.root.get_left_mut().get_right_mut() = Node::new_black(1.5);
+    t.raw.nodes.view_mut().get_right_mut().swap_colour(); // simulate the situation
+    t.raw
+        .nodes
+        .view_mut()
+        .get_left_mut()
+        .get_right_mut()
+        .set_black(1.5);
     t.insert(0.0);
-    assert_eq!(*t.root.get_right().get_left().value().unwrap(), 1.5);
+    assert_eq!(
+        *t.raw.nodes.view().get_right().get_left().value().unwrap(),
+        1.5
+    );
 
     // creates a valid rbtree to test the scenario
     let mut t = RBTree::new();
     t.insert(2.0);
     t.insert(3.0);
     t.insert(1.0);
-    t.root.get_right_mut().swap_colour();
-    t.root.get_left_mut().swap_colour();
+    t.raw.nodes.view_mut().get_right_mut().swap_colour();
+    t.raw.nodes.view_mut().get_left_mut().swap_colour();
     t.insert(1.5);
-    t.root.get_left_mut().get_right_mut().swap_colour();
-    t.root.get_left_mut().swap_colour();
+    t.raw
+        .nodes
+        .view_mut()
+        .get_left_mut()
+        .get_right_mut()
+        .swap_colour();
+    t.raw.nodes.view_mut().get_left_mut().swap_colour();
     t.insert(1.25);
     t.insert(1.75);
     println!("{:?}", t);
@@ -308,6 +407,7 @@ fn test_insertion_transfer_children() {
     }
 }
 
+#[cfg(all(feature = "set", not(feature = "chunked")))]
 #[test]
 fn test_complex_insertion() {
     let mut t = RBTree::new();
@@ -376,15 +476,39 @@ fn test_complex_insertion() {
     }
 }
 
+#[cfg(all(feature = "set", not(feature = "chunked")))]
+#[test]
+fn test_leaves_not_allocated() {
+    let mut t = RBTree::new();
+    (0..100).for_each(|v| {
+        t.insert(v);
+    });
+    assert_eq!(t.raw.nodes.allocated(), 100);
+
+    // slots freed by removals are reused by later insertions
+    (0..50).for_each(|v| {
+        t.remove(&v);
+    });
+    (100..150).for_each(|v| {
+        t.insert(v);
+    });
+    assert_eq!(t.raw.nodes.allocated(), 100);
+    assert_eq!(t.len(), 100);
+    t.clear();
+    assert_eq!(t.raw.nodes.allocated(), 0);
+}
+
+#[cfg(feature = "set")]
 #[test]
 fn test_removal_empty() {
     let mut t = RBTree::new();
     assert!(t.take(&3.0).is_none());
-    assert_eq!(t.contained, 0);
+    assert_eq!(t.raw.contained, 0);
     #[cfg(feature = "serde")]
     assert!(serde_json::to_string(&t).is_ok());
 }
 
+#[cfg(feature = "set")]
 #[test]
 fn test_removal_notfound() {
     let mut t = RBTree::new();
@@ -397,7 +521,7 @@ fn test_removal_notfound() {
     assert!(t.take(&1.8).is_none());
     assert!(t.take(&2.1).is_none());
     assert!(t.take(&3.9).is_none());
-    assert_eq!(t.contained, 4);
+    assert_eq!(t.raw.contained, 4);
     assert_eq!(format!("{}", t), "[1.0, 1.5, 2.0, 3.0]");
     #[cfg(feature = "serde")]
     {
@@ -407,6 +531,7 @@ fn test_removal_notfound() {
     }
 }
 
+#[cfg(feature = "set")]
 #[test]
 fn test_remove_only_value() {
     let mut t = RBTree::new();
@@ -422,6 +547,7 @@ fn test_remove_only_value() {
     }
 }
 
+#[cfg(all(feature = "set", not(feature = "chunked")))]
 #[test]
 fn test_remove_root() {
     let mut t = RBTree::new();
@@ -467,14 +593,18 @@ fn test_remove_root() {
     }
 }
 
+#[cfg(all(
+    feature = "set",
+    not(any(feature = "chunked", feature = "debug_invariants"))
+))]
 #[test]
 fn test_removal_no_double_black() {
     let mut t = RBTree::new();
     t.insert(2.0);
     t.insert(1.0);
-    t.root.get_left_mut().swap_colour(); // simulating again...
+    t.raw.nodes.view_mut().get_left_mut().swap_colour(); // simulating again...
     t.insert(3.0);
-    t.root.get_right_mut().swap_colour();
+    t.raw.nodes.view_mut().get_right_mut().swap_colour();
     t.insert(1.5);
     t.insert(2.5);
     println!("{:?}", t);
@@ -496,6 +626,7 @@ fn test_removal_no_double_black() {
     }
 }
 
+#[cfg(all(feature = "set", not(feature = "chunked")))]
 #[test]
 fn test_removal_simple_case() {
     let mut t = RBTree::new();
@@ -518,6 +649,7 @@ fn test_removal_simple_case() {
     }
 }
 
+#[cfg(all(feature = "set", not(feature = "chunked")))]
 #[test]
 fn test_black_leaf_removal() {
     let mut t = RBTree::new();
@@ -550,6 +682,7 @@ fn test_black_leaf_removal() {
 
 // test case is deletion example 3 from this doc
 // https://www.csee.umbc.edu/courses/undergraduate/341/spring04/Lectures/RedBlack/redblack.pdf
+#[cfg(all(feature = "set", not(feature = "chunked")))]
 #[test]
 fn test_remove_accumulative_changes() {
     let mut t = RBTree::new();
@@ -581,6 +714,7 @@ fn test_remove_accumulative_changes() {
     }
 }
 
+#[cfg(all(feature = "set", not(feature = "chunked")))]
 #[test]
 fn test_removal_case2_inner() {
     let mut t = RBTree::new();
@@ -623,6 +757,7 @@ fn test_removal_case2_inner() {
     }
 }
 
+#[cfg(all(feature = "set", not(feature = "chunked")))]
 #[test]
 fn test_removal_case2_outer() {
     let mut t = RBTree::new();
@@ -692,6 +827,7 @@ fn test_removal_case2_outer() {
     }
 }
 
+#[cfg(all(feature = "set", not(feature = "chunked")))]
 #[test]
 fn test_removal_case3_red_parent() {
     let mut t = RBTree::new();
@@ -720,6 +856,7 @@ fn test_removal_case3_red_parent() {
     }
 }
 
+#[cfg(all(feature = "set", not(feature = "chunked")))]
 #[test]
 fn test_removeal_case4() {
     let mut t = RBTree::new();
@@ -745,6 +882,7 @@ fn test_removeal_case4() {
     }
 }
 
+#[cfg(feature = "map")]
 #[test]
 fn test_remove_immediate_right_double_black() {
     let mut rbmap = RBMap::<usize, usize>::new();
@@ -770,6 +908,7 @@ fn test_remove_immediate_right_double_black() {
     }
 }
 
+#[cfg(all(feature = "set", not(feature = "chunked")))]
 #[test]
 fn test_gets_correct_descendant() {
     let mut t = RBTree::new();
@@ -809,6 +948,7 @@ fn test_gets_correct_descendant() {
     }
 }
 
+#[cfg(feature = "set")]
 #[test]
 fn test_complicated_example() {
     let mut t = RBTree::new();
@@ -838,6 +978,7 @@ fn test_complicated_example() {
     assert!(serde_json::to_string(&t).is_ok());
 }
 
+#[cfg(feature = "set")]
 #[test]
 fn test_get() {
     let mut t = RBTree::new();
@@ -858,6 +999,7 @@ fn test_get() {
     }
 }
 
+#[cfg(feature = "set")]
 #[test]
 fn test_pop() {
     let mut t = RBTree::new();
@@ -888,6 +1030,7 @@ fn test_pop() {
     }
 }
 
+#[cfg(feature = "set")]
 #[test]
 fn test_pop_back() {
     let mut t = RBTree::new();
@@ -914,6 +1057,7 @@ fn test_pop_back() {
     assert!(serde_json::to_string(&t).is_ok());
 }
 
+#[cfg(feature = "set")]
 #[test]
 fn test_peek() {
     let mut t = RBTree::new();
@@ -938,6 +1082,7 @@ fn test_peek() {
     }
 }
 
+#[cfg(feature = "set")]
 #[test]
 fn test_peek_back() {
     let mut t = RBTree::new();
@@ -962,6 +1107,7 @@ fn test_peek_back() {
     }
 }
 
+#[cfg(feature = "set")]
 #[test]
 fn test_difference() {
     let mut t1 = RBTree::new();
@@ -1040,6 +1186,7 @@ fn test_difference() {
     }
 }
 
+#[cfg(feature = "set")]
 #[test]
 fn test_symmetric_difference() {
     let mut t1 = RBTree::new();
@@ -1090,6 +1237,7 @@ fn test_symmetric_difference() {
     }
 }
 
+#[cfg(feature = "set")]
 #[test]
 fn test_intersection() {
     let mut t1 = RBTree::new();
@@ -1178,6 +1326,7 @@ fn test_intersection() {
     }
 }
 
+#[cfg(feature = "set")]
 #[test]
 fn test_union() {
     let mut t1 = RBTree::new();
@@ -1228,6 +1377,7 @@ fn test_union() {
     }
 }
 
+#[cfg(feature = "set")]
 #[test]
 fn test_iterator() {
     let mut t = RBTree::new();
@@ -1264,6 +1414,37 @@ fn test_iterator() {
     }
 }
 
+#[cfg(all(feature = "set", not(feature = "chunked")))]
+#[test]
+fn test_parent_links() {
+    let mut t: RBTree<i32> = (0..64).collect();
+    (0..64).step_by(3).for_each(|v| {
+        t.remove(&v);
+    });
+
+    // every node's children point back at it
+    let mut stack = vec![t.raw.nodes.root()];
+    while let Some(at) = stack.pop() {
+        for child in [t.raw.nodes.get_left(at), t.raw.nodes.get_right(at)] {
+            if t.raw.nodes.value(child).is_some() {
+                assert_eq!(t.raw.nodes.get_parent(child), at);
+                stack.push(child);
+            }
+        }
+    }
+
+    // stepping backwards from the end visits everything in reverse
+    let mut cur = t.raw.nodes.outermost(t.raw.nodes.root(), true);
+    let mut reversed = Vec::new();
+    while let Some(v) = t.raw.nodes.value(cur) {
+        reversed.push(*v);
+        cur = t.raw.nodes.step(cur, false);
+    }
+    reversed.reverse();
+    assert_eq!(reversed, t.iter().copied().collect::<Vec<i32>>());
+}
+
+#[cfg(feature = "set")]
 #[test]
 fn test_extend() {
     let mut t = RBTree::new();
@@ -1287,3 +1468,3713 @@ fn test_extend() {
         assert!(t.iter().zip(t_serde.iter()).all(|(lhs, rhs)| lhs == rhs))
     }
 }
+
+#[cfg(feature = "persistent")]
+#[test]
+fn test_persistent_versions() {
+    use crate::colour::Colour::{Black, Red};
+    use crate::pnode::Link;
+    use crate::PersistentRBTree;
+
+    // returns the black height, checking colours and order on the way
+    fn check(link: &Link<i32>, lo: i32, hi: i32) -> usize {
+        match link {
+            None => 1,
+            Some(n) => {
+                assert!(lo < *n.value() && *n.value() < hi);
+                if n.colour() == Red {
+                    for child in [n.left(), n.right()].iter() {
+                        assert!(child.iter().all(|c| c.colour() == Black));
+                    }
+                }
+                let bh = check(n.left(), lo, *n.value());
+                assert_eq!(bh, check(n.right(), *n.value(), hi));
+                bh + if n.colour() == Black { 1 } else { 0 }
+            }
+        }
+    }
+
+    let mut versions = vec![PersistentRBTree::new()];
+    for i in 0..64 {
+        let next = versions.last().unwrap().insert((i * 37) % 64);
+        versions.push(next);
+    }
+    for i in (0..64).step_by(3) {
+        let next = versions.last().unwrap().remove(&i);
+        versions.push(next);
+    }
+
+    // every version still holds exactly what it did when made
+    for (i, t) in versions.iter().enumerate().take(65) {
+        let mut expected = (0..i as i32).map(|v| (v * 37) % 64).collect::<Vec<i32>>();
+        expected.sort_unstable();
+        assert_eq!(t.iter().copied().collect::<Vec<i32>>(), expected);
+        assert_eq!(t.len(), i);
+    }
+    let last = versions.last().unwrap();
+    assert!(last.iter().copied().eq((0..64).filter(|v| v % 3 != 0)));
+    for t in versions.iter() {
+        check(&t.root, -1, 64);
+    }
+
+    // changing one side of the tree leaves the other side shared
+    let root = versions[64].root.as_ref().unwrap();
+    let changed = versions[64].remove(&63);
+    assert!(std::sync::Arc::ptr_eq(
+        root.left().as_ref().unwrap(),
+        changed.root.as_ref().unwrap().left().as_ref().unwrap()
+    ));
+}
+
+#[cfg(feature = "persistent")]
+#[test]
+fn test_persistent_copy_on_write() {
+    use crate::PersistentRBMap;
+    use std::sync::Arc;
+
+    let mut m: PersistentRBMap<i32, i32> = (0..64).map(|v| (v, v)).collect();
+    let root = Arc::as_ptr(m.map.root.as_ref().unwrap());
+
+    // nothing is shared, so nothing is copied
+    *m.get_mut(&0).unwrap() = 100;
+    assert_eq!(Arc::as_ptr(m.map.root.as_ref().unwrap()), root);
+
+    // only the path to the change is copied after a clone
+    let snapshot = m.clone();
+    *m.get_mut(&0).unwrap() = 200;
+    let (old, new) = (
+        snapshot.map.root.as_ref().unwrap(),
+        m.map.root.as_ref().unwrap(),
+    );
+    assert!(!Arc::ptr_eq(old, new));
+    assert!(Arc::ptr_eq(
+        old.right().as_ref().unwrap(),
+        new.right().as_ref().unwrap()
+    ));
+    assert_eq!(snapshot.get(&0), Some(&100));
+    assert_eq!(m.get(&0), Some(&200));
+
+    // and the copy is now unshared again
+    let root = Arc::as_ptr(m.map.root.as_ref().unwrap());
+    *m.get_mut(&0).unwrap() = 300;
+    assert_eq!(Arc::as_ptr(m.map.root.as_ref().unwrap()), root);
+
+    assert_eq!(m.remove_in_place(&0), Some(300));
+    assert_eq!(m.insert_in_place(64, 64), None);
+    assert_eq!(m.len(), 64);
+    assert_eq!(snapshot.len(), 64);
+    assert!(snapshot.keys().copied().eq(0..64));
+    assert!(m.keys().copied().eq(1..65));
+}
+
+#[cfg(feature = "persistent")]
+#[test]
+fn test_persistent_in_place() {
+    use crate::PersistentRBTree;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    // counts its clones, and panics once it has been
+    // compared as often as allowed
+    static BUDGET: AtomicUsize = AtomicUsize::new(usize::MAX);
+    static CLONES: AtomicUsize = AtomicUsize::new(0);
+    #[derive(PartialEq)]
+    struct Touchy(u32);
+    impl Clone for Touchy {
+        fn clone(&self) -> Touchy {
+            CLONES.fetch_add(1, Relaxed);
+            Touchy(self.0)
+        }
+    }
+    impl PartialOrd for Touchy {
+        fn partial_cmp(&self, other: &Touchy) -> Option<std::cmp::Ordering> {
+            assert!(BUDGET.fetch_sub(1, Relaxed) > 0, "compared too often");
+            self.0.partial_cmp(&other.0)
+        }
+    }
+
+    // nothing is copied from a tree no other version shares
+    let mut t: PersistentRBTree<Touchy> = (0..64).map(|v| Touchy(v * 2)).collect();
+    assert!(t.insert_in_place(Touchy(201)));
+    assert!(t.take_in_place(&Touchy(201)).is_some());
+    assert!(t.take_in_place(&Touchy(201)).is_none());
+    assert_eq!(CLONES.load(Relaxed), 0);
+    let snapshot = t.clone();
+    assert!(t.insert_in_place(Touchy(201)));
+    assert!(CLONES.load(Relaxed) > 0);
+    assert!(t.take_in_place(&Touchy(201)).is_some());
+    drop(snapshot);
+
+    // a comparison that panics leaves the tree as it was
+    let unchanged = |t: &PersistentRBTree<Touchy>| {
+        assert_eq!(t.len(), 64);
+        assert!(t.iter().map(|v| v.0).eq((0..64).map(|v| v * 2)));
+    };
+    BUDGET.store(2, Relaxed);
+    let res = catch_unwind(AssertUnwindSafe(|| t.insert_in_place(Touchy(13))));
+    assert!(res.is_err());
+    BUDGET.store(2, Relaxed);
+    let res = catch_unwind(AssertUnwindSafe(|| t.take_in_place(&Touchy(20))));
+    assert!(res.is_err());
+    BUDGET.store(usize::MAX, Relaxed);
+    unchanged(&t);
+    assert!(t.insert_in_place(Touchy(13)));
+    assert!(t.take_in_place(&Touchy(20)).is_some());
+}
+
+#[cfg(feature = "set")]
+#[test]
+fn test_freeze() {
+    fn is_send_sync<S: Send + Sync>(_: &S) {}
+
+    let mut t: RBTree<i32> = (0..64).rev().collect();
+    (0..64).step_by(3).for_each(|v| {
+        t.remove(&v);
+    });
+    let frozen = t.clone().freeze();
+    is_send_sync(&frozen);
+
+    // the freed slots left behind don't show up
+    assert_eq!(frozen.len(), t.len());
+    assert!(frozen.iter().eq(t.iter()));
+    for v in -1..65 {
+        assert_eq!(frozen.get(&v), t.get(&v));
+    }
+    let thawed = frozen.thaw();
+    check_tree(&thawed.raw.nodes);
+    assert!(thawed.iter().eq(t.iter()));
+}
+
+#[cfg(all(feature = "set", not(feature = "chunked")))]
+#[test]
+fn test_try_insert_alloc() {
+    let mut t = RBTree::new();
+    assert!(t.try_reserve(usize::MAX).is_err());
+    assert_eq!(t.try_reserve(4), Ok(()));
+    (0..4).for_each(|v| assert_eq!(t.try_insert_alloc(v), Ok(true)));
+    assert_eq!(t.try_insert_alloc(2), Ok(false));
+
+    // vacant slots are reused without allocating
+    t.remove(&1);
+    assert_eq!(t.try_insert_alloc(5), Ok(true));
+    assert_eq!(t.raw.nodes.allocated(), 4);
+    assert!(t.iter().copied().eq(vec![0, 2, 3, 5]));
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_incomparable_elements() {
+    use crate::Error;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let mut t: RBTree<f64> = (0..16).map(|v| v as f64).collect();
+    assert_eq!(t.try_insert(f64::NAN), Err(Error::IncomparableElement));
+    assert_eq!(t.try_get(&f64::NAN), Err(Error::IncomparableElement));
+    assert_eq!(t.try_insert(16.0), Ok(true));
+    assert_eq!(t.try_get(&16.0), Ok(Some(&16.0)));
+
+    // the infallible methods panic without changing the tree
+    assert!(catch_unwind(AssertUnwindSafe(|| t.insert(f64::NAN))).is_err());
+    assert_eq!(t.len(), 17);
+    assert!(t.iter().copied().eq((0..17).map(|v| v as f64)));
+
+    let mut m: RBMap<f64, usize> = RBMap::new();
+    assert_eq!(m.try_insert(1.0, 1), Ok(None));
+    assert_eq!(m.try_insert(1.0, 2), Ok(Some((1.0, 1))));
+    assert_eq!(m.try_insert(f64::NAN, 3), Err(Error::IncomparableElement));
+    assert_eq!(m.try_get(&1.0), Ok(Some(&2)));
+    assert_eq!(m.len(), 1);
+}
+
+#[cfg(feature = "set")]
+#[test]
+fn test_comparators() {
+    use crate::{NaturalOrder, Reversed, TotalOrder};
+
+    // ordered by the second field alone
+    let by_second = |l: &(i32, i32), r: &(i32, i32)| l.1.cmp(&r.1);
+    let mut t = RBTree::with_comparator(by_second);
+    assert!(t.insert((0, 2)));
+    assert!(t.insert((1, 1)));
+    assert!(!t.insert((2, 2)));
+    assert_eq!(t.replace((3, 1)), Some((1, 1)));
+    assert_eq!(t.get(&(9, 2)), Some(&(2, 2)));
+    assert!(t.iter().copied().eq(vec![(3, 1), (2, 2)]));
+    assert_eq!(t.take(&(9, 1)), Some((3, 1)));
+
+    let mut l = RBTree::with_comparator(Reversed(TotalOrder));
+    let mut r = RBTree::with_comparator(Reversed(TotalOrder));
+    l.extend(0..8);
+    r.extend(4..12);
+    assert!(l.iter().copied().eq((0..8).rev()));
+    assert!(l.union(&r).copied().eq((0..12).rev()));
+    assert!(l.intersection(&r).copied().eq((4..8).rev()));
+    assert!(l.difference(&r).copied().eq((0..4).rev()));
+    assert!(l
+        .symmetric_difference(&r)
+        .copied()
+        .eq((8..12).rev().chain((0..4).rev())));
+    l.retain(|v| v % 2 == 0);
+    assert!(l.iter().copied().eq(vec![6, 4, 2, 0]));
+
+    // the comparator survives freezing and thawing
+    let frozen = l.freeze();
+    assert_eq!(frozen.get(&4), Some(&4));
+    assert_eq!(frozen.get(&5), None);
+    let mut thawed = frozen.thaw();
+    thawed.insert(8);
+    assert_eq!(thawed.peek(), Some(&8));
+
+    let d: RBTree<f64, Reversed<NaturalOrder>> = vec![1.0, 3.0, 2.0].into_iter().collect();
+    assert!(d.into_iter().eq(vec![3.0, 2.0, 1.0]));
+}
+
+#[cfg(feature = "set")]
+#[test]
+fn test_search_by() {
+    let t: RBTree<_> = (0..100).map(|v| v * 2).collect();
+    for target in -1..201 {
+        let expected = t.iter().find(|&&v| v >= target);
+        assert_eq!(t.partition_point(|&v| v < target), expected);
+        let found = t.search_by(|v| v.cmp(&target));
+        assert_eq!(found, t.get(&target));
+    }
+    assert_eq!(RBTree::<i32>::new().partition_point(|_| true), None);
+}
+
+#[cfg(all(feature = "set", not(feature = "chunked")))]
+#[test]
+fn test_encoding() {
+    use crate::node::{Arena, NIL};
+    use crate::Encode;
+    use std::io::ErrorKind;
+
+    // every size gives a valid tree when read back
+    for n in 0..70u32 {
+        let t: RBTree<u32> = (0..n).collect();
+        let mut buf = Vec::new();
+        t.write_to(&mut buf).unwrap();
+        let read: RBTree<u32> = RBTree::read_from(&buf[..]).unwrap();
+        assert_eq!(read.len(), t.len());
+        assert!(read.iter().eq(t.iter()));
+
+        fn black_height(nodes: &Arena<u32>, at: crate::node::Index) -> usize {
+            if at == NIL {
+                return 1;
+            }
+            if nodes.is_red(at) {
+                assert!(nodes.is_black(nodes.get_left(at)));
+                assert!(nodes.is_black(nodes.get_right(at)));
+            }
+            let l = black_height(nodes, nodes.get_left(at));
+            assert_eq!(l, black_height(nodes, nodes.get_right(at)));
+            l + nodes.is_black(at) as usize
+        }
+        assert!(read.raw.nodes.is_black(read.raw.nodes.root()));
+        black_height(&read.raw.nodes, read.raw.nodes.root());
+
+        let mut read = read;
+        read.insert(n);
+        read.remove(&0);
+        assert!(read.iter().copied().eq(1..=n));
+    }
+
+    let t: RBTree<(String, Option<char>)> =
+        vec![("b".to_string(), None), ("a".to_string(), Some('x'))]
+            .into_iter()
+            .collect();
+    let mut buf = Vec::new();
+    t.write_to(&mut buf).unwrap();
+    let read: RBTree<(String, Option<char>)> = RBTree::read_from(&buf[..]).unwrap();
+    assert!(read.iter().eq(t.iter()));
+
+    // out of order and truncated input is rejected
+    let mut buf = Vec::new();
+    2usize.encode(&mut buf).unwrap();
+    buf.extend_from_slice(&[2, 1]);
+    let err = RBTree::<u8>::read_from(&buf[..]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    let err = RBTree::<u8>::read_from(&buf[..9]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+}
+
+#[cfg(feature = "set")]
+#[test]
+fn test_subset_superset() {
+    let empty: RBTree<i32> = RBTree::new();
+    let evens: RBTree<_> = (0..20).step_by(2).collect();
+    let all: RBTree<_> = (0..20).collect();
+    let beyond: RBTree<_> = (10..30).collect();
+    assert!(empty.is_subset(&evens));
+    assert!(evens.is_subset(&all));
+    assert!(!all.is_subset(&evens));
+    assert!(!evens.is_subset(&beyond));
+    assert!(all.is_superset(&evens));
+    assert!(all.is_superset(&all));
+    assert!(!beyond.is_superset(&all));
+
+    // a missing value past the end of other
+    let last: RBTree<_> = vec![0, 40].into_iter().collect();
+    assert!(!last.is_subset(&all));
+}
+
+#[cfg(feature = "set")]
+#[test]
+fn test_duplicate_policy() {
+    // equal by the first field, but not identical
+    let by_first = |l: &(i32, &str), r: &(i32, &str)| l.0.cmp(&r.0);
+    let mut t = RBTree::with_comparator(by_first);
+    assert!(t.insert((1, "a")));
+    assert!(!t.insert((1, "b")));
+    assert_eq!(t.get(&(1, "")), Some(&(1, "b")));
+
+    assert_eq!(t.insert_keep_existing((1, "c")), Some((1, "c")));
+    assert_eq!(t.insert_keep_existing((2, "d")), None);
+    assert_eq!(t.insert_replace((2, "e")), Some((2, "d")));
+    assert_eq!(t.len(), 2);
+    assert!(t.iter().copied().eq(vec![(1, "b"), (2, "e")]));
+}
+
+#[cfg(feature = "set")]
+#[test]
+fn test_get_mut_guard() {
+    let by_first = |l: &(i32, i32), r: &(i32, i32)| l.0.cmp(&r.0);
+    let mut t = RBTree::with_comparator(by_first);
+    (0..10).for_each(|v| {
+        t.insert((v * 2, 0));
+    });
+    assert!(t.get_mut_guard(&(1, 0)).is_none());
+
+    // stays in place, including at the ends
+    for v in [0, 8, 18] {
+        let mut g = t.get_mut_guard(&(v, 0)).unwrap();
+        g.1 = 1;
+        g.0 += 1;
+    }
+    assert_eq!(t.len(), 10);
+    assert_eq!(t.get(&(9, 0)), Some(&(9, 1)));
+
+    // moves past its neighbours
+    t.get_mut_guard(&(1, 0)).unwrap().0 = 15;
+    t.get_mut_guard(&(19, 0)).unwrap().0 = -1;
+    assert!(t
+        .iter()
+        .map(|v| v.0)
+        .eq(vec![-1, 2, 4, 6, 9, 10, 12, 14, 15, 16]));
+
+    // replaces an equal item it's moved onto
+    t.get_mut_guard(&(-1, 0)).unwrap().0 = 4;
+    assert_eq!(t.len(), 9);
+    assert_eq!(t.get(&(4, 0)), Some(&(4, 1)));
+    assert!(t
+        .iter()
+        .map(|v| v.0)
+        .eq(vec![2, 4, 6, 9, 10, 12, 14, 15, 16]));
+}
+
+#[cfg(feature = "set")]
+#[test]
+fn test_batch_contains_remove() {
+    let mut t: RBTree<i32> = (0..50).map(|v| v * 3).collect();
+    assert!(t.contains_all(Vec::<i32>::new()));
+    assert!(t.contains_all(vec![147, 0, 3, 3, 60]));
+    assert!(!t.contains_all(vec![147, 0, 4]));
+    assert!(!t.contains_all(vec![150]));
+
+    assert_eq!(t.remove_all(Vec::<i32>::new()), 0);
+    assert_eq!(t.remove_all(vec![147, -3, 0, 1, 60, 60, 200]), 3);
+    assert_eq!(t.len(), 47);
+    let expected: Vec<i32> = (1..49).map(|v| v * 3).filter(|&v| v != 60).collect();
+    assert!(t.iter().copied().eq(expected.clone()));
+
+    // the tree still works as normal
+    t.insert(60);
+    t.remove(&3);
+    assert_eq!(t.len(), 47);
+    assert_eq!(t.remove_all(expected), 46);
+    assert!(t.iter().copied().eq(vec![60]));
+
+    // a few keys are looked up one at a time
+    let mut t: RBTree<i32> = (0..1000).collect();
+    assert_eq!(t.remove_all(vec![999, 0, 500, 500, 1000]), 3);
+    assert_eq!(t.len(), 997);
+    assert!(t.iter().copied().eq((1..999).filter(|&v| v != 500)));
+}
+
+#[cfg(feature = "set")]
+#[test]
+fn test_remove_all_panic() {
+    use crate::Comparator;
+    use std::cmp::Ordering;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    // panics when a key meets the element it was made with
+    struct Probe(u32);
+    #[derive(Clone, Copy)]
+    struct Fussy(u32);
+    impl Comparator<u32> for Fussy {
+        fn compare(&self, l: &u32, r: &u32) -> Ordering {
+            l.cmp(r)
+        }
+    }
+    impl Comparator<Probe> for Fussy {
+        fn compare(&self, l: &Probe, r: &Probe) -> Ordering {
+            l.0.cmp(&r.0)
+        }
+    }
+    impl Comparator<Probe, u32> for Fussy {
+        fn compare(&self, l: &Probe, r: &u32) -> Ordering {
+            assert!(*r != self.0, "compared with {}", r);
+            l.0.cmp(r)
+        }
+    }
+
+    // the keys found before the panic are left in, whether
+    // the tree is walked or the keys are looked up
+    for n in [10, 1000] {
+        let bad = n - 2;
+        let mut t = RBTree::with_comparator(Fussy(bad));
+        t.extend(0..n);
+        let keys = vec![Probe(2), Probe(3), Probe(bad), Probe(n - 1)];
+        assert!(catch_unwind(AssertUnwindSafe(|| t.remove_all(keys))).is_err());
+        assert_eq!(t.len(), n as usize);
+        assert!(t.iter().copied().eq(0..n));
+    }
+}
+
+#[cfg(feature = "set")]
+#[test]
+fn test_union_of_trees() {
+    use crate::Comparator;
+    use std::cmp::Ordering;
+
+    #[derive(Default)]
+    struct ByFirst;
+    impl Comparator<(i32, usize)> for ByFirst {
+        fn compare(&self, l: &(i32, usize), r: &(i32, usize)) -> Ordering {
+            l.0.cmp(&r.0)
+        }
+    }
+
+    let shards = (0..4).map(|s| {
+        let mut t = RBTree::with_comparator(ByFirst);
+        t.extend(
+            (0..20)
+                .filter(|v| v % (s + 2) == 0)
+                .map(|v| (v, s as usize)),
+        );
+        t
+    });
+    // equal items are taken from the earliest shard
+    let expected: Vec<(i32, usize)> = (0..20)
+        .filter_map(|v| (0..4).find(|s| v % (s + 2) == 0).map(|s| (v, s as usize)))
+        .collect();
+    let union: RBTree<_, ByFirst> = shards.collect();
+    assert_eq!(union.len(), expected.len());
+    assert!(union.iter().copied().eq(expected));
+
+    let sum: RBTree<i32> = vec![(0..5).collect(), RBTree::new(), (3..8).collect()]
+        .into_iter()
+        .sum();
+    assert!(sum.iter().copied().eq(0..8));
+    let empty: RBTree<i32> = Vec::<RBTree<i32>>::new().into_iter().collect();
+    assert!(empty.is_empty());
+}
+
+#[cfg(all(feature = "map", feature = "queue"))]
+#[test]
+fn test_iterator_clone_debug() {
+    use crate::RBQueue;
+
+    let t: RBTree<_> = (0..5).collect();
+    let mut iter = t.iter();
+    iter.next();
+    let fork = iter.clone();
+    assert!(iter.eq(fork.clone()));
+    assert_eq!(format!("{:?}", fork), "[1, 2, 3, 4]");
+
+    let o: RBTree<_> = (3..8).collect();
+    let mut union = t.union(&o);
+    union.next();
+    assert_eq!(format!("{:?}", union), "[1, 2, 3, 4, 5, 6, 7]");
+    assert_eq!(format!("{:?}", t.intersection(&o)), "[3, 4]");
+    assert_eq!(format!("{:?}", t.difference(&o)), "[0, 1, 2]");
+    assert_eq!(
+        format!("{:?}", t.symmetric_difference(&o)),
+        "[0, 1, 2, 5, 6, 7]"
+    );
+    let mut into = t.clone().into_iter();
+    into.next();
+    assert_eq!(format!("{:?}", into.clone()), "[1, 2, 3, 4]");
+    assert!(into.eq(1..5));
+
+    let m: RBMap<_, _> = vec![(1, 'a'), (2, 'b')].into_iter().collect();
+    let mut iter = m.iter();
+    iter.next();
+    assert_eq!(format!("{:?}", iter.clone()), "[(2, 'b')]");
+    assert_eq!(format!("{:?}", m.keys()), "[1, 2]");
+    assert_eq!(format!("{:?}", m.values()), "['a', 'b']");
+    assert_eq!(
+        format!("{:?}", m.clone().into_iter()),
+        "[(1, 'a'), (2, 'b')]"
+    );
+
+    let mut q = RBQueue::new(|l: &i32, r: &i32| r.cmp(l));
+    q.extend(vec![1, 3, 2]);
+    assert_eq!(format!("{:?}", q.iter()), "[3, 2, 1]");
+    let mut into = q.into_iter();
+    into.next();
+    assert_eq!(format!("{:?}", into.clone()), "[2, 1]");
+}
+
+#[cfg(all(
+    feature = "set",
+    not(any(feature = "chunked", feature = "debug_invariants"))
+))]
+#[test]
+fn test_ascending_insertion() {
+    use std::cell::Cell;
+
+    // appending a new greatest value takes one comparison
+    let comparisons = Cell::new(0);
+    let counted = |l: &i32, r: &i32| {
+        comparisons.set(comparisons.get() + 1);
+        l.cmp(r)
+    };
+    let mut t = RBTree::with_comparator(&counted);
+    (0..1000).for_each(|v| {
+        t.insert(v);
+    });
+    assert_eq!(comparisons.get(), 999);
+    assert!(t.iter().copied().eq(0..1000));
+
+    // removing the greatest value doesn't leave a stale hint
+    t.pop_back();
+    t.pop_back();
+    assert!(t.insert(998));
+    assert!(!t.insert(998));
+    assert!(t.insert(2000));
+    t.remove(&2000);
+    assert!(t.insert(1500));
+    t.clear();
+    assert!(t.insert(7));
+    assert!(t.insert(8));
+    assert!(t.iter().copied().eq(vec![7, 8]));
+}
+
+#[cfg(feature = "set")]
+#[test]
+fn test_ordered_into() {
+    let mut t: RBTree<_> = (0..10).rev().collect();
+    let mut refs = Vec::new();
+    t.ordered_into(&mut refs);
+    assert!(refs.iter().copied().eq(t.iter()));
+    drop(refs);
+
+    let mut buf = Vec::new();
+    t.drain_into(&mut buf);
+    assert!(buf.iter().copied().eq(0..10));
+    assert!(t.is_empty());
+
+    // the buffer and the tree can be reused without allocating,
+    // including when the tree has freed slots
+    let mut t: RBTree<i32> = (0..500).rev().collect();
+    (0..500).step_by(7).for_each(|v| {
+        t.remove(&v);
+    });
+    buf.clear();
+    t.drain_into(&mut buf);
+    assert!(buf.iter().copied().eq((0..500).filter(|v| v % 7 != 0)));
+    buf.reserve(500);
+    t.extend(0..500);
+    #[cfg(not(feature = "chunked"))]
+    let nodes = t.raw.nodes.capacity();
+    let capacity = buf.capacity();
+    for _ in 0..3 {
+        buf.clear();
+        t.drain_into(&mut buf);
+        assert!(t.is_empty());
+        assert!(buf.iter().copied().eq(0..500));
+        assert_eq!(buf.capacity(), capacity);
+        #[cfg(not(feature = "chunked"))]
+        assert_eq!(t.raw.nodes.capacity(), nodes);
+        t.extend(0..500);
+    }
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_range() {
+    use std::collections::BTreeMap;
+    use std::ops::Bound::{self, Excluded, Included, Unbounded};
+
+    let t: RBTree<i32> = (0..40).map(|v| v * 2).collect();
+    let b: std::collections::BTreeSet<i32> = t.iter().copied().collect();
+    let bounds = |v: i32| vec![Included(v), Excluded(v), Unbounded];
+    for s in -2..82 {
+        for e in s..82 {
+            for sb in bounds(s) {
+                for eb in bounds(e) {
+                    if s == e && sb == Excluded(s) && eb == Excluded(e) {
+                        // BTreeSet rejects this one
+                        continue;
+                    }
+                    let r: (Bound<i32>, Bound<i32>) = (sb, eb);
+                    assert!(t.range(r).eq(b.range(r)), "{:?}", r);
+                }
+            }
+        }
+    }
+    // backwards ranges are empty
+    assert_eq!(t.range((Included(10), Excluded(4))).count(), 0);
+    assert_eq!(RBTree::<i32>::new().range::<i32, _>(..).count(), 0);
+
+    let mut m: RBMap<i32, i32> = (0..20).map(|k| (k, k)).collect();
+    let bm: BTreeMap<i32, i32> = (0..20).map(|k| (k, k)).collect();
+    assert!(m.range(5..=9).eq(bm.range(5..=9)));
+    assert!(m.range(..3).eq(bm.range(..3)));
+    m.range_mut(15..).for_each(|(_, v)| *v = -*v);
+    assert!(m.values().copied().eq((0..15).chain((15..20).map(|v| -v))));
+}
+
+#[cfg(all(feature = "map", not(feature = "chunked")))]
+#[test]
+fn test_retain_in_place() {
+    let mut m: RBMap<u32, u32> = (0..200).map(|k| (k, k)).collect();
+    let allocated = m.map.raw.nodes.allocated();
+    m.retain(|k, v| {
+        *v += 1;
+        k % 20 != 0
+    });
+    assert_eq!(m.len(), 190);
+    assert!(m
+        .iter()
+        .map(|(&k, &v)| (k, v))
+        .eq((0..200).filter(|k| k % 20 != 0).map(|k| (k, k + 1))));
+    // the removed nodes' slots are reused before allocating more
+    (0..200).step_by(20).for_each(|k| {
+        m.insert(k, k);
+    });
+    assert_eq!(m.map.raw.nodes.allocated(), allocated);
+
+    let mut t: RBTree<u32> = (0..100).collect();
+    t.retain(|v| v % 3 == 0);
+    assert_eq!(t.len(), 34);
+    assert!(t.iter().copied().eq((0..100).step_by(3)));
+    t.retain(|_| false);
+    assert!(t.is_empty());
+    assert_eq!(t.pop(), None);
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_entry_variants() {
+    use crate::rbmap::Entry;
+
+    let words = "the quick brown fox jumps over the lazy dog the end";
+    let mut counts: RBMap<String, usize> = RBMap::new();
+    for w in words.split(' ') {
+        *counts.entry(w.to_string()).or_insert(0) += 1;
+    }
+    assert_eq!(counts.len(), 9);
+    assert_eq!(counts.get(&"the".to_string()), Some(&3));
+    assert!(counts.keys().zip(counts.keys().skip(1)).all(|(l, r)| l < r));
+
+    match counts.entry("fox".to_string()) {
+        Entry::Occupied(e) => {
+            assert_eq!(e.key(), "fox");
+            assert_eq!(e.remove_entry(), ("fox".to_string(), 1));
+        }
+        Entry::Vacant(_) => panic!("fox should be present"),
+    }
+    match counts.entry("zebra".to_string()) {
+        Entry::Vacant(e) => {
+            assert_eq!(e.key(), "zebra");
+            *e.insert(5) += 1;
+        }
+        Entry::Occupied(_) => panic!("zebra shouldn't be present"),
+    }
+    assert_eq!(counts.len(), 9);
+    assert_eq!(counts.peek_back(), Some(&6));
+    assert_eq!(counts.entry("dog".to_string()).key(), "dog");
+    assert_eq!(counts.entry("cat".to_string()).key(), "cat");
+    assert_eq!(
+        *counts
+            .entry("cat".to_string())
+            .or_insert_with_key(|k| k.len()),
+        3
+    );
+    assert_eq!(
+        *counts.entry("cat".to_string()).or_insert_with_key(|_| 0),
+        3
+    );
+    counts.remove(&"cat".to_string());
+    // inserting after a vacant insertion at the end
+    counts.insert("zz".to_string(), 0);
+    assert_eq!(counts.peek_back(), Some(&0));
+    assert!(counts.keys().zip(counts.keys().skip(1)).all(|(l, r)| l < r));
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_update() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    let mut counts = RBMap::new();
+    for word in "a b a c b a".split(' ') {
+        counts.update(word, |v| Some(v.unwrap_or(0) + 1));
+    }
+    assert_eq!(counts.get(&"a"), Some(&3));
+    assert_eq!(counts.get(&"b"), Some(&2));
+    assert_eq!(counts.len(), 3);
+
+    // returning None removes the entry, or skips inserting it
+    assert_eq!(counts.update("b", |_| None), None);
+    assert_eq!(counts.update("d", |_| None), None);
+    assert!(!counts.contains_key(&"b"));
+    assert_eq!(counts.len(), 2);
+
+    *counts.update("c", |v| v).unwrap() += 10;
+    assert_eq!(counts.get(&"c"), Some(&11));
+
+    // a panic in f removes the taken entry
+    assert!(catch_unwind(AssertUnwindSafe(|| {
+        counts.update("a", |_| panic!("oops"));
+    }))
+    .is_err());
+    assert!(!counts.contains_key(&"a"));
+    assert_eq!(counts.len(), 1);
+    assert_eq!(counts.keys().collect::<Vec<_>>(), vec!(&"c"));
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_merge_with() {
+    let mut left = RBMap::new();
+    let mut right = RBMap::new();
+    for i in 0..20 {
+        left.insert(i, i);
+        right.insert(i + 10, 100);
+    }
+    let mut seen = Vec::new();
+    left.merge_with(right, |k, l, r| {
+        seen.push(*k);
+        l + r
+    });
+    assert_eq!(seen, (10..20).collect::<Vec<_>>());
+    assert_eq!(left.len(), 30);
+    assert_eq!(left.get(&5), Some(&5));
+    assert_eq!(left.get(&15), Some(&115));
+    assert_eq!(left.get(&25), Some(&100));
+    assert!(left.keys().zip(left.keys().skip(1)).all(|(l, r)| l < r));
+
+    left.merge_with(RBMap::new(), |_, l, _| l);
+    assert_eq!(left.len(), 30);
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_borrowed_lookups() {
+    use std::ops::Bound::{Excluded, Included, Unbounded};
+    let mut map: RBMap<String, usize> = ["a", "bb", "ccc", "dddd"]
+        .iter()
+        .map(|s| (s.to_string(), s.len()))
+        .collect();
+    assert!(map.contains_key("bb"));
+    assert!(!map.contains_key("b"));
+    assert_eq!(map.get("ccc"), Some(&3));
+    assert_eq!(map.get_pair("a"), Some((&"a".to_string(), &1)));
+    *map.get_mut("a").unwrap() += 10;
+    assert_eq!(map.get_pair_mut("a").map(|(_, v)| *v), Some(11));
+    assert_eq!(map.try_get("dddd"), Ok(Some(&4)));
+    assert_eq!(
+        map.range::<str, _>((Included("b"), Excluded("d")))
+            .map(|(_, v)| *v)
+            .collect::<Vec<_>>(),
+        vec!(2, 3)
+    );
+    map.range_mut::<str, _>((Included("d"), Unbounded))
+        .for_each(|(_, v)| *v = 0);
+    assert_eq!(map.remove("dddd"), Some(0));
+    assert_eq!(map.remove_entry("bb"), Some(("bb".to_string(), 2)));
+    assert_eq!(map.len(), 2);
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_first_last_entry() {
+    let mut map: RBMap<i32, i32> = RBMap::new();
+    assert!(map.first_entry().is_none());
+    assert!(map.last_entry().is_none());
+
+    map.extend((0..10).map(|k| (k, k * k)));
+    // pop from the front while the values are small
+    while let Some(e) = map.first_entry() {
+        if *e.get() >= 10 {
+            break;
+        }
+        e.remove();
+    }
+    assert_eq!(map.len(), 6);
+    assert_eq!(map.peek_pair(), Some((&4, &16)));
+
+    let mut last = map.last_entry().unwrap();
+    assert_eq!(last.key(), &9);
+    *last.get_mut() = 0;
+    assert_eq!(map.get(&9), Some(&0));
+    assert_eq!(map.last_entry().unwrap().remove_entry(), (9, 0));
+    assert_eq!(map.keys().copied().collect::<Vec<_>>(), vec!(4, 5, 6, 7, 8));
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_double_ended() {
+    let mut map: RBMap<_, _> = (0..10).map(|k| (k, k * 2)).collect();
+    let rev: Vec<_> = (0..10).rev().collect();
+    assert_eq!(map.keys().rev().copied().collect::<Vec<_>>(), rev);
+    assert_eq!(
+        map.values().rev().copied().collect::<Vec<_>>(),
+        rev.iter().map(|k| k * 2).collect::<Vec<_>>()
+    );
+    assert_eq!(map.iter().next_back(), Some((&9, &18)));
+
+    // meeting in the middle yields every pair exactly once
+    let mut iter = map.iter();
+    let mut seen = Vec::new();
+    while let (Some(l), r) = (iter.next(), iter.next_back()) {
+        seen.push(*l.0);
+        seen.extend(r.map(|p| *p.0));
+        assert_eq!(iter.len(), 10 - seen.len());
+    }
+    seen.sort();
+    assert_eq!(seen, (0..10).collect::<Vec<_>>());
+
+    let mut keys = map.keys();
+    keys.next_back();
+    assert_eq!(keys.len(), 9);
+    assert_eq!(keys.next(), Some(&0));
+
+    map.iter_mut().rev().take(3).for_each(|(_, v)| *v = 0);
+    if let Some(v) = map.values_mut().next_back() {
+        *v += 1;
+    }
+    assert_eq!(map.get(&6), Some(&12));
+    assert_eq!(map.get(&7), Some(&0));
+    assert_eq!(map.get(&9), Some(&1));
+
+    let mut odd = map.iter_mut();
+    for _ in 0..5 {
+        odd.next();
+        odd.next_back();
+    }
+    assert!(odd.next().is_none() && odd.next_back().is_none());
+
+    let mut t: RBTree<_> = (0..5).collect();
+    let mut iter = t.iter();
+    assert_eq!(iter.next_back(), Some(&4));
+    assert_eq!(iter.next(), Some(&0));
+    assert_eq!(iter.rev().copied().collect::<Vec<_>>(), vec!(3, 2, 1));
+    t.clear();
+    assert_eq!(t.iter().next_back(), None);
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_lazy_iterators() {
+    let map: RBMap<_, _> = (0..1000).map(|k| (k, k + 1)).collect();
+    let mut iter = map.iter();
+    assert_eq!(iter.len(), 1000);
+    assert_eq!(iter.by_ref().take(3).count(), 3);
+    assert_eq!(iter.size_hint(), (997, Some(997)));
+    let resumed = iter.clone();
+    assert_eq!(iter.next(), Some((&3, &4)));
+    assert_eq!(
+        resumed.take(2).collect::<Vec<_>>(),
+        vec!((&3, &4), (&4, &5))
+    );
+
+    let mut keys = map.keys().skip(500);
+    assert_eq!(keys.next(), Some(&500));
+    assert_eq!(map.values().nth(998), Some(&999));
+    assert_eq!(map.values().next_back(), Some(&1000));
+    let mut tail = map.keys();
+    tail.nth(996);
+    assert_eq!(format!("{:?}", tail), "[997, 998, 999]");
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_iter_mut_sound() {
+    // remove entries so that slot order differs from key order
+    let mut map: RBMap<_, _> = (0..50).map(|k| ((k * 7) % 50, k)).collect();
+    map.retain(|k, _| k % 3 != 0);
+    map.extend((0..50).filter(|k| k % 6 == 0).map(|k| (k, 100)));
+
+    let mut iter = map.iter_mut();
+    let len = iter.len();
+    let first = iter.next().unwrap();
+    let last = iter.next_back().unwrap();
+    let (first_key, first_val, last_key, last_val) = (*first.0, *first.1, *last.0, *last.1);
+    // both can be held at once
+    std::mem::swap(first.1, last.1);
+    assert_eq!(iter.len(), len - 2);
+    iter.for_each(|(k, v)| *v = *k);
+    assert_eq!(map.get(&first_key), Some(&last_val));
+    assert_eq!(map.get(&last_key), Some(&first_val));
+    assert!(map.iter().skip(1).take(len - 2).all(|(k, v)| k == v));
+
+    let expected: Vec<_> = map
+        .keys()
+        .filter(|k| (10..20).contains(*k))
+        .copied()
+        .collect();
+    let mut range = map.range_mut(10..20);
+    assert_eq!(
+        format!("{:?}", range),
+        format!("{:?}", expected.iter().map(|k| (k, k)).collect::<Vec<_>>())
+    );
+    let held: Vec<_> = range.by_ref().collect();
+    assert_eq!(held.len(), expected.len());
+    for (_, v) in held {
+        *v += 1000;
+    }
+    assert!(map.range(10..20).all(|(k, v)| *v == k + 1000));
+    assert_eq!(map.range_mut(30..30).count(), 0);
+
+    // the pairs are handed out one at a time from either end,
+    // and those still to come are shown at every point
+    let pairs: Vec<_> = map.iter().map(|(k, v)| (*k, *v)).collect();
+    for fronts in 1..4 {
+        let mut iter = map.iter_mut();
+        let (mut lo, mut hi) = (0, pairs.len());
+        while lo < hi {
+            let rest = &pairs[lo..hi];
+            assert_eq!(format!("{:?}", iter), format!("{:?}", rest));
+            assert_eq!(iter.len(), hi - lo);
+            let (k, _) = if (lo + pairs.len() - hi) % (fronts + 1) < fronts {
+                lo += 1;
+                iter.next().unwrap()
+            } else {
+                hi -= 1;
+                iter.next_back().unwrap()
+            };
+            assert!(*k == pairs[lo - 1].0 || *k == pairs[hi].0);
+        }
+        assert!(iter.next().is_none() && iter.next_back().is_none());
+    }
+    for lo in 0..51 {
+        for hi in lo..51 {
+            let rest: Vec<_> = pairs
+                .iter()
+                .filter(|(k, _)| (lo..hi).contains(k))
+                .collect();
+            let mut range = map.range_mut(lo..hi);
+            for i in 0..=rest.len() {
+                assert_eq!(format!("{:?}", range), format!("{:?}", &rest[i..]));
+                assert_eq!(range.next().map(|(k, _)| *k), rest.get(i).map(|p| p.0));
+            }
+        }
+    }
+    let mut values = map.values_mut();
+    values.next_back();
+    let shown: Vec<_> = pairs[..pairs.len() - 1].iter().map(|p| p.1).collect();
+    assert_eq!(format!("{:?}", values), format!("{:?}", shown));
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_debug() {
+    #[derive(Debug)]
+    struct Holder {
+        map: RBMap<&'static str, u32>,
+    }
+    let mut h = Holder { map: RBMap::new() };
+    assert_eq!(format!("{:?}", h), "Holder { map: {} }");
+    assert_eq!(h.map.dump_structure(), "___");
+    h.map.insert("b", 2);
+    h.map.insert("a", 1);
+    h.map.insert("c", 3);
+    assert_eq!(
+        format!("{:?}", h),
+        r#"Holder { map: {"a": 1, "b": 2, "c": 3} }"#
+    );
+    assert_eq!(
+        format!("{:#?}", h.map),
+        "{\n    \"a\": 1,\n    \"b\": 2,\n    \"c\": 3,\n}"
+    );
+    #[cfg(not(feature = "chunked"))]
+    assert_eq!(h.map.dump_structure().lines().count(), 3);
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_std_conversions() {
+    use std::collections::{BTreeMap, HashMap};
+    let b: BTreeMap<_, _> = (0..100).map(|k| (k, k.to_string())).collect();
+    let map = RBMap::from(b.clone());
+    assert_eq!(map.len(), 100);
+    assert!(map.iter().zip(b.iter()).all(|(l, r)| l == r));
+    assert_eq!(BTreeMap::from(map.clone()), b);
+
+    let h: HashMap<_, _> = map.clone().into();
+    assert_eq!(h.len(), 100);
+    let back = RBMap::from(h);
+    assert!(back.iter().eq(map.iter()));
+
+    let empty = RBMap::from(BTreeMap::<u8, u8>::new());
+    assert!(empty.is_empty());
+    assert_eq!(empty.peek_pair(), None);
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_from_sorted_iter() {
+    use crate::UnsortedError;
+    for n in [0, 1, 2, 3, 7, 8, 100, 1000] {
+        let map = RBMap::from_sorted_iter((0..n).map(|k| (k, k * 3))).unwrap();
+        assert_eq!(map.len(), n);
+        assert!(map
+            .iter()
+            .map(|(k, v)| (*k, *v))
+            .eq((0..n).map(|k| (k, k * 3))));
+        // the result is a valid tree that can be modified
+        let mut map = map;
+        map.insert(n, 0);
+        map.remove(&0);
+        assert_eq!(map.len(), n);
+    }
+
+    let dup = RBMap::from_sorted_iter(vec![(1, 1), (1, 2), (1, 3), (2, 4)]).unwrap();
+    assert_eq!(dup.len(), 2);
+    assert_eq!(dup.get(&1), Some(&3));
+
+    assert_eq!(
+        RBMap::from_sorted_iter(vec![(1, ()), (3, ()), (2, ())]).unwrap_err(),
+        UnsortedError { position: 2 }
+    );
+    assert_eq!(
+        RBMap::from_sorted_iter(vec![(0.0, ()), (f64::NAN, ())]).unwrap_err(),
+        UnsortedError { position: 1 }
+    );
+    assert_eq!(
+        UnsortedError { position: 2 }.to_string(),
+        "element 2 of the input is out of order"
+    );
+}
+
+// checks the subtree sizes of every node against a recount
+#[cfg(any(feature = "map", all(feature = "set", not(feature = "chunked"))))]
+fn check_sizes<T>(nodes: &crate::node::Arena<T>, at: crate::node::Index) -> usize {
+    if at == crate::node::NIL {
+        return 0;
+    }
+    let size = 1 + check_sizes(nodes, nodes.get_left(at)) + check_sizes(nodes, nodes.get_right(at));
+    assert_eq!(nodes.size(at), size);
+    size
+}
+
+// checks the links and colours of every node, returning
+// the black height of the subtree
+#[cfg(all(feature = "set", not(feature = "chunked")))]
+fn check_colours<T>(
+    nodes: &crate::node::Arena<T>,
+    at: crate::node::Index,
+    parent: crate::node::Index,
+) -> usize {
+    if at == crate::node::NIL {
+        return 1;
+    }
+    assert_eq!(nodes.get_parent(at), parent);
+    let (left, right) = (nodes.get_left(at), nodes.get_right(at));
+    if nodes.is_red(at) {
+        assert!(nodes.is_black(left) && nodes.is_black(right));
+    }
+    let height = check_colours(nodes, left, at);
+    assert_eq!(height, check_colours(nodes, right, at));
+    height + nodes.is_black(at) as usize
+}
+
+// checks the bookkeeping of the tree, which is kept
+// per chunk with the "chunked" feature
+#[cfg(all(feature = "set", not(feature = "chunked")))]
+fn check_tree<T>(nodes: &crate::node::Arena<T>) {
+    check_sizes(nodes, nodes.root());
+    assert!(nodes.is_black(nodes.root()));
+    check_colours(nodes, nodes.root(), crate::node::NIL);
+}
+#[cfg(all(feature = "map", feature = "chunked"))]
+fn check_tree<T: PartialOrd>(nodes: &crate::chunk::Arena<T>) {
+    nodes.check();
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_rank_nth() {
+    let mut map = RBMap::new();
+    let mut keys = Vec::new();
+    // a scrambled but deterministic insertion order
+    for i in 0..300u32 {
+        let k = (i * 113) % 300;
+        map.insert(k, i);
+        keys.push(k);
+    }
+    for k in 0..300u32 {
+        if k % 4 == 1 {
+            map.remove(&k);
+        }
+    }
+    map.retain(|k, _| k % 7 != 0);
+    map.update(1000, |_| Some(0));
+    map.entry(999).or_insert(0);
+    check_tree(&map.map.raw.nodes);
+
+    let sorted: Vec<u32> = map.keys().copied().collect();
+    for (i, k) in sorted.iter().enumerate() {
+        assert_eq!(map.rank(k), Some(i));
+        assert_eq!(map.nth(i).map(|(k, _)| *k), Some(*k));
+    }
+    assert_eq!(map.rank(&1), None);
+    assert_eq!(map.nth(sorted.len()), None);
+
+    let built = RBMap::from_sorted_iter((0..100).map(|k| (k, ()))).unwrap();
+    check_tree(&built.map.raw.nodes);
+    assert_eq!(built.nth(37), Some((&37, &())));
+    assert_eq!(RBMap::<u8, u8>::new().nth(0), None);
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_count_range() {
+    use std::ops::Bound::{Excluded, Included, Unbounded};
+    let mut map: RBMap<_, _> = (0..200).map(|k| (k, ())).collect();
+    map.retain(|k, _| k % 3 != 0);
+    let bounds = [
+        Unbounded,
+        Included(-1),
+        Included(0),
+        Excluded(50),
+        Included(133),
+        Excluded(199),
+        Included(500),
+    ];
+    for start in bounds.iter() {
+        for end in bounds.iter() {
+            let expected = map.range((*start, *end)).count();
+            assert_eq!(
+                map.count_range((*start, *end)),
+                expected,
+                "{:?}..{:?}",
+                start,
+                end
+            );
+        }
+    }
+    assert_eq!(map.count_range(3..4), 0);
+    assert_eq!(map.count_range(..), map.len());
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_keys_values_range() {
+    let map: RBMap<_, _> = (0..20).map(|k| (k, k.to_string())).collect();
+    let keys = map.keys_range(5..8);
+    assert_eq!(format!("{:?}", keys), "[5, 6, 7]");
+    assert_eq!(keys.copied().collect::<Vec<_>>(), vec!(5, 6, 7));
+    let values = map.values_range(18..);
+    assert_eq!(values.clone().count(), 2);
+    assert_eq!(values.collect::<Vec<_>>(), vec!("18", "19"));
+    assert_eq!(map.keys_range(30..).next(), None);
+    assert!(map.values_range(..).eq(map.values()));
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_iter_from() {
+    let map: RBMap<String, usize> = (0..50).map(|k| (format!("{:02}", k), k)).collect();
+    // paging through the map using the last key as a cursor
+    let mut pages = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let page: Vec<usize> = match &cursor {
+            Some(c) => map
+                .iter_from(c.as_str())
+                .skip_while(|(k, _)| *k == c)
+                .take(8)
+                .map(|(_, v)| *v)
+                .collect(),
+            None => map.values().take(8).copied().collect(),
+        };
+        if page.is_empty() {
+            break;
+        }
+        cursor = Some(format!("{:02}", page[page.len() - 1]));
+        pages.push(page);
+    }
+    assert_eq!(pages.len(), 7);
+    assert_eq!(pages.concat(), (0..50).collect::<Vec<_>>());
+
+    assert_eq!(map.iter_from("4").next(), Some((&"40".to_string(), &40)));
+    assert_eq!(map.iter_from("5").next(), None);
+    assert_eq!(map.iter_from("").count(), 50);
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_map_values() {
+    let mut map: RBMap<_, _> = (0..50).map(|k| ((k * 17) % 50, k)).collect();
+    map.retain(|k, _| k % 5 != 0);
+    let before = map.dump_structure();
+    let keys: Vec<_> = map.keys().copied().collect();
+
+    let mut seen = Vec::new();
+    let mapped = map.map_values(|v| {
+        seen.push(v);
+        v as f64 / 2.0
+    });
+    // the values are visited in key order
+    assert_eq!(seen.len(), keys.len());
+    assert!(keys
+        .iter()
+        .zip(seen.iter())
+        .all(|(k, v)| (v * 17) % 50 == *k));
+    assert_eq!(mapped.len(), keys.len());
+    assert!(mapped.keys().copied().eq(keys.iter().copied()));
+    assert_eq!(mapped.get(&17), Some(&0.5));
+
+    // the tree keeps its shape and remains usable
+    let back = mapped.map_values(|v| (v * 2.0) as i32);
+    assert_eq!(back.dump_structure(), before);
+    let mut back = back;
+    back.insert(5, 0);
+    back.remove(&17);
+    assert_eq!(back.len(), keys.len());
+
+    let empty: RBMap<u8, u8> = RBMap::new();
+    assert!(empty.map_values(|v| v as u16).is_empty());
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_multimap() {
+    use crate::RBMultiMap;
+    let words = "the cat sat on the mat the end";
+    let mut index = RBMultiMap::new();
+    for (pos, word) in words.split(' ').enumerate() {
+        index.insert(word.to_string(), pos);
+    }
+    assert_eq!(index.len(), 8);
+    assert_eq!(index.keys_len(), 6);
+    assert_eq!(index.get_all("the"), &[0, 4, 6]);
+    assert_eq!(index.get("the"), Some(&0));
+    assert!(index.get_all("dog").is_empty());
+
+    let flat: Vec<_> = index.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+    assert_eq!(
+        flat,
+        vec!(
+            ("cat", 1),
+            ("end", 7),
+            ("mat", 5),
+            ("on", 3),
+            ("sat", 2),
+            ("the", 0),
+            ("the", 4),
+            ("the", 6)
+        )
+    );
+    assert_eq!(index.iter().len(), 8);
+    let mut iter = index.iter();
+    iter.nth(5);
+    assert_eq!(iter.len(), 2);
+    assert_eq!(
+        index
+            .groups()
+            .next_back()
+            .map(|(k, v)| (k.as_str(), v.len())),
+        Some(("the", 3))
+    );
+
+    assert_eq!(index.remove_one("the"), Some(0));
+    assert_eq!(index.get_all("the"), &[4, 6]);
+    assert_eq!(index.remove_one("cat"), Some(1));
+    assert!(!index.contains_key("cat"));
+    assert_eq!(index.remove_one("cat"), None);
+    assert_eq!(index.remove_all("the"), vec!(4, 6));
+    assert_eq!(index.len(), 4);
+    assert_eq!(index.keys_len(), 4);
+
+    let collected: RBMultiMap<_, _> = vec![(1, 'a'), (0, 'b'), (1, 'c')].into_iter().collect();
+    assert_eq!(format!("{:?}", collected), "{0: ['b'], 1: ['a', 'c']}");
+    let mut other = RBMultiMap::new();
+    other.extend(vec![(0, 'b'), (1, 'a'), (1, 'c')]);
+    assert!(collected == other);
+    other.clear();
+    assert!(other.is_empty());
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_replace_key() {
+    use crate::ReplaceKeyError;
+    let mut map: RBMap<_, _> = (0..100).map(|k| (k, vec![k; 3])).collect();
+    let ptr = map.get(&10).unwrap().as_ptr();
+    assert_eq!(map.replace_key(&10, 1000), Ok(()));
+    // the value was moved rather than copied
+    assert_eq!(map.get(&1000).unwrap().as_ptr(), ptr);
+    assert!(!map.contains_key(&10));
+    assert_eq!(map.len(), 100);
+    check_tree(&map.map.raw.nodes);
+
+    for k in 0..50 {
+        if k != 10 {
+            assert_eq!(map.replace_key(&k, -k - 1), Ok(()));
+        }
+    }
+    assert!(map.keys().zip(map.keys().skip(1)).all(|(l, r)| l < r));
+    assert_eq!(map.get(&-50), Some(&vec![49; 3]));
+    check_tree(&map.map.raw.nodes);
+
+    assert_eq!(map.replace_key(&50, 51), Err(ReplaceKeyError::Occupied(51)));
+    assert_eq!(map.replace_key(&10, 11), Err(ReplaceKeyError::NotFound(11)));
+    assert_eq!(ReplaceKeyError::Occupied(51).into_key(), 51);
+    assert_eq!(map.replace_key(&50, 50), Ok(()));
+    assert_eq!(map.len(), 100);
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_aggregate_map_ranges() {
+    use crate::aggregate::{Max, Total};
+    use crate::RBAggregateMap;
+    let mut map: RBAggregateMap<u32, u64, (Total<u64>, Max<u64>)> = RBAggregateMap::new();
+    let mut brute = std::collections::BTreeMap::new();
+    // a scrambled but deterministic insertion order
+    for i in 0..200u32 {
+        let k = (i * 71) % 200;
+        let v = ((i as u64) * 37) % 101;
+        map.insert(k, v);
+        brute.insert(k, v);
+    }
+    for k in (0..200u32).step_by(3) {
+        assert_eq!(map.remove(&k), brute.remove(&k));
+    }
+    for k in (0..200u32).step_by(7) {
+        map.insert(k, 1000 + k as u64);
+        brute.insert(k, 1000 + k as u64);
+    }
+    map.modify(&1, |v| *v += 5000);
+    *brute.get_mut(&1).unwrap() += 5000;
+    check_sizes(&map.nodes, map.nodes.root());
+    assert_eq!(map.len(), brute.len());
+
+    let expect = |lo: u32, hi: u32| {
+        let vals: Vec<u64> = brute.range(lo..hi).map(|(_, v)| *v).collect();
+        if vals.is_empty() {
+            None
+        } else {
+            Some((Total(vals.iter().sum()), Max(*vals.iter().max().unwrap())))
+        }
+    };
+    for lo in (0..210u32).step_by(5) {
+        for hi in (lo..215).step_by(4) {
+            assert_eq!(map.aggregate_range(lo..hi), expect(lo, hi));
+        }
+    }
+    assert_eq!(map.aggregate(), expect(0, 200));
+    assert_eq!(map.aggregate_range(..), expect(0, 200));
+    assert_eq!(map.aggregate_range(10..=10), expect(10, 11));
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_peek_mut() {
+    let mut map: RBMap<u32, Vec<u32>> = RBMap::new();
+    assert!(map.peek_mut().is_none());
+    assert!(map.peek_pair_back_mut().is_none());
+    for k in (0..50).rev() {
+        map.insert(k, vec![k]);
+    }
+    map.peek_mut().unwrap().push(100);
+    map.peek_back_mut().unwrap().push(200);
+    {
+        let (k, v) = map.peek_pair_mut().unwrap();
+        v.push(*k + 1);
+    }
+    {
+        let (k, v) = map.peek_pair_back_mut().unwrap();
+        v.push(*k + 1);
+    }
+    assert_eq!(map.get(&0), Some(&vec![0, 100, 1]));
+    assert_eq!(map.get(&49), Some(&vec![49, 200, 50]));
+    // the rest of the map is untouched
+    assert!((1..49).all(|k| map.get(&k) == Some(&vec![k])));
+    assert_eq!(map.len(), 50);
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_pop_pair_while() {
+    let mut map: RBMap<u32, u32> = (0..100).map(|k| (k, k * 2)).collect();
+    let popped: Vec<_> = map.pop_pair_while(|k, _| *k < 10).collect();
+    assert_eq!(popped, (0..10).map(|k| (k, k * 2)).collect::<Vec<_>>());
+    assert_eq!(map.len(), 90);
+    assert_eq!(map.peek_pair(), Some((&10, &20)));
+    check_tree(&map.map.raw.nodes);
+
+    // stopping early leaves the rest of the matching pairs
+    assert_eq!(map.pop_pair_while(|_, v| *v < 60).next(), Some((10, 20)));
+    assert_eq!(map.len(), 89);
+
+    // once the predicate fails it is not consulted again
+    let mut calls = 0;
+    {
+        let mut it = map.pop_pair_while(|k, _| {
+            calls += 1;
+            *k == 11
+        });
+        assert_eq!(it.next(), Some((11, 22)));
+        assert_eq!(it.next(), None);
+        assert_eq!(it.next(), None);
+    }
+    assert_eq!(calls, 2);
+
+    assert_eq!(map.pop_pair_while(|_, _| true).count(), 88);
+    assert!(map.is_empty());
+    assert_eq!(map.pop_pair_while(|_, _| true).next(), None);
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_entry_ref_clones_lazily() {
+    use std::cell::Cell;
+    thread_local!(static CLONES: Cell<usize> = const { Cell::new(0) });
+
+    #[derive(PartialEq, PartialOrd, Debug)]
+    struct Key(u32);
+    impl Clone for Key {
+        fn clone(&self) -> Self {
+            CLONES.with(|c| c.set(c.get() + 1));
+            Key(self.0)
+        }
+    }
+
+    let mut map: RBMap<Key, u32> = RBMap::new();
+    for i in 0..100 {
+        *map.entry_ref(&Key(i % 10)).or_insert(0) += 1;
+    }
+    // only the ten insertions needed an owned key
+    assert_eq!(CLONES.with(|c| c.get()), 10);
+    assert_eq!(map.len(), 10);
+    assert!((0..10).all(|k| map.get(&Key(k)) == Some(&10)));
+
+    map.entry_ref(&Key(3)).and_modify(|v| *v = 0).or_default();
+    assert_eq!(map.entry_ref(&Key(3)).key(), &Key(3));
+    assert_eq!(map.get(&Key(3)), Some(&0));
+    assert_eq!(CLONES.with(|c| c.get()), 10);
+    check_tree(&map.map.raw.nodes);
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_remove_nth() {
+    let mut map: RBMap<u32, u32> = (0..200).map(|k| ((k * 113) % 200, k)).collect();
+    let mut brute: Vec<(u32, u32)> = map.iter().map(|(k, v)| (*k, *v)).collect();
+    // removes from the middle, then the ends
+    for n in (0..150).rev().step_by(3) {
+        let i = n % brute.len();
+        assert_eq!(map.remove_nth(i), Some(brute.remove(i)));
+        check_tree(&map.map.raw.nodes);
+    }
+    assert_eq!(map.remove_nth(brute.len()), None);
+    assert_eq!(map.remove_nth(brute.len() - 1), brute.pop());
+    assert_eq!(map.remove_nth(0), Some(brute.remove(0)));
+    assert_eq!(map.len(), brute.len());
+    assert!(map.iter().map(|(k, v)| (*k, *v)).eq(brute.into_iter()));
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_retain_map() {
+    let mut map: RBMap<u32, String> = (0..100).map(|k| (k, k.to_string())).collect();
+    let mut seen = Vec::new();
+    map.retain_map(|k, v| {
+        seen.push(*k);
+        if k % 3 == 0 {
+            None
+        } else {
+            Some(v + "!")
+        }
+    });
+    assert_eq!(seen, (0..100).collect::<Vec<_>>());
+    assert_eq!(map.len(), 66);
+    assert!(map
+        .iter()
+        .all(|(k, v)| k % 3 != 0 && *v == format!("{}!", k)));
+    check_tree(&map.map.raw.nodes);
+
+    // a panic part way through removes the pair being mapped
+    let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        map.retain_map(|k, v| if *k == 50 { panic!() } else { Some(v) })
+    }));
+    assert!(res.is_err());
+    assert_eq!(map.len(), 65);
+    assert!(!map.contains_key(&50));
+    check_tree(&map.map.raw.nodes);
+
+    let lens = map.filter_map_values(|k, v| if k % 2 == 0 { Some(v.len()) } else { None });
+    assert_eq!(lens.len(), 32);
+    assert_eq!(lens.get(&98), Some(&3));
+    assert!(lens.keys().zip(lens.keys().skip(1)).all(|(l, r)| l < r));
+    check_tree(&lens.map.raw.nodes);
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_retain_remove_keys() {
+    let map: RBMap<u32, u32> = (0..300).map(|k| (k * 2, k)).collect();
+    let keys: RBTree<u32> = (0..200).map(|k| k * 3).collect();
+
+    let mut kept = map.clone();
+    kept.retain_keys(&keys);
+    let expect: Vec<u32> = (0..300)
+        .map(|k| k * 2)
+        .filter(|k| keys.contains(k))
+        .collect();
+    assert_eq!(kept.keys().copied().collect::<Vec<_>>(), expect);
+    assert_eq!(kept.len(), expect.len());
+    check_tree(&kept.map.raw.nodes);
+
+    let mut removed = map.clone();
+    removed.remove_keys(&keys);
+    let expect: Vec<u32> = (0..300)
+        .map(|k| k * 2)
+        .filter(|k| !keys.contains(k))
+        .collect();
+    assert_eq!(removed.keys().copied().collect::<Vec<_>>(), expect);
+    assert_eq!(removed.len(), expect.len());
+    check_tree(&removed.map.raw.nodes);
+
+    let mut all = map.clone();
+    all.remove_keys(&RBTree::new());
+    assert_eq!(all.len(), 300);
+    all.retain_keys(&RBTree::new());
+    assert!(all.is_empty());
+}
+
+#[cfg(all(feature = "map", feature = "serde"))]
+#[test]
+fn test_map_serde_as_map() {
+    let mut map = RBMap::new();
+    map.insert("b".to_string(), 2);
+    map.insert("a".to_string(), 1);
+    map.insert("c".to_string(), 3);
+    let json = serde_json::to_string(&map).unwrap();
+    assert_eq!(json, r#"{"a":1,"b":2,"c":3}"#);
+
+    // any order is accepted, keeping the last of repeated keys
+    let map: RBMap<String, u32> = serde_json::from_str(r#"{"z":26,"m":13,"a":0,"m":14}"#).unwrap();
+    assert_eq!(
+        map.iter()
+            .map(|(k, v)| (k.as_str(), *v))
+            .collect::<Vec<_>>(),
+        vec![("a", 0), ("m", 14), ("z", 26)]
+    );
+    check_tree(&map.map.raw.nodes);
+
+    let nums: RBMap<u32, Vec<u32>> = (0..100).rev().map(|k| (k, vec![k; 2])).collect();
+    let back: RBMap<u32, Vec<u32>> =
+        serde_json::from_str(&serde_json::to_string(&nums).unwrap()).unwrap();
+    assert!(nums.iter().eq(back.iter()));
+    assert_eq!(back.len(), 100);
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_pop_pair_if() {
+    let mut map: RBMap<u32, u32> = (0..20).map(|k| (k, k * k)).collect();
+    assert_eq!(map.pop_pair_if(|k, _| *k > 0), None);
+    assert_eq!(map.pop_pair_back_if(|_, v| *v < 100), None);
+    assert_eq!(map.len(), 20);
+    assert_eq!(map.pop_pair_if(|_, v| *v == 0), Some((0, 0)));
+    assert_eq!(map.pop_pair_back_if(|k, _| *k == 19), Some((19, 361)));
+    assert_eq!(map.len(), 18);
+    assert_eq!(map.peek_pair(), Some((&1, &1)));
+    assert_eq!(map.peek_pair_back(), Some((&18, &324)));
+    check_tree(&map.map.raw.nodes);
+
+    while map.pop_pair_back_if(|_, _| true).is_some() {}
+    assert!(map.is_empty());
+    assert_eq!(map.pop_pair_if(|_, _| true), None);
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_sets_built_in_order() {
+    // values compare by their first field only
+    #[derive(Debug, Clone, Copy)]
+    struct Tagged(u32, u32);
+    impl PartialEq for Tagged {
+        fn eq(&self, other: &Tagged) -> bool {
+            self.0 == other.0
+        }
+    }
+    impl PartialOrd for Tagged {
+        fn partial_cmp(&self, other: &Tagged) -> Option<std::cmp::Ordering> {
+            self.0.partial_cmp(&other.0)
+        }
+    }
+
+    let map: RBMap<u32, Tagged> = (0..200).map(|k| (k, Tagged((k * 7) % 50, k))).collect();
+    let mut inserted = RBTree::new();
+    for v in map.values() {
+        inserted.insert(*v);
+    }
+
+    let keys = map.keyset();
+    assert_eq!(keys.len(), 200);
+    assert!(keys.iter().map(|k| **k).eq(0..200));
+    check_tree(&keys.raw.nodes);
+
+    let values = map.valueset();
+    assert_eq!(values.len(), 50);
+    // the last of each run of equal values is kept, as with inserting
+    assert!(values.iter().zip(inserted.iter()).all(|(l, r)| l.1 == r.1));
+    check_tree(&values.raw.nodes);
+
+    let (kset, vset) = map.clone().into_sets();
+    assert!(kset.iter().copied().eq(0..200));
+    assert!(vset.iter().zip(inserted.iter()).all(|(l, r)| l.1 == r.1));
+    assert_eq!(vset.len(), 50);
+    assert!(map.clone().into_keyset().iter().copied().eq(0..200));
+    let vset = map.into_valueset();
+    assert!(vset
+        .iter()
+        .zip(inserted.iter())
+        .all(|(l, r)| l.0 == r.0 && l.1 == r.1));
+    check_tree(&vset.raw.nodes);
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_bimap_stays_consistent() {
+    use crate::rbbimap::Overwritten;
+    use crate::RBBiMap;
+    let mut map = RBBiMap::new();
+    // scrambled pairs that often collide on one side or both
+    for i in 0..500u32 {
+        let (l, r) = ((i * 37) % 60, (i * 11) % 45);
+        let had_l = map.get_by_left(&l).copied();
+        let had_r = map.get_by_right(&r).copied();
+        let res = map.insert(l, r);
+        match (had_l, had_r) {
+            (None, None) => assert_eq!(res, Overwritten::Neither),
+            (Some(old), None) => assert_eq!(res, Overwritten::Left(l, old)),
+            (None, Some(old)) => assert_eq!(res, Overwritten::Right(old, r)),
+            (Some(ol), Some(_)) if ol == r => assert_eq!(res, Overwritten::Pair(l, r)),
+            (Some(ol), Some(or)) => assert_eq!(res, Overwritten::Both((l, ol), (or, r))),
+        }
+        if i % 7 == 0 {
+            let _ = map.remove_by_right(&((i * 3) % 45));
+        }
+        // every pair is found from both sides
+        assert_eq!(map.iter().count(), map.len());
+        assert_eq!(map.iter_by_right().count(), map.len());
+        assert!(map.iter().all(|(l, r)| map.get_by_right(r) == Some(l)));
+        assert!(map
+            .iter_by_right()
+            .all(|(r, l)| map.get_by_left(l) == Some(r)));
+    }
+    let copy: RBBiMap<u32, u32> = map.iter().map(|(l, r)| (*l, *r)).collect();
+    assert_eq!(copy, map);
+    assert_eq!(map.insert_no_overwrite(1000, 1000), Ok(()));
+    assert_ne!(copy, map);
+    assert_eq!(map.insert_no_overwrite(1000, 1), Err((1000, 1)));
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_get_or_insert_with() {
+    let mut map: RBMap<u32, u32> = RBMap::new();
+    let mut calls = 0;
+    for i in 0..300u32 {
+        *map.get_or_insert_with((i * 7) % 100, || {
+            calls += 1;
+            0
+        }) += 1;
+    }
+    assert_eq!(calls, 100);
+    assert_eq!(map.len(), 100);
+    assert!(map.values().all(|v| *v == 3));
+    check_tree(&map.map.raw.nodes);
+}
+
+#[cfg(all(feature = "set", feature = "queue"))]
+#[test]
+fn test_queue_with_comparator() {
+    use crate::{NaturalOrder, RBQueue, Reversed};
+
+    let mut q: RBQueue<u32, Reversed<NaturalOrder>> = RBQueue::default();
+    q.extend((0..100).map(|v| (v * 37) % 100));
+    assert_eq!(q.len(), 100);
+    assert!(q.iter().copied().eq((0..100).rev()));
+    assert_eq!(q.pop(), Some(99));
+    assert!(q.contains(&50));
+    assert!(q.remove(&50));
+    assert_eq!(q.get(&50), None);
+
+    // a queue of comparator type converts like a closure queue
+    let t: RBTree<u32> = q.clone().into();
+    assert_eq!(t.len(), 98);
+    assert_eq!(t.peek(), Some(&0));
+    #[cfg(feature = "serde")]
+    {
+        let back: RBQueue<u32, Reversed<NaturalOrder>> =
+            serde_json::from_str(&serde_json::to_string(&q).unwrap()).unwrap();
+        assert!(back.iter().eq(q.iter()));
+    }
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_min_max_queues() {
+    use crate::{MaxQueue, MinQueue, RBQueue};
+
+    let mut min: MinQueue<String> = RBQueue::min();
+    let mut max: MaxQueue<String> = MaxQueue::default();
+    for w in "the quick brown fox jumps".split(' ') {
+        min.insert(w.to_string());
+        max.insert(w.to_string());
+    }
+    assert_eq!(min.pop().as_deref(), Some("brown"));
+    assert_eq!(max.pop().as_deref(), Some("the"));
+    assert_eq!(min.pop_back().as_deref(), Some("the"));
+    assert_eq!(max.pop_back().as_deref(), Some("brown"));
+    assert!(min.iter().eq(max.iter().rev()));
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_queue_change_priority() {
+    use crate::RBQueue;
+
+    // a small Dijkstra over a weighted graph, decreasing keys in place
+    let edges = [
+        (0, 1, 7),
+        (0, 2, 9),
+        (0, 5, 14),
+        (1, 2, 10),
+        (1, 3, 15),
+        (2, 3, 11),
+        (2, 5, 2),
+        (3, 4, 6),
+        (4, 5, 9),
+    ];
+    let mut dist = [u32::MAX; 6];
+    dist[0] = 0;
+    let mut q = RBQueue::min();
+    q.insert((0u32, 0usize));
+    while let Some((d, n)) = q.pop() {
+        for &(a, b, w) in edges.iter() {
+            let m = if a == n {
+                b
+            } else if b == n {
+                a
+            } else {
+                continue;
+            };
+            if d + w < dist[m] {
+                if dist[m] == u32::MAX {
+                    q.insert((d + w, m));
+                } else {
+                    assert!(q.change_priority(&(dist[m], m), (d + w, m)));
+                }
+                dist[m] = d + w;
+            }
+        }
+    }
+    assert_eq!(dist, [0, 7, 9, 20, 20, 11]);
+
+    let mut q: RBQueue<(u32, char)> = RBQueue::default();
+    q.extend(vec![(1, 'a'), (2, 'b'), (3, 'c')]);
+    assert!(q.modify(&(1, 'a'), |v| v.0 = 4));
+    assert!(!q.modify(&(1, 'a'), |v| v.0 = 0));
+    assert_eq!(q.len(), 3);
+    // moving onto an equal element replaces it
+    assert!(q.change_priority(&(4, 'a'), (2, 'b')));
+    assert_eq!(q.len(), 2);
+    assert_eq!(q.ordered(), vec![&(2, 'b'), &(3, 'c')]);
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_queue_bounded() {
+    use crate::rbqueue::BoundPolicy;
+    use crate::{MaxQueue, RBQueue};
+
+    // a top-3 tracker over a stream with repeats
+    let mut top: MaxQueue<u32> = RBQueue::with_capacity_bounded(3, BoundPolicy::EvictWorst);
+    for v in [4, 17, 4, 9, 2, 30, 17, 11, 1] {
+        top.insert(v);
+        assert!(top.len() <= 3);
+    }
+    assert_eq!(top.ordered(), vec![&30, &17, &11]);
+    // no worse than the back, so nothing is evicted
+    assert!(!top.insert(11));
+    assert!(!top.insert(5));
+    assert_eq!(top.len(), 3);
+    // equal elements replace without needing room
+    assert_eq!(top.replace(30), Some(30));
+    assert_eq!(top.replace(3), None);
+    assert_eq!(top.ordered(), vec![&30, &17, &11]);
+
+    let mut rejecting = RBQueue::new(|l: &i32, r| l.cmp(r)).bounded(2, BoundPolicy::Reject);
+    assert!(rejecting.insert(5));
+    assert!(rejecting.insert(9));
+    assert!(!rejecting.insert(1));
+    assert_eq!(rejecting.ordered(), vec![&5, &9]);
+    // removing makes room again
+    assert_eq!(rejecting.pop(), Some(5));
+    assert!(rejecting.insert(1));
+    assert_eq!(rejecting.ordered(), vec![&1, &9]);
+
+    // bounding a fuller queue trims its back
+    let mut q = RBQueue::<i32>::default();
+    q.extend(0..10);
+    let q = q.bounded(4, BoundPolicy::Reject);
+    assert_eq!(q.bound(), Some(4));
+    assert_eq!(q.ordered(), vec![&0, &1, &2, &3]);
+
+    let mut empty = RBQueue::<i32>::with_capacity_bounded(0, BoundPolicy::EvictWorst);
+    assert!(!empty.insert(1));
+    assert!(empty.is_empty());
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_queue_pop_batch() {
+    use crate::RBQueue;
+
+    let mut q = RBQueue::new(|l: &i32, r| r.cmp(l));
+    q.extend(0..100);
+    // small batches pop one at a time
+    assert_eq!(q.pop_batch(3), vec![99, 98, 97]);
+    assert_eq!(q.len(), 97);
+    // large batches rebuild what remains
+    let batch = q.pop_batch(90);
+    assert_eq!(batch, (7..97).rev().collect::<Vec<_>>());
+    assert_eq!(q.len(), 7);
+    assert!(q.contains(&6));
+    assert!(!q.contains(&7));
+    assert!(q.insert(50));
+    assert_eq!(q.pop(), Some(50));
+    assert_eq!(q.pop_batch(0), Vec::<i32>::new());
+    assert_eq!(q.pop_batch(100), (0..7).rev().collect::<Vec<_>>());
+    assert!(q.is_empty());
+    assert!(q.pop_batch(1).is_empty());
+    q.insert(1);
+    assert_eq!(q.ordered(), vec![&1]);
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_queue_drain_while() {
+    use crate::RBQueue;
+
+    // a queue of (due time, id) timers
+    let mut timers = RBQueue::<(u64, u32)>::default();
+    for id in 0..50 {
+        timers.insert(((id * 7) % 50, id as u32));
+    }
+    let due: Vec<_> = timers.drain_up_to(&(9, u32::MAX)).collect();
+    assert_eq!(due.len(), 10);
+    assert!(due.windows(2).all(|w| w[0] < w[1]));
+    assert!(due.iter().all(|(t, _)| *t <= 9));
+    assert_eq!(timers.len(), 40);
+    assert_eq!(timers.peek().map(|t| t.0), Some(10));
+
+    // nothing is due yet
+    assert_eq!(timers.drain_up_to(&(5, 0)).len(), 0);
+    assert_eq!(timers.len(), 40);
+
+    // a bound equal to an element includes it
+    let (t, id) = *timers.peek().unwrap();
+    assert_eq!(
+        timers.drain_up_to(&(t, id)).collect::<Vec<_>>(),
+        vec![(t, id)]
+    );
+
+    let mut calls = 0;
+    let early = timers.drain_while(|(t, _)| {
+        calls += 1;
+        *t < 40
+    });
+    assert_eq!(early.len(), 29);
+    // stops at the first element that fails
+    assert_eq!(calls, 30);
+    assert_eq!(timers.len(), 10);
+    assert_eq!(timers.drain_while(|_| true).len(), 10);
+    assert!(timers.is_empty());
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_queue_double_ended() {
+    use crate::RBQueue;
+
+    let mut q = RBQueue::new(|l: &i32, r| r.cmp(l));
+    q.extend(0..10);
+    assert!(q.iter().rev().copied().eq(0..10));
+
+    // both ends meet without overlapping
+    let mut it = q.iter();
+    let mut seen = Vec::new();
+    while let (Some(front), Some(back)) = (it.next(), it.next_back()) {
+        seen.push((*front, *back));
+        assert_eq!(it.len(), 10 - 2 * seen.len());
+    }
+    assert_eq!(seen, vec![(9, 0), (8, 1), (7, 2), (6, 3), (5, 4)]);
+    assert_eq!(it.next(), None);
+    assert_eq!(it.next_back(), None);
+
+    let mut into = q.clone().into_iter();
+    assert_eq!(into.next_back(), Some(0));
+    assert_eq!(into.next(), Some(9));
+    assert_eq!(format!("{:?}", into), "[8, 7, 6, 5, 4, 3, 2, 1]");
+    assert_eq!(into.len(), 8);
+    assert_eq!(into.rev().collect::<Vec<_>>(), (1..9).collect::<Vec<_>>());
+    assert_eq!(q.len(), 10);
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_queue_from_iter_with() {
+    use crate::{RBQueue, TotalOrder};
+
+    // ordered by the first field only, so later equal
+    // elements replace earlier ones as with insert
+    let by_key = |l: &(u32, char), r: &(u32, char)| l.0.cmp(&r.0);
+    let pairs = vec![(3, 'a'), (1, 'b'), (3, 'c'), (2, 'd'), (1, 'e')];
+    let bulk = RBQueue::from_iter_with(by_key, pairs.clone());
+    let mut one_by_one = RBQueue::new(by_key);
+    for p in pairs {
+        one_by_one.replace(p);
+    }
+    assert_eq!(bulk.ordered(), one_by_one.ordered());
+    assert_eq!(bulk.ordered(), vec![&(1, 'e'), &(2, 'd'), &(3, 'c')]);
+
+    let mut q = RBQueue::from_iter_with(TotalOrder, (0..1000).rev());
+    assert_eq!(q.len(), 1000);
+    assert!(q.iter().copied().eq(0..1000));
+    assert!(q.remove(&500));
+    assert!(q.insert(1000));
+    assert_eq!(q.pop_back(), Some(1000));
+    assert_eq!(q.pop(), Some(0));
+
+    let empty = RBQueue::from_iter_with(TotalOrder, Vec::<u8>::new());
+    assert!(empty.is_empty());
+}
+
+#[cfg(all(feature = "queue", not(feature = "chunked")))]
+#[test]
+fn test_queue_append() {
+    use crate::rbqueue::BoundPolicy;
+    use crate::{RBQueue, TotalOrder};
+
+    fn black_height(nodes: &crate::node::Arena<i32>, at: crate::node::Index) -> usize {
+        if at == crate::node::NIL {
+            return 1;
+        }
+        if nodes.is_red(at) {
+            assert!(nodes.is_black(nodes.get_left(at)));
+            assert!(nodes.is_black(nodes.get_right(at)));
+        }
+        let l = black_height(nodes, nodes.get_left(at));
+        assert_eq!(l, black_height(nodes, nodes.get_right(at)));
+        l + nodes.is_black(at) as usize
+    }
+    fn check(q: &RBQueue<i32, TotalOrder>, expected: Vec<i32>) {
+        assert_eq!(q.len(), expected.len());
+        assert!(q.iter().copied().eq(expected.iter().copied()));
+        assert!(q.raw.nodes.is_black(q.raw.nodes.root()));
+        black_height(&q.raw.nodes, q.raw.nodes.root());
+        // the tree is still usable in both directions
+        let mut q = q.clone();
+        for v in expected.iter().rev() {
+            assert!(q.contains(v));
+            assert_eq!(q.pop_back(), Some(*v));
+        }
+        assert!(q.is_empty());
+    }
+    let queue = |r: std::ops::Range<i32>| RBQueue::from_iter_with(TotalOrder, r);
+
+    // into an empty queue
+    let mut q = RBQueue::min();
+    q.append(queue(0..10));
+    check(&q, (0..10).collect());
+    // past the back, then before the front
+    q.append(queue(10..300));
+    check(&q, (0..300).collect());
+    q.append(queue(-300..0));
+    check(&q, (-300..300).collect());
+    // appending nothing
+    q.append(RBQueue::min());
+    check(&q, (-300..300).collect());
+    // queues of many sizes joined on either side,
+    // the shorter tree going into the taller and
+    // the other way around
+    for n in (0..30).chain((30..600).step_by(71)) {
+        for m in (0..30).chain((30..600).step_by(89)) {
+            let mut q = queue(0..n);
+            q.append(queue(n..n + m));
+            check_tree(&q.raw.nodes);
+            check(&q, (0..n + m).collect());
+            let mut q = queue(m..m + n);
+            q.append(queue(0..m));
+            check_tree(&q.raw.nodes);
+            check(&q, (0..n + m).collect());
+        }
+    }
+    // a few overlapping elements are inserted
+    let mut evens = RBQueue::min();
+    evens.extend((-10..10).map(|v| v * 50));
+    q.append(evens);
+    check(
+        &q,
+        (-500..-300)
+            .step_by(50)
+            .chain(-300..300)
+            .chain((300..500).step_by(50))
+            .collect(),
+    );
+    // a larger overlapping queue is merged
+    let mut q = RBQueue::min();
+    q.extend((0..100).map(|v| v * 2));
+    let mut odds = RBQueue::min();
+    odds.extend((0..100).map(|v| v * 2 + 1));
+    odds.insert(0);
+    q.append(odds);
+    check(&q, (0..200).collect());
+    assert!(q.insert(500));
+    assert_eq!(q.pop_back(), Some(500));
+
+    // equal elements are replaced by those appended
+    let by_key = |l: &(u8, char), r: &(u8, char)| l.0.cmp(&r.0);
+    let mut q = RBQueue::from_iter_with(by_key, vec![(1, 'a'), (2, 'a')]);
+    q.append(RBQueue::from_iter_with(by_key, vec![(2, 'b'), (3, 'b')]));
+    assert_eq!(q.ordered(), vec![&(1, 'a'), &(2, 'b'), &(3, 'b')]);
+
+    // a bounded queue keeps to its bound
+    let mut top = RBQueue::<i32, TotalOrder>::with_capacity_bounded(3, BoundPolicy::EvictWorst);
+    top.append(queue(5..10));
+    top.append(queue(0..4));
+    check(&top, vec![0, 1, 2]);
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_queue_set_ops() {
+    use crate::RBQueue;
+
+    // jobs ordered by priority alone, so jobs of equal
+    // priority in the two queues are considered the same
+    let by_priority = |l: &(u8, &str), r: &(u8, &str)| r.0.cmp(&l.0);
+    let mut mine = RBQueue::new(by_priority);
+    mine.extend(vec![(1, "a"), (5, "b"), (3, "c"), (8, "d")]);
+    let mut theirs = RBQueue::new(by_priority);
+    theirs.extend(vec![(5, "x"), (2, "y"), (8, "z"), (9, "w")]);
+
+    let names =
+        |it: &mut dyn Iterator<Item = &(u8, &'static str)>| it.map(|(_, n)| *n).collect::<Vec<_>>();
+    assert_eq!(
+        names(&mut mine.union(&theirs)),
+        vec!["w", "d", "b", "c", "y", "a"]
+    );
+    assert_eq!(
+        names(&mut theirs.union(&mine)),
+        vec!["w", "z", "x", "c", "y", "a"]
+    );
+    assert_eq!(names(&mut mine.intersection(&theirs)), vec!["d", "b"]);
+    assert_eq!(names(&mut theirs.intersection(&mine)), vec!["z", "x"]);
+    assert_eq!(names(&mut mine.difference(&theirs)), vec!["c", "a"]);
+    assert_eq!(names(&mut theirs.difference(&mine)), vec!["w", "y"]);
+    assert_eq!(
+        format!("{:?}", mine.intersection(&theirs)),
+        "[(8, \"d\"), (5, \"b\")]"
+    );
+
+    let empty = RBQueue::new(by_priority);
+    assert_eq!(mine.union(&empty).count(), 4);
+    assert_eq!(empty.union(&mine).count(), 4);
+    assert_eq!(mine.intersection(&empty).next(), None);
+    assert_eq!(mine.difference(&empty).count(), 4);
+    assert_eq!(empty.difference(&mine).next(), None);
+}
+
+#[cfg(feature = "queue")]
+#[test]
+#[allow(clippy::reversed_empty_ranges)]
+fn test_queue_range() {
+    use crate::{MaxQueue, RBQueue};
+
+    let mut q = RBQueue::new(|l: &i32, r| l.cmp(r));
+    q.extend((0..20).map(|v| v * 5));
+    assert!(q.range(10..25).copied().eq(vec![10, 15, 20]));
+    assert!(q.range(11..=25).copied().eq(vec![15, 20, 25]));
+    assert!(q.range(..7).copied().eq(vec![0, 5]));
+    assert!(q.range(90..).copied().eq(vec![90, 95]));
+    assert_eq!(q.range(..).count(), 20);
+    assert_eq!(q.range(96..).next(), None);
+    assert_eq!(q.range(11..14).next(), None);
+    // a start after the end gives nothing
+    assert_eq!(q.range(50..20).next(), None);
+    assert_eq!(format!("{:?}", q.range(-5..6)), "[0, 5]");
+
+    // the bounds follow the queue's own order
+    let mut max: MaxQueue<i32> = RBQueue::max();
+    max.extend(0..10);
+    assert!(max.range(7..=3).copied().eq(vec![7, 6, 5, 4, 3]));
+    assert_eq!(max.range(3..7).next(), None);
+
+    let empty = RBQueue::<i32>::default();
+    assert_eq!(empty.range(..).next(), None);
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_queue_at_rank() {
+    use crate::RBQueue;
+
+    let mut q = RBQueue::new(|l: &u32, r| r.cmp(l));
+    q.extend((0..200).map(|v| (v * 37) % 200));
+    for (i, v) in q.clone().into_iter().enumerate() {
+        assert_eq!(q.at(i), Some(&v));
+        assert_eq!(q.rank(&v), Some(i));
+    }
+    assert_eq!(q.at(200), None);
+    // positions shift as elements come and go
+    assert_eq!(q.pop(), Some(199));
+    assert!(q.remove(&100));
+    assert_eq!(q.at(0), Some(&198));
+    assert_eq!(q.rank(&99), Some(98));
+    assert_eq!(q.rank(&100), None);
+    assert_eq!(q.pop_batch(150).len(), 150);
+    assert_eq!(q.at(47), Some(&0));
+    assert_eq!(q.at(48), None);
+    assert_eq!(q.rank(&0), Some(47));
+
+    let empty = RBQueue::<u32>::default();
+    assert_eq!(empty.at(0), None);
+    assert_eq!(empty.rank(&0), None);
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_queue_probe() {
+    use crate::RBQueue;
+
+    // jobs ordered by (priority, id), looked up by both
+    // or by priority alone when it's unique
+    let mut q =
+        RBQueue::new(|l: &(u8, u32, String), r: &(u8, u32, String)| (l.0, l.1).cmp(&(r.0, r.1)));
+    for id in 0..50u32 {
+        q.insert(((id % 5) as u8, id, format!("job{}", id)));
+    }
+    for id in 0..50u32 {
+        let job = q.get_by(|j| (j.0, j.1).cmp(&((id % 5) as u8, id)));
+        assert_eq!(
+            job.map(|j| j.2.as_str()),
+            Some(format!("job{}", id).as_str())
+        );
+    }
+    assert!(q.get_by(|j| (j.0, j.1).cmp(&(1, 2))).is_none());
+    assert!(q.get_by(|j| j.0.cmp(&9)).is_none());
+
+    let taken = q.take_by(|j| (j.0, j.1).cmp(&(3, 13))).unwrap();
+    assert_eq!(taken.2, "job13");
+    assert_eq!(q.len(), 49);
+    assert!(q.take_by(|j| (j.0, j.1).cmp(&(3, 13))).is_none());
+    assert_eq!(q.len(), 49);
+    // the tree is intact after the removal
+    let order: Vec<_> = q.iter().map(|j| (j.0, j.1)).collect();
+    let mut sorted = order.clone();
+    sorted.sort();
+    assert_eq!(order, sorted);
+    while let Some(front) = q.peek().map(|j| (j.0, j.1)) {
+        assert_eq!(
+            q.take_by(|j| (j.0, j.1).cmp(&front)).map(|j| (j.0, j.1)),
+            Some(front)
+        );
+    }
+    assert!(q.is_empty());
+}
+
+#[cfg(all(feature = "map", feature = "queue"))]
+#[test]
+fn test_priority_map() {
+    use crate::RBPriorityMap;
+
+    // Dijkstra over the same graph as test_queue_change_priority,
+    // decreasing each vertex's distance by its key
+    let edges: Vec<Vec<(usize, u32)>> = vec![
+        vec![(1, 7), (2, 9), (5, 14)],
+        vec![(0, 7), (2, 10), (3, 15)],
+        vec![(0, 9), (1, 10), (3, 11), (5, 2)],
+        vec![(1, 15), (2, 11), (4, 6)],
+        vec![(3, 6), (5, 9)],
+        vec![(0, 14), (2, 2), (4, 9)],
+    ];
+    let mut dist = vec![u32::MAX; 6];
+    let mut frontier = RBPriorityMap::new();
+    frontier.push(0, 0);
+    while let Some((v, d)) = frontier.pop() {
+        dist[v] = d;
+        for &(w, len) in &edges[v] {
+            if dist[w] != u32::MAX {
+                continue;
+            }
+            match frontier.get_priority(&w) {
+                Some(&old) if old <= d + len => {}
+                Some(_) => {
+                    frontier.change_priority(&w, d + len);
+                }
+                None => {
+                    frontier.push(w, d + len);
+                }
+            }
+        }
+        assert_eq!(frontier.len(), frontier.iter().count());
+    }
+    assert_eq!(dist, vec![0, 7, 9, 20, 20, 11]);
+    assert!(frontier.is_empty());
+
+    let mut map: RBPriorityMap<String, f64> = vec![
+        ("b".to_string(), 2.5),
+        ("a".to_string(), 2.5),
+        ("c".to_string(), -1.0),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(format!("{:?}", map), r#"{"c": -1.0, "a": 2.5, "b": 2.5}"#);
+    assert_eq!(map.push("c".to_string(), 3.0), Some(-1.0));
+    assert_eq!(map.len(), 3);
+    assert_eq!(map.peek(), Some((&"a".to_string(), &2.5)));
+    assert_eq!(map.remove("a"), Some(2.5));
+    assert_eq!(map.remove("a"), None);
+    assert_eq!(map.change_priority("a", 0.0), None);
+    assert!(!map.contains_key("a"));
+    assert_eq!(map.change_priority("c", 0.5), Some(3.0));
+    let clone = map.clone();
+    assert_eq!(clone, map);
+    assert!(map.iter().rev().map(|(k, _)| k.as_str()).eq(vec!["b", "c"]));
+    assert_eq!(map.pop(), Some(("c".to_string(), 0.5)));
+    assert_ne!(clone, map);
+    map.clear();
+    assert!(map.is_empty());
+    assert_eq!(map.pop(), None);
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_queue_push_pop() {
+    use crate::RBQueue;
+
+    // checked against inserting and popping separately
+    let mut fast = RBQueue::<i32>::default();
+    let mut slow = RBQueue::<i32>::default();
+    fast.extend(vec![10, 20, 30]);
+    slow.extend(vec![10, 20, 30]);
+    for v in [5, 10, 15, 20, 25, 40, 25, 26, 1, 30, 35] {
+        slow.insert(v);
+        let expected = slow.pop().unwrap();
+        assert_eq!(fast.push_pop(v), expected);
+        assert!(fast.iter().eq(slow.iter()));
+    }
+    // pushing an element already queued shrank the queue
+    assert!(fast.len() < 3);
+    fast.extend(vec![10, 20]);
+
+    let mut slow = fast.clone();
+    for v in [5, 40, 36, 38, 38, 50, 0] {
+        let expected = slow.pop();
+        slow.insert(v);
+        assert_eq!(fast.pop_push(v), expected);
+        assert!(fast.iter().eq(slow.iter()));
+    }
+    // the tree holds up after values were swapped in place
+    let mut expected: Vec<_> = fast.iter().copied().collect();
+    assert!(fast.remove(&expected[1]));
+    expected.remove(1);
+    assert!(fast.insert(-1));
+    expected.insert(0, -1);
+    assert!(fast.iter().copied().eq(expected));
+
+    let mut empty = RBQueue::<i32>::default();
+    assert_eq!(empty.push_pop(3), 3);
+    assert!(empty.is_empty());
+    assert_eq!(empty.pop_push(3), None);
+    assert_eq!(empty.ordered(), vec![&3]);
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_queue_eq() {
+    use crate::{MaxQueue, RBQueue, Reversed, TotalOrder};
+
+    let by_len = |l: &&str, r: &&str| l.len().cmp(&r.len()).then(l.cmp(r));
+    let mut words = RBQueue::new(by_len);
+    words.extend(vec!["ccc", "b", "aa"]);
+    let mut sorted = RBQueue::<&str>::default();
+    sorted.extend(vec!["aa", "ccc", "b"]);
+    // the same elements in a different order
+    assert_ne!(words, sorted);
+    // a different closure giving the same order
+    let mut by_len_desc = RBQueue::new(|l: &&str, r: &&str| r.len().cmp(&l.len()).then(r.cmp(l)));
+    by_len_desc.extend(vec!["b", "ccc", "aa"]);
+    assert_ne!(words, by_len_desc);
+    let flipped: Vec<&str> = by_len_desc.into_iter().rev().collect();
+    let mut by_len_again = RBQueue::new(|l: &&str, r: &&str| (l.len(), *l).cmp(&(r.len(), *r)));
+    by_len_again.extend(flipped);
+    assert_eq!(words, by_len_again);
+    assert_eq!(by_len_again, words);
+
+    // the golden state of a queue after some work
+    let mut max: MaxQueue<u32> = RBQueue::max();
+    max.extend(0..10);
+    max.pop_batch(3);
+    let expected = RBQueue::from_iter_with(Reversed(TotalOrder), 0..7);
+    assert_eq!(max, expected);
+    assert!(max == max.clone());
+    max.insert(100);
+    assert_ne!(max, expected);
+    assert_ne!(RBQueue::<u32>::default(), expected);
+    assert_eq!(RBQueue::<u32>::default(), RBQueue::<u32, TotalOrder>::min());
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_top_k() {
+    use crate::TopK;
+
+    // checked against sorting everything offered
+    let stream: Vec<u32> = (0..500).map(|v| (v * 7919) % 1009).collect();
+    let mut top = TopK::new(10);
+    let mut seen = Vec::new();
+    for &v in &stream {
+        let out = top.offer(v);
+        seen.push(v);
+        seen.sort_unstable();
+        seen.dedup();
+        let kept: Vec<u32> = seen.iter().rev().take(10).copied().collect();
+        assert!(top.iter().copied().eq(kept.iter().copied()));
+        // whatever comes back is not kept
+        if let Some(o) = out {
+            assert!(o == v || !kept.contains(&o));
+        }
+    }
+    assert_eq!(top.len(), 10);
+    assert_eq!(top.peek_best(), seen.last());
+    assert_eq!(top.peek_worst(), seen.iter().rev().nth(9));
+    let sorted = top.clone().into_sorted_vec();
+    assert_eq!(sorted, seen[seen.len() - 10..].to_vec());
+    assert_eq!(
+        format!("{:?}", top),
+        format!("{:?}", sorted.iter().rev().collect::<Vec<_>>())
+    );
+
+    let mut none = TopK::new(0);
+    assert_eq!(none.offer('a'), Some('a'));
+    assert!(none.is_empty());
+    assert_eq!(none.k(), 0);
+    assert!(none.into_sorted_vec().is_empty());
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_queue_pop_equal_by() {
+    use crate::RBQueue;
+
+    // events scheduled as (instant, id)
+    let mut events = RBQueue::<(u32, u32)>::default();
+    for id in 0..60 {
+        events.insert(((id * 13) % 6, id));
+    }
+    let mut instants = Vec::new();
+    loop {
+        let group = events.pop_equal_by(|front, e| front.0 == e.0);
+        if group.is_empty() {
+            break;
+        }
+        assert_eq!(group.len(), 10);
+        assert!(group
+            .windows(2)
+            .all(|w| w[0].0 == w[1].0 && w[0].1 < w[1].1));
+        instants.push(group[0].0);
+    }
+    assert_eq!(instants, (0..6).collect::<Vec<_>>());
+    assert!(events.is_empty());
+
+    // the front is always taken, even if `same` rejects it
+    events.extend(vec![(1, 1), (1, 2)]);
+    assert_eq!(events.pop_equal_by(|_, _| false), vec![(1, 1)]);
+    assert_eq!(events.len(), 1);
+
+    // by the queue's own comparator, ties replace each other
+    assert_eq!(events.pop_equal(), vec![(1, 2)]);
+    assert!(events.pop_equal().is_empty());
+    let mut by_instant = RBQueue::new(|l: &(u32, u32), r: &(u32, u32)| l.0.cmp(&r.0));
+    by_instant.extend(vec![(3, 1), (2, 1), (3, 2)]);
+    assert_eq!(by_instant.pop_equal(), vec![(2, 1)]);
+    assert_eq!(by_instant.pop_equal(), vec![(3, 2)]);
+    assert!(by_instant.is_empty());
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_queue_lazy_iter() {
+    use crate::RBQueue;
+
+    let mut q = RBQueue::new(|l: &u32, r| r.cmp(l));
+    for v in 0..1000 {
+        q.insert((v * 389) % 1000);
+        if v % 3 == 0 {
+            q.remove(&((v * 7) % 1000));
+        }
+    }
+    assert!(q.iter().eq(q.ordered().into_iter()));
+    assert_eq!(q.iter().size_hint(), (q.len(), Some(q.len())));
+
+    // taking a few from each end only walks that far
+    let mut it = q.iter();
+    let front: Vec<_> = it.by_ref().take(3).collect();
+    let back: Vec<_> = it.by_ref().rev().take(3).collect();
+    assert_eq!(front, q.ordered()[..3].to_vec());
+    assert_eq!(
+        back,
+        q.ordered().into_iter().rev().take(3).collect::<Vec<_>>()
+    );
+    assert_eq!(it.len(), q.len() - 6);
+    let rest = it.clone().count();
+    assert_eq!(rest, q.len() - 6);
+    assert_eq!(format!("{:?}", it).matches(',').count(), rest - 1);
+}
+
+#[cfg(all(feature = "set", not(feature = "chunked")))]
+#[test]
+fn test_reserve_nodes() {
+    let mut t = RBTree::new();
+    t.reserve_nodes(64);
+    let capacity = t.raw.nodes.capacity();
+    assert!(capacity >= 64);
+    (0..64).for_each(|v| {
+        t.insert(v);
+    });
+    assert_eq!(t.raw.nodes.capacity(), capacity);
+
+    // the slots of removed values count towards the reservation
+    (0..32).for_each(|v| {
+        t.remove(&v);
+    });
+    t.reserve_nodes(32);
+    assert_eq!(t.raw.nodes.capacity(), capacity);
+
+    // a steady cycle of inserts and removes reuses the slots
+    for v in 64..1000 {
+        t.insert(v);
+        t.remove(&(v - 32));
+    }
+    assert_eq!(t.len(), 32);
+    assert_eq!(t.raw.nodes.allocated(), 64);
+    assert_eq!(t.raw.nodes.capacity(), capacity);
+
+    // clearing keeps the pool
+    t.clear();
+    t.reserve_nodes(64);
+    assert_eq!(t.raw.nodes.capacity(), capacity);
+    check_tree(&t.raw.nodes);
+}
+
+#[cfg(all(feature = "set", not(feature = "chunked")))]
+#[test]
+fn test_colour_packed_into_size() {
+    use crate::node::{Index, Innards};
+    use std::mem::size_of;
+
+    // the colour takes no room of its own in a node
+    assert_eq!(
+        size_of::<Innards<u64>>(),
+        size_of::<u64>() + 4 * size_of::<Index>()
+    );
+
+    // and recolouring leaves the sizes intact
+    let mut t: RBTree<u32> = (0..500).collect();
+    (0..500).step_by(3).for_each(|v| {
+        t.remove(&v);
+    });
+    check_tree(&t.raw.nodes);
+    assert_eq!(t.raw.nodes.size(t.raw.nodes.root()), t.len());
+}
+
+#[cfg(all(feature = "set", feature = "chunked"))]
+#[test]
+fn test_chunked_set() {
+    use std::collections::BTreeSet;
+    let mut t = RBTree::new();
+    let mut expected = BTreeSet::new();
+    // scrambled but deterministic, with enough repeats to
+    // remove values from every part of the tree
+    for i in 0..2000u32 {
+        let v = (i * 7919) % 613;
+        if i % 3 == 2 {
+            assert_eq!(t.remove(&v), expected.remove(&v));
+        } else {
+            assert_eq!(t.insert(v), expected.insert(v));
+        }
+        if i % 97 == 0 {
+            t.raw.nodes.check();
+        }
+    }
+    t.raw.nodes.check();
+    assert_eq!(t.len(), expected.len());
+    assert!(t.iter().eq(expected.iter()));
+    assert!(t.iter().rev().eq(expected.iter().rev()));
+    assert!(t.range(100..400).eq(expected.range(100..400)));
+    assert_eq!(
+        t.partition_point(|&v| v < 300),
+        expected.range(300..).next()
+    );
+    assert_eq!(t.search_by(|v| v.cmp(&301)), expected.get(&301));
+
+    t.retain(|v| v % 5 != 0);
+    expected.retain(|v| v % 5 != 0);
+    t.raw.nodes.check();
+    assert!(t.iter().eq(expected.iter()));
+    #[cfg(feature = "serde")]
+    {
+        // the counts are kept up to date after loading
+        let mut t_serde: RBTree<u32> =
+            serde_json::from_str(serde_json::to_string(&t).unwrap().as_str()).unwrap();
+        t_serde.insert(1000);
+        assert!(t_serde.remove(t.iter().next().unwrap()));
+        t_serde.raw.nodes.check();
+        assert_eq!(t_serde.len(), t.len());
+    }
+    while let Some(v) = t.pop_back() {
+        assert_eq!(Some(v), expected.pop_last());
+    }
+    assert_eq!(t.raw.nodes.chunk_count(), 0);
+}
+
+#[cfg(all(feature = "set", feature = "chunked"))]
+#[test]
+fn test_chunked_from_sorted() {
+    let mut t: RBTree<u32> = (0..100).map(|v| v * 2).collect();
+    t.raw.nodes.check();
+    // built from full chunks
+    assert_eq!(t.raw.nodes.chunk_count(), 13);
+
+    // values between those of a full chunk split it, while
+    // those past its ends go beside it
+    for v in (0..100).map(|v| v * 2 + 1) {
+        t.insert(v);
+    }
+    t.insert(1000);
+    t.raw.nodes.check();
+    assert!(t.iter().copied().eq((0..200).chain(Some(1000))));
+}
+
+#[cfg(all(feature = "map", feature = "chunked"))]
+#[test]
+fn test_chunked_map() {
+    use std::collections::BTreeMap;
+    let mut map = RBMap::new();
+    let mut expected = BTreeMap::new();
+    for i in 0..1500u32 {
+        let k = (i * 113) % 500;
+        if i % 4 == 3 {
+            assert_eq!(map.remove(&k), expected.remove(&k));
+        } else {
+            *map.entry(k).or_insert(0) += i;
+            *expected.entry(k).or_insert(0) += i;
+        }
+    }
+    check_tree(&map.map.raw.nodes);
+    assert!(map.iter().eq(expected.iter()));
+    for (n, k) in expected.keys().enumerate() {
+        assert_eq!(map.rank(k), Some(n));
+        assert_eq!(map.nth(n).map(|(k, _)| k), Some(k));
+    }
+    for (_, v) in map.range_mut(100..200) {
+        *v += 1;
+    }
+    for (_, v) in expected.range_mut(100..200) {
+        *v += 1;
+    }
+    assert!(map.iter().eq(expected.iter()));
+
+    // removals from the middle of a chunk
+    // move the values after them along
+    map.retain_map(|k, v| if k % 3 == 0 { None } else { Some(v * 2) });
+    expected.retain(|k, _| k % 3 != 0);
+    expected.values_mut().for_each(|v| *v *= 2);
+    check_tree(&map.map.raw.nodes);
+    assert!(map.iter().eq(expected.iter()));
+
+    let k = *expected.keys().nth(10).unwrap();
+    assert_eq!(map.replace_key(&k, 1000), Ok(()));
+    let v = expected.remove(&k).unwrap();
+    expected.insert(1000, v);
+    check_tree(&map.map.raw.nodes);
+    assert!(map.iter().eq(expected.iter()));
+}
+
+#[cfg(all(feature = "queue", feature = "chunked"))]
+#[test]
+fn test_chunked_queue() {
+    use crate::RBQueue;
+    let queue_of = |values: std::ops::Range<i32>| {
+        let mut q = RBQueue::min();
+        q.extend(values);
+        q
+    };
+    let mut q = queue_of(0..50);
+    // appended past either end, value by value
+    q.append(queue_of(50..100));
+    q.append(queue_of(-50..0));
+    q.raw.nodes.check();
+    assert!(q.iter().copied().eq(-50..100));
+    assert_eq!(q.at(120), Some(&70));
+    assert_eq!(q.rank(&70), Some(120));
+    for v in -50..100 {
+        assert_eq!(q.pop(), Some(v));
+    }
+    assert_eq!(q.pop(), None);
+}
+
+#[cfg(all(feature = "set", not(feature = "chunked")))]
+#[test]
+fn test_root_ref() {
+    use crate::rbtree::{Colour, NodeRef};
+
+    // collects the elements in order, checking the colours
+    // and depths along the way; returns the black height
+    fn walk<'a>(node: Option<NodeRef<'a, u32>>, depth: usize, out: &mut Vec<&'a u32>) -> usize {
+        let n = match node {
+            Some(n) => n,
+            None => return 1,
+        };
+        assert_eq!(n.depth(), depth);
+        if n.colour() == Colour::Red {
+            let red = |c: Option<NodeRef<u32>>| matches!(c.map(|c| c.colour()), Some(Colour::Red));
+            assert!(!red(n.left()) && !red(n.right()));
+        }
+        let height = walk(n.left(), depth + 1, out);
+        out.push(n.value());
+        assert_eq!(height, walk(n.right(), depth + 1, out));
+        height + (n.colour() == Colour::Black) as usize
+    }
+
+    let t: RBTree<u32> = RBTree::new();
+    assert!(t.root_ref().is_none());
+
+    let mut t = RBTree::new();
+    for i in 0..500u32 {
+        t.insert((i * 7919) % 1000);
+    }
+    (0..1000).step_by(7).for_each(|v| {
+        t.remove(&v);
+    });
+    let root = t.root_ref().unwrap();
+    assert_eq!(root.colour(), Colour::Black);
+    let mut out = Vec::new();
+    walk(Some(root), 0, &mut out);
+    assert_eq!(out, t.ordered());
+    let single: RBTree<u32> = vec![1].into_iter().collect();
+    assert_eq!(
+        format!("{:?}", single.root_ref().unwrap()),
+        "NodeRef { value: 1, colour: Black, depth: 0 }"
+    );
+}
+
+#[cfg(all(
+    feature = "set",
+    feature = "debug_invariants",
+    not(feature = "chunked")
+))]
+#[cfg(feature = "set")]
+#[test]
+#[should_panic(expected = "red-black invariant violated")]
+fn test_debug_invariants_colours() {
+    let mut t = RBTree::new();
+    t.insert(2);
+    t.insert(3);
+    t.insert(1);
+    // unbalances the black heights beneath the root
+    t.raw.nodes.view_mut().get_right_mut().swap_colour();
+    t.insert(4);
+}
+
+#[cfg(all(feature = "set", feature = "debug_invariants"))]
+#[test]
+#[should_panic(expected = "ordering invariant violated")]
+fn test_debug_invariants_ordering() {
+    let mut t: RBTree<_> = (0..20).collect();
+    let first = t.raw.nodes.first();
+    *t.raw.nodes.value_mut(first).unwrap() = 50;
+    t.insert(30);
+}
+
+#[cfg(feature = "test_util")]
+#[test]
+fn test_shadow_models() {
+    use crate::test_util::{Churn, MapOp, SetOp, ShadowMap, ShadowSet};
+
+    let mut map = ShadowMap::new();
+    map.run(Churn::new(38, 7, 14).take(5000));
+    map.run(vec![
+        MapOp::Insert(1, 1),
+        MapOp::Insert(1, 2),
+        MapOp::Get(1),
+    ]);
+    map.apply(MapOp::Clear);
+    assert!(map.map().is_empty());
+
+    let mut set = ShadowSet::new();
+    set.run(Churn::new(38, 50, 200).take(5000).map(SetOp::from));
+    let len = set.set().len();
+    assert!((50..=200).contains(&len));
+    assert_eq!(set.into_inner().len(), len);
+
+    // a churn never removes keys it hasn't inserted
+    let mut live = std::collections::BTreeSet::new();
+    for op in Churn::new(5, 0, 3).take(1000) {
+        match op {
+            MapOp::Insert(k, _) => {
+                live.insert(k);
+            }
+            MapOp::Remove(k) | MapOp::Get(k) => assert!(live.contains(&k)),
+            MapOp::PopFirst => assert!(live.pop_first().is_some()),
+            MapOp::PopLast => assert!(live.pop_last().is_some()),
+            MapOp::Clear => live.clear(),
+        }
+        if let MapOp::Remove(k) = op {
+            live.remove(&k);
+        }
+        assert!(live.len() <= 3);
+    }
+}
+
+#[cfg(feature = "test_util")]
+#[test]
+#[should_panic(expected = "but the model holds")]
+fn test_shadow_model_mismatch() {
+    use crate::test_util::{MapOp, ShadowMap};
+
+    let mut map = ShadowMap::new();
+    map.apply(MapOp::Insert(1, 1));
+    map.map.insert(2, 2);
+    map.apply(MapOp::Get(1));
+}
+
+#[cfg(feature = "persistent")]
+#[test]
+fn test_snapshot_map() {
+    use crate::SnapshotMap;
+    use std::thread;
+
+    // every version the writer makes holds the keys 0..n
+    let map = SnapshotMap::new();
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let mut reader = map.reader();
+            thread::spawn(move || {
+                let mut last = 0;
+                while last < 500 {
+                    let version = reader.snapshot();
+                    let len = version.len();
+                    assert!(version.keys().copied().eq(0..len));
+                    assert!(len >= last);
+                    last = len;
+                }
+                reader.version()
+            })
+        })
+        .collect();
+    let writer = map.clone();
+    thread::spawn(move || {
+        for k in 0..500 {
+            writer.insert(k, k);
+        }
+    })
+    .join()
+    .unwrap();
+    for r in readers {
+        assert_eq!(r.join().unwrap(), 500);
+    }
+    assert_eq!(map.version(), 500);
+
+    // a panicking writer leaves the map as it was
+    let writer = map.clone();
+    let res = thread::spawn(move || {
+        writer.update(|m| {
+            m.remove_in_place(&0);
+            panic!("abandoned");
+        })
+    })
+    .join();
+    assert!(res.is_err());
+    assert_eq!(map.snapshot().len(), 500);
+    assert_eq!(map.version(), 500);
+    assert_eq!(map.insert(0, 1), Some((0, 0)));
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_error_categories() {
+    use crate::{AllocError, CompareError, Error, UnsortedError};
+    use std::error::Error as _;
+
+    let mut m = RBMap::new();
+    assert_eq!(m.try_insert_new(2.0, 'a'), Ok(()));
+    assert_eq!(m.try_insert_new(2.0, 'b'), Err(Error::OccupiedKey));
+    assert_eq!(
+        m.try_insert_new(f64::NAN, 'c'),
+        Err(Error::IncomparableElement)
+    );
+    assert_eq!(m.get(&2.0), Some(&'a'));
+    assert_eq!(m.len(), 1);
+
+    let mut t = RBTree::new();
+    t.insert(1);
+    match t.try_reserve(usize::MAX) {
+        Err(Error::CapacityExceeded) | Err(Error::AllocError(_)) => (),
+        other => panic!("expected an allocation failure, got {:?}", other),
+    }
+    assert_eq!(t.len(), 1);
+
+    // the specific errors convert into their categories
+    assert_eq!(Error::from(CompareError), Error::IncomparableElement);
+    assert_eq!(
+        Error::from(AllocError::CapacityOverflow),
+        Error::CapacityExceeded
+    );
+    let unsorted = Error::from(UnsortedError { position: 3 });
+    assert_eq!(
+        unsorted,
+        Error::CorruptInvariant(UnsortedError { position: 3 }.to_string())
+    );
+    assert!(unsorted.to_string().contains("element 3"));
+    assert!(unsorted.source().is_none());
+    let failed = vec![0u8].try_reserve(usize::MAX).unwrap_err();
+    assert!(Error::from(AllocError::ReserveFailed(failed))
+        .source()
+        .is_some());
+}
+
+#[cfg(all(feature = "rayon", feature = "map"))]
+#[test]
+fn test_par_bulk_build() {
+    let values: Vec<u32> = (0..10_000u32)
+        .map(|i| i.wrapping_mul(7919) % 5_000)
+        .collect();
+    let t = RBTree::par_from_slice(&values);
+    let expected: RBTree<u32> = values.iter().copied().collect();
+    assert_eq!(t.len(), expected.len());
+    assert!(t.iter().eq(expected.iter()));
+    assert!(RBTree::<u32>::par_from_slice(&[]).is_empty());
+
+    // the last of equal elements is kept, as with insertion
+    #[derive(Clone, Debug)]
+    struct Tagged(u32, usize);
+    impl PartialEq for Tagged {
+        fn eq(&self, other: &Self) -> bool {
+            self.0 == other.0
+        }
+    }
+    impl PartialOrd for Tagged {
+        fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+            self.0.partial_cmp(&other.0)
+        }
+    }
+    let tagged: Vec<Tagged> = (0..1000).map(|i| Tagged(i % 10, i as usize)).collect();
+    let t = RBTree::par_from_slice(&tagged);
+    assert_eq!(t.len(), 10);
+    assert!(t.iter().all(|v| v.1 >= 990));
+
+    let pairs: Vec<(u32, usize)> = values.iter().enumerate().map(|(i, k)| (*k, i)).collect();
+    let m = RBMap::par_from_pairs(&pairs);
+    let expected: RBMap<u32, usize> = pairs.iter().copied().collect();
+    assert_eq!(m.len(), expected.len());
+    assert!(m.iter().eq(expected.iter()));
+}
+
+#[cfg(all(feature = "rayon", feature = "set"))]
+#[test]
+#[should_panic(expected = "incomparable")]
+fn test_par_bulk_build_incomparable() {
+    RBTree::par_from_slice(&[1.0, f64::NAN, 0.5]);
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_deadline_queue() {
+    use crate::DeadlineQueue;
+    use std::time::{Duration, Instant};
+
+    let start = Instant::now();
+    let at = |ms: u64| start + Duration::from_millis(ms);
+    let mut q = DeadlineQueue::new();
+    for (i, ms) in [50, 10, 30, 10, 40, 10].iter().enumerate() {
+        q.schedule(at(*ms), i);
+    }
+    assert_eq!(q.len(), 6);
+    assert_eq!(q.next_deadline(), Some(at(10)));
+    assert_eq!(q.peek(), Some((at(10), &1)));
+
+    // nothing is due before the earliest deadline
+    assert_eq!(q.pop_due(start), None);
+    // items due together come back in the order scheduled
+    let due: Vec<usize> = std::iter::from_fn(|| q.pop_due(at(30))).collect();
+    assert_eq!(due, vec![1, 3, 5, 2]);
+    assert_eq!(q.next_deadline(), Some(at(40)));
+    assert_eq!(
+        format!("{:?}", q),
+        format!("{:?}", [(at(40), 4), (at(50), 0)])
+    );
+
+    let mut copy = q.clone();
+    assert_eq!(copy.pop_due(at(100)), Some(4));
+    assert_eq!(copy.pop_due(at(100)), Some(0));
+    assert!(copy.is_empty());
+    assert_eq!(q.len(), 2);
+
+    q.clear();
+    assert_eq!(q.next_deadline(), None);
+    assert_eq!(q.pop_due(at(100)), None);
+}
+
+#[cfg(feature = "set")]
+#[test]
+fn test_sorted_list() {
+    use crate::SortedList;
+
+    // checked against a vec kept sorted, inserting
+    // each value after those equal to it
+    let mut list = SortedList::new();
+    let mut model: Vec<(u32, usize)> = Vec::new();
+    let mut state = 0x2545_f491u64;
+    for i in 0..3000 {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let key = (state % 64) as u32;
+        match state % 5 {
+            0 if !model.is_empty() => {
+                let index = (state >> 8) as usize % model.len();
+                assert_eq!(list.remove_at(index), Some(Tagged(model.remove(index))));
+            }
+            1 => {
+                let expected = model
+                    .iter()
+                    .position(|v| v.0 == key)
+                    .map(|p| model.remove(p));
+                assert_eq!(list.remove(&Tagged((key, 0))), expected.map(Tagged));
+            }
+            _ => {
+                let pos = model.partition_point(|v| v.0 <= key);
+                model.insert(pos, (key, i));
+                assert_eq!(list.insert(Tagged((key, i))), pos);
+            }
+        }
+        let probe = Tagged((key, 0));
+        assert_eq!(list.len(), model.len());
+        assert_eq!(
+            list.lower_bound(&probe),
+            model.partition_point(|v| v.0 < key)
+        );
+        assert_eq!(
+            list.upper_bound(&probe),
+            model.partition_point(|v| v.0 <= key)
+        );
+        assert_eq!(
+            list.count(&probe),
+            model.iter().filter(|v| v.0 == key).count()
+        );
+        assert_eq!(list.rank(&probe), model.iter().position(|v| v.0 == key));
+    }
+    assert!(list.iter().map(|t| t.0).eq(model.iter().copied()));
+    for (index, v) in model.iter().enumerate() {
+        assert_eq!(list.at(index).map(|t| t.0), Some(*v));
+    }
+    assert_eq!(list.at(model.len()), None);
+    assert!(list
+        .slice(10..40)
+        .map(|t| t.0)
+        .eq(model[10..40].iter().copied()));
+    assert!(list
+        .slice(model.len() - 3..)
+        .map(|t| t.0)
+        .eq(model[model.len() - 3..].iter().copied()));
+    assert_eq!(list.slice(5..5).next(), None);
+    assert_eq!(list.slice(model.len()..).next(), None);
+
+    // equal values come back out in the order they went in
+    let first = model.iter().find(|v| v.0 == 7).copied();
+    assert_eq!(list.remove(&Tagged((7, 0))).map(|t| t.0), first);
+    let removed = list.remove_all(&Tagged((9, 0)));
+    assert_eq!(removed, model.iter().filter(|v| v.0 == 9).count());
+    assert!(!list.contains(&Tagged((9, 0))));
+
+    let collected: SortedList<_> = vec![3, 1, 3, 2, 1].into_iter().collect();
+    assert_eq!(format!("{:?}", collected), "[1, 1, 2, 3, 3]");
+    let mut extended = SortedList::new();
+    extended.extend(vec![3, 1, 3, 2, 1]);
+    assert_eq!(collected, extended);
+    assert_eq!(
+        extended.range(2..).copied().collect::<Vec<_>>(),
+        vec![2, 3, 3]
+    );
+    assert_eq!(
+        extended.into_iter().collect::<Vec<_>>(),
+        vec![1, 1, 2, 3, 3]
+    );
+
+    // compares on the key only, so that values equal
+    // to each other can still be told apart
+    #[derive(Debug)]
+    struct Tagged((u32, usize));
+    impl PartialEq for Tagged {
+        fn eq(&self, other: &Self) -> bool {
+            self.0 == other.0
+        }
+    }
+    impl PartialOrd for Tagged {
+        fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+            (self.0).0.partial_cmp(&(other.0).0)
+        }
+    }
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_range_map() {
+    use crate::RangeMap;
+
+    // checked against the value of each key in a small domain
+    let mut map = RangeMap::new();
+    let mut model: Vec<Option<u8>> = vec![None; 64];
+    let mut state = 0x9e37_79b9u64;
+    for _ in 0..2000 {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let a = (state % 65) as u32;
+        let b = ((state >> 8) % 65) as u32;
+        let val = ((state >> 16) % 3) as u8;
+        if (state >> 24) & 3 == 0 {
+            map.remove(a..b);
+            (a..b).for_each(|k| model[k as usize] = None);
+        } else {
+            map.insert(a..b, val);
+            (a..b).for_each(|k| model[k as usize] = Some(val));
+        }
+        for (k, expected) in model.iter().enumerate() {
+            assert_eq!(map.get(&(k as u32)), expected.as_ref());
+        }
+        // no range is empty, and neighbours that meet
+        // always hold different values
+        let ranges: Vec<_> = map.iter().collect();
+        for (range, _) in ranges.iter() {
+            assert!(range.start < range.end);
+        }
+        for pair in ranges.windows(2) {
+            assert!(pair[0].0.end <= pair[1].0.start);
+            assert!(pair[0].0.end < pair[1].0.start || pair[0].1 != pair[1].1);
+        }
+        assert_eq!(map.len(), ranges.len());
+    }
+
+    let mut times: RangeMap<f64, &str> = vec![(0.0..1.5, "a"), (1.5..3.0, "a"), (4.0..5.0, "b")]
+        .into_iter()
+        .collect();
+    assert_eq!(times.len(), 2);
+    assert_eq!(times.get_key_value(&2.0), Some((&0.0..&3.0, &"a")));
+    assert_eq!(times.get(&3.0), None);
+    times.insert(2.0..4.5, "c");
+    assert_eq!(
+        format!("{:?}", times),
+        "{0.0..2.0: \"a\", 2.0..4.5: \"c\", 4.5..5.0: \"b\"}"
+    );
+    times.remove(1.0..10.0);
+    assert_eq!(times.iter().next_back(), Some((&0.0..&1.0, &"a")));
+    // empty and backwards ranges change nothing
+    times.insert(3.0..3.0, "d");
+    times.insert(3.0..2.0, "d");
+    times.remove(0.5..0.5);
+    assert_eq!(times.len(), 1);
+    assert!(times.contains_key(&0.5));
+    let copy = times.clone();
+    times.clear();
+    assert!(times.is_empty());
+    assert_ne!(copy, times);
+}
+
+#[cfg(all(feature = "set", not(feature = "chunked")))]
+#[test]
+fn test_shrink_to_fit() {
+    let mut t: RBTree<usize> = (0..1000).collect();
+    let capacity = t.raw.nodes.capacity();
+    // leave the survivors scattered across the arena
+    for v in 0..1000 {
+        if v % 37 != 5 {
+            t.remove(&v);
+        }
+    }
+    let kept: Vec<usize> = t.iter().copied().collect();
+    assert_eq!(t.raw.nodes.allocated(), 1000);
+    t.shrink_to_fit();
+    assert_eq!(t.raw.nodes.allocated(), kept.len());
+    assert!(t.raw.nodes.capacity() < capacity);
+    assert!(t.iter().copied().eq(kept.iter().copied()));
+    check_tree(&t.raw.nodes);
+
+    // the tree is as usable as before, including
+    // appending values greater than all it holds
+    t.insert(2000);
+    t.insert(3);
+    assert_eq!(t.peek_back(), Some(&2000));
+    assert_eq!(t.len(), kept.len() + 2);
+    check_tree(&t.raw.nodes);
+
+    // nothing to give back once compact
+    t.shrink_to_fit();
+    assert_eq!(t.raw.nodes.allocated(), t.len());
+    for v in kept.iter().rev() {
+        assert!(t.remove(v));
+        check_tree(&t.raw.nodes);
+    }
+    assert!(t.iter().copied().eq(vec![3, 2000]));
+    t.clear();
+    t.shrink_to_fit();
+    assert_eq!(t.raw.nodes.capacity(), 0);
+}
+
+#[cfg(feature = "set")]
+#[test]
+fn test_binary_search() {
+    let mut t = RBTree::new();
+    assert_eq!(t.binary_search(&1), Err(0));
+
+    // agrees with searching a sorted vec of the same values
+    let mut state = 0x2545_f491u64;
+    let mut values = Vec::new();
+    for _ in 0..500 {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let v = (state % 2000) as u32 * 2;
+        if t.insert(v) {
+            values.push(v);
+        }
+    }
+    values.sort_unstable();
+    for probe in 0..4002 {
+        assert_eq!(t.binary_search(&probe), values.binary_search(&probe));
+    }
+
+    // the positions follow a custom ordering
+    let mut rev = RBTree::with_comparator(|l: &u8, r: &u8| r.cmp(l));
+    rev.extend(vec![1, 5, 3]);
+    assert_eq!(rev.binary_search(&5), Ok(0));
+    assert_eq!(rev.binary_search(&4), Err(1));
+    assert_eq!(rev.binary_search(&0), Err(3));
+}
+
+#[cfg(feature = "set")]
+#[test]
+fn test_select_range() {
+    use std::ops::Bound::{Excluded, Included, Unbounded};
+
+    let t: RBTree<u32> = (0..300).map(|v| v * 3).collect();
+    let all: Vec<u32> = t.iter().copied().collect();
+    for start in (0..310).step_by(7) {
+        for end in (start..310).step_by(11) {
+            let expected: Vec<u32> = all.iter().copied().skip(start).take(end - start).collect();
+            assert!(t
+                .select_range(start..end)
+                .copied()
+                .eq(expected.iter().copied()));
+            assert_eq!(
+                t.select_range((Excluded(start), Included(end))).count(),
+                all.len().min(end + 1).saturating_sub(start + 1)
+            );
+        }
+        assert_eq!(
+            t.select_range((Included(start), Unbounded)).count(),
+            all.len().saturating_sub(start)
+        );
+    }
+    assert_eq!(t.select_range((Included(5), Excluded(2))).next(), None);
+    assert_eq!(t.select_range(..usize::MAX).count(), 300);
+    assert_eq!(t.select_range(..=usize::MAX).count(), 300);
+    assert_eq!(RBTree::<u8>::new().select_range(..).next(), None);
+}
+
+#[cfg(feature = "set")]
+#[test]
+fn test_remove_sorted() {
+    use std::collections::BTreeSet;
+
+    let mut state = 0x8f3a_1c2du64;
+    let mut next = move |n: u64| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state % n
+    };
+    let mut t: RBTree<u64> = (0..3000).map(|_| next(5000)).collect();
+    let mut model: BTreeSet<u64> = t.iter().copied().collect();
+    for round in 0..20 {
+        // batches of every size, with repeats and absent keys
+        let mut keys: Vec<u64> = (0..round * 40).map(|_| next(5200)).collect();
+        keys.sort_unstable();
+        let expected = keys.iter().filter(|k| model.remove(k)).count();
+        assert_eq!(t.remove_sorted(keys), expected);
+        assert_eq!(t.len(), model.len());
+        assert!(t.iter().eq(model.iter()));
+        check_tree(&t.raw.nodes);
+    }
+
+    // every other element, then the rest
+    let all: Vec<u64> = model.iter().copied().collect();
+    assert_eq!(
+        t.remove_sorted(all.iter().copied().step_by(2)),
+        all.len() - all.len() / 2
+    );
+    assert!(t.iter().copied().eq(all.iter().copied().skip(1).step_by(2)));
+    let remaining = t.len();
+    assert_eq!(t.remove_sorted(all), remaining);
+    assert!(t.is_empty());
+    assert_eq!(t.remove_sorted(vec![1, 2, 3]), 0);
+}
+
+#[cfg(feature = "set")]
+#[test]
+fn test_get_all() {
+    let t: RBTree<u32> = (0..2000).map(|v| v * 3).collect();
+    let mut state = 0x51ed_270bu64;
+    let keys: Vec<u32> = (0..5000)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % 6100) as u32
+        })
+        .collect();
+    let found = t.get_all(keys.clone());
+    assert_eq!(found.len(), keys.len());
+    for (key, item) in keys.iter().zip(found) {
+        assert_eq!(item, t.get(key));
+    }
+
+    let mut rev = RBTree::with_comparator(|l: &u8, r: &u8| r.cmp(l));
+    rev.extend(vec![2, 4, 6]);
+    assert!(rev
+        .get_all(vec![6, 5, 2, 6])
+        .rev()
+        .eq(vec![Some(&6), Some(&2), None, Some(&6)]));
+    assert_eq!(RBTree::<u8>::new().get_all(vec![1]).next(), Some(None));
+    assert_eq!(t.get_all(Vec::<u32>::new()).next(), None);
+}
+
+#[cfg(feature = "set")]
+#[test]
+fn test_filter_map() {
+    use std::collections::BTreeSet;
+
+    let t: RBTree<i64> = (-500..500).map(|v| v * 7).collect();
+
+    // order preserving, built from the sorted values
+    let kept = t
+        .clone()
+        .filter_map(|v| if v % 3 == 0 { Some(v * 2 + 1) } else { None });
+    let model: BTreeSet<i64> = t
+        .iter()
+        .filter(|v| *v % 3 == 0)
+        .map(|v| v * 2 + 1)
+        .collect();
+    assert!(kept.iter().eq(model.iter()));
+    check_tree(&kept.raw.nodes);
+
+    // order reversing, with values colliding
+    let flipped = t.clone().filter_map(|v| Some(-(v / 10)));
+    let model: BTreeSet<i64> = t.iter().map(|v| -(v / 10)).collect();
+    assert_eq!(flipped.len(), model.len());
+    assert!(flipped.iter().eq(model.iter()));
+    check_tree(&flipped.raw.nodes);
+
+    // the new tree takes the natural order, whatever
+    // the order of the one consumed
+    let mut desc = RBTree::with_comparator(|l: &&str, r: &&str| r.cmp(l));
+    desc.extend(vec!["bb", "a", "cc", "ddd"]);
+    let lens = desc.filter_map(|s| Some(s.len()).filter(|n| *n > 1));
+    assert_eq!(lens.ordered(), vec![&2, &3]);
+
+    assert!(t.filter_map(|_| None::<u8>).is_empty());
+}
+
+#[cfg(feature = "set")]
+#[test]
+fn test_map_monotone() {
+    let t: RBTree<u32> = (0..5000).map(|v| v * 2).collect();
+    let mut encoded = t.clone().map_monotone(|v| format!("{:08x}", v));
+    assert_eq!(encoded.len(), t.len());
+    assert!(encoded.iter().eq(t
+        .iter()
+        .map(|v| format!("{:08x}", v))
+        .collect::<Vec<_>>()
+        .iter()));
+    check_tree(&encoded.raw.nodes);
+
+    // the new tree carries on as any other
+    assert!(encoded.contains(&format!("{:08x}", 4096)));
+    assert!(!encoded.contains(&format!("{:08x}", 4097)));
+    assert!(encoded.insert(format!("{:08x}", 4097)));
+    assert!(encoded.remove(&format!("{:08x}", 0)));
+    assert_eq!(encoded.peek().map(String::as_str), Some("00000002"));
+    assert_eq!(encoded.len(), t.len());
+    check_tree(&encoded.raw.nodes);
+
+    // the order of the tree consumed is kept as is
+    let mut desc = RBTree::with_comparator(|l: &i32, r: &i32| r.cmp(l));
+    desc.extend(vec![3, 1, 2]);
+    let negated = desc.map_monotone(|v| -v);
+    assert_eq!(negated.ordered(), vec![&-3, &-2, &-1]);
+    assert!(RBTree::<u8>::new().map_monotone(|v| v).is_empty());
+}
+
+#[cfg(all(feature = "set", feature = "debug_invariants"))]
+#[test]
+#[should_panic(expected = "ordering invariant violated")]
+fn test_map_monotone_unordered() {
+    let t: RBTree<i32> = (0..20).collect();
+    t.map_monotone(|v| -v);
+}
+
+#[cfg(all(feature = "queue", feature = "map"))]
+#[test]
+fn test_queue_split_off() {
+    use crate::rbqueue::BoundPolicy;
+    use crate::RBQueue;
+
+    let values: Vec<u32> = (0..600).map(|v| (v * 7919) % 1000).collect();
+    let mut sorted = values.clone();
+    sorted.sort_unstable();
+    sorted.dedup();
+    for at in (0..1001).step_by(50) {
+        let mut q = RBQueue::<u32>::default();
+        q.extend(values.iter().copied());
+        let mut tail = q.split_off(&at);
+        let (head, rest): (Vec<u32>, Vec<u32>) = sorted.iter().partition(|v| **v < at);
+        assert!(q.iter().copied().eq(head.iter().copied()));
+        assert!(tail.iter().copied().eq(rest.iter().copied()));
+        check_tree(&q.raw.nodes);
+        check_tree(&tail.raw.nodes);
+
+        // both halves carry on as queues of their own
+        tail.insert(0);
+        assert_eq!(tail.peek(), Some(&0));
+        q.insert(2000);
+        assert_eq!(q.peek_back(), Some(&2000));
+    }
+
+    // every place in queues of many shapes, splitting
+    // off the shorter part and the longer
+    for n in (0..40u32).chain((40..700).step_by(97)) {
+        for at in (0..=n).step_by(1 + n as usize / 20) {
+            let mut q = RBQueue::<u32>::default();
+            q.extend((0..n).rev());
+            let tail = q.split_off(&at);
+            assert!(q.iter().copied().eq(0..at));
+            assert!(tail.iter().copied().eq(at..n));
+            assert_eq!((q.len(), tail.len()), (at as usize, (n - at) as usize));
+            check_tree(&q.raw.nodes);
+            check_tree(&tail.raw.nodes);
+        }
+    }
+
+    // the split follows the queue's own order
+    let mut desc = RBQueue::new(|l: &u32, r: &u32| r.cmp(l));
+    desc.extend(1..=10);
+    let low = desc.split_off(&4);
+    assert!(desc.iter().copied().eq((5..=10).rev()));
+    assert!(low.iter().copied().eq((1..=4).rev()));
+
+    let mut bounded = RBQueue::<u8>::default().bounded(4, BoundPolicy::EvictWorst);
+    bounded.extend(vec![1, 2, 3, 4]);
+    let mut tail = bounded.split_off(&3);
+    assert_eq!(bounded.ordered(), [&1, &2]);
+    assert_eq!(bounded.bound(), Some(4));
+    assert_eq!(tail.bound(), None);
+    tail.extend(vec![5, 6, 7]);
+    assert_eq!(tail.len(), 5);
+    assert!(RBQueue::<u8>::default().split_off(&0).is_empty());
+}
+
+#[cfg(feature = "async")]
+fn block_on<F: std::future::Future>(f: F) -> F::Output {
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct Unpark(std::thread::Thread);
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+    let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut f = Box::pin(f);
+    loop {
+        if let Poll::Ready(v) = f.as_mut().poll(&mut cx) {
+            return v;
+        }
+        std::thread::park();
+    }
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_async_queue() {
+    use crate::AsyncRBQueue;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    // every element pushed goes to exactly one waiting consumer
+    let q = AsyncRBQueue::<u32>::new();
+    let consumers: Vec<_> = (0..4)
+        .map(|_| {
+            let q = q.clone();
+            thread::spawn(move || (0..250).map(|_| block_on(q.pop())).collect::<Vec<_>>())
+        })
+        .collect();
+    let producers: Vec<_> = (0..4)
+        .map(|p| {
+            let q = q.clone();
+            thread::spawn(move || (0..250).for_each(|v| assert!(q.push(p * 1000 + v))))
+        })
+        .collect();
+    producers.into_iter().for_each(|p| p.join().unwrap());
+    let mut popped: Vec<u32> = consumers
+        .into_iter()
+        .flat_map(|c| c.join().unwrap())
+        .collect();
+    popped.sort_unstable();
+    let mut expected: Vec<u32> = (0..4)
+        .flat_map(|p| (0..250).map(move |v| p * 1000 + v))
+        .collect();
+    expected.sort_unstable();
+    assert_eq!(popped, expected);
+    assert!(q.is_empty());
+
+    // an earlier deadline pushed while waiting is handed back first
+    let start = Instant::now();
+    let timers = AsyncRBQueue::<(Instant, u32)>::new();
+    timers.push((start + Duration::from_millis(200), 2));
+    let pusher = timers.clone();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        pusher.push((start + Duration::from_millis(40), 1));
+    });
+    let due = |timer: &(Instant, u32)| timer.0;
+    assert_eq!(block_on(timers.pop_due(due)).1, 1);
+    assert!(start.elapsed() < Duration::from_millis(200));
+    assert_eq!(block_on(timers.pop_due(due)).1, 2);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    // elements already due are handed back without waiting
+    timers.push((start, 3));
+    assert_eq!(block_on(timers.pop_due(due)).1, 3);
+    assert_eq!(timers.armed(), 0);
+
+    // a future dropped while waiting cancels its wake-up
+    use std::future::Future;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    struct Ignore;
+    impl Wake for Ignore {
+        fn wake(self: Arc<Self>) {}
+    }
+    let waker = Waker::from(Arc::new(Ignore));
+    let mut cx = Context::from_waker(&waker);
+    timers.push((Instant::now() + Duration::from_secs(60), 4));
+    let mut waits: Vec<_> = (0..3).map(|_| Box::pin(timers.pop_due(due))).collect();
+    for wait in waits.iter_mut() {
+        assert_eq!(wait.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(wait.as_mut().poll(&mut cx), Poll::Pending);
+    }
+    assert_eq!(timers.armed(), 3);
+    waits.pop();
+    assert_eq!(timers.armed(), 2);
+    drop(waits);
+    assert_eq!(timers.armed(), 0);
+    let mut prefilled = crate::RBQueue::<u8>::default();
+    prefilled.extend(vec![2, 1]);
+    let expected = format!("{:?}", prefilled);
+    let shared = AsyncRBQueue::from(prefilled);
+    assert_eq!(format!("{:?}", shared), expected);
+    assert_eq!(shared.try_pop(), Some(1));
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_map_observers() {
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    // a replica kept only by the observers, which also checks
+    // that a pair is removed before another takes its place
+    let replica = Arc::new(Mutex::new(BTreeMap::new()));
+    let mut map: RBMap<u32, u32> = (0..8).map(|k| (k, k)).collect();
+    replica
+        .lock()
+        .unwrap()
+        .extend(map.iter().map(|(k, v)| (*k, *v)));
+    let inserts = replica.clone();
+    map.on_insert(move |k, v| assert_eq!(inserts.lock().unwrap().insert(*k, *v), None));
+    let removals = replica.clone();
+    map.on_remove(move |k, v| assert_eq!(removals.lock().unwrap().remove(k), Some(*v)));
+    let in_step = |map: &RBMap<u32, u32>| {
+        let replica = replica.lock().unwrap();
+        assert!(map.iter().eq(replica.iter()));
+    };
+
+    assert_eq!(map.insert(20, 1), None);
+    assert_eq!(map.insert(20, 2), Some((20, 1)));
+    assert_eq!(map.try_insert(21, 1), Ok(None));
+    assert_eq!(map.try_insert(21, 2), Ok(Some((21, 1))));
+    assert_eq!(map.try_insert_new(21, 3), Err(crate::Error::OccupiedKey));
+    assert_eq!(map.try_insert_alloc(22, 1), Ok(None));
+    in_step(&map);
+    assert_eq!(map.remove(&20), Some(2));
+    assert_eq!(map.remove(&20), None);
+    assert_eq!(map.remove_entry(&21), Some((21, 2)));
+    assert_eq!(map.remove_nth(1), Some((1, 1)));
+    assert_eq!(map.pop(), Some(0));
+    assert_eq!(map.pop_back(), Some(1));
+    assert_eq!(map.pop_pair_if(|k, _| *k == 2), Some((2, 2)));
+    assert_eq!(map.pop_pair_back_if(|k, _| *k == 0), None);
+    assert_eq!(map.pop_pair_while(|k, _| *k < 4).count(), 1);
+    in_step(&map);
+
+    map.extend((10..20).map(|k| (k, k)));
+    map.retain(|k, _| k & 3 != 0);
+    let keys: RBTree<u32> = (5..15).collect();
+    map.retain_keys(&keys);
+    map.remove_keys(&vec![6, 7].into_iter().collect());
+    map.retain_map(|k, v| if k & 1 == 0 { Some(v * 2) } else { None });
+    in_step(&map);
+    assert_eq!(map.update(10, |v| v.map(|v| v + 1)), Some(&mut 21));
+    assert_eq!(map.update(14, |_| None), None);
+    assert_eq!(map.update(30, |_| Some(30)), Some(&mut 30));
+    let other: RBMap<u32, u32> = vec![(10, 1), (31, 31)].into_iter().collect();
+    map.merge_with(other, |_, l, r| l + r);
+    assert_eq!(map.get(&10), Some(&22));
+    in_step(&map);
+
+    map.entry(40).or_insert(40);
+    assert_eq!(*map.get_or_insert_with(41, || 41), 41);
+    assert_eq!(map.entry(40).insert(42), (&40, &mut 42));
+    map.entry_ref(&43).or_default();
+    if let crate::rbmap::Entry::Occupied(mut e) = map.entry(41) {
+        assert_eq!(e.insert(1), 41);
+        assert_eq!(e.remove(), 1);
+    }
+    map.first_entry().unwrap().remove_entry();
+    // updates made through a value in place aren't observed
+    *map.get_mut(&43).unwrap() = 5;
+    replica.lock().unwrap().insert(43, 5);
+    in_step(&map);
+    assert_eq!(map.replace_key(&43, 44), Ok(()));
+    assert_eq!(map.replace_key(&44, 44), Ok(()));
+    in_step(&map);
+
+    // a clone goes unobserved, unlike the original
+    let mut copy = map.clone();
+    copy.clear();
+    in_step(&map);
+    let drained: Vec<_> = map.drain().take(1).collect();
+    assert_eq!(drained.len(), 1);
+    assert!(replica.lock().unwrap().is_empty());
+    map.insert(1, 1);
+    map.clear();
+    in_step(&map);
+    map.clear_observers();
+    map.insert(2, 2);
+    assert!(replica.lock().unwrap().is_empty());
+}
+
+#[cfg(all(feature = "tracing", feature = "set", not(feature = "chunked")))]
+#[test]
+fn test_tracing_spans() {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    // counts the spans and events seen by their names
+    #[derive(Clone, Default)]
+    struct Counter(Arc<Mutex<HashMap<&'static str, usize>>>);
+    impl Counter {
+        fn add(&self, name: &'static str) {
+            *self.0.lock().unwrap().entry(name).or_insert(0) += 1;
+        }
+        fn get(&self, name: &str) -> usize {
+            self.0.lock().unwrap().get(name).copied().unwrap_or(0)
+        }
+    }
+    impl Subscriber for Counter {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            self.add(span.metadata().name());
+            Id::from_u64(1)
+        }
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, event: &Event<'_>) {
+            let mut message = String::new();
+            event.record(&mut |f: &tracing::field::Field, v: &dyn std::fmt::Debug| {
+                if f.name() == "message" {
+                    message = format!("{:?}", v);
+                }
+            });
+            self.add(if message == "rotate" {
+                "rotate"
+            } else {
+                "recolour"
+            });
+        }
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    let counter = Counter::default();
+    tracing::subscriber::with_default(counter.clone(), || {
+        let mut t = RBTree::new();
+        (0..100).for_each(|v| {
+            t.insert(v);
+        });
+        (0..50).for_each(|v| {
+            t.take(&v);
+        });
+    });
+    assert_eq!(counter.get("insert"), 100);
+    assert_eq!(counter.get("remove"), 50);
+    // every insertion may need rebalancing, but only the removal
+    // of a black node can
+    let rebalances = counter.get("rebalance");
+    assert!(100 < rebalances && rebalances < 150);
+    // ascending inserts keep rotating at the right edge
+    assert!(counter.get("rotate") > 0);
+    assert!(counter.get("recolour") > 0);
+}
@@ -0,0 +1,280 @@
+use crate::{PersistentRBMap, SnapshotMap, SnapshotReader};
+
+use arc_swap::ArcSwap;
+use std::fmt::{Debug, Formatter, Result};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+// the state behind every handle to a SnapshotMap; the current
+// version sits behind an atomically swapped pointer, so readers
+// never take a lock and a writer's new version replaces it
+// in a single step
+pub(crate) struct Shared<K: PartialOrd, V> {
+    // the number of the current version, raised once it is in
+    // place so readers can tell theirs is stale without loading it
+    version: AtomicUsize,
+    current: ArcSwap<(usize, PersistentRBMap<K, V>)>,
+    // writers take turns building on the current version
+    writer: Mutex<()>,
+}
+
+impl<K: PartialOrd, V> Shared<K, V> {
+    fn load(&self) -> (usize, PersistentRBMap<K, V>) {
+        let current = self.current.load();
+        (current.0, current.1.clone())
+    }
+}
+
+impl<K: PartialOrd + Debug, V: Debug> Debug for SnapshotMap<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{:?}", self.snapshot())
+    }
+}
+
+impl<K: PartialOrd, V> Clone for SnapshotMap<K, V> {
+    fn clone(&self) -> Self {
+        SnapshotMap {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<K: PartialOrd, V> SnapshotMap<K, V> {
+    /// Creates and returns a new, empty SnapshotMap.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SnapshotMap;
+    ///
+    /// let map = SnapshotMap::<u32, u32>::new();
+    /// assert!(map.snapshot().is_empty());
+    /// ```
+    pub fn new() -> SnapshotMap<K, V> {
+        SnapshotMap::from(PersistentRBMap::new())
+    }
+
+    /// Returns the current version of the map, which
+    /// later writes leave unchanged.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SnapshotMap;
+    ///
+    /// let map = SnapshotMap::new();
+    /// map.insert(1, 'a');
+    /// let before = map.snapshot();
+    /// map.insert(2, 'b');
+    /// assert_eq!(before.len(), 1);
+    /// assert_eq!(map.snapshot().len(), 2);
+    /// ```
+    pub fn snapshot(&self) -> PersistentRBMap<K, V> {
+        self.shared.load().1
+    }
+
+    /// Returns the number of writes made to the map.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SnapshotMap;
+    ///
+    /// let map = SnapshotMap::new();
+    /// assert_eq!(map.version(), 0);
+    /// map.insert(1, 'a');
+    /// map.remove(&1);
+    /// assert_eq!(map.version(), 2);
+    /// ```
+    pub fn version(&self) -> usize {
+        self.shared.version.load(Ordering::Acquire)
+    }
+
+    /// Returns a reader of the map, starting
+    /// from its current version.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SnapshotMap;
+    /// use std::thread;
+    ///
+    /// let map = SnapshotMap::new();
+    /// map.insert(1, 'a');
+    /// let mut reader = map.reader();
+    /// thread::spawn(move || {
+    ///     assert_eq!(reader.snapshot().get(&1), Some(&'a'));
+    /// })
+    /// .join()
+    /// .unwrap();
+    /// ```
+    pub fn reader(&self) -> SnapshotReader<K, V> {
+        let (version, cached) = self.shared.load();
+        SnapshotReader {
+            shared: self.shared.clone(),
+            version,
+            cached,
+        }
+    }
+
+    /// Makes a new version of the map by calling `f` on a
+    /// copy of the current one, then puts it in place of
+    /// the current one. Writers wait for each other, but
+    /// readers never wait for `f`. If `f` panics the map
+    /// is left as it was. Returns the result of `f`.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SnapshotMap;
+    ///
+    /// let map = SnapshotMap::new();
+    /// let len = map.update(|m| {
+    ///     m.insert_in_place(1, 'a');
+    ///     m.insert_in_place(2, 'b');
+    ///     m.len()
+    /// });
+    /// assert_eq!(len, 2);
+    /// assert_eq!(map.version(), 1);
+    /// ```
+    pub fn update<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut PersistentRBMap<K, V>) -> R,
+    {
+        let _writing = self
+            .shared
+            .writer
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let (version, mut next) = self.shared.load();
+        let res = f(&mut next);
+        self.shared.current.store(Arc::new((version + 1, next)));
+        self.shared.version.store(version + 1, Ordering::Release);
+        res
+    }
+}
+
+impl<K: PartialOrd + Clone, V: Clone> SnapshotMap<K, V> {
+    /// Associates `val` with `key` in a new version of the
+    /// map, returning the previously associated key-value
+    /// pair if one existed.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SnapshotMap;
+    ///
+    /// let map = SnapshotMap::new();
+    /// assert_eq!(map.insert(1, 'a'), None);
+    /// assert_eq!(map.insert(1, 'b'), Some((1, 'a')));
+    /// assert_eq!(map.snapshot().get(&1), Some(&'b'));
+    /// ```
+    pub fn insert(&self, key: K, val: V) -> Option<(K, V)> {
+        self.update(|m| m.insert_in_place(key, val))
+    }
+
+    /// Removes the entry for `key` in a new version of
+    /// the map, returning the value that was associated
+    /// with it if it existed.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SnapshotMap;
+    ///
+    /// let map = SnapshotMap::new();
+    /// map.insert(1, 'a');
+    /// assert_eq!(map.remove(&1), Some('a'));
+    /// assert_eq!(map.remove(&1), None);
+    /// ```
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self.update(|m| m.remove_in_place(key))
+    }
+}
+
+impl<K: PartialOrd, V> Default for SnapshotMap<K, V> {
+    fn default() -> Self {
+        SnapshotMap::new()
+    }
+}
+
+/// Starts a SnapshotMap from an existing version.
+/// # Example:
+/// ```
+/// use rb_tree::{PersistentRBMap, SnapshotMap};
+///
+/// let map = SnapshotMap::from(PersistentRBMap::new().insert(1, 'a'));
+/// assert_eq!(map.snapshot().get(&1), Some(&'a'));
+/// assert_eq!(map.version(), 0);
+/// ```
+impl<K: PartialOrd, V> From<PersistentRBMap<K, V>> for SnapshotMap<K, V> {
+    fn from(map: PersistentRBMap<K, V>) -> Self {
+        SnapshotMap {
+            shared: Arc::new(Shared {
+                version: AtomicUsize::new(0),
+                current: ArcSwap::from_pointee((0, map)),
+                writer: Mutex::new(()),
+            }),
+        }
+    }
+}
+
+impl<K: PartialOrd + Debug, V: Debug> Debug for SnapshotReader<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{:?}", self.cached)
+    }
+}
+
+impl<K: PartialOrd, V> SnapshotReader<K, V> {
+    /// Returns the current version of the map. Unless the
+    /// map has been written to since the last call, this is
+    /// the version returned then, found without locking.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SnapshotMap;
+    ///
+    /// let map = SnapshotMap::new();
+    /// let mut reader = map.reader();
+    /// assert!(reader.snapshot().is_empty());
+    /// map.insert(1, 'a');
+    /// assert_eq!(reader.snapshot().get(&1), Some(&'a'));
+    /// ```
+    pub fn snapshot(&mut self) -> &PersistentRBMap<K, V> {
+        if self.shared.version.load(Ordering::Acquire) != self.version {
+            let (version, cached) = self.shared.load();
+            self.version = version;
+            self.cached = cached;
+        }
+        &self.cached
+    }
+
+    /// Returns the version last returned by `snapshot`,
+    /// without checking for a newer one.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SnapshotMap;
+    ///
+    /// let map = SnapshotMap::new();
+    /// let mut reader = map.reader();
+    /// map.insert(1, 'a');
+    /// assert!(reader.cached().is_empty());
+    /// reader.snapshot();
+    /// assert_eq!(reader.cached().len(), 1);
+    /// ```
+    pub fn cached(&self) -> &PersistentRBMap<K, V> {
+        &self.cached
+    }
+
+    /// Returns the number of writes made to the map
+    /// before the version last returned by `snapshot`.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SnapshotMap;
+    ///
+    /// let map = SnapshotMap::new();
+    /// let mut reader = map.reader();
+    /// map.insert(1, 'a');
+    /// assert_eq!(reader.version(), 0);
+    /// reader.snapshot();
+    /// assert_eq!(reader.version(), 1);
+    /// ```
+    pub fn version(&self) -> usize {
+        self.version
+    }
+}
+
+impl<K: PartialOrd, V> Clone for SnapshotReader<K, V> {
+    fn clone(&self) -> Self {
+        SnapshotReader {
+            shared: self.shared.clone(),
+            version: self.version,
+            cached: self.cached.clone(),
+        }
+    }
+}
@@ -0,0 +1,493 @@
+use crate::node::{Index, NIL};
+use crate::raw::RawTree;
+pub use crate::rbtree::{IntoIter, Iter, Range};
+use crate::{Comparator, NaturalOrder, SortedList};
+
+use std::cmp::Ordering::{Equal, Greater, Less};
+use std::fmt::{Debug, Formatter, Result};
+use std::iter::FromIterator;
+use std::ops::RangeBounds;
+
+impl<T: Debug, C> Debug for SortedList<T, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialOrd> SortedList<T> {
+    /// Creates and returns a new, empty SortedList.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let mut l = SortedList::new();
+    /// l.insert(2);
+    /// l.insert(1);
+    /// l.insert(2);
+    /// assert_eq!(l.iter().collect::<Vec<_>>(), vec!(&1, &2, &2));
+    /// ```
+    pub fn new() -> SortedList<T> {
+        SortedList::with_comparator(NaturalOrder)
+    }
+}
+
+impl<T, C> SortedList<T, C> {
+    /// Creates and returns a new, empty SortedList
+    /// ordered by the given comparator.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let mut l = SortedList::with_comparator(|l: &&str, r: &&str| l.len().cmp(&r.len()));
+    /// l.extend(vec!("ccc", "a", "bb", "b"));
+    /// assert_eq!(l.iter().collect::<Vec<_>>(), vec!(&"a", &"b", &"bb", &"ccc"));
+    /// ```
+    pub fn with_comparator(cmp: C) -> SortedList<T, C> {
+        SortedList {
+            raw: RawTree::new(cmp),
+        }
+    }
+
+    /// Returns the comparator ordering the list.
+    pub fn comparator(&self) -> &C {
+        &self.raw.cmp
+    }
+
+    /// Returns the number of elements in the list,
+    /// counting each of a run of equal elements.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let l: SortedList<_> = vec!(1, 1, 1).into_iter().collect();
+    /// assert_eq!(l.len(), 3);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.raw.len()
+    }
+
+    /// Returns true if the list holds no elements.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let mut l = SortedList::new();
+    /// assert!(l.is_empty());
+    /// l.insert(1);
+    /// assert!(!l.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.raw.len() == 0
+    }
+
+    /// Removes all elements from the list.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let mut l: SortedList<_> = (0..10).collect();
+    /// l.clear();
+    /// assert!(l.is_empty());
+    /// ```
+    pub fn clear(&mut self) {
+        self.raw.clear();
+    }
+
+    /// Returns the element at position `index` in the list,
+    /// or None if the list has no more than `index` elements.
+    /// Takes O(log n) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let l: SortedList<_> = vec!(30, 10, 20, 10).into_iter().collect();
+    /// assert_eq!(l.at(0), Some(&10));
+    /// assert_eq!(l.at(2), Some(&20));
+    /// assert_eq!(l.at(4), None);
+    /// ```
+    pub fn at(&self, index: usize) -> Option<&T> {
+        let nodes = &self.raw.nodes;
+        nodes.value(nodes.nth(index))
+    }
+
+    /// Removes and returns the element at position `index`
+    /// in the list, or None if the list has no more than
+    /// `index` elements. Takes O(log n) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let mut l: SortedList<_> = vec!(3, 1, 2).into_iter().collect();
+    /// assert_eq!(l.remove_at(1), Some(2));
+    /// assert_eq!(l.remove_at(2), None);
+    /// assert_eq!(l.iter().collect::<Vec<_>>(), vec!(&1, &3));
+    /// ```
+    pub fn remove_at(&mut self, index: usize) -> Option<T> {
+        if index >= self.len() {
+            return None;
+        }
+        let at = self.raw.nodes.nth(index);
+        self.raw.contained -= 1;
+        Some(self.raw.nodes.remove_at(at))
+    }
+
+    /// Returns an iterator over the elements at the positions
+    /// within `range`, in order. Finding the first element
+    /// takes O(log n) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let l: SortedList<_> = (0..10).rev().collect();
+    /// assert_eq!(l.slice(2..5).collect::<Vec<_>>(), vec!(&2, &3, &4));
+    /// assert_eq!(l.slice(8..).collect::<Vec<_>>(), vec!(&8, &9));
+    /// assert_eq!(l.slice(..=1).count(), 2);
+    /// assert_eq!(l.slice(12..20).next(), None);
+    /// ```
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> Range<'_, T> {
+        self.raw.positions(range)
+    }
+
+    /// Returns the smallest element of the list, or
+    /// the first inserted of the equal smallest.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let l: SortedList<_> = vec!(2, 1, 3).into_iter().collect();
+    /// assert_eq!(l.peek(), Some(&1));
+    /// ```
+    pub fn peek(&self) -> Option<&T> {
+        self.raw.peek(false)
+    }
+
+    /// Returns the largest element of the list, or
+    /// the last inserted of the equal largest.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let l: SortedList<_> = vec!(2, 1, 3).into_iter().collect();
+    /// assert_eq!(l.peek_back(), Some(&3));
+    /// ```
+    pub fn peek_back(&self) -> Option<&T> {
+        self.raw.peek(true)
+    }
+
+    /// Removes and returns the smallest element of the
+    /// list, or the first inserted of the equal smallest.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let mut l: SortedList<_> = vec!(2, 1, 1).into_iter().collect();
+    /// assert_eq!(l.pop(), Some(1));
+    /// assert_eq!(l.pop(), Some(1));
+    /// assert_eq!(l.pop(), Some(2));
+    /// assert_eq!(l.pop(), None);
+    /// ```
+    pub fn pop(&mut self) -> Option<T> {
+        self.raw.pop(false)
+    }
+
+    /// Removes and returns the largest element of the
+    /// list, or the last inserted of the equal largest.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let mut l: SortedList<_> = vec!(2, 1, 3).into_iter().collect();
+    /// assert_eq!(l.pop_back(), Some(3));
+    /// assert_eq!(l.len(), 2);
+    /// ```
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.raw.pop(true)
+    }
+
+    /// Returns an iterator over the elements of
+    /// the list, in order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let l: SortedList<_> = vec!('b', 'a', 'b').into_iter().collect();
+    /// assert_eq!(l.iter().collect::<String>(), "abb");
+    /// ```
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.raw.iter()
+    }
+
+    /// Inserts `val` into the list, after any elements
+    /// equal to it, and returns the position it was
+    /// inserted at.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let mut l = SortedList::new();
+    /// assert_eq!(l.insert(5), 0);
+    /// assert_eq!(l.insert(1), 0);
+    /// assert_eq!(l.insert(5), 2);
+    /// assert_eq!(l.insert(3), 1);
+    /// assert_eq!(l.iter().collect::<Vec<_>>(), vec!(&1, &3, &5, &5));
+    /// ```
+    pub fn insert(&mut self, val: T) -> usize
+    where
+        C: Comparator<T>,
+    {
+        let cmp = &self.raw.cmp;
+        let next = self
+            .raw
+            .nodes
+            .partition_point(|v| cmp.compare(&val, v) != Less);
+        let at = self.raw.nodes.insert_before(next, val);
+        self.raw.contained += 1;
+        self.raw.verify();
+        self.raw.nodes.rank(at)
+    }
+
+    // the first node holding a value not less than val
+    // (strict=false) or greater than val (strict=true)
+    fn bound<K>(&self, val: &K, strict: bool) -> Index
+    where
+        C: Comparator<K, T>,
+    {
+        let cmp = &self.raw.cmp;
+        self.raw
+            .nodes
+            .partition_point(|v| match cmp.compare(val, v) {
+                Greater => true,
+                Equal => strict,
+                Less => false,
+            })
+    }
+
+    // the position of the node, or the length of the list for NIL
+    fn position(&self, at: Index) -> usize {
+        if at == NIL {
+            self.len()
+        } else {
+            self.raw.nodes.rank(at)
+        }
+    }
+
+    /// Returns the number of elements less than `val`,
+    /// which is the position of the first element equal
+    /// to it if there is one. Takes O(log n) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let l: SortedList<_> = vec!(1, 2, 2, 2, 3).into_iter().collect();
+    /// assert_eq!(l.lower_bound(&2), 1);
+    /// assert_eq!(l.lower_bound(&0), 0);
+    /// assert_eq!(l.lower_bound(&9), 5);
+    /// ```
+    pub fn lower_bound<K>(&self, val: &K) -> usize
+    where
+        C: Comparator<K, T>,
+    {
+        self.position(self.bound(val, false))
+    }
+
+    /// Returns the number of elements less than or equal
+    /// to `val`, which is the position just past the last
+    /// element equal to it if there is one. Takes O(log n)
+    /// time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let l: SortedList<_> = vec!(1, 2, 2, 2, 3).into_iter().collect();
+    /// assert_eq!(l.upper_bound(&2), 4);
+    /// assert_eq!(l.upper_bound(&0), 0);
+    /// assert_eq!(l.upper_bound(&9), 5);
+    /// ```
+    pub fn upper_bound<K>(&self, val: &K) -> usize
+    where
+        C: Comparator<K, T>,
+    {
+        self.position(self.bound(val, true))
+    }
+
+    /// Returns the position of the first element equal
+    /// to `val`, or None if there isn't one. Takes
+    /// O(log n) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let l: SortedList<_> = vec!(1, 2, 2, 3).into_iter().collect();
+    /// assert_eq!(l.rank(&2), Some(1));
+    /// assert_eq!(l.rank(&3), Some(3));
+    /// assert_eq!(l.rank(&4), None);
+    /// ```
+    pub fn rank<K>(&self, val: &K) -> Option<usize>
+    where
+        C: Comparator<K, T>,
+    {
+        let at = self.bound(val, false);
+        match self.raw.nodes.value(at) {
+            Some(v) if self.raw.cmp.compare(val, v) == Equal => Some(self.raw.nodes.rank(at)),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of elements equal
+    /// to `val`. Takes O(log n) time.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let l: SortedList<_> = vec!("b", "a", "b", "c", "b").into_iter().collect();
+    /// assert_eq!(l.count(&"b"), 3);
+    /// assert_eq!(l.count(&"d"), 0);
+    /// ```
+    pub fn count<K>(&self, val: &K) -> usize
+    where
+        C: Comparator<K, T>,
+    {
+        self.upper_bound(val) - self.lower_bound(val)
+    }
+
+    /// Returns true if the list holds an element
+    /// equal to `val`, false otherwise.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let l: SortedList<_> = vec!(1, 3).into_iter().collect();
+    /// assert!(l.contains(&3));
+    /// assert!(!l.contains(&2));
+    /// ```
+    pub fn contains<K>(&self, val: &K) -> bool
+    where
+        C: Comparator<K, T>,
+    {
+        self.rank(val).is_some()
+    }
+
+    /// Removes and returns the first inserted of
+    /// the elements equal to `val`, if there are any.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let mut l: SortedList<_> = vec!(2, 1, 2).into_iter().collect();
+    /// assert_eq!(l.remove(&2), Some(2));
+    /// assert_eq!(l.remove(&5), None);
+    /// assert_eq!(l.iter().collect::<Vec<_>>(), vec!(&1, &2));
+    /// ```
+    pub fn remove<K>(&mut self, val: &K) -> Option<T>
+    where
+        C: Comparator<K, T>,
+    {
+        let at = self.bound(val, false);
+        match self.raw.nodes.value(at) {
+            Some(v) if self.raw.cmp.compare(val, v) == Equal => {
+                self.raw.contained -= 1;
+                Some(self.raw.nodes.remove_at(at))
+            }
+            _ => None,
+        }
+    }
+
+    /// Removes every element equal to `val`,
+    /// returning the number removed.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let mut l: SortedList<_> = vec!(2, 1, 2, 3, 2).into_iter().collect();
+    /// assert_eq!(l.remove_all(&2), 3);
+    /// assert_eq!(l.iter().collect::<Vec<_>>(), vec!(&1, &3));
+    /// ```
+    pub fn remove_all<K>(&mut self, val: &K) -> usize
+    where
+        C: Comparator<K, T>,
+    {
+        let mut removed = 0;
+        while self.remove(val).is_some() {
+            removed += 1;
+        }
+        removed
+    }
+
+    /// Returns an iterator over the elements
+    /// within `range`, in order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::SortedList;
+    ///
+    /// let l: SortedList<_> = vec!(1, 3, 3, 5, 7).into_iter().collect();
+    /// assert_eq!(l.range(3..=5).collect::<Vec<_>>(), vec!(&3, &3, &5));
+    /// ```
+    pub fn range<K, R>(&self, range: R) -> Range<'_, T>
+    where
+        R: RangeBounds<K>,
+        C: Comparator<K, T>,
+    {
+        self.raw.range(range)
+    }
+}
+
+impl<T, C: Default> Default for SortedList<T, C> {
+    fn default() -> Self {
+        SortedList::with_comparator(C::default())
+    }
+}
+
+impl<T: PartialEq, C> PartialEq for SortedList<T, C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq, C> Eq for SortedList<T, C> {}
+
+impl<T, C> IntoIterator for SortedList<T, C> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter::new(self.raw.nodes, self.raw.contained)
+    }
+}
+
+impl<'a, T, C> IntoIterator for &'a SortedList<T, C> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// Builds the list in linear time once the
+/// elements are sorted, rather than inserting
+/// each; equal elements keep their order.
+/// # Example:
+/// ```
+/// use rb_tree::SortedList;
+///
+/// let l: SortedList<_> = vec!(3, 1, 2, 1).into_iter().collect();
+/// assert_eq!(l.iter().collect::<Vec<_>>(), vec!(&1, &1, &2, &3));
+/// ```
+impl<T, C: Comparator<T> + Default> FromIterator<T> for SortedList<T, C> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list: SortedList<T, C> = SortedList::default();
+        let mut values: Vec<T> = iter.into_iter().collect();
+        let cmp = &list.raw.cmp;
+        values.sort_by(|l, r| cmp.compare(l, r));
+        list.raw.rebuild(values);
+        list
+    }
+}
+
+impl<T, C: Comparator<T>> Extend<T> for SortedList<T, C> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for v in iter {
+            self.insert(v);
+        }
+    }
+}
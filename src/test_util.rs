@@ -0,0 +1,407 @@
+//! Tools for checking the collections of this crate against
+//! those of the standard library, as its own stress tests do.
+//!
+//! A `ShadowMap` (or `ShadowSet`) applies each operation it is
+//! given to both an `RBMap` and a `BTreeMap` (or an `RBTree`
+//! and a `BTreeSet`), panicking as soon as the two disagree
+//! on a result or on their contents. The operations can be
+//! scripted by hand, or generated by a `Churn`.
+//!
+//! Only available with the "test_util" feature.
+
+use crate::{RBMap, RBTree};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+
+/// An operation to apply to a map.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MapOp<K, V> {
+    Insert(K, V),
+    Remove(K),
+    Get(K),
+    PopFirst,
+    PopLast,
+    Clear,
+}
+
+/// An operation to apply to a set.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SetOp<T> {
+    Insert(T),
+    Remove(T),
+    Contains(T),
+    PopFirst,
+    PopLast,
+    Clear,
+}
+
+/// Turns a map operation into the same operation on
+/// the map's keys.
+/// # Example:
+/// ```
+/// use rb_tree::test_util::{MapOp, SetOp};
+///
+/// assert_eq!(SetOp::from(MapOp::Insert(1, 'a')), SetOp::Insert(1));
+/// assert_eq!(SetOp::from(MapOp::<u8, char>::Get(1)), SetOp::Contains(1));
+/// ```
+impl<K, V> From<MapOp<K, V>> for SetOp<K> {
+    fn from(op: MapOp<K, V>) -> SetOp<K> {
+        match op {
+            MapOp::Insert(k, _) => SetOp::Insert(k),
+            MapOp::Remove(k) => SetOp::Remove(k),
+            MapOp::Get(k) => SetOp::Contains(k),
+            MapOp::PopFirst => SetOp::PopFirst,
+            MapOp::PopLast => SetOp::PopLast,
+            MapOp::Clear => SetOp::Clear,
+        }
+    }
+}
+
+// panics naming the operation if the results differ
+fn compare<O: Debug, R: PartialEq + Debug>(n: usize, op: &O, tree: R, model: R) {
+    if tree != model {
+        panic!(
+            "operation {} ({:?}) gave {:?}, but the model gave {:?}",
+            n, op, tree, model
+        );
+    }
+}
+
+/// An RBMap shadowed by a BTreeMap, which every
+/// operation is checked against.
+pub struct ShadowMap<K: PartialOrd, V> {
+    pub(crate) map: RBMap<K, V>,
+    model: BTreeMap<K, V>,
+    applied: usize,
+}
+
+impl<K: Ord + Clone + Debug, V: Clone + PartialEq + Debug> ShadowMap<K, V> {
+    /// Creates and returns a new, empty ShadowMap.
+    /// # Example:
+    /// ```
+    /// use rb_tree::test_util::ShadowMap;
+    ///
+    /// let shadow = ShadowMap::<u32, u32>::new();
+    /// assert!(shadow.map().is_empty());
+    /// ```
+    pub fn new() -> ShadowMap<K, V> {
+        ShadowMap {
+            map: RBMap::new(),
+            model: BTreeMap::new(),
+            applied: 0,
+        }
+    }
+
+    /// Applies the operation to both maps, then panics
+    /// if they gave different results or no longer
+    /// hold the same pairs.
+    /// # Example:
+    /// ```
+    /// use rb_tree::test_util::{MapOp, ShadowMap};
+    ///
+    /// let mut shadow = ShadowMap::new();
+    /// shadow.apply(MapOp::Insert(1, 'a'));
+    /// shadow.apply(MapOp::Insert(1, 'b'));
+    /// shadow.apply(MapOp::Remove(2));
+    /// assert_eq!(shadow.map().get(&1), Some(&'b'));
+    /// ```
+    pub fn apply(&mut self, op: MapOp<K, V>) {
+        let n = self.applied;
+        match &op {
+            MapOp::Insert(k, v) => compare(
+                n,
+                &op,
+                self.map.insert(k.clone(), v.clone()).map(|(_, v)| v),
+                self.model.insert(k.clone(), v.clone()),
+            ),
+            MapOp::Remove(k) => compare(n, &op, self.map.remove(k), self.model.remove(k)),
+            MapOp::Get(k) => compare(n, &op, self.map.get(k), self.model.get(k)),
+            MapOp::PopFirst => compare(n, &op, self.map.pop_pair(), self.model.pop_first()),
+            MapOp::PopLast => compare(n, &op, self.map.pop_pair_back(), self.model.pop_last()),
+            MapOp::Clear => {
+                self.map.clear();
+                self.model.clear();
+            }
+        }
+        self.applied += 1;
+        self.check();
+    }
+
+    /// Applies each of the operations in turn, as `apply`.
+    /// # Example:
+    /// ```
+    /// use rb_tree::test_util::{Churn, ShadowMap};
+    ///
+    /// let mut shadow = ShadowMap::new();
+    /// shadow.run(Churn::new(7, 10, 20).take(1000));
+    /// assert!(shadow.map().len() <= 20);
+    /// ```
+    pub fn run<I: IntoIterator<Item = MapOp<K, V>>>(&mut self, ops: I) {
+        for op in ops {
+            self.apply(op);
+        }
+    }
+
+    /// Panics unless both maps hold the same pairs
+    /// in the same order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::test_util::ShadowMap;
+    ///
+    /// ShadowMap::<u8, u8>::new().check();
+    /// ```
+    pub fn check(&self) {
+        if self.map.len() != self.model.len() || !self.map.iter().eq(self.model.iter()) {
+            panic!(
+                "after {} operations the map holds {:?}, but the model holds {:?}",
+                self.applied, self.map, self.model
+            );
+        }
+    }
+
+    /// Returns the RBMap being checked.
+    /// # Example:
+    /// ```
+    /// use rb_tree::test_util::{MapOp, ShadowMap};
+    ///
+    /// let mut shadow = ShadowMap::new();
+    /// shadow.apply(MapOp::Insert(1, 2));
+    /// assert_eq!(shadow.map().get(&1), Some(&2));
+    /// ```
+    pub fn map(&self) -> &RBMap<K, V> {
+        &self.map
+    }
+
+    /// Consumes the ShadowMap, returning the RBMap.
+    /// # Example:
+    /// ```
+    /// use rb_tree::test_util::{MapOp, ShadowMap};
+    ///
+    /// let mut shadow = ShadowMap::new();
+    /// shadow.apply(MapOp::Insert(1, 2));
+    /// assert_eq!(shadow.into_inner().remove(&1), Some(2));
+    /// ```
+    pub fn into_inner(self) -> RBMap<K, V> {
+        self.map
+    }
+}
+
+impl<K: Ord + Clone + Debug, V: Clone + PartialEq + Debug> Default for ShadowMap<K, V> {
+    fn default() -> Self {
+        ShadowMap::new()
+    }
+}
+
+/// An RBTree shadowed by a BTreeSet, which every
+/// operation is checked against.
+pub struct ShadowSet<T> {
+    set: RBTree<T>,
+    model: BTreeSet<T>,
+    applied: usize,
+}
+
+impl<T: Ord + Clone + Debug> ShadowSet<T> {
+    /// Creates and returns a new, empty ShadowSet.
+    /// # Example:
+    /// ```
+    /// use rb_tree::test_util::ShadowSet;
+    ///
+    /// let shadow = ShadowSet::<u32>::new();
+    /// assert!(shadow.set().is_empty());
+    /// ```
+    pub fn new() -> ShadowSet<T> {
+        ShadowSet {
+            set: RBTree::new(),
+            model: BTreeSet::new(),
+            applied: 0,
+        }
+    }
+
+    /// Applies the operation to both sets, then panics
+    /// if they gave different results or no longer
+    /// hold the same elements.
+    /// # Example:
+    /// ```
+    /// use rb_tree::test_util::{SetOp, ShadowSet};
+    ///
+    /// let mut shadow = ShadowSet::new();
+    /// shadow.apply(SetOp::Insert(3));
+    /// shadow.apply(SetOp::Insert(1));
+    /// shadow.apply(SetOp::PopLast);
+    /// assert!(shadow.set().iter().eq(&[1]));
+    /// ```
+    pub fn apply(&mut self, op: SetOp<T>) {
+        let n = self.applied;
+        match &op {
+            SetOp::Insert(v) => compare(
+                n,
+                &op,
+                self.set.insert(v.clone()),
+                self.model.insert(v.clone()),
+            ),
+            SetOp::Remove(v) => compare(n, &op, self.set.remove(v), self.model.remove(v)),
+            SetOp::Contains(v) => compare(n, &op, self.set.contains(v), self.model.contains(v)),
+            SetOp::PopFirst => compare(n, &op, self.set.pop(), self.model.pop_first()),
+            SetOp::PopLast => compare(n, &op, self.set.pop_back(), self.model.pop_last()),
+            SetOp::Clear => {
+                self.set.clear();
+                self.model.clear();
+            }
+        }
+        self.applied += 1;
+        self.check();
+    }
+
+    /// Applies each of the operations in turn, as `apply`.
+    /// # Example:
+    /// ```
+    /// use rb_tree::test_util::{Churn, SetOp, ShadowSet};
+    ///
+    /// let mut shadow = ShadowSet::new();
+    /// shadow.run(Churn::new(7, 10, 20).take(1000).map(SetOp::from));
+    /// assert!(shadow.set().len() <= 20);
+    /// ```
+    pub fn run<I: IntoIterator<Item = SetOp<T>>>(&mut self, ops: I) {
+        for op in ops {
+            self.apply(op);
+        }
+    }
+
+    /// Panics unless both sets hold the same elements
+    /// in the same order.
+    /// # Example:
+    /// ```
+    /// use rb_tree::test_util::ShadowSet;
+    ///
+    /// ShadowSet::<u8>::new().check();
+    /// ```
+    pub fn check(&self) {
+        if self.set.len() != self.model.len() || !self.set.iter().eq(self.model.iter()) {
+            panic!(
+                "after {} operations the set holds {:?}, but the model holds {:?}",
+                self.applied, self.set, self.model
+            );
+        }
+    }
+
+    /// Returns the RBTree being checked.
+    /// # Example:
+    /// ```
+    /// use rb_tree::test_util::{SetOp, ShadowSet};
+    ///
+    /// let mut shadow = ShadowSet::new();
+    /// shadow.apply(SetOp::Insert(1));
+    /// assert!(shadow.set().contains(&1));
+    /// ```
+    pub fn set(&self) -> &RBTree<T> {
+        &self.set
+    }
+
+    /// Consumes the ShadowSet, returning the RBTree.
+    /// # Example:
+    /// ```
+    /// use rb_tree::test_util::{SetOp, ShadowSet};
+    ///
+    /// let mut shadow = ShadowSet::new();
+    /// shadow.apply(SetOp::Insert(1));
+    /// assert_eq!(shadow.into_inner().pop(), Some(1));
+    /// ```
+    pub fn into_inner(self) -> RBTree<T> {
+        self.set
+    }
+}
+
+impl<T: Ord + Clone + Debug> Default for ShadowSet<T> {
+    fn default() -> Self {
+        ShadowSet::new()
+    }
+}
+
+/// An endless, reproducible stream of operations on
+/// `u32` keys that keeps a map's size within the given
+/// bounds once it has grown to them. Mostly inserts
+/// keys not yet present and removes keys that are,
+/// with the odd lookup and pop thrown in.
+#[derive(Clone, Debug)]
+pub struct Churn {
+    state: u64,
+    live: Vec<u32>,
+    min_size: usize,
+    max_size: usize,
+}
+
+impl Churn {
+    /// Creates and returns a new Churn, whose operations
+    /// are determined by `seed`.
+    /// # Example:
+    /// ```
+    /// use rb_tree::test_util::Churn;
+    ///
+    /// let ops: Vec<_> = Churn::new(1, 0, 5).take(10).collect();
+    /// assert_eq!(ops, Churn::new(1, 0, 5).take(10).collect::<Vec<_>>());
+    /// ```
+    pub fn new(seed: u64, min_size: usize, max_size: usize) -> Churn {
+        Churn {
+            // xorshift gets stuck on a state of zero
+            state: seed | 1,
+            live: Vec::new(),
+            min_size,
+            max_size: max_size.max(min_size).max(1),
+        }
+    }
+
+    // xorshift64
+    fn gen(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    fn gen_below(&mut self, n: usize) -> usize {
+        (self.gen() % n as u64) as usize
+    }
+
+    // takes the nth smallest key out of the live ones
+    fn take_live(&mut self, n: usize) -> u32 {
+        self.live.sort_unstable();
+        self.live.remove(n)
+    }
+}
+
+impl Iterator for Churn {
+    type Item = MapOp<u32, u32>;
+
+    fn next(&mut self) -> Option<MapOp<u32, u32>> {
+        let len = self.live.len();
+        let roll = self.gen_below(16);
+        let op = if len > 0 && roll == 0 {
+            let n = self.gen_below(len);
+            MapOp::Get(self.live[n])
+        } else if len > 0 && roll == 1 {
+            MapOp::PopFirst
+        } else if len > 0 && roll == 2 {
+            MapOp::PopLast
+        } else if len == 0 || len < self.min_size || (len < self.max_size && roll < 10) {
+            let key = self.gen() as u32;
+            if !self.live.contains(&key) {
+                self.live.push(key);
+            }
+            MapOp::Insert(key, key)
+        } else {
+            let n = self.gen_below(len);
+            MapOp::Remove(self.live.swap_remove(n))
+        };
+        match op {
+            MapOp::PopFirst => {
+                self.take_live(0);
+            }
+            MapOp::PopLast => {
+                self.take_live(len - 1);
+            }
+            _ => (),
+        }
+        Some(op)
+    }
+}
@@ -0,0 +1,165 @@
+use crate::rbqueue;
+use crate::{MaxQueue, TopK};
+
+use std::fmt::{Debug, Formatter, Result};
+
+impl<T: Ord + Debug> Debug for TopK<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_list().entries(self.best.iter()).finish()
+    }
+}
+
+impl<T: Ord> TopK<T> {
+    /// Creates and returns a new, empty TopK that
+    /// keeps the `k` largest elements offered to it.
+    /// # Example:
+    /// ```
+    /// use rb_tree::TopK;
+    ///
+    /// let mut top = TopK::new(2);
+    /// top.extend(vec!(5, 1, 9, 3));
+    /// assert_eq!(top.into_sorted_vec(), vec!(5, 9));
+    /// ```
+    pub fn new(k: usize) -> TopK<T> {
+        TopK {
+            best: MaxQueue::default(),
+            k,
+        }
+    }
+
+    /// Offers an element, keeping it if it is among the
+    /// `k` largest seen so far. Returns the element that
+    /// no longer fits, which is either the smallest kept
+    /// element, evicted to make room, or `item` itself.
+    /// If an equal element is already kept, `item`
+    /// replaces it and the old element is returned.
+    /// # Example:
+    /// ```
+    /// use rb_tree::TopK;
+    ///
+    /// let mut top = TopK::new(2);
+    /// assert_eq!(top.offer(4), None);
+    /// assert_eq!(top.offer(7), None);
+    /// assert_eq!(top.offer(5), Some(4));
+    /// assert_eq!(top.offer(1), Some(1));
+    /// assert_eq!(top.offer(7), Some(7));
+    /// ```
+    pub fn offer(&mut self, item: T) -> Option<T> {
+        if self.best.len() < self.k || self.best.contains(&item) {
+            return self.best.replace(item);
+        }
+        match self.best.peek_back() {
+            Some(worst) if item > *worst => {
+                let evicted = self.best.pop_back();
+                self.best.insert(item);
+                evicted
+            }
+            _ => Some(item),
+        }
+    }
+
+    /// Returns the largest element kept, if any.
+    /// # Example:
+    /// ```
+    /// use rb_tree::TopK;
+    ///
+    /// let mut top = TopK::new(3);
+    /// top.extend(vec!(2, 8, 4));
+    /// assert_eq!(top.peek_best(), Some(&8));
+    /// ```
+    pub fn peek_best(&self) -> Option<&T> {
+        self.best.peek()
+    }
+
+    /// Returns the smallest element kept, which is the
+    /// one an element must beat to be kept once `k`
+    /// elements are held.
+    /// # Example:
+    /// ```
+    /// use rb_tree::TopK;
+    ///
+    /// let mut top = TopK::new(3);
+    /// top.extend(vec!(2, 8, 4, 6));
+    /// assert_eq!(top.peek_worst(), Some(&4));
+    /// ```
+    pub fn peek_worst(&self) -> Option<&T> {
+        self.best.peek_back()
+    }
+
+    /// Returns the most elements kept at once.
+    /// # Example:
+    /// ```
+    /// use rb_tree::TopK;
+    ///
+    /// let top = TopK::<u8>::new(10);
+    /// assert_eq!(top.k(), 10);
+    /// ```
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Returns the number of elements kept.
+    /// # Example:
+    /// ```
+    /// use rb_tree::TopK;
+    ///
+    /// let mut top = TopK::new(3);
+    /// top.extend(vec!(2, 8));
+    /// assert_eq!(top.len(), 2);
+    /// top.extend(vec!(1, 9));
+    /// assert_eq!(top.len(), 3);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.best.len()
+    }
+
+    /// Returns true if no elements are kept.
+    /// # Example:
+    /// ```
+    /// use rb_tree::TopK;
+    ///
+    /// let mut top = TopK::new(3);
+    /// assert!(top.is_empty());
+    /// top.offer(1);
+    /// assert!(!top.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.best.is_empty()
+    }
+
+    /// Returns an iterator over the kept
+    /// elements, from largest to smallest.
+    /// # Example:
+    /// ```
+    /// use rb_tree::TopK;
+    ///
+    /// let mut top = TopK::new(3);
+    /// top.extend(vec!(2, 8, 4, 6));
+    /// assert_eq!(top.iter().collect::<Vec<_>>(), vec!(&8, &6, &4));
+    /// ```
+    pub fn iter(&self) -> rbqueue::Iter<'_, T> {
+        self.best.iter()
+    }
+
+    /// Consumes the TopK, returning the kept
+    /// elements from smallest to largest.
+    /// # Example:
+    /// ```
+    /// use rb_tree::TopK;
+    ///
+    /// let mut top = TopK::new(3);
+    /// top.extend(vec!("pear", "fig", "apple", "kiwi"));
+    /// assert_eq!(top.into_sorted_vec(), vec!("fig", "kiwi", "pear"));
+    /// ```
+    pub fn into_sorted_vec(self) -> Vec<T> {
+        self.best.into_iter().rev().collect()
+    }
+}
+
+impl<T: Ord> Extend<T> for TopK<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for item in iter {
+            self.offer(item);
+        }
+    }
+}
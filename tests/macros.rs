@@ -0,0 +1,93 @@
+// the macros are used here as any other crate would use them,
+// without importing the collections they expand to
+#[cfg(feature = "set")]
+#[test]
+fn test_new_set_without_imports() {
+    let t = rb_tree::new_set!(3, 1, 2);
+    assert_eq!(t.iter().collect::<Vec<_>>(), [&1, &2, &3]);
+
+    let empty: rb_tree::RBTree<u8> = rb_tree::new_set!();
+    assert!(empty.is_empty());
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_new_map_without_imports() {
+    let m = rb_tree::new_map!((2, 'b'), (1, 'a'));
+    assert_eq!(m.get(&1), Some(&'a'));
+    assert_eq!(m.get(&2), Some(&'b'));
+    assert_eq!(m.len(), 2);
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_new_queue_without_imports() {
+    let mut q = rb_tree::new_queue!(|l: &i32, r: &i32| r.cmp(l); 1, 3, 2);
+    assert_eq!(q.pop(), Some(3));
+    assert_eq!(q.pop(), Some(2));
+    assert_eq!(q.pop(), Some(1));
+    assert_eq!(q.pop(), None);
+}
+
+#[cfg(feature = "queue")]
+#[test]
+fn test_new_c_queue_without_imports() {
+    let mut q = rb_tree::new_c_queue!(|l: &i64, r| r - l);
+    q.insert(1);
+    q.insert(2);
+    assert_eq!(q.ordered(), [&2, &1]);
+
+    let q = rb_tree::new_c_queue!(|l: &i64, r| l - r; 3, 1, 2);
+    assert_eq!(q.ordered(), [&1, &2, &3]);
+}
+
+#[cfg(feature = "set")]
+#[test]
+fn test_new_set_from_iterables() {
+    let t = rb_tree::new_set!(from 0..100);
+    assert_eq!(t.len(), 100);
+    assert!((0..100).eq(t.iter().copied()));
+
+    let t = rb_tree::new_set!(from vec!['c', 'a', 'b', 'a']);
+    assert_eq!(t.iter().collect::<String>(), "abc");
+
+    let t = rb_tree::new_set!(from t.iter().map(|c| c.to_ascii_uppercase()));
+    assert_eq!(t.iter().collect::<String>(), "ABC");
+
+    // a variable named `from` is still an ordinary item
+    let from = 5;
+    let t = rb_tree::new_set!(from, 1);
+    assert_eq!(t.iter().collect::<Vec<_>>(), [&1, &5]);
+}
+
+#[cfg(feature = "map")]
+#[test]
+fn test_new_map_from_iterables() {
+    let pairs = vec![(3, "c"), (1, "a"), (2, "b")];
+    let m = rb_tree::new_map!(from pairs);
+    assert_eq!(m.values().copied().collect::<Vec<_>>(), ["a", "b", "c"]);
+
+    let m = rb_tree::new_map!(from m.iter().map(|(k, v)| (*v, k * 10)));
+    assert_eq!(m.get(&"b"), Some(&20));
+    assert_eq!(m.len(), 3);
+
+    let empty = rb_tree::new_map!(from Vec::<(u8, u8)>::new());
+    assert!(empty.is_empty());
+}
+
+// the macros still expand correctly through a re-export
+mod reexported {
+    #[cfg(feature = "map")]
+    pub use rb_tree::new_map as map;
+    #[cfg(feature = "set")]
+    pub use rb_tree::new_set as set;
+}
+
+#[cfg(all(feature = "set", feature = "map"))]
+#[test]
+fn test_macros_through_reexports() {
+    let t = reexported::set!('a', 'b');
+    assert!(t.contains(&'b'));
+    let m = reexported::map!(('a', 1));
+    assert_eq!(m.get(&'a'), Some(&1));
+}